@@ -155,8 +155,8 @@ mod spec_tests {
                                         "    let wasm_bytes: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/wasm/trap_module_{file}_{idx}.wasm\"));\n",
                                         "    let module = Module::new(wasm_bytes).unwrap();\n",
                                         "    let mut store = Store::new();\n",
-                                        "    let imports = setup_spectest_imports(&mut store, &module);\n",
-                                        "    let result = store.instantiate(&module, imports);\n",
+                                        "    let linker = spectest_linker(&mut store);\n",
+                                        "    let result = linker.instantiate(&mut store, &module);\n",
                                         "    assert!(result.is_err(), \"expected module instantiation to trap, but it succeeded\");\n",
                                         "}}\n",
                                     ),
@@ -251,8 +251,8 @@ mod spec_tests {
                                 "    let wasm_bytes: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/wasm/unlinkable_{file}_{idx}.wasm\"));\n",
                                 "    let module = Module::new(wasm_bytes).unwrap();\n",
                                 "    let mut store = Store::new();\n",
-                                "    let imports = setup_spectest_imports(&mut store, &module);\n",
-                                "    let result = store.instantiate(&module, imports);\n",
+                                "    let linker = spectest_linker(&mut store);\n",
+                                "    let result = linker.instantiate(&mut store, &module);\n",
                                 "    assert!(result.is_err(), \"expected unlinkable module to fail instantiation, but it succeeded\");\n",
                                 "}}\n",
                             ),
@@ -303,36 +303,30 @@ mod spec_tests {
                     }
                     prereq_indices.sort();
 
-                    let mut setup = String::new();
-                    // Instantiate each prerequisite module
+                    let mut setup =
+                        String::from("    let mut linker = spectest_linker(&mut store);\n");
+                    // Instantiate each prerequisite module, then register it under
+                    // its names so later prerequisites can import from it
                     for pidx in &prereq_indices {
                         setup.push_str(&format!(
                             concat!(
                                 "    let prereq_wasm_{pidx}: &[u8] = include_bytes!(concat!(env!(\"OUT_DIR\"), \"/wasm/{file}_{pidx}.wasm\"));\n",
                                 "    let prereq_module_{pidx} = Module::new(prereq_wasm_{pidx}).unwrap();\n",
-                                "    let prereq_imports_{pidx} = setup_spectest_imports(&mut store, &prereq_module_{pidx});\n",
-                                "    let prereq_instance_{pidx} = store.instantiate(&prereq_module_{pidx}, prereq_imports_{pidx}).unwrap();\n",
-                                "    let prereq_exports_{pidx}: Vec<ExportInstance> = store.exports(prereq_instance_{pidx}).to_vec();\n",
+                                "    let prereq_instance_{pidx} = linker.instantiate(&mut store, &prereq_module_{pidx}).unwrap();\n",
                             ),
                             pidx = pidx,
                             file = safe_name,
                         ));
+                        for (name, _) in deps.iter().filter(|(_, idx)| idx == pidx) {
+                            setup.push_str(&format!(
+                                "    linker.instance(&store, \"{}\", prereq_instance_{});\n",
+                                name, pidx
+                            ));
+                        }
                     }
-
-                    // Build the registered_exports vec
-                    setup.push_str(
-                        "    let registered_exports: Vec<(&str, &[ExportInstance])> = vec![",
-                    );
-                    for (name, dep_idx) in deps {
-                        setup.push_str(&format!("(\"{}\", &prereq_exports_{}), ", name, dep_idx));
-                    }
-                    setup.push_str("];\n");
-
-                    // Resolve imports using registered modules
-                    setup.push_str("    let imports = resolve_imports_with_registered(&mut store, &module, &registered_exports);\n");
                     setup
                 } else {
-                    "    let imports = setup_spectest_imports(&mut store, &module);\n".to_string()
+                    "    let linker = spectest_linker(&mut store);\n".to_string()
                 };
 
                 all_tests.push_str(&format!(
@@ -343,7 +337,7 @@ mod spec_tests {
                         "    let module = Module::new(wasm_bytes).unwrap();\n",
                         "    let mut store = Store::new();\n",
                         "{setup}",
-                        "    let instance = store.instantiate(&module, imports).unwrap();\n",
+                        "    let instance = linker.instantiate(&mut store, &module).unwrap();\n",
                         "    let mut failures: Vec<String> = Vec::new();\n",
                         "{steps}\n",
                         "    if !failures.is_empty() {{\n",
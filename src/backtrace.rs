@@ -0,0 +1,64 @@
+use std::fmt;
+
+use crate::dwarf::SourceLocation;
+
+/// The guest call stack at the time of a trap, innermost frame first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backtrace {
+    pub frames: Vec<FrameInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Index of the instance in the store
+    pub module_idx: usize,
+    /// Index in the module's function index space, `None` for the synthetic
+    /// functions that run element and data segment initializers
+    pub func_idx: Option<u32>,
+    pub name: Option<String>,
+    /// Index of the faulting op, or of the call for outer frames
+    pub pc: usize,
+    /// Byte offset of the instruction from the start of the module binary
+    pub wasm_offset: Option<u32>,
+    /// Source position from the module's DWARF line table
+    pub location: Option<SourceLocation>,
+}
+
+impl Backtrace {
+    pub const fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "wasm backtrace:")?;
+        for (i, frame) in self.frames.iter().enumerate() {
+            writeln!(f, "  {i:>4}: {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, self.func_idx) {
+            (Some(name), Some(idx)) => {
+                write!(f, "{name} (module {}, func {idx})", self.module_idx)?
+            }
+            (None, Some(idx)) => write!(f, "<func {idx}> (module {})", self.module_idx)?,
+            (_, None) => write!(f, "<init> (module {})", self.module_idx)?,
+        }
+
+        match self.wasm_offset {
+            Some(offset) => write!(f, " at {offset:#x}")?,
+            None => write!(f, " at pc {}", self.pc)?,
+        }
+
+        if let Some(location) = &self.location {
+            write!(f, " ({location})")?;
+        }
+
+        Ok(())
+    }
+}
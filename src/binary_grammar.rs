@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 pub const MAGIC_NUMBER: [u8; 4] = *b"\0asm";
 
 pub mod section_id {
@@ -32,6 +34,9 @@ pub struct ParsedModule {
     pub exports: Vec<Export>,
     pub tags: Vec<Tag>,
     pub customs: Vec<CustomSection>,
+    pub names: NameMap,
+    /// Byte offset of the code section's contents in the module
+    pub code_offset: u32,
 }
 
 impl ParsedModule {
@@ -50,6 +55,8 @@ impl ParsedModule {
             exports: vec![],
             tags: vec![],
             customs: vec![],
+            names: NameMap::new(),
+            code_offset: 0,
         }
     }
 }
@@ -72,7 +79,8 @@ pub enum Section {
     Tag(TagSection),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum HeapType {
     Func,     // 0x70
     Extern,   // 0x6F
@@ -95,14 +103,14 @@ pub enum AddrType {
     I64,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RefType {
     FuncRef,
     ExternRef,
     Ref { nullable: bool, heap_type: HeapType },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ValueType {
     I32,
     I64,
@@ -112,10 +120,10 @@ pub enum ValueType {
     Ref(RefType),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ResultType(pub Vec<ValueType>);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FunctionType(pub ResultType, pub ResultType);
 
 #[derive(Debug, Clone, Copy)]
@@ -174,7 +182,7 @@ pub struct SubType {
     pub composite_type: CompositeType,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Mutability {
     Const,
     Var,
@@ -192,6 +200,55 @@ pub struct CustomSection {
     pub bytes: Vec<u8>,
 }
 
+/// Index → name associations from the `name` custom section
+pub type IndexNames = BTreeMap<u32, String>;
+
+/// Debug names decoded from the `name` custom section
+///
+/// `locals` and `labels` are keyed by function index, then by local or label
+/// index.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameMap {
+    pub module: Option<String>,
+    pub functions: IndexNames,
+    pub locals: BTreeMap<u32, IndexNames>,
+    pub labels: BTreeMap<u32, IndexNames>,
+    pub types: IndexNames,
+    pub tables: IndexNames,
+    pub memories: IndexNames,
+    pub globals: IndexNames,
+    pub elems: IndexNames,
+    pub datas: IndexNames,
+}
+
+impl NameMap {
+    pub const fn new() -> Self {
+        Self {
+            module: None,
+            functions: BTreeMap::new(),
+            locals: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            types: BTreeMap::new(),
+            tables: BTreeMap::new(),
+            memories: BTreeMap::new(),
+            globals: BTreeMap::new(),
+            elems: BTreeMap::new(),
+            datas: BTreeMap::new(),
+        }
+    }
+
+    pub fn function(&self, func_idx: u32) -> Option<&str> {
+        self.functions.get(&func_idx).map(String::as_str)
+    }
+
+    pub fn local(&self, func_idx: u32, local_idx: u32) -> Option<&str> {
+        self.locals
+            .get(&func_idx)
+            .and_then(|locals| locals.get(&local_idx))
+            .map(String::as_str)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TypeSection {
     pub types: Vec<SubType>,
@@ -787,7 +844,7 @@ pub enum Instruction {
     I32x4RelaxedDotI8x16I7x16AddSigned,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExportDescription {
     Func(u32),
     Table(u32),
@@ -840,10 +897,14 @@ pub struct Function {
     pub type_index: u32,
     pub locals: Vec<Local>,
     pub body: Vec<Instruction>,
+    /// Module offsets of every instruction in `body` in pre-order, then of
+    /// the closing `end`
+    pub offsets: Vec<u32>,
 }
 
 #[derive(Debug, Clone)]
 pub struct CodeSection {
+    pub offset: u32,
     pub codes: Vec<Function>,
 }
 
@@ -1,7 +1,8 @@
 use crate::binary_grammar::{
-    BlockType, CompositeType, Function, Instruction, ParsedModule, SubType, ValueType,
+    BlockType, CompositeType, Function, Instruction, NameMap, ParsedModule, SubType, ValueType,
 };
-use crate::ir::{CompiledFunction, JumpTableEntry, Op};
+use crate::dwarf::{LineTable, SourceLocation};
+use crate::ir::{CompiledFunction, JumpTableEntry, OffsetMap, Op};
 
 const UNREACHABLE_DEPTH: i32 = i32::MIN;
 
@@ -51,6 +52,8 @@ pub struct ModuleCode {
     pub(crate) v128_constants: Vec<i128>,
     pub(crate) jump_tables: Vec<Vec<JumpTableEntry>>,
     pub(crate) shuffle_masks: Vec<[u8; 16]>,
+    pub(crate) names: NameMap,
+    pub(crate) line_table: Option<LineTable>,
 }
 
 struct Compiler<'a> {
@@ -65,6 +68,12 @@ struct Compiler<'a> {
     v128_constants: Vec<i128>,
     jump_tables: Vec<Vec<JumpTableEntry>>,
     shuffle_masks: Vec<[u8; 16]>,
+    instruction_offsets: &'a [u32],
+    next_instruction: usize,
+    /// Offset of the instruction being compiled
+    offset: Option<u32>,
+    /// Offset of each `CompilerOp::Op` in `ops`, labels excluded
+    op_offsets: Vec<Option<u32>>,
 }
 
 pub fn compile(module: &ParsedModule) -> ModuleCode {
@@ -92,10 +101,12 @@ pub fn compile(module: &ParsedModule) -> ModuleCode {
         func_signatures.push(resolve_sig(f.type_index, &module.types));
     }
 
+    let num_imported_funcs = func_signatures.len() - module.functions.len();
     let functions = module
         .functions
         .iter()
-        .map(|f| {
+        .enumerate()
+        .map(|(i, f)| {
             let mut compiler = Compiler {
                 types: &module.types,
                 func_signatures: func_signatures.clone(),
@@ -108,8 +119,13 @@ pub fn compile(module: &ParsedModule) -> ModuleCode {
                 v128_constants: std::mem::take(&mut v128_constants),
                 jump_tables: std::mem::take(&mut jump_tables),
                 shuffle_masks: std::mem::take(&mut shuffle_masks),
+                instruction_offsets: &[],
+                next_instruction: 0,
+                offset: None,
+                op_offsets: Vec::new(),
             };
-            let cf = compiler.compile_function(f);
+            let mut cf = compiler.compile_function(f);
+            cf.func_idx = Some((num_imported_funcs + i) as u32);
 
             v128_constants = compiler.v128_constants;
             jump_tables = compiler.jump_tables;
@@ -124,12 +140,30 @@ pub fn compile(module: &ParsedModule) -> ModuleCode {
         v128_constants,
         jump_tables,
         shuffle_masks,
+        names: module.names.clone(),
+        line_table: line_table(module),
     }
 }
 
-pub fn compile_function_into_code(
-    types: &[SubType],
-    func: &Function,
+impl ModuleCode {
+    pub(crate) fn source_location(&self, wasm_offset: u32) -> Option<SourceLocation> {
+        self.line_table.as_ref()?.lookup(wasm_offset)
+    }
+}
+
+#[cfg(feature = "dwarf")]
+fn line_table(module: &ParsedModule) -> Option<LineTable> {
+    LineTable::from_customs(&module.customs, module.code_offset)
+}
+
+#[cfg(not(feature = "dwarf"))]
+const fn line_table(_module: &ParsedModule) -> Option<LineTable> {
+    None
+}
+
+pub fn compile_function_into_code<'a>(
+    types: &'a [SubType],
+    func: &'a Function,
     code: &mut ModuleCode,
 ) -> CompiledFunction {
     let mut compiler = Compiler {
@@ -144,6 +178,10 @@ pub fn compile_function_into_code(
         v128_constants: std::mem::take(&mut code.v128_constants),
         jump_tables: std::mem::take(&mut code.jump_tables),
         shuffle_masks: std::mem::take(&mut code.shuffle_masks),
+        instruction_offsets: &[],
+        next_instruction: 0,
+        offset: None,
+        op_offsets: Vec::new(),
     };
     let cf = compiler.compile_function(func);
     code.v128_constants = compiler.v128_constants;
@@ -174,7 +212,7 @@ impl<'a> Compiler<'a> {
         }
     }
 
-    fn compile_function(&mut self, func: &Function) -> CompiledFunction {
+    fn compile_function(&mut self, func: &'a Function) -> CompiledFunction {
         let st = &self.types[func.type_index as usize];
 
         let (num_args, num_results) = if let CompositeType::Func(ft) = &st.composite_type {
@@ -184,6 +222,8 @@ impl<'a> Compiler<'a> {
         };
 
         self.jump_table_base = self.jump_tables.len();
+        self.instruction_offsets = &func.offsets;
+        self.next_instruction = 0;
 
         let start_label = self.next_label();
         let end_label = self.next_label();
@@ -204,11 +244,13 @@ impl<'a> Compiler<'a> {
 
         self.block_stack.pop().unwrap();
         self.emit_label(end_label);
+        self.offset = self.instruction_offsets.last().copied();
         self.emit(Op::Return);
         self.strip_dead_labels();
         self.fuse_ops();
 
         let assembled = self.assemble();
+        let offsets = OffsetMap::from_ops(&std::mem::take(&mut self.op_offsets));
 
         let extra_locals: usize = func.locals.iter().map(|l| l.count as usize).sum();
         let mut local_types: Vec<ValueType> = match &st.composite_type {
@@ -230,8 +272,10 @@ impl<'a> Compiler<'a> {
             ops: assembled,
             type_index: func.type_index,
             num_args: num_args as u32,
+            func_idx: None,
             local_types,
             max_stack_height: self.max_stack_height as u32,
+            offsets,
         }
     }
 
@@ -247,6 +291,7 @@ impl<'a> Compiler<'a> {
 
     fn emit(&mut self, op: Op) {
         self.ops.push(CompilerOp::Op(op));
+        self.op_offsets.push(self.offset);
     }
 
     fn emit_branch(&mut self, depth: u32, conditional: bool, negate: bool) {
@@ -384,9 +429,13 @@ impl<'a> Compiler<'a> {
 
     fn fuse_ops(&mut self) {
         let mut out = Vec::with_capacity(self.ops.len());
+        let mut offsets = Vec::with_capacity(self.op_offsets.len());
+        let mut op_idx = 0;
         let mut i = 0;
 
         while i < self.ops.len() {
+            let start = i;
+
             match &self.ops[i..] {
                 [CompilerOp::Op(Op::LocalGet { local_idx }), CompilerOp::Op(Op::Return), ..] => {
                     out.push(
@@ -839,9 +888,20 @@ impl<'a> Compiler<'a> {
                 }
                 [] => break,
             }
+
+            // a fused op keeps the offset of the first instruction it replaces
+            let num_ops = self.ops[start..i]
+                .iter()
+                .filter(|cop| matches!(cop, CompilerOp::Op(_)))
+                .count();
+            if num_ops > 0 {
+                offsets.push(self.op_offsets[op_idx]);
+                op_idx += num_ops;
+            }
         }
 
         self.ops = out;
+        self.op_offsets = offsets;
     }
 
     #[cfg(test)]
@@ -852,12 +912,17 @@ impl<'a> Compiler<'a> {
             v128_constants: Vec::new(),
             jump_tables: Vec::new(),
             shuffle_masks: Vec::new(),
+            names: NameMap::new(),
+            line_table: None,
         };
         let cf = compile_function_into_code(types, func, &mut code);
         cf.ops
     }
 
     fn compile_instruction(&mut self, instr: &Instruction) {
+        self.offset = self.instruction_offsets.get(self.next_instruction).copied();
+        self.next_instruction += 1;
+
         match instr {
             Instruction::Block(..) | Instruction::Loop(..) | Instruction::IfElse(..) => {}
             _ => {
@@ -2948,6 +3013,7 @@ mod tests {
             type_index,
             locals: vec![],
             body,
+            offsets: vec![],
         }
     }
 
@@ -3099,4 +3165,50 @@ mod tests {
         ]
         "#);
     }
+
+    #[test]
+    fn offsets_follow_fusion() {
+        let types = vec![i32_func_type()];
+        let func = Function {
+            offsets: vec![10, 12, 14, 15],
+            ..make_func(
+                0,
+                vec![
+                    Instruction::LocalGet(0),
+                    Instruction::LocalGet(1),
+                    Instruction::I32Add,
+                ],
+            )
+        };
+        let mut code = ModuleCode {
+            compiled_funcs: Vec::new(),
+            types: types.clone(),
+            v128_constants: Vec::new(),
+            jump_tables: Vec::new(),
+            shuffle_masks: Vec::new(),
+            names: NameMap::new(),
+            line_table: None,
+        };
+        let cf = compile_function_into_code(&types, &func, &mut code);
+
+        insta::assert_debug_snapshot!(cf.offsets.iter().collect::<Vec<_>>(), @r#"
+        [
+            (
+                0,
+                10,
+            ),
+            (
+                1,
+                14,
+            ),
+            (
+                2,
+                15,
+            ),
+        ]
+        "#);
+        assert_eq!(cf.offsets.pc(12), None);
+        assert_eq!(cf.offsets.pc(14), Some(1));
+        assert_eq!(cf.offsets.wasm_offset(2), Some(15));
+    }
 }
@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::compiler::ModuleCode;
+use crate::dwarf::SourceLocation;
+use crate::ir::{CompiledFunction, JumpTableEntry, Op};
+
+/// How often every op ran, see [`crate::Store::start_coverage`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageCounters {
+    /// Indexed by instance, compiled function and pc, allocated as functions
+    /// first run
+    pub(crate) counts: Vec<Vec<Vec<u64>>>,
+    /// Indexed by instance and compiled function. Entry block counts include
+    /// loops back to the entry, so calls are counted on their own
+    pub(crate) calls: Vec<Vec<u64>>,
+}
+
+impl CoverageCounters {
+    /// Counts a run of the op at `pc` of a function with `num_ops` ops
+    #[inline]
+    pub(crate) fn hit(&mut self, mi: usize, func_idx: usize, pc: usize, num_ops: usize) {
+        if let Some(count) = self
+            .counts
+            .get_mut(mi)
+            .and_then(|funcs| funcs.get_mut(func_idx))
+            .and_then(|ops| ops.get_mut(pc))
+        {
+            *count += 1;
+            return;
+        }
+
+        if self.counts.len() <= mi {
+            self.counts.resize_with(mi + 1, Vec::new);
+        }
+        let funcs = &mut self.counts[mi];
+        if funcs.len() <= func_idx {
+            funcs.resize_with(func_idx + 1, Vec::new);
+        }
+        funcs[func_idx].resize(num_ops, 0);
+        funcs[func_idx][pc] += 1;
+    }
+
+    pub(crate) fn call(&mut self, mi: usize, func_idx: usize) {
+        if self.calls.len() <= mi {
+            self.calls.resize_with(mi + 1, Vec::new);
+        }
+        let funcs = &mut self.calls[mi];
+        if funcs.len() <= func_idx {
+            funcs.resize(func_idx + 1, 0);
+        }
+        funcs[func_idx] += 1;
+    }
+
+    /// Symbolizes the counts of the functions in `modules`, one per instance.
+    /// Segment initializers are left out
+    pub(crate) fn report<'a>(&self, modules: impl Iterator<Item = &'a ModuleCode>) -> Coverage {
+        let mut functions = vec![];
+        for (module_idx, code) in modules.enumerate() {
+            let counts = self.counts.get(module_idx);
+            for (compiled_idx, cf) in code.compiled_funcs.iter().enumerate() {
+                let Some(func_idx) = cf.func_idx else {
+                    continue;
+                };
+                let counts = counts.and_then(|funcs| funcs.get(compiled_idx));
+                let hits = |pc: usize| counts.and_then(|ops| ops.get(pc)).copied().unwrap_or(0);
+
+                let starts = block_starts(cf, &code.jump_tables);
+                let blocks = starts
+                    .iter()
+                    .zip(starts.iter().skip(1).chain([&cf.ops.len()]))
+                    .map(|(&start, &end)| {
+                        let mut wasm_offsets: Vec<u32> = vec![];
+                        let mut locations: Vec<SourceLocation> = vec![];
+                        for pc in start..end {
+                            let Some(offset) = cf.offsets.wasm_offset(pc) else {
+                                continue;
+                            };
+                            if wasm_offsets.last() != Some(&offset) {
+                                wasm_offsets.push(offset);
+                            }
+                            if let Some(location) = code.source_location(offset) {
+                                if !locations.contains(&location) {
+                                    locations.push(location);
+                                }
+                            }
+                        }
+
+                        BlockCoverage {
+                            pc: start,
+                            hits: hits(start),
+                            wasm_offsets,
+                            locations,
+                        }
+                    })
+                    .collect();
+
+                functions.push(FunctionCoverage {
+                    module_idx,
+                    func_idx,
+                    name: code.names.function(func_idx).map(str::to_owned),
+                    calls: self
+                        .calls
+                        .get(module_idx)
+                        .and_then(|funcs| funcs.get(compiled_idx))
+                        .copied()
+                        .unwrap_or(0),
+                    blocks,
+                });
+            }
+        }
+
+        Coverage { functions }
+    }
+}
+
+/// The first pc of every basic block of `cf`: its entry, every jump target,
+/// and every op after a branch
+fn block_starts(cf: &CompiledFunction, jump_tables: &[Vec<JumpTableEntry>]) -> Vec<usize> {
+    let mut starts = vec![false; cf.ops.len()];
+    if let Some(entry) = starts.first_mut() {
+        *entry = true;
+    }
+
+    for (pc, op) in cf.ops.iter().enumerate() {
+        let targets = match *op {
+            Op::JumpTable { index, .. } => jump_tables[index as usize]
+                .iter()
+                .map(|entry| entry.target)
+                .collect(),
+            _ => op.jump_target().into_iter().collect::<Vec<_>>(),
+        };
+        for target in &targets {
+            starts[*target as usize] = true;
+        }
+
+        let branches = !targets.is_empty()
+            || matches!(
+                op,
+                Op::Return
+                    | Op::LocalGetReturn { .. }
+                    | Op::ReturnCall { .. }
+                    | Op::ReturnCallIndirect { .. }
+                    | Op::ReturnCallRef { .. }
+                    | Op::Unreachable
+                    | Op::Throw { .. }
+                    | Op::ThrowRef
+            );
+        if branches && pc + 1 < starts.len() {
+            starts[pc + 1] = true;
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .filter_map(|(pc, &start)| start.then_some(pc))
+        .collect()
+}
+
+/// Which blocks of guest code ran and how often
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub functions: Vec<FunctionCoverage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    /// Index of the instance in the store
+    pub module_idx: usize,
+    pub func_idx: u32,
+    pub name: Option<String>,
+    pub calls: u64,
+    /// In pc order, starting with the function's entry
+    pub blocks: Vec<BlockCoverage>,
+}
+
+/// A straight run of ops, entered only at its first op
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockCoverage {
+    /// Index of the block's first op
+    pub pc: usize,
+    pub hits: u64,
+    /// Byte offsets in the module of the instructions in the block
+    pub wasm_offsets: Vec<u32>,
+    /// Distinct source positions of those instructions, from DWARF
+    pub locations: Vec<SourceLocation>,
+}
+
+impl FunctionCoverage {
+    fn display_name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("wasm-function[{}]", self.func_idx))
+    }
+}
+
+/// The functions and lines of one source file in an LCOV report
+#[derive(Default)]
+struct LcovFile {
+    /// (first line, name, hits)
+    functions: Vec<(u32, String, u64)>,
+    lines: BTreeMap<u32, u64>,
+}
+
+impl Coverage {
+    /// The counts added since `base`, an earlier report of the same store
+    ///
+    /// Stores restored from a snapshot keep the counts it was taken with, so
+    /// forks of one snapshot should be reduced to what they ran themselves
+    /// before they're merged, or the runs before the snapshot count once per
+    /// fork.
+    pub fn since(&self, base: &Self) -> Self {
+        let mut coverage = self.clone();
+        for function in &mut coverage.functions {
+            let Some(before) = base.functions.iter().find(|before| {
+                (before.module_idx, before.func_idx) == (function.module_idx, function.func_idx)
+            }) else {
+                continue;
+            };
+
+            function.calls = function.calls.saturating_sub(before.calls);
+            for block in &mut function.blocks {
+                if let Some(before) = before.blocks.iter().find(|before| before.pc == block.pc) {
+                    block.hits = block.hits.saturating_sub(before.hits);
+                }
+            }
+        }
+        coverage
+    }
+
+    /// Adds the counts of `other`, a report for the same modules, see
+    /// [`Self::since`] for merging forks
+    pub fn merge(&mut self, other: &Self) {
+        for function in &other.functions {
+            let Some(ours) = self.functions.iter_mut().find(|ours| {
+                (ours.module_idx, ours.func_idx) == (function.module_idx, function.func_idx)
+            }) else {
+                self.functions.push(function.clone());
+                continue;
+            };
+
+            ours.calls += function.calls;
+            for block in &function.blocks {
+                match ours.blocks.iter_mut().find(|ours| ours.pc == block.pc) {
+                    Some(ours) => ours.hits += block.hits,
+                    None => ours.blocks.push(block.clone()),
+                }
+            }
+        }
+    }
+
+    /// Writes the report in LCOV's tracefile format
+    ///
+    /// Lines come from DWARF. Functions without it are reported under
+    /// `module<idx>.wasm`, with instruction offsets in place of lines.
+    pub fn write_lcov(&self, mut out: impl Write) -> io::Result<()> {
+        let mut files: BTreeMap<String, LcovFile> = BTreeMap::new();
+        for function in &self.functions {
+            let has_lines = function
+                .blocks
+                .iter()
+                .any(|block| !block.locations.is_empty());
+
+            let mut first_line = None;
+            for block in &function.blocks {
+                let lines: Vec<(String, u32)> = if has_lines {
+                    block
+                        .locations
+                        .iter()
+                        .map(|location| (location.file.clone(), location.line))
+                        .collect()
+                } else {
+                    let file = format!("module{}.wasm", function.module_idx);
+                    block
+                        .wasm_offsets
+                        .iter()
+                        .map(|&offset| (file.clone(), offset))
+                        .collect()
+                };
+
+                for (file, line) in lines {
+                    first_line.get_or_insert_with(|| (file.clone(), line));
+                    let hits = files
+                        .entry(file)
+                        .or_default()
+                        .lines
+                        .entry(line)
+                        .or_default();
+                    *hits = (*hits).max(block.hits);
+                }
+            }
+
+            if let Some((file, line)) = first_line {
+                files.entry(file).or_default().functions.push((
+                    line,
+                    function.display_name(),
+                    function.calls,
+                ));
+            }
+        }
+
+        for (path, file) in &files {
+            writeln!(out, "TN:")?;
+            writeln!(out, "SF:{path}")?;
+            for (line, name, _) in &file.functions {
+                writeln!(out, "FN:{line},{name}")?;
+            }
+            for (_, name, hits) in &file.functions {
+                writeln!(out, "FNDA:{hits},{name}")?;
+            }
+            writeln!(out, "FNF:{}", file.functions.len())?;
+            let functions_hit = file.functions.iter().filter(|f| f.2 > 0).count();
+            writeln!(out, "FNH:{functions_hit}")?;
+            for (line, hits) in &file.lines {
+                writeln!(out, "DA:{line},{hits}")?;
+            }
+            writeln!(out, "LF:{}", file.lines.len())?;
+            let lines_hit = file.lines.values().filter(|&&hits| hits > 0).count();
+            writeln!(out, "LH:{lines_hit}")?;
+            writeln!(out, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
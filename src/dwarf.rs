@@ -0,0 +1,746 @@
+use std::fmt;
+
+#[cfg(feature = "dwarf")]
+use std::collections::BTreeMap;
+
+#[cfg(feature = "dwarf")]
+use crate::binary_grammar::CustomSection;
+
+/// A position in the source a module was compiled from
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            0 => write!(f, "{}:{}", self.file, self.line),
+            column => write!(f, "{}:{}:{}", self.file, self.line, column),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRow {
+    /// Offset from the start of the code section's contents
+    pub(crate) address: u32,
+    pub(crate) file: u32,
+    pub(crate) line: u32,
+    pub(crate) column: u32,
+    pub(crate) end_sequence: bool,
+}
+
+/// The rows of every `.debug_line` program in a module, sorted by address
+///
+/// DWARF for wasm addresses code relative to the code section's contents, so
+/// the table keeps that section's offset to answer lookups by module offset.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LineTable {
+    pub(crate) code_offset: u32,
+    pub(crate) files: Vec<String>,
+    pub(crate) rows: Vec<LineRow>,
+}
+
+impl LineTable {
+    /// The source location of the instruction at `wasm_offset`, a byte offset
+    /// into the module
+    pub fn lookup(&self, wasm_offset: u32) -> Option<SourceLocation> {
+        let address = wasm_offset.checked_sub(self.code_offset)?;
+
+        let idx = self
+            .rows
+            .partition_point(|row| row.address <= address)
+            .checked_sub(1)?;
+        let row = &self.rows[idx];
+
+        // an end_sequence row marks the first address past its sequence
+        if row.end_sequence || row.line == 0 {
+            return None;
+        }
+
+        Some(SourceLocation {
+            file: self.files.get(row.file as usize)?.clone(),
+            line: row.line,
+            column: row.column,
+        })
+    }
+
+    /// Builds the line table from a module's DWARF custom sections. Missing
+    /// or malformed debug info yields `None`, it never fails the module
+    #[cfg(feature = "dwarf")]
+    pub(crate) fn from_customs(customs: &[CustomSection], code_offset: u32) -> Option<Self> {
+        let section = |name: &str| {
+            customs
+                .iter()
+                .find(|custom| custom.name == name)
+                .map(|custom| custom.bytes.as_slice())
+        };
+
+        let debug_line = section(".debug_line")?;
+        let strings = StringSections {
+            line_str: section(".debug_line_str").unwrap_or_default(),
+            str: section(".debug_str").unwrap_or_default(),
+            str_offsets: section(".debug_str_offsets").unwrap_or_default(),
+        };
+
+        // compilation directories, by the offset of their unit's line program
+        let mut comp_dirs = BTreeMap::new();
+        if let (Some(debug_info), Some(debug_abbrev)) =
+            (section(".debug_info"), section(".debug_abbrev"))
+        {
+            let mut reader = Reader::new(debug_info);
+            while !reader.is_empty() {
+                if reader
+                    .compile_unit(debug_abbrev, &strings, &mut comp_dirs)
+                    .is_none()
+                {
+                    break;
+                }
+            }
+        }
+
+        let mut table = Self {
+            code_offset,
+            ..Self::default()
+        };
+
+        let mut reader = Reader::new(debug_line);
+        while !reader.is_empty() {
+            let comp_dir = comp_dirs
+                .get(&(reader.cursor as u64))
+                .map_or("", String::as_str);
+            if reader
+                .line_program(&strings, comp_dir, &mut table)
+                .is_none()
+            {
+                break;
+            }
+        }
+
+        if table.rows.is_empty() {
+            return None;
+        }
+
+        table.rows.sort_by_key(|row| row.address);
+        Some(table)
+    }
+}
+
+#[cfg(feature = "dwarf")]
+struct StringSections<'a> {
+    line_str: &'a [u8],
+    str: &'a [u8],
+    str_offsets: &'a [u8],
+}
+
+#[cfg(feature = "dwarf")]
+struct Reader<'a> {
+    buf: &'a [u8],
+    cursor: usize,
+}
+
+#[cfg(feature = "dwarf")]
+mod constants {
+    pub const DW_LNS_COPY: u8 = 1;
+    pub const DW_LNS_ADVANCE_PC: u8 = 2;
+    pub const DW_LNS_ADVANCE_LINE: u8 = 3;
+    pub const DW_LNS_SET_FILE: u8 = 4;
+    pub const DW_LNS_SET_COLUMN: u8 = 5;
+    pub const DW_LNS_CONST_ADD_PC: u8 = 8;
+    pub const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+    pub const DW_LNE_END_SEQUENCE: u8 = 1;
+    pub const DW_LNE_SET_ADDRESS: u8 = 2;
+    pub const DW_LNE_DEFINE_FILE: u8 = 3;
+
+    pub const DW_LNCT_PATH: u64 = 1;
+    pub const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+    pub const DW_UT_COMPILE: u8 = 0x01;
+    pub const DW_UT_PARTIAL: u8 = 0x03;
+    pub const DW_UT_SKELETON: u8 = 0x04;
+    pub const DW_UT_SPLIT_COMPILE: u8 = 0x05;
+
+    pub const DW_AT_STMT_LIST: u64 = 0x10;
+    pub const DW_AT_COMP_DIR: u64 = 0x1b;
+    pub const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+
+    pub const DW_FORM_ADDR: u64 = 0x01;
+    pub const DW_FORM_BLOCK2: u64 = 0x03;
+    pub const DW_FORM_BLOCK4: u64 = 0x04;
+    pub const DW_FORM_DATA2: u64 = 0x05;
+    pub const DW_FORM_DATA4: u64 = 0x06;
+    pub const DW_FORM_DATA8: u64 = 0x07;
+    pub const DW_FORM_STRING: u64 = 0x08;
+    pub const DW_FORM_BLOCK: u64 = 0x09;
+    pub const DW_FORM_BLOCK1: u64 = 0x0a;
+    pub const DW_FORM_DATA1: u64 = 0x0b;
+    pub const DW_FORM_FLAG: u64 = 0x0c;
+    pub const DW_FORM_SDATA: u64 = 0x0d;
+    pub const DW_FORM_STRP: u64 = 0x0e;
+    pub const DW_FORM_UDATA: u64 = 0x0f;
+    pub const DW_FORM_REF_ADDR: u64 = 0x10;
+    pub const DW_FORM_REF1: u64 = 0x11;
+    pub const DW_FORM_REF2: u64 = 0x12;
+    pub const DW_FORM_REF4: u64 = 0x13;
+    pub const DW_FORM_REF8: u64 = 0x14;
+    pub const DW_FORM_REF_UDATA: u64 = 0x15;
+    pub const DW_FORM_SEC_OFFSET: u64 = 0x17;
+    pub const DW_FORM_EXPRLOC: u64 = 0x18;
+    pub const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+    pub const DW_FORM_STRX: u64 = 0x1a;
+    pub const DW_FORM_ADDRX: u64 = 0x1b;
+    pub const DW_FORM_REF_SUP4: u64 = 0x1c;
+    pub const DW_FORM_STRP_SUP: u64 = 0x1d;
+    pub const DW_FORM_DATA16: u64 = 0x1e;
+    pub const DW_FORM_LINE_STRP: u64 = 0x1f;
+    pub const DW_FORM_REF_SIG8: u64 = 0x20;
+    pub const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+    pub const DW_FORM_LOCLISTX: u64 = 0x22;
+    pub const DW_FORM_RNGLISTX: u64 = 0x23;
+    pub const DW_FORM_REF_SUP8: u64 = 0x24;
+    pub const DW_FORM_STRX1: u64 = 0x25;
+    pub const DW_FORM_STRX4: u64 = 0x28;
+    pub const DW_FORM_ADDRX1: u64 = 0x29;
+    pub const DW_FORM_ADDRX4: u64 = 0x2c;
+}
+
+#[cfg(feature = "dwarf")]
+use constants::*;
+
+/// The line number state machine registers this reader tracks
+#[cfg(feature = "dwarf")]
+struct Registers {
+    address: u64,
+    file: u64,
+    line: i64,
+    column: u64,
+}
+
+#[cfg(feature = "dwarf")]
+impl Registers {
+    const fn new() -> Self {
+        Self {
+            address: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+        }
+    }
+}
+
+/// An attribute of a DIE or of a DWARF 5 line program header entry
+#[cfg(feature = "dwarf")]
+enum EntryValue {
+    Str(String),
+    /// An index into the unit's `.debug_str_offsets` entries
+    StrIndex(u64),
+    Num(u64),
+    Skipped,
+}
+
+#[cfg(feature = "dwarf")]
+impl<'a> Reader<'a> {
+    const fn new(buf: &'a [u8]) -> Self {
+        Self { buf, cursor: 0 }
+    }
+
+    const fn is_empty(&self) -> bool {
+        self.cursor >= self.buf.len()
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.cursor..self.cursor.checked_add(n)?)?;
+        self.cursor += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    /// A unit's length, and whether it's in the 64-bit DWARF format
+    fn unit_length(&mut self) -> Option<(u64, bool)> {
+        match self.u32()? {
+            0xffff_ffff => Some((self.u64()?, true)),
+            length => Some((u64::from(length), false)),
+        }
+    }
+
+    /// A section offset, 4 or 8 bytes wide depending on the DWARF format
+    fn offset(&mut self, dwarf64: bool) -> Option<u64> {
+        match dwarf64 {
+            true => self.u64(),
+            false => self.u32().map(u64::from),
+        }
+    }
+
+    fn uleb(&mut self) -> Option<u64> {
+        let (value, seen) = crate::leb128::read_u64(self.buf.get(self.cursor..)?).ok()?;
+        self.cursor += seen;
+        Some(value)
+    }
+
+    fn sleb(&mut self) -> Option<i64> {
+        let (value, seen) = crate::leb128::read_i64(self.buf.get(self.cursor..)?).ok()?;
+        self.cursor += seen;
+        Some(value)
+    }
+
+    fn cstr(&mut self) -> Option<String> {
+        let rest = self.buf.get(self.cursor..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        self.cursor += len + 1;
+        Some(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+
+    /// Reads a `.debug_info` unit's root DIE, recording its compilation
+    /// directory under the offset of its line program
+    fn compile_unit(
+        &mut self,
+        debug_abbrev: &[u8],
+        strings: &StringSections,
+        comp_dirs: &mut BTreeMap<u64, String>,
+    ) -> Option<()> {
+        let (unit_length, dwarf64) = self.unit_length()?;
+        let unit_end = self
+            .cursor
+            .checked_add(usize::try_from(unit_length).ok()?)?;
+
+        let (abbrev_offset, address_size) = match self.u16()? {
+            2..=4 => (self.offset(dwarf64)?, self.u8()?),
+            5 => {
+                let unit_type = self.u8()?;
+                let address_size = self.u8()?;
+                let abbrev_offset = self.offset(dwarf64)?;
+                match unit_type {
+                    DW_UT_COMPILE | DW_UT_PARTIAL => {}
+                    DW_UT_SKELETON | DW_UT_SPLIT_COMPILE => {
+                        let _dwo_id = self.u64()?;
+                    }
+                    _ => {
+                        self.cursor = unit_end;
+                        return Some(());
+                    }
+                }
+                (abbrev_offset, address_size)
+            }
+            _ => {
+                self.cursor = unit_end;
+                return Some(());
+            }
+        };
+
+        let code = self.uleb()?;
+        let mut stmt_list = None;
+        let mut comp_dir = None;
+        let mut str_offsets_base = None;
+        for (name, form) in abbreviation(debug_abbrev, abbrev_offset, code)? {
+            match (
+                name,
+                self.entry_value(form, strings, dwarf64, address_size)?,
+            ) {
+                (DW_AT_STMT_LIST, EntryValue::Num(offset)) => stmt_list = Some(offset),
+                (DW_AT_COMP_DIR, value) => comp_dir = Some(value),
+                (DW_AT_STR_OFFSETS_BASE, EntryValue::Num(base)) => str_offsets_base = Some(base),
+                _ => {}
+            }
+        }
+
+        let comp_dir = match comp_dir {
+            Some(EntryValue::Str(dir)) => Some(dir),
+            // without a base, the offsets start past the section's header
+            Some(EntryValue::StrIndex(index)) => {
+                let base = str_offsets_base.unwrap_or(if dwarf64 { 16 } else { 8 });
+                str_index(strings, base, index, dwarf64)
+            }
+            _ => None,
+        };
+        if let (Some(offset), Some(dir)) = (stmt_list, comp_dir) {
+            comp_dirs.insert(offset, dir);
+        }
+
+        self.cursor = unit_end;
+        Some(())
+    }
+
+    /// Runs one line number program, appending its rows and files to `table`
+    ///
+    /// `comp_dir` is the directory of the unit that owns the program, which
+    /// DWARF 2-4 headers leave out.
+    fn line_program(
+        &mut self,
+        strings: &StringSections,
+        comp_dir: &str,
+        table: &mut LineTable,
+    ) -> Option<()> {
+        let (unit_length, dwarf64) = self.unit_length()?;
+        let unit_end = self
+            .cursor
+            .checked_add(usize::try_from(unit_length).ok()?)?;
+
+        let version = self.u16()?;
+        if !(2..=5).contains(&version) {
+            self.cursor = unit_end;
+            return Some(());
+        }
+
+        let mut address_size = 4;
+        if version >= 5 {
+            address_size = self.u8()?;
+            let _segment_selector_size = self.u8()?;
+        }
+
+        let header_length = self.offset(dwarf64)?;
+        let program_start = self
+            .cursor
+            .checked_add(usize::try_from(header_length).ok()?)?;
+
+        let min_inst_length = self.u8()?;
+        if version >= 4 {
+            let _max_ops_per_inst = self.u8()?;
+        }
+        let _default_is_stmt = self.u8()?;
+        let line_base = self.u8()? as i8;
+        let line_range = self.u8()?;
+        let opcode_base = self.u8()?;
+        let standard_opcode_lengths = self.bytes(usize::from(opcode_base.checked_sub(1)?))?;
+
+        if line_range == 0 {
+            return None;
+        }
+
+        // file indices in the program are relative to this unit
+        let file_base = table.files.len() as u32;
+        let files = if version >= 5 {
+            self.v5_files(strings, dwarf64, address_size)?
+        } else {
+            self.legacy_files(comp_dir)?
+        };
+        table.files.extend(files);
+
+        // DWARF 5 numbers files from 0, earlier versions from 1
+        let first_file = if version >= 5 { 0 } else { 1 };
+        let file_index =
+            |file: u64| file_base.saturating_add(file.saturating_sub(first_file) as u32);
+
+        self.cursor = program_start;
+
+        let mut regs = Registers::new();
+        let row = |regs: &Registers, end_sequence: bool| LineRow {
+            address: regs.address as u32,
+            file: file_index(regs.file),
+            line: regs.line.max(0) as u32,
+            column: regs.column as u32,
+            end_sequence,
+        };
+
+        while self.cursor < unit_end {
+            let opcode = self.u8()?;
+
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                regs.address = regs
+                    .address
+                    .checked_add(u64::from(adjusted / line_range) * u64::from(min_inst_length))?;
+                regs.line = regs
+                    .line
+                    .checked_add(i64::from(line_base) + i64::from(adjusted % line_range))?;
+                table.rows.push(row(&regs, false));
+                continue;
+            }
+
+            match opcode {
+                0 => {
+                    let len = usize::try_from(self.uleb()?).ok()?;
+                    let end = self.cursor.checked_add(len)?;
+                    match self.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            table.rows.push(row(&regs, true));
+                            regs = Registers::new();
+                        }
+                        DW_LNE_SET_ADDRESS => {
+                            regs.address = match len.checked_sub(1)? {
+                                4 => u64::from(self.u32()?),
+                                8 => self.u64()?,
+                                _ => return None,
+                            };
+                        }
+                        DW_LNE_DEFINE_FILE => {
+                            let name = self.cstr()?;
+                            table.files.push(name);
+                        }
+                        _ => {}
+                    }
+                    self.cursor = end;
+                }
+                DW_LNS_COPY => table.rows.push(row(&regs, false)),
+                DW_LNS_ADVANCE_PC => {
+                    let advance = self.uleb()?.checked_mul(u64::from(min_inst_length))?;
+                    regs.address = regs.address.checked_add(advance)?;
+                }
+                DW_LNS_ADVANCE_LINE => regs.line = regs.line.checked_add(self.sleb()?)?,
+                DW_LNS_SET_FILE => regs.file = self.uleb()?,
+                DW_LNS_SET_COLUMN => regs.column = self.uleb()?,
+                DW_LNS_CONST_ADD_PC => {
+                    let adjusted = 255 - opcode_base;
+                    regs.address = regs.address.checked_add(
+                        u64::from(adjusted / line_range) * u64::from(min_inst_length),
+                    )?;
+                }
+                DW_LNS_FIXED_ADVANCE_PC => {
+                    regs.address = regs.address.checked_add(u64::from(self.u16()?))?;
+                }
+                _ => {
+                    // standard opcodes without operands we track, or ones
+                    // newer than this reader
+                    for _ in 0..standard_opcode_lengths[usize::from(opcode) - 1] {
+                        self.uleb()?;
+                    }
+                }
+            }
+        }
+
+        self.cursor = unit_end;
+        Some(())
+    }
+
+    /// `include_directories` and `file_names` from a DWARF 2-4 header, with
+    /// relative paths resolved against `comp_dir`
+    fn legacy_files(&mut self, comp_dir: &str) -> Option<Vec<String>> {
+        let mut dirs = vec![comp_dir.to_owned()];
+        loop {
+            let dir = self.cstr()?;
+            if dir.is_empty() {
+                break;
+            }
+            dirs.push(join_path(comp_dir, &dir));
+        }
+
+        let mut files = Vec::new();
+        loop {
+            let name = self.cstr()?;
+            if name.is_empty() {
+                break;
+            }
+            let dir = self.uleb()?;
+            let _mtime = self.uleb()?;
+            let _length = self.uleb()?;
+
+            // directory 0 is the compilation directory
+            files.push(match dirs.get(dir as usize) {
+                Some(dir) => join_path(dir, &name),
+                None => name,
+            });
+        }
+
+        Some(files)
+    }
+
+    /// The self-describing directory and file tables of a DWARF 5 header
+    fn v5_files(
+        &mut self,
+        strings: &StringSections,
+        dwarf64: bool,
+        address_size: u8,
+    ) -> Option<Vec<String>> {
+        let dirs = self
+            .v5_entries(strings, dwarf64, address_size)?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+
+        let files = self
+            .v5_entries(strings, dwarf64, address_size)?
+            .into_iter()
+            .map(|(name, dir)| match dirs.get(dir as usize) {
+                Some(dir) => join_path(dir, &name),
+                None => name,
+            })
+            .collect();
+
+        Some(files)
+    }
+
+    fn v5_entries(
+        &mut self,
+        strings: &StringSections,
+        dwarf64: bool,
+        address_size: u8,
+    ) -> Option<Vec<(String, u64)>> {
+        let format_count = self.u8()?;
+        let formats = (0..format_count)
+            .map(|_| Some((self.uleb()?, self.uleb()?)))
+            .collect::<Option<Vec<_>>>()?;
+
+        // every entry takes at least a byte, unless it has no attributes
+        let count = self.uleb()?;
+        let max_count = match formats.is_empty() {
+            true => 0,
+            false => self.buf.len() - self.cursor,
+        };
+        if count > max_count as u64 {
+            return None;
+        }
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let mut path = String::new();
+            let mut dir = 0;
+
+            for &(content_type, form) in &formats {
+                match (
+                    content_type,
+                    self.entry_value(form, strings, dwarf64, address_size)?,
+                ) {
+                    (DW_LNCT_PATH, EntryValue::Str(s)) => path = s,
+                    (DW_LNCT_DIRECTORY_INDEX, EntryValue::Num(n)) => dir = n,
+                    _ => {}
+                }
+            }
+
+            entries.push((path, dir));
+        }
+
+        Some(entries)
+    }
+
+    fn entry_value(
+        &mut self,
+        form: u64,
+        strings: &StringSections,
+        dwarf64: bool,
+        address_size: u8,
+    ) -> Option<EntryValue> {
+        let value = match form {
+            DW_FORM_STRING => EntryValue::Str(self.cstr()?),
+            DW_FORM_LINE_STRP => EntryValue::Str(str_at(strings.line_str, self.offset(dwarf64)?)?),
+            DW_FORM_STRP => EntryValue::Str(str_at(strings.str, self.offset(dwarf64)?)?),
+            DW_FORM_STRX => EntryValue::StrIndex(self.uleb()?),
+            DW_FORM_STRX1..=DW_FORM_STRX4 => {
+                let mut index = [0; 8];
+                let width = usize::try_from(form - DW_FORM_STRX1 + 1).ok()?;
+                index[..width].copy_from_slice(self.bytes(width)?);
+                EntryValue::StrIndex(u64::from_le_bytes(index))
+            }
+            DW_FORM_UDATA => EntryValue::Num(self.uleb()?),
+            DW_FORM_DATA1 => EntryValue::Num(u64::from(self.u8()?)),
+            DW_FORM_DATA2 => EntryValue::Num(u64::from(self.u16()?)),
+            DW_FORM_DATA4 => EntryValue::Num(u64::from(self.u32()?)),
+            DW_FORM_DATA8 => EntryValue::Num(self.u64()?),
+            DW_FORM_SEC_OFFSET => EntryValue::Num(self.offset(dwarf64)?),
+            _ => {
+                self.skip_form(form, dwarf64, address_size)?;
+                EntryValue::Skipped
+            }
+        };
+
+        Some(value)
+    }
+
+    /// Skips over a value this reader has no use for
+    fn skip_form(&mut self, form: u64, dwarf64: bool, address_size: u8) -> Option<()> {
+        let len = match form {
+            DW_FORM_FLAG_PRESENT | DW_FORM_IMPLICIT_CONST => 0,
+            DW_FORM_FLAG | DW_FORM_REF1 => 1,
+            DW_FORM_REF2 => 2,
+            DW_FORM_REF4 | DW_FORM_REF_SUP4 => 4,
+            DW_FORM_REF8 | DW_FORM_REF_SUP8 | DW_FORM_REF_SIG8 => 8,
+            DW_FORM_DATA16 => 16,
+            DW_FORM_ADDRX1..=DW_FORM_ADDRX4 => usize::try_from(form - DW_FORM_ADDRX1 + 1).ok()?,
+            DW_FORM_ADDR => usize::from(address_size),
+            DW_FORM_REF_ADDR | DW_FORM_STRP_SUP => match dwarf64 {
+                true => 8,
+                false => 4,
+            },
+            DW_FORM_SDATA => {
+                self.sleb()?;
+                0
+            }
+            DW_FORM_REF_UDATA | DW_FORM_ADDRX | DW_FORM_LOCLISTX | DW_FORM_RNGLISTX => {
+                self.uleb()?;
+                0
+            }
+            DW_FORM_BLOCK1 => usize::from(self.u8()?),
+            DW_FORM_BLOCK2 => usize::from(self.u16()?),
+            DW_FORM_BLOCK4 => usize::try_from(self.u32()?).ok()?,
+            DW_FORM_BLOCK | DW_FORM_EXPRLOC => usize::try_from(self.uleb()?).ok()?,
+            _ => return None,
+        };
+
+        self.bytes(len)?;
+        Some(())
+    }
+}
+
+/// The `(name, form)` attribute pairs of abbreviation `code`, in the table at
+/// `offset` of `.debug_abbrev`
+#[cfg(feature = "dwarf")]
+fn abbreviation(debug_abbrev: &[u8], offset: u64, code: u64) -> Option<Vec<(u64, u64)>> {
+    let mut reader = Reader::new(debug_abbrev);
+    reader.cursor = usize::try_from(offset).ok()?;
+
+    loop {
+        let entry = reader.uleb()?;
+        if entry == 0 {
+            return None;
+        }
+        let _tag = reader.uleb()?;
+        let _has_children = reader.u8()?;
+
+        let mut attributes = Vec::new();
+        loop {
+            let (name, form) = (reader.uleb()?, reader.uleb()?);
+            if (name, form) == (0, 0) {
+                break;
+            }
+            if form == DW_FORM_IMPLICIT_CONST {
+                reader.sleb()?;
+            }
+            attributes.push((name, form));
+        }
+
+        if entry == code {
+            return Some(attributes);
+        }
+    }
+}
+
+/// String `index` of a unit whose `.debug_str_offsets` entries start at `base`
+#[cfg(feature = "dwarf")]
+fn str_index(strings: &StringSections, base: u64, index: u64, dwarf64: bool) -> Option<String> {
+    let width = if dwarf64 { 8 } else { 4 };
+    let mut reader = Reader::new(strings.str_offsets);
+    reader.cursor = usize::try_from(index.checked_mul(width)?.checked_add(base)?).ok()?;
+    str_at(strings.str, reader.offset(dwarf64)?)
+}
+
+#[cfg(feature = "dwarf")]
+fn str_at(section: &[u8], offset: u64) -> Option<String> {
+    let mut reader = Reader::new(section);
+    reader.cursor = usize::try_from(offset).ok()?;
+    reader.cstr()
+}
+
+#[cfg(feature = "dwarf")]
+fn join_path(dir: &str, name: &str) -> String {
+    if name.starts_with('/') || dir.is_empty() {
+        return name.to_owned();
+    }
+
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
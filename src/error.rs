@@ -1,4 +1,10 @@
-use std::{array::TryFromSliceError, fmt, str::Utf8Error};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::backtrace::Backtrace;
+use crate::binary_grammar::{ExportDescription, FunctionType, GlobalType, MemoryType, TableType};
+use crate::execution_grammar::RawValue;
+use crate::store::{Execution, ExecutionState};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -17,6 +23,53 @@ pub enum Trap {
     CastFailure,
     OutOfBoundsArrayAccess,
     CallStackExhausted,
+    ResourceLimitExceeded,
+    /// The guest asked to exit, e.g. through WASI's `proc_exit`
+    Exit(u32),
+    /// The embedder aborted the guest, see [`crate::Store::resume_with_trap`]
+    Host(HostError),
+}
+
+impl Trap {
+    pub fn host(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self::Host(HostError(Arc::from(err.into())))
+    }
+
+    pub const fn exit_code(&self) -> Option<u32> {
+        match self {
+            Self::Exit(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The embedder's error, if it's a `T`
+    pub fn downcast_ref<T: std::error::Error + 'static>(&self) -> Option<&T> {
+        match self {
+            Self::Host(err) => err.0.downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+}
+
+/// An embedder's error carried by [`Trap::Host`]
+///
+/// Host errors are equal only if they're the same error. Restored from a
+/// snapshot, only the message is left.
+#[derive(Debug, Clone)]
+pub struct HostError(pub Arc<dyn std::error::Error + Send + Sync>);
+
+impl PartialEq for HostError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for HostError {}
+
+impl fmt::Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
 }
 
 impl fmt::Display for Trap {
@@ -35,65 +88,379 @@ impl fmt::Display for Trap {
             Self::CastFailure => write!(f, "cast failure"),
             Self::OutOfBoundsArrayAccess => write!(f, "out of bounds array access"),
             Self::CallStackExhausted => write!(f, "call stack exhausted"),
+            Self::ResourceLimitExceeded => write!(f, "resource limit exceeded"),
+            Self::Exit(code) => write!(f, "exit with code {code}"),
+            Self::Host(err) => write!(f, "host error: {err}"),
+        }
+    }
+}
+
+/// Why a module failed to decode, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// Byte offset into the module binary
+    pub offset: usize,
+    /// Id of the section being decoded, if past the preamble
+    pub section: Option<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedEof,
+    BadMagic,
+    UnsupportedVersion,
+    InvalidLeb128,
+    MalformedUtf8,
+    UnknownSection(u8),
+    /// An opcode, with the prefix byte for multi-byte opcodes
+    InvalidOpcode {
+        prefix: Option<u8>,
+        opcode: u32,
+    },
+    Malformed(String),
+}
+
+impl ParseError {
+    pub const fn new(offset: usize, kind: ParseErrorKind) -> Self {
+        Self {
+            kind,
+            offset,
+            section: None,
+        }
+    }
+
+    pub(crate) fn malformed(offset: usize, msg: impl Into<String>) -> Self {
+        Self::new(offset, ParseErrorKind::Malformed(msg.into()))
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of input"),
+            Self::BadMagic => write!(f, "bad magic number"),
+            Self::UnsupportedVersion => write!(f, "unsupported version"),
+            Self::InvalidLeb128 => write!(f, "invalid LEB128 integer"),
+            Self::MalformedUtf8 => write!(f, "malformed UTF-8"),
+            Self::UnknownSection(id) => write!(f, "unknown section id {id}"),
+            Self::InvalidOpcode {
+                prefix: Some(prefix),
+                opcode,
+            } => write!(f, "invalid opcode 0x{prefix:02X} 0x{opcode:X}"),
+            Self::InvalidOpcode {
+                prefix: None,
+                opcode,
+            } => write!(f, "invalid opcode 0x{opcode:02X}"),
+            Self::Malformed(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset 0x{:x}", self.kind, self.offset)?;
+        if let Some(id) = self.section {
+            write!(f, " in section {id}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The type of an import or of the definition it resolved to
+#[derive(Debug, Clone)]
+pub enum ExternType {
+    Func(FunctionType),
+    Table(TableType),
+    Memory(MemoryType),
+    Global(GlobalType),
+    Tag(FunctionType),
+}
+
+impl fmt::Display for ExternType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Func(ft) => write!(f, "function {ft:?}"),
+            Self::Table(tt) => write!(f, "table {tt:?}"),
+            Self::Memory(mt) => write!(f, "memory {mt:?}"),
+            Self::Global(gt) => write!(f, "global {gt:?}"),
+            Self::Tag(ft) => write!(f, "tag {ft:?}"),
+        }
+    }
+}
+
+/// An import that couldn't be resolved
+#[derive(Debug, Clone)]
+pub struct LinkError {
+    pub module: String,
+    pub name: String,
+    pub kind: LinkErrorKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum LinkErrorKind {
+    /// Nothing is defined under the import's name
+    UnknownImport,
+    /// The definition doesn't match the import's type. Tables and memories
+    /// are given with their current size as minimum
+    IncompatibleType {
+        expected: ExternType,
+        actual: ExternType,
+    },
+    /// The import refers to a type that isn't a function type
+    NotAFunctionType(u32),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (module, name) = (&self.module, &self.name);
+        match &self.kind {
+            LinkErrorKind::UnknownImport => write!(f, "unknown import \"{module}\".\"{name}\""),
+            LinkErrorKind::IncompatibleType { expected, actual } => write!(
+                f,
+                "incompatible import type for \"{module}\".\"{name}\": expected {expected}, got {actual}"
+            ),
+            LinkErrorKind::NotAFunctionType(idx) => write!(
+                f,
+                "import \"{module}\".\"{name}\" refers to type {idx}, which is not a function type"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstantiationError {
+    /// An active data segment doesn't fit in its memory
+    DataSegmentOutOfBounds {
+        segment: u32,
+        offset: u64,
+        len: u64,
+        memory_size: u64,
+    },
+    /// An active element segment doesn't fit in its table
+    ElementSegmentOutOfBounds {
+        segment: u32,
+        offset: u64,
+        len: u64,
+        table_size: u64,
+    },
+    /// An export refers to a definition the module doesn't have
+    ExportIndexOutOfBounds {
+        name: String,
+        description: ExportDescription,
+    },
+    /// Anything else, including misuse of the store's API
+    Other(String),
+}
+
+impl fmt::Display for InstantiationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DataSegmentOutOfBounds {
+                segment,
+                offset,
+                len,
+                memory_size,
+            } => write!(
+                f,
+                "data segment {segment} out of bounds: {len} bytes at offset {offset} in a memory of {memory_size} bytes"
+            ),
+            Self::ElementSegmentOutOfBounds {
+                segment,
+                offset,
+                len,
+                table_size,
+            } => write!(
+                f,
+                "element segment {segment} out of bounds: {len} elements at offset {offset} in a table of {table_size} elements"
+            ),
+            Self::ExportIndexOutOfBounds { name, description } => {
+                let (kind, idx) = match description {
+                    ExportDescription::Func(idx) => ("function", idx),
+                    ExportDescription::Table(idx) => ("table", idx),
+                    ExportDescription::Mem(idx) => ("memory", idx),
+                    ExportDescription::Global(idx) => ("global", idx),
+                    ExportDescription::Tag(idx) => ("tag", idx),
+                };
+                write!(f, "export \"{name}\" refers to {kind} {idx}, which is out of bounds")
+            }
+            Self::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl From<String> for InstantiationError {
+    fn from(msg: String) -> Self {
+        Self::Other(msg)
+    }
+}
+
+impl From<&str> for InstantiationError {
+    fn from(msg: &str) -> Self {
+        Self::Other(msg.to_owned())
+    }
+}
+
+/// A [`crate::Replayer`]'s guest stopped following its log at `event`
+#[derive(Debug, Clone)]
+pub struct ReplayDivergence {
+    pub event: usize,
+    pub kind: DivergenceKind,
+}
+
+#[derive(Debug, Clone)]
+pub enum DivergenceKind {
+    /// Invoking spawned another execution than the logged one
+    Spawned {
+        expected: Execution,
+        actual: Execution,
+    },
+    /// The guest got to `actual` instead of making the logged host call
+    HostCall {
+        module_name: String,
+        func_name: String,
+        args: Vec<RawValue>,
+        actual: ExecutionState,
+    },
+}
+
+impl fmt::Display for ReplayDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "replay diverged at event {}: ", self.event)?;
+        match &self.kind {
+            DivergenceKind::Spawned { expected, actual } => write!(
+                f,
+                "spawned execution {} instead of {}",
+                actual.0, expected.0
+            ),
+            DivergenceKind::HostCall {
+                module_name,
+                func_name,
+                actual,
+                ..
+            } => write!(
+                f,
+                "expected host call {module_name}.{func_name}, got {actual:?}"
+            ),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum Error {
-    Parse(String),
-    Instantiation(String),
-    Trap(Trap),
+    Parse(ParseError),
+    Link(Box<LinkError>),
+    Instantiation(InstantiationError),
+    Trap(Trap, Backtrace),
+    /// A host log replay stopped following the log
+    Replay(Box<ReplayDivergence>),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Parse(msg) => write!(f, "parse error: {msg}"),
-            Self::Instantiation(msg) => write!(f, "instantiation error: {msg}"),
-            Self::Trap(trap) => write!(f, "trap: {trap}"),
+            Self::Parse(err) => write!(f, "parse error: {err}"),
+            Self::Link(err) => write!(f, "link error: {err}"),
+            Self::Instantiation(err) => write!(f, "instantiation error: {err}"),
+            Self::Trap(trap, backtrace) if backtrace.is_empty() => write!(f, "trap: {trap}"),
+            Self::Trap(trap, backtrace) => write!(f, "trap: {trap}\n{backtrace}"),
+            Self::Replay(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error {
+    /// The embedder's error this trapped with, if it's a `T`
+    pub fn downcast_ref<T: std::error::Error + 'static>(&self) -> Option<&T> {
+        match self {
+            Self::Trap(trap, _) => trap.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// The code the guest exited with, if it did
+    pub const fn exit_code(&self) -> Option<u32> {
+        match self {
+            Self::Trap(trap, _) => trap.exit_code(),
+            _ => None,
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Trap(Trap::Host(err), _) => Some(err.0.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl From<Trap> for Error {
     fn from(trap: Trap) -> Self {
-        Self::Trap(trap)
+        Self::Trap(trap, Backtrace::default())
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Self {
+        Self::Parse(err)
     }
 }
 
-impl From<TryFromSliceError> for Error {
-    fn from(e: TryFromSliceError) -> Self {
-        Self::Parse(e.to_string())
+impl From<LinkError> for Error {
+    fn from(err: LinkError) -> Self {
+        Self::Link(Box::new(err))
     }
 }
 
-impl From<Utf8Error> for Error {
-    fn from(e: Utf8Error) -> Self {
-        Self::Parse(e.to_string())
+impl From<InstantiationError> for Error {
+    fn from(err: InstantiationError) -> Self {
+        Self::Instantiation(err)
     }
 }
 
 #[macro_export]
 macro_rules! parse_err {
-    ($($arg:tt)*) => {
-        return Err($crate::error::Error::Parse(format!($($arg)*)))
+    ($offset:expr, $fmt:literal $($arg:tt)*) => {
+        return Err($crate::error::Error::Parse($crate::error::ParseError::new(
+            $offset,
+            $crate::error::ParseErrorKind::Malformed(format!($fmt $($arg)*)),
+        )))
+    };
+    ($offset:expr, $kind:expr) => {
+        return Err($crate::error::Error::Parse($crate::error::ParseError::new(
+            $offset, $kind,
+        )))
+    };
+}
+
+#[macro_export]
+macro_rules! link_err {
+    ($import:expr, $kind:expr) => {
+        return Err($crate::error::Error::Link(Box::new(
+            $crate::error::LinkError {
+                module: $import.module.clone(),
+                name: $import.name.clone(),
+                kind: $kind,
+            },
+        )))
     };
 }
 
 #[macro_export]
 macro_rules! instantiation_err {
     ($($arg:tt)*) => {
-        return Err($crate::error::Error::Instantiation(format!($($arg)*)))
+        return Err($crate::error::Error::Instantiation(
+            $crate::error::InstantiationError::Other(format!($($arg)*)),
+        ))
     };
 }
 
 #[macro_export]
 macro_rules! trap {
     ($trap:expr) => {
-        return Err($crate::error::Error::Trap($trap))
+        return Err($crate::error::Error::from($trap))
     };
 }
 
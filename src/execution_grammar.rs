@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 use std::rc::Rc;
 
-use crate::binary_grammar::{Function, FunctionType, GlobalType, MemoryType, RefType, TableType};
+use crate::binary_grammar::{
+    Function, FunctionType, GlobalType, HeapType, MemoryType, RefType, TableType, ValueType,
+};
 
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -66,6 +68,11 @@ impl RawValue {
     pub const fn as_v128(self, lo: Self) -> i128 {
         (self.0 as i128) << 64 | lo.0 as i128
     }
+
+    /// The value's bits, whatever its type
+    pub const fn to_bits(self) -> u64 {
+        self.0
+    }
 }
 
 impl From<i32> for RawValue {
@@ -92,6 +99,60 @@ impl From<f64> for RawValue {
     }
 }
 
+/// A value tagged with its type, for APIs that check what they're given
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    V128(i128),
+    Ref(Ref),
+}
+
+impl Value {
+    /// Whether the value fits `value_type`. Function references aren't
+    /// checked against their signature
+    pub const fn has_type(&self, value_type: &ValueType) -> bool {
+        match (self, value_type) {
+            (Self::I32(_), ValueType::I32)
+            | (Self::I64(_), ValueType::I64)
+            | (Self::F32(_), ValueType::F32)
+            | (Self::F64(_), ValueType::F64)
+            | (Self::V128(_), ValueType::V128) => true,
+            (Self::Ref(r), ValueType::Ref(ref_type)) => match (r, ref_type) {
+                (Ref::Null, RefType::FuncRef | RefType::ExternRef) => true,
+                (Ref::Null, RefType::Ref { nullable, .. }) => *nullable,
+                (Ref::FunctionAddr(_), RefType::FuncRef) => true,
+                (Ref::RefExtern(_), RefType::ExternRef) => true,
+                (Ref::FunctionAddr(_), RefType::Ref { heap_type, .. }) => {
+                    matches!(heap_type, HeapType::Func | HeapType::TypeIndex(_))
+                }
+                (Ref::RefExtern(_), RefType::Ref { heap_type, .. }) => {
+                    matches!(heap_type, HeapType::Extern)
+                }
+                (Ref::I31(_), RefType::Ref { heap_type, .. }) => {
+                    matches!(heap_type, HeapType::I31 | HeapType::Eq | HeapType::Any)
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Appends the value's stack slots, two for a v128
+    pub fn push_raw(&self, raw: &mut Vec<RawValue>) {
+        match *self {
+            Self::I32(v) => raw.push(v.into()),
+            Self::I64(v) => raw.push(v.into()),
+            Self::F32(v) => raw.push(v.into()),
+            Self::F64(v) => raw.push(v.into()),
+            Self::V128(v) => raw.extend(<[RawValue; 2]>::from(RawValue::from_v128(v))),
+            Self::Ref(r) => raw.push(RawValue::from_ref(r)),
+        }
+    }
+}
+
 /// A temporary struct that accumulates address mappings during instantiation
 #[derive(Debug, Clone, Default)]
 pub struct AddressMap {
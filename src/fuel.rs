@@ -0,0 +1,175 @@
+use crate::ir::Op;
+
+/// Fuel charged per instruction, by category
+///
+/// Costs are defined in terms of wasm instructions rather than compiled ops: a
+/// fused op is charged the sum of the instructions it replaces, so fuel counts
+/// don't change when the compiler learns new fusions. Bulk memory and table
+/// ops are charged `bulk` plus one unit per `bulk_unit` bytes or elements, and
+/// calls into host functions, WASI included, are charged `host_call` on top of
+/// `call`. An op is only executed once the store has enough fuel to pay for it
+/// in full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FuelCosts {
+    /// Numeric, local, global, constant, reference and control instructions
+    pub instruction: u64,
+    /// Loads and stores, including SIMD lane accesses
+    pub memory_access: u64,
+    /// `call`, `call_indirect`, `call_ref` and their tail-call variants
+    pub call: u64,
+    pub host_call: u64,
+    /// `memory.grow` and `table.grow`
+    pub grow: u64,
+    /// `memory.copy`, `memory.fill`, `memory.init` and their table equivalents
+    pub bulk: u64,
+    /// Bytes or elements covered by one unit of fuel in bulk ops, 0 disables
+    /// size-proportional charges
+    pub bulk_unit: u64,
+}
+
+impl Default for FuelCosts {
+    fn default() -> Self {
+        Self {
+            instruction: 1,
+            memory_access: 1,
+            call: 1,
+            host_call: 1,
+            grow: 1,
+            bulk: 1,
+            bulk_unit: 64,
+        }
+    }
+}
+
+impl FuelCosts {
+    /// Every instruction costs one unit and bulk ops cost the same regardless
+    /// of their size
+    pub const fn uniform() -> Self {
+        Self {
+            instruction: 1,
+            memory_access: 1,
+            call: 1,
+            host_call: 0,
+            grow: 1,
+            bulk: 1,
+            bulk_unit: 0,
+        }
+    }
+
+    /// The size-proportional part of a bulk op over `len` bytes or elements
+    pub(crate) const fn bulk_len_cost(&self, len: u64) -> u64 {
+        match self.bulk_unit {
+            0 => 0,
+            unit => len.div_ceil(unit),
+        }
+    }
+
+    /// The fixed part of an op's cost
+    pub(crate) const fn op_cost(&self, op: &Op) -> u64 {
+        match op {
+            Op::Call { .. }
+            | Op::CallIndirect { .. }
+            | Op::ReturnCall { .. }
+            | Op::ReturnCallIndirect { .. }
+            | Op::CallRef { .. }
+            | Op::ReturnCallRef { .. } => self.call,
+
+            Op::MemoryGrow { .. } | Op::TableGrow { .. } => self.grow,
+
+            Op::MemoryCopy { .. }
+            | Op::MemoryFill { .. }
+            | Op::MemoryInit { .. }
+            | Op::TableCopy { .. }
+            | Op::TableFill { .. }
+            | Op::TableInit { .. } => self.bulk,
+
+            Op::I32Load { .. }
+            | Op::I64Load { .. }
+            | Op::F32Load { .. }
+            | Op::F64Load { .. }
+            | Op::I32Load8Signed { .. }
+            | Op::I32Load8Unsigned { .. }
+            | Op::I32Load16Signed { .. }
+            | Op::I32Load16Unsigned { .. }
+            | Op::I64Load8Signed { .. }
+            | Op::I64Load8Unsigned { .. }
+            | Op::I64Load16Signed { .. }
+            | Op::I64Load16Unsigned { .. }
+            | Op::I64Load32Signed { .. }
+            | Op::I64Load32Unsigned { .. }
+            | Op::I32Store { .. }
+            | Op::I64Store { .. }
+            | Op::F32Store { .. }
+            | Op::F64Store { .. }
+            | Op::I32Store8 { .. }
+            | Op::I32Store16 { .. }
+            | Op::I64Store8 { .. }
+            | Op::I64Store16 { .. }
+            | Op::I64Store32 { .. }
+            | Op::V128Load { .. }
+            | Op::V128Load8x8Signed { .. }
+            | Op::V128Load8x8Unsigned { .. }
+            | Op::V128Load16x4Signed { .. }
+            | Op::V128Load16x4Unsigned { .. }
+            | Op::V128Load32x2Signed { .. }
+            | Op::V128Load32x2Unsigned { .. }
+            | Op::V128Load8Splat { .. }
+            | Op::V128Load16Splat { .. }
+            | Op::V128Load32Splat { .. }
+            | Op::V128Load64Splat { .. }
+            | Op::V128Load32Zero { .. }
+            | Op::V128Load64Zero { .. }
+            | Op::V128Store { .. }
+            | Op::V128Load8Lane { .. }
+            | Op::V128Load16Lane { .. }
+            | Op::V128Load32Lane { .. }
+            | Op::V128Load64Lane { .. }
+            | Op::V128Store8Lane { .. }
+            | Op::V128Store16Lane { .. }
+            | Op::V128Store32Lane { .. }
+            | Op::V128Store64Lane { .. } => self.memory_access,
+
+            // superinstructions: a comparison or local.get followed by a
+            // branch, local.get or return
+            Op::I32EqZeroJumpIf { .. }
+            | Op::I32EqZeroJumpIfNot { .. }
+            | Op::I32EqJumpIf { .. }
+            | Op::I32NeJumpIf { .. }
+            | Op::I32LtSignedJumpIf { .. }
+            | Op::I32LtUnsignedJumpIf { .. }
+            | Op::I32GtSignedJumpIf { .. }
+            | Op::I32GtUnsignedJumpIf { .. }
+            | Op::I32LeSignedJumpIf { .. }
+            | Op::I32LeUnsignedJumpIf { .. }
+            | Op::I32GeSignedJumpIf { .. }
+            | Op::I32GeUnsignedJumpIf { .. }
+            | Op::I64EqZeroJumpIf { .. }
+            | Op::I64EqJumpIf { .. }
+            | Op::I64NeJumpIf { .. }
+            | Op::I64LtSignedJumpIf { .. }
+            | Op::I64LtUnsignedJumpIf { .. }
+            | Op::I64GtSignedJumpIf { .. }
+            | Op::I64GtUnsignedJumpIf { .. }
+            | Op::I64LeSignedJumpIf { .. }
+            | Op::I64LeUnsignedJumpIf { .. }
+            | Op::I64GeSignedJumpIf { .. }
+            | Op::I64GeUnsignedJumpIf { .. }
+            | Op::F32EqJumpIf { .. }
+            | Op::F32NeJumpIf { .. }
+            | Op::F32LtJumpIf { .. }
+            | Op::F32GtJumpIf { .. }
+            | Op::F32LeJumpIf { .. }
+            | Op::F32GeJumpIf { .. }
+            | Op::F64EqJumpIf { .. }
+            | Op::F64NeJumpIf { .. }
+            | Op::F64LtJumpIf { .. }
+            | Op::F64GtJumpIf { .. }
+            | Op::F64LeJumpIf { .. }
+            | Op::F64GeJumpIf { .. }
+            | Op::LocalGet2 { .. }
+            | Op::LocalGetReturn { .. } => 2 * self.instruction,
+
+            _ => self.instruction,
+        }
+    }
+}
@@ -0,0 +1,528 @@
+use std::io::{self, Read, Write};
+
+use crate::error::{Error, Result};
+use crate::{ExecutionState, Instance, Module, RawValue, StepKind, Store, WatchKind};
+
+/// LLDB's wasm addresses keep the address space in the top two bits and the
+/// module id in the next 30, code lives in the object space
+const OBJECT_SPACE: u64 = 1 << 62;
+const SPACE_MASK: u64 = 3 << 62;
+
+const TRIPLE: &str = "wasm32-unknown-unknown-wasm";
+
+/// A GDB remote serial protocol server for one guest invocation
+///
+/// Speaks the subset of the protocol LLDB needs to debug wasm, including its
+/// `qWasmCallStack`, `qWasmLocal`, `qWasmGlobal`, `qWasmStackValue` and
+/// `qWasmMem` extensions. The guest is paused before its first instruction
+/// when the client connects and only runs while the client waits on a
+/// continue or step, so it can't be interrupted from the client.
+pub struct GdbStub<'a> {
+    store: &'a mut Store,
+    instance: Instance,
+    module: &'a Module,
+    bytes: &'a [u8],
+    name: String,
+    no_ack: bool,
+}
+
+enum Reply {
+    Packet(String),
+    /// The invocation is over, send the packet and stop serving
+    Exit(String, Result<Vec<RawValue>>),
+    /// Stop serving without a reply
+    Close(Result<Vec<RawValue>>),
+}
+
+impl<'a> GdbStub<'a> {
+    /// `bytes` are the module's binary, which the client reads to find its
+    /// debug info. `name` is the module's file name
+    pub fn new(
+        store: &'a mut Store,
+        instance: Instance,
+        module: &'a Module,
+        bytes: &'a [u8],
+        name: impl Into<String>,
+    ) -> Self {
+        Self {
+            store,
+            instance,
+            module,
+            bytes,
+            name: name.into(),
+            no_ack: false,
+        }
+    }
+
+    /// Invokes `func_name` and serves `stream` until the invocation ends or
+    /// the client kills it. A client that detaches or disconnects lets the
+    /// guest run to completion
+    pub fn run<S: Read + Write>(
+        &mut self,
+        mut stream: S,
+        func_name: &str,
+        args: Vec<RawValue>,
+    ) -> io::Result<Result<Vec<RawValue>>> {
+        let state = self.start(func_name, args);
+        if let Some(result) = Self::finished(state) {
+            return Ok(result);
+        }
+
+        loop {
+            let Some(packet) = self.read_packet(&mut stream)? else {
+                return Ok(self.detach());
+            };
+
+            match self.handle(&packet) {
+                Reply::Packet(reply) => self.send(&mut stream, &reply)?,
+                Reply::Exit(reply, result) => {
+                    self.send(&mut stream, &reply)?;
+                    return Ok(result);
+                }
+                Reply::Close(result) => return Ok(result),
+            }
+        }
+    }
+
+    /// Invokes `func_name` and pauses it before its first instruction
+    fn start(&mut self, func_name: &str, args: Vec<RawValue>) -> Result<ExecutionState> {
+        let func_idx = self.module.exported_function(func_name).ok_or_else(|| {
+            Error::Instantiation(format!("export '{}' is not a function", func_name).into())
+        })?;
+        let entry = self
+            .module
+            .function_offsets(func_idx)
+            .and_then(|offsets| offsets.iter().next())
+            .map(|(_, offset)| offset)
+            .ok_or_else(|| {
+                Error::Instantiation(
+                    format!("export '{}' is not a local function", func_name).into(),
+                )
+            })?;
+
+        self.store.add_breakpoint(self.instance, func_idx, entry)?;
+        let state = self.store.invoke(self.instance, func_name, args);
+        self.store
+            .remove_breakpoint(self.instance, func_idx, entry)?;
+        state
+    }
+
+    /// The invocation's result if `state` ended it
+    fn finished(state: Result<ExecutionState>) -> Option<Result<Vec<RawValue>>> {
+        match state {
+            Ok(ExecutionState::Completed(values)) => Some(Ok(values)),
+            Ok(ExecutionState::Suspended { func_name, .. }) => Some(Err(Error::Instantiation(
+                format!(
+                    "host function {} can't be called while debugging",
+                    func_name
+                )
+                .into(),
+            ))),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    fn detach(&mut self) -> Result<Vec<RawValue>> {
+        self.store.clear_breakpoints();
+        self.store.resume()?.into_completed()
+    }
+
+    fn handle(&mut self, packet: &str) -> Reply {
+        let reply = match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_owned()
+            }
+            "?" | "qThreadStopInfo1" => self.stop_reply(5, "breakpoint"),
+            "qSupported" => "PacketSize=1000;qXfer:libraries:read+".to_owned(),
+            "qHostInfo" => format!(
+                "vendor:gabagool;ostype:wasi;arch:wasm32;triple:{};endian:little;ptrsize:4;",
+                hex(TRIPLE.as_bytes())
+            ),
+            "qProcessInfo" => format!(
+                "pid:1;parent-pid:1;vendor:gabagool;ostype:wasi;arch:wasm32;triple:{};endian:little;ptrsize:4;",
+                hex(TRIPLE.as_bytes())
+            ),
+            "qRegisterInfo0" => "name:pc;alt-name:pc;bitsize:64;offset:0;encoding:uint;\
+                format:hex;set:General Purpose Registers;gcc:16;dwarf:16;generic:pc;"
+                .to_owned(),
+            "qC" => "QC1".to_owned(),
+            "qAttached" => "1".to_owned(),
+            "qfThreadInfo" => "m1".to_owned(),
+            "qsThreadInfo" => "l".to_owned(),
+            "g" | "p0" => hex(&self.pc().to_le_bytes()),
+            "c" => {
+                let state = self.store.resume();
+                return self.stopped(state, 5, "breakpoint");
+            }
+            "s" => {
+                let state = self.store.step(StepKind::Into);
+                return self.stopped(state, 5, "trace");
+            }
+            "D" => return Reply::Exit("OK".to_owned(), self.detach()),
+            "k" => {
+                return Reply::Close(Err(Error::Instantiation("killed by debugger".into())));
+            }
+            "QEnableErrorStrings" | "QThreadSuffixSupported" | "QListThreadsInStopReply" => {
+                "OK".to_owned()
+            }
+            _ => self.handle_with_args(packet).unwrap_or_default(),
+        };
+
+        Reply::Packet(reply)
+    }
+
+    /// Packets that carry arguments. `None` marks an unsupported packet,
+    /// which gets an empty reply
+    fn handle_with_args(&mut self, packet: &str) -> Option<String> {
+        if packet.starts_with("qRegisterInfo") {
+            return Some("E45".to_owned());
+        }
+
+        if packet.starts_with('H') {
+            return Some("OK".to_owned());
+        }
+
+        if let Some(args) = packet.strip_prefix("qXfer:libraries:read::") {
+            let (offset, len) = args.split_once(',')?;
+            let (offset, len) = (parse_hex(offset)? as usize, parse_hex(len)? as usize);
+            return Some(self.libraries(offset, len));
+        }
+
+        if packet.starts_with("qWasmCallStack") {
+            let pcs = self
+                .store
+                .frames()
+                .iter()
+                .flat_map(|frame| code_address(frame.module_idx, frame.wasm_offset).to_le_bytes())
+                .collect::<Vec<_>>();
+            return Some(hex(&pcs));
+        }
+
+        if let Some(args) = packet.strip_prefix("qWasmLocal:") {
+            let (frame, idx) = args.split_once(';')?;
+            let (frame, idx) = (parse_dec(frame)?, parse_dec(idx)?);
+            return Some(or_error(
+                self.store
+                    .locals(frame)
+                    .ok()
+                    .and_then(|locals| locals.get(idx))
+                    .map(|value| value_hex(*value)),
+            ));
+        }
+
+        if let Some(args) = packet.strip_prefix("qWasmStackValue:") {
+            let (frame, idx) = args.split_once(';')?;
+            let (frame, idx) = (parse_dec(frame)?, parse_dec(idx)?);
+            return Some(or_error(
+                self.store
+                    .operand_stack(frame)
+                    .ok()
+                    .and_then(|operands| operands.get(idx))
+                    .map(|value| value_hex(*value)),
+            ));
+        }
+
+        if let Some(args) = packet.strip_prefix("qWasmGlobal:") {
+            let (frame, idx) = args.split_once(';')?;
+            let (frame, idx) = (parse_dec(frame)?, parse_dec(idx)?);
+            let instance = self.frame_instance(frame)?;
+            return Some(or_error(
+                self.store.global(instance, idx as u32).ok().map(value_hex),
+            ));
+        }
+
+        if let Some(args) = packet.strip_prefix("qWasmMem:") {
+            let mut args = args.split(';');
+            let frame = parse_dec(args.next()?)?;
+            let addr = parse_hex(args.next()?)? as usize;
+            let len = parse_hex(args.next()?)? as usize;
+            let instance = self.frame_instance(frame)?;
+            return Some(or_error(
+                self.store
+                    .memory(instance, 0)
+                    .ok()
+                    .and_then(|memory| memory.get(addr..addr.checked_add(len)?))
+                    .map(hex),
+            ));
+        }
+
+        if let Some(args) = packet.strip_prefix('m') {
+            let (addr, len) = args.split_once(',')?;
+            return Some(or_error(
+                self.read_memory(parse_hex(addr)?, parse_hex(len)? as usize),
+            ));
+        }
+
+        if let Some(args) = packet.strip_prefix('M') {
+            let (target, data) = args.split_once(':')?;
+            let (addr, _len) = target.split_once(',')?;
+            let written = self.write_memory(parse_hex(addr)?, &unhex(data)?);
+            return Some(or_error(written.map(|()| "OK".to_owned())));
+        }
+
+        if let Some(args) = packet.strip_prefix(['Z', 'z']) {
+            let add = packet.starts_with('Z');
+            let mut args = args.split(',');
+            let kind = args.next()?;
+            let addr = parse_hex(args.next()?)?;
+            let len = parse_hex(args.next()?)?;
+
+            let done = match kind {
+                "0" => self.breakpoint(addr, add),
+                "2" => self.watchpoint(addr, len, WatchKind::Write, add),
+                "3" => self.watchpoint(addr, len, WatchKind::Read, add),
+                "4" => self.watchpoint(addr, len, WatchKind::ReadWrite, add),
+                _ => return None,
+            };
+            return Some(or_error(done.map(|()| "OK".to_owned())));
+        }
+
+        None
+    }
+
+    /// Reports where a continue or step left the guest
+    fn stopped(&self, state: Result<ExecutionState>, signal: u8, reason: &str) -> Reply {
+        match state {
+            Ok(ExecutionState::FuelExhausted | ExecutionState::Interrupted) => {
+                Reply::Packet(self.stop_reply(2, "signal"))
+            }
+            Ok(ExecutionState::Breakpoint) => Reply::Packet(self.stop_reply(signal, reason)),
+            Ok(ExecutionState::Watchpoint(hit)) => {
+                let stop = match hit.write {
+                    true => "watch",
+                    false => "rwatch",
+                };
+                let addr = ((hit.instance.0 as u64) << 32) | hit.address;
+                let reply = self.stop_reply(5, "watchpoint");
+                Reply::Packet(format!("{reply}{stop}:{addr:x};"))
+            }
+            state => match Self::finished(state) {
+                Some(Ok(values)) => Reply::Exit("W00".to_owned(), Ok(values)),
+                Some(Err(e)) => Reply::Exit("X06".to_owned(), Err(e)),
+                None => unreachable!("paused states are handled above"),
+            },
+        }
+    }
+
+    fn stop_reply(&self, signal: u8, reason: &str) -> String {
+        let pc = self.pc();
+        format!(
+            "T{signal:02x}thread:1;thread-pcs:{pc:016x};00:{};reason:{reason};",
+            hex(&pc.to_le_bytes())
+        )
+    }
+
+    fn pc(&self) -> u64 {
+        self.store
+            .frames()
+            .first()
+            .map_or(0, |frame| code_address(frame.module_idx, frame.wasm_offset))
+    }
+
+    fn frame_instance(&self, frame: usize) -> Option<Instance> {
+        let module_idx = self.store.frames().get(frame)?.module_idx;
+        Some(self.store.instance(module_idx))
+    }
+
+    fn libraries(&self, offset: usize, len: usize) -> String {
+        let xml = format!(
+            "<library-list><library name=\"{}\"><section address=\"{:#x}\"/></library></library-list>",
+            xml_escape(&self.name),
+            code_address(self.instance.0, Some(0))
+        );
+
+        // the offsets count bytes, and the escaped XML is ASCII so a chunk
+        // never splits a character
+        let chunk = xml.as_bytes().get(offset..).unwrap_or_default();
+        let (kind, chunk) = match chunk.len() > len {
+            true => ('m', &chunk[..len]),
+            false => ('l', chunk),
+        };
+        format!("{kind}{}", escape(&String::from_utf8_lossy(chunk)))
+    }
+
+    fn read_memory(&self, addr: u64, len: usize) -> Option<String> {
+        let (space, module_idx, offset) = split_address(addr);
+        let bytes = match space {
+            OBJECT_SPACE if module_idx == self.instance.0 => self.bytes,
+            0 if module_idx == self.instance.0 => self.store.memory(self.instance, 0).ok()?,
+            _ => return None,
+        };
+
+        bytes.get(offset..offset.checked_add(len)?).map(hex)
+    }
+
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> Option<()> {
+        let (space, module_idx, offset) = split_address(addr);
+        if space != 0 || module_idx != self.instance.0 {
+            return None;
+        }
+
+        let memory = self.store.memory_mut(self.instance, 0).ok()?;
+        memory
+            .get_mut(offset..offset.checked_add(data.len())?)?
+            .copy_from_slice(data);
+        Some(())
+    }
+
+    fn breakpoint(&mut self, addr: u64, add: bool) -> Option<()> {
+        let (space, module_idx, offset) = split_address(addr);
+        if space != OBJECT_SPACE || module_idx != self.instance.0 {
+            return None;
+        }
+
+        let offset = offset as u32;
+        let func_idx = self.module.function_at(offset)?;
+        match add {
+            true => self
+                .store
+                .add_breakpoint(self.instance, func_idx, offset)
+                .ok(),
+            false => self
+                .store
+                .remove_breakpoint(self.instance, func_idx, offset)
+                .ok()
+                .map(drop),
+        }
+    }
+
+    /// Watches `len` bytes at a memory space address
+    fn watchpoint(&mut self, addr: u64, len: u64, kind: WatchKind, add: bool) -> Option<()> {
+        let (space, module_idx, offset) = split_address(addr);
+        if space != 0 || module_idx != self.instance.0 {
+            return None;
+        }
+
+        let instance = self.instance;
+        let range = offset as u64..(offset as u64).checked_add(len)?;
+        match add {
+            true => self.store.add_watchpoint(instance, 0, range, kind).ok(),
+            false => self
+                .store
+                .remove_watchpoint(instance, 0, range, kind)
+                .ok()
+                .map(drop),
+        }
+    }
+
+    /// The next packet's payload, acknowledging it unless acks are off.
+    /// Packets with a bad checksum are refused with `-` so the client sends
+    /// them again. `None` once the client disconnects
+    fn read_packet<S: Read + Write>(&self, stream: &mut S) -> io::Result<Option<String>> {
+        let mut byte = [0];
+        loop {
+            // skip acks and interrupt requests until the packet starts
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'$' {
+                    break;
+                }
+            }
+
+            let mut payload = Vec::new();
+            loop {
+                if stream.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                if byte[0] == b'#' {
+                    break;
+                }
+                payload.push(byte[0]);
+            }
+
+            let mut checksum = [0; 2];
+            stream.read_exact(&mut checksum)?;
+
+            // without acks there's no asking for a resend
+            if !self.no_ack {
+                let sum = payload.iter().copied().fold(0u8, u8::wrapping_add);
+                let valid = std::str::from_utf8(&checksum)
+                    .ok()
+                    .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                    == Some(sum);
+                if !valid {
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+                stream.write_all(b"+")?;
+            }
+
+            return Ok(Some(String::from_utf8_lossy(&payload).into_owned()));
+        }
+    }
+
+    fn send<S: Write>(&self, stream: &mut S, payload: &str) -> io::Result<()> {
+        let checksum = payload.bytes().fold(0u8, u8::wrapping_add);
+        write!(stream, "${payload}#{checksum:02x}")?;
+        stream.flush()
+    }
+}
+
+fn code_address(module_idx: usize, wasm_offset: Option<u32>) -> u64 {
+    OBJECT_SPACE | ((module_idx as u64) << 32) | u64::from(wasm_offset.unwrap_or(0))
+}
+
+/// The address space, module id and offset of a wasm address
+const fn split_address(addr: u64) -> (u64, usize, usize) {
+    (
+        addr & SPACE_MASK,
+        ((addr & !SPACE_MASK) >> 32) as usize,
+        (addr & 0xffff_ffff) as usize,
+    )
+}
+
+fn value_hex(value: RawValue) -> String {
+    hex(&value.as_i64().to_le_bytes())
+}
+
+fn or_error(reply: Option<String>) -> String {
+    reply.unwrap_or_else(|| "E01".to_owned())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+fn parse_dec(s: &str) -> Option<usize> {
+    s.parse().ok()
+}
+
+/// Escapes the bytes the protocol reserves in binary replies
+/// `s` as XML attribute text, in ASCII
+fn xml_escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_owned(),
+            '<' => "&lt;".to_owned(),
+            '>' => "&gt;".to_owned(),
+            '"' => "&quot;".to_owned(),
+            '\'' => "&apos;".to_owned(),
+            c if c.is_ascii() && !c.is_ascii_control() => c.to_string(),
+            c => format!("&#x{:x};", u32::from(c)),
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.chars()
+        .flat_map(|c| match c {
+            '#' | '$' | '}' | '*' => vec!['}', (c as u8 ^ 0x20) as char],
+            c => vec![c],
+        })
+        .collect()
+}
@@ -0,0 +1,411 @@
+use crate::error::{DivergenceKind, Error, ReplayDivergence, Result, Trap};
+use crate::snapshot::{decode_bulk, encode_bulk, Snapshot};
+use crate::store::{Execution, ExecutionState, Store};
+use crate::{ensure, RawValue};
+
+const HOST_LOG_MAGIC: &[u8; 4] = b"gabl";
+const HOST_LOG_VERSION: u32 = 2;
+
+/// Everything that passed between the embedder and the guest since a
+/// starting snapshot, see [`Store::start_host_log`]
+///
+/// Replaying the events on the snapshot with a [`Replayer`] reproduces the
+/// guest's execution without the host. WASI calls are answered inside the
+/// store and aren't logged, so their inputs only replay the same from a
+/// [`crate::Vfs`] in a deterministic store.
+#[derive(Debug, Clone)]
+pub struct HostLog {
+    /// The store when logging started
+    pub snapshot: Vec<u8>,
+    pub events: Vec<HostEvent>,
+    /// The execution the last events were for
+    pub(crate) execution: Option<Execution>,
+    /// Memories handed to the embedder by [`Store::memory_mut`], as they were
+    /// before, so what it wrote can be logged with the next event
+    pub(crate) lent: Vec<(usize, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum HostEvent {
+    /// The embedder invoked the function at `func_addr`
+    Invoke {
+        func_addr: usize,
+        args: Vec<RawValue>,
+    },
+    /// The guest suspended on a host import
+    Call {
+        module_name: String,
+        func_name: String,
+        args: Vec<RawValue>,
+    },
+    /// The embedder answered the pending host call
+    Return(Vec<RawValue>),
+    /// The embedder made the pending host call trap
+    Trap(Trap),
+    /// The following events are for `execution`, or for the store's own
+    /// invocation when `None`. Invoking while in an execution spawned it
+    Execution(Option<Execution>),
+    /// The embedder wrote `bytes` at `offset` in the memory at `mem_addr`
+    WriteMemory {
+        mem_addr: usize,
+        offset: usize,
+        bytes: Vec<u8>,
+    },
+    /// The embedder overwrote the global at `global_addr`
+    SetGlobal { global_addr: usize, value: RawValue },
+    /// The embedder overwrote operand `idx` of `frame`
+    SetOperand {
+        frame: usize,
+        idx: usize,
+        value: RawValue,
+    },
+}
+
+impl HostLog {
+    pub(crate) const fn new(snapshot: Vec<u8>) -> Self {
+        Self {
+            snapshot,
+            events: vec![],
+            execution: None,
+            lent: vec![],
+        }
+    }
+
+    pub(crate) fn push(&mut self, running: Option<Execution>, event: HostEvent) {
+        if self.execution != running {
+            self.execution = running;
+            self.events.push(HostEvent::Execution(running));
+        }
+        self.events.push(event);
+    }
+
+    /// Logs the runs of bytes that differ between `before` and `after` as
+    /// writes to the memory at `mem_addr`
+    pub(crate) fn push_memory_writes(
+        &mut self,
+        running: Option<Execution>,
+        mem_addr: usize,
+        before: &[u8],
+        after: &[u8],
+    ) {
+        let changed = |i: usize| before.get(i) != after.get(i);
+        let mut i = 0;
+        while i < after.len() {
+            if !changed(i) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < after.len() && changed(i) {
+                i += 1;
+            }
+            self.push(
+                running,
+                HostEvent::WriteMemory {
+                    mem_addr,
+                    offset: start,
+                    bytes: after[start..i].to_vec(),
+                },
+            );
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(HOST_LOG_MAGIC);
+        HOST_LOG_VERSION.encode(&mut buf);
+        self.encode(&mut buf);
+        buf
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        assert_eq!(&bytes[..4], HOST_LOG_MAGIC, "invalid host log magic");
+        let buf = &mut &bytes[4..];
+        let version = u32::decode(buf);
+        assert_eq!(
+            version, HOST_LOG_VERSION,
+            "unsupported host log version {version}"
+        );
+        Self::decode(buf)
+    }
+}
+
+impl Snapshot for HostLog {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_bulk(&self.snapshot, buf);
+        self.events.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            snapshot: decode_bulk(buf),
+            events: Vec::decode(buf),
+            execution: None,
+            lent: vec![],
+        }
+    }
+}
+
+impl Snapshot for HostEvent {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::Invoke { func_addr, args } => {
+                0u8.encode(buf);
+                func_addr.encode(buf);
+                args.encode(buf);
+            }
+            Self::Call {
+                module_name,
+                func_name,
+                args,
+            } => {
+                1u8.encode(buf);
+                module_name.encode(buf);
+                func_name.encode(buf);
+                args.encode(buf);
+            }
+            Self::Return(values) => {
+                2u8.encode(buf);
+                values.encode(buf);
+            }
+            Self::Execution(execution) => {
+                3u8.encode(buf);
+                execution.encode(buf);
+            }
+            Self::Trap(trap) => {
+                4u8.encode(buf);
+                trap.encode(buf);
+            }
+            Self::WriteMemory {
+                mem_addr,
+                offset,
+                bytes,
+            } => {
+                5u8.encode(buf);
+                mem_addr.encode(buf);
+                offset.encode(buf);
+                encode_bulk(bytes, buf);
+            }
+            Self::SetGlobal { global_addr, value } => {
+                6u8.encode(buf);
+                global_addr.encode(buf);
+                value.encode(buf);
+            }
+            Self::SetOperand { frame, idx, value } => {
+                7u8.encode(buf);
+                frame.encode(buf);
+                idx.encode(buf);
+                value.encode(buf);
+            }
+        }
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        match u8::decode(buf) {
+            0 => Self::Invoke {
+                func_addr: usize::decode(buf),
+                args: Vec::decode(buf),
+            },
+            1 => Self::Call {
+                module_name: String::decode(buf),
+                func_name: String::decode(buf),
+                args: Vec::decode(buf),
+            },
+            2 => Self::Return(Vec::decode(buf)),
+            3 => Self::Execution(Option::decode(buf)),
+            4 => Self::Trap(Trap::decode(buf)),
+            5 => Self::WriteMemory {
+                mem_addr: usize::decode(buf),
+                offset: usize::decode(buf),
+                bytes: decode_bulk(buf),
+            },
+            6 => Self::SetGlobal {
+                global_addr: usize::decode(buf),
+                value: RawValue::decode(buf),
+            },
+            7 => Self::SetOperand {
+                frame: usize::decode(buf),
+                idx: usize::decode(buf),
+                value: RawValue::decode(buf),
+            },
+            tag => panic!("invalid host event tag {tag}"),
+        }
+    }
+}
+
+/// Drives the store restored from a [`HostLog`] through its events,
+/// answering host calls with the logged results
+///
+/// Pauses for fuel, interrupts and debug stops are resumed through, with
+/// fuel refilled, since they don't change what the guest computes.
+#[derive(Debug)]
+pub struct Replayer {
+    store: Store,
+    events: Vec<HostEvent>,
+    next: usize,
+    /// The execution the events are for, `None` for the store's own
+    execution: Option<Execution>,
+}
+
+impl Replayer {
+    pub fn new(log: &HostLog) -> Self {
+        Self {
+            store: Store::from_snapshot(&log.snapshot),
+            events: log.events.clone(),
+            next: 0,
+            execution: None,
+        }
+    }
+
+    pub const fn store(&self) -> &Store {
+        &self.store
+    }
+
+    pub const fn store_mut(&mut self) -> &mut Store {
+        &mut self.store
+    }
+
+    pub fn into_store(self) -> Store {
+        self.store
+    }
+
+    /// Replays the remaining events, then runs on until the guest completes
+    /// or calls the host past the end of the log
+    ///
+    /// Errors with [`Error::Replay`] if the guest makes a different host call
+    /// than the log, or none where the log has one.
+    pub fn run(&mut self) -> Result<ExecutionState> {
+        let mut state = None;
+        while let Some(event) = self.events.get(self.next).cloned() {
+            self.next += 1;
+            state = Some(match &event {
+                HostEvent::Invoke { func_addr, args } => match self.execution {
+                    None => self.store.invoke_by_addr(*func_addr, args.clone())?,
+                    Some(execution) => {
+                        let (spawned, state) =
+                            self.store.spawn_by_addr(*func_addr, args.clone())?;
+                        ensure!(
+                            spawned == execution,
+                            self.diverged(DivergenceKind::Spawned {
+                                expected: execution,
+                                actual: spawned,
+                            })
+                        );
+                        state
+                    }
+                },
+                HostEvent::Call {
+                    module_name,
+                    func_name,
+                    args,
+                } => {
+                    let state = self.advance(state)?;
+                    let diverged = match &state {
+                        ExecutionState::Suspended {
+                            module_name: actual_module,
+                            func_name: actual_func,
+                            args: actual_args,
+                        } => {
+                            actual_module != module_name
+                                || actual_func != func_name
+                                || !same_values(actual_args, args)
+                        }
+                        _ => true,
+                    };
+                    if diverged {
+                        return Err(self.diverged(DivergenceKind::HostCall {
+                            module_name: module_name.clone(),
+                            func_name: func_name.clone(),
+                            args: args.clone(),
+                            actual: state,
+                        }));
+                    }
+                    state
+                }
+                HostEvent::Return(values) => match self.execution {
+                    None => self.store.resume_with(values)?,
+                    Some(execution) => self.store.resume_execution_with(execution, values)?,
+                },
+                HostEvent::Trap(trap) => {
+                    let result = match self.execution {
+                        None => self.store.resume_with_trap(trap.clone()),
+                        Some(execution) => self
+                            .store
+                            .resume_execution_with_trap(execution, trap.clone()),
+                    };
+                    match result {
+                        Err(Error::Trap(..)) => {}
+                        result => {
+                            result?;
+                        }
+                    }
+                    state = None;
+                    continue;
+                }
+                HostEvent::Execution(execution) => {
+                    self.execution = *execution;
+                    state = None;
+                    continue;
+                }
+                // writes leave the guest where it was
+                HostEvent::WriteMemory {
+                    mem_addr,
+                    offset,
+                    bytes,
+                } => {
+                    self.store.memories[*mem_addr].data[*offset..*offset + bytes.len()]
+                        .copy_from_slice(bytes);
+                    continue;
+                }
+                HostEvent::SetGlobal { global_addr, value } => {
+                    self.store.globals[*global_addr].value = *value;
+                    continue;
+                }
+                HostEvent::SetOperand { frame, idx, value } => {
+                    self.store.set_operand(*frame, *idx, *value)?;
+                    continue;
+                }
+            });
+        }
+
+        self.advance(state)
+    }
+
+    /// Runs from `state`, or from where the current execution is paused,
+    /// until the guest completes or suspends
+    fn advance(&mut self, state: Option<ExecutionState>) -> Result<ExecutionState> {
+        let mut state = match state {
+            Some(state) => state,
+            None => self.resume()?,
+        };
+        loop {
+            state = match state {
+                ExecutionState::FuelExhausted => {
+                    self.store.set_fuel(u64::MAX);
+                    self.resume()?
+                }
+                ExecutionState::Interrupted
+                | ExecutionState::Breakpoint
+                | ExecutionState::Watchpoint(_) => self.resume()?,
+                state => return Ok(state),
+            }
+        }
+    }
+
+    fn resume(&mut self) -> Result<ExecutionState> {
+        match self.execution {
+            None => self.store.resume(),
+            Some(execution) => self.store.resume_execution(execution),
+        }
+    }
+
+    /// The error for the event just taken not happening as logged
+    fn diverged(&self, kind: DivergenceKind) -> Error {
+        Error::Replay(Box::new(ReplayDivergence {
+            event: self.next - 1,
+            kind,
+        }))
+    }
+}
+
+fn same_values(a: &[RawValue], b: &[RawValue]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.to_bits() == b.to_bits())
+}
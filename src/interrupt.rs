@@ -0,0 +1,26 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Pauses a running [`crate::Store`] from another thread
+///
+/// The store polls the handle at loop back-edges and function entries, so an
+/// interrupt costs nothing on straight-line code. An interrupted invocation
+/// returns [`crate::ExecutionState::Interrupted`] and can be resumed or
+/// snapshotted like one that ran out of fuel. A request made while nothing is
+/// running is kept until the next invocation observes it.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle {
+    requested: Arc<AtomicBool>,
+}
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    /// Consumes a pending request, if any
+    #[inline]
+    pub(crate) fn take(&self) -> bool {
+        self.requested.load(Ordering::Relaxed) && self.requested.swap(false, Ordering::Relaxed)
+    }
+}
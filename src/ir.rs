@@ -1,3 +1,5 @@
+use std::{mem, slice};
+
 use crate::binary_grammar::{HeapType, ValueType};
 
 const _: () = assert!(std::mem::size_of::<Op>() <= 16);
@@ -13,10 +15,91 @@ pub struct CompiledFunction {
     pub ops: Vec<Op>,
     pub type_index: u32,
     pub num_args: u32,
+    /// Index in the module's function index space, `None` for synthetic
+    /// functions
+    pub func_idx: Option<u32>,
 
     // contains [args; num_args] [...local_types]
     pub local_types: Vec<ValueType>,
     pub(crate) max_stack_height: u32,
+    pub offsets: OffsetMap,
+}
+
+/// Maps ops back to the wasm instructions they were compiled from
+///
+/// Only pcs where the offset changes are stored. Ops are emitted in
+/// instruction order, so offsets never decrease as the pc grows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OffsetMap {
+    /// `(pc, offset)` pairs, sorted by pc and by offset
+    pub(crate) entries: Vec<(u32, u32)>,
+}
+
+impl OffsetMap {
+    pub(crate) fn from_ops(offsets: &[Option<u32>]) -> Self {
+        let mut entries: Vec<(u32, u32)> = Vec::new();
+        for (pc, offset) in offsets.iter().enumerate() {
+            let Some(offset) = *offset else {
+                continue;
+            };
+            if entries.last().is_none_or(|&(_, last)| last != offset) {
+                entries.push((pc as u32, offset));
+            }
+        }
+
+        Self { entries }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Byte offset in the module of the instruction that produced the op at
+    /// `pc`
+    pub fn wasm_offset(&self, pc: usize) -> Option<u32> {
+        let idx = self
+            .entries
+            .partition_point(|&(start, _)| start as usize <= pc)
+            .checked_sub(1)?;
+        Some(self.entries[idx].1)
+    }
+
+    /// The first op compiled from the instruction at `wasm_offset`, `None` if
+    /// the instruction was dropped or folded into its predecessor
+    pub fn pc(&self, wasm_offset: u32) -> Option<usize> {
+        let idx = self
+            .entries
+            .partition_point(|&(_, offset)| offset < wasm_offset);
+        match self.entries.get(idx) {
+            Some(&(pc, offset)) if offset == wasm_offset => Some(pc as usize),
+            _ => None,
+        }
+    }
+
+    /// Whether the op at `pc` is the first one of its instruction. Every op
+    /// counts when there are no offsets, as in synthetic functions
+    pub fn starts_instruction(&self, pc: usize) -> bool {
+        self.entries.is_empty()
+            || self
+                .entries
+                .binary_search_by_key(&pc, |&(start, _)| start as usize)
+                .is_ok()
+    }
+
+    /// Whether `wasm_offset` lies between the first and last instruction
+    pub fn contains(&self, wasm_offset: u32) -> bool {
+        match (self.entries.first(), self.entries.last()) {
+            (Some(&(_, first)), Some(&(_, last))) => (first..=last).contains(&wasm_offset),
+            _ => false,
+        }
+    }
+
+    /// `(pc, offset)` pairs for every instruction that produced ops
+    pub fn iter(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        self.entries
+            .iter()
+            .map(|&(pc, offset)| (pc as usize, offset))
+    }
 }
 
 #[repr(u16)]
@@ -907,6 +990,714 @@ pub enum Op {
 }
 
 impl Op {
+    /// The bytes of this op with its padding zeroed
+    ///
+    /// Padding is whatever happened to be in memory when the op was built,
+    /// so copying ops verbatim would leak it into snapshots and make
+    /// identical stores encode differently
+    pub(crate) fn canonical_bytes(&self) -> [u8; mem::size_of::<Self>()] {
+        fn field<T: Copy>(value: &T, copy: &mut impl FnMut(*const u8, usize)) {
+            copy(value as *const T as *const u8, mem::size_of::<T>());
+        }
+
+        let mut bytes = [0; mem::size_of::<Self>()];
+        let base = self as *const Self as usize;
+        let mut copy = |ptr: *const u8, len: usize| {
+            let offset = ptr as usize - base;
+            // SAFETY: `ptr` points at `len` initialized bytes inside `self`
+            let src = unsafe { slice::from_raw_parts(ptr, len) };
+            bytes[offset..offset + len].copy_from_slice(src);
+        };
+
+        // `repr(u16)` puts the tag first
+        copy(self as *const Self as *const u8, mem::size_of::<u16>());
+        match self {
+            Self::Jump { target, keep, drop }
+            | Self::JumpIf { target, keep, drop }
+            | Self::JumpIfNot { target, keep, drop }
+            | Self::BrOnNull { target, keep, drop }
+            | Self::BrOnNonNull { target, keep, drop }
+            | Self::I32EqZeroJumpIf { target, keep, drop }
+            | Self::I32EqZeroJumpIfNot { target, keep, drop }
+            | Self::I32EqJumpIf { target, keep, drop }
+            | Self::I32NeJumpIf { target, keep, drop }
+            | Self::I32LtSignedJumpIf { target, keep, drop }
+            | Self::I32LtUnsignedJumpIf { target, keep, drop }
+            | Self::I32GtSignedJumpIf { target, keep, drop }
+            | Self::I32GtUnsignedJumpIf { target, keep, drop }
+            | Self::I32LeSignedJumpIf { target, keep, drop }
+            | Self::I32LeUnsignedJumpIf { target, keep, drop }
+            | Self::I32GeSignedJumpIf { target, keep, drop }
+            | Self::I32GeUnsignedJumpIf { target, keep, drop }
+            | Self::I64EqZeroJumpIf { target, keep, drop }
+            | Self::I64EqJumpIf { target, keep, drop }
+            | Self::I64NeJumpIf { target, keep, drop }
+            | Self::I64LtSignedJumpIf { target, keep, drop }
+            | Self::I64LtUnsignedJumpIf { target, keep, drop }
+            | Self::I64GtSignedJumpIf { target, keep, drop }
+            | Self::I64GtUnsignedJumpIf { target, keep, drop }
+            | Self::I64LeSignedJumpIf { target, keep, drop }
+            | Self::I64LeUnsignedJumpIf { target, keep, drop }
+            | Self::I64GeSignedJumpIf { target, keep, drop }
+            | Self::I64GeUnsignedJumpIf { target, keep, drop }
+            | Self::F32EqJumpIf { target, keep, drop }
+            | Self::F32NeJumpIf { target, keep, drop }
+            | Self::F32LtJumpIf { target, keep, drop }
+            | Self::F32GtJumpIf { target, keep, drop }
+            | Self::F32LeJumpIf { target, keep, drop }
+            | Self::F32GeJumpIf { target, keep, drop }
+            | Self::F64EqJumpIf { target, keep, drop }
+            | Self::F64NeJumpIf { target, keep, drop }
+            | Self::F64LtJumpIf { target, keep, drop }
+            | Self::F64GtJumpIf { target, keep, drop }
+            | Self::F64LeJumpIf { target, keep, drop }
+            | Self::F64GeJumpIf { target, keep, drop } => {
+                field(target, &mut copy);
+                field(keep, &mut copy);
+                field(drop, &mut copy);
+            }
+            Self::JumpTable { index, keep } => {
+                field(index, &mut copy);
+                field(keep, &mut copy);
+            }
+            Self::Call { func_idx }
+            | Self::ReturnCall { func_idx }
+            | Self::RefFunc { func_idx } => {
+                field(func_idx, &mut copy);
+            }
+            Self::CallIndirect {
+                type_idx,
+                table_idx,
+            }
+            | Self::ReturnCallIndirect {
+                type_idx,
+                table_idx,
+            } => {
+                field(type_idx, &mut copy);
+                field(table_idx, &mut copy);
+            }
+            Self::CallRef { type_idx } | Self::ReturnCallRef { type_idx } => {
+                field(type_idx, &mut copy);
+            }
+            Self::I32Const { value } => {
+                field(value, &mut copy);
+            }
+            Self::I64Const { value } => {
+                field(value, &mut copy);
+            }
+            Self::F32Const { value } => {
+                field(value, &mut copy);
+            }
+            Self::F64Const { value } => {
+                field(value, &mut copy);
+            }
+            Self::V128Const { table_idx }
+            | Self::TableGet { table_idx }
+            | Self::TableSet { table_idx }
+            | Self::TableGrow { table_idx }
+            | Self::TableSize { table_idx }
+            | Self::TableFill { table_idx }
+            | Self::I8x16Shuffle { table_idx } => {
+                field(table_idx, &mut copy);
+            }
+            Self::LocalGet { local_idx }
+            | Self::LocalSet { local_idx }
+            | Self::LocalTee { local_idx }
+            | Self::LocalGetReturn { local_idx } => {
+                field(local_idx, &mut copy);
+            }
+            Self::GlobalGet { global_idx } | Self::GlobalSet { global_idx } => {
+                field(global_idx, &mut copy);
+            }
+            Self::RefNull(heap_type) => {
+                // `HeapType` is `repr(u8)`, so its tag is its first byte
+                copy(heap_type as *const HeapType as *const u8, 1);
+                if let HeapType::TypeIndex(idx) = heap_type {
+                    field(idx, &mut copy);
+                }
+            }
+            Self::Throw { tag_idx } => {
+                field(tag_idx, &mut copy);
+            }
+            Self::TableInit {
+                elem_idx,
+                table_idx,
+            } => {
+                field(elem_idx, &mut copy);
+                field(table_idx, &mut copy);
+            }
+            Self::ElemDrop { elem_idx } => {
+                field(elem_idx, &mut copy);
+            }
+            Self::TableCopy {
+                dst_table_idx,
+                src_table_idx,
+            } => {
+                field(dst_table_idx, &mut copy);
+                field(src_table_idx, &mut copy);
+            }
+            Self::I32Load { offset, memory }
+            | Self::I64Load { offset, memory }
+            | Self::F32Load { offset, memory }
+            | Self::F64Load { offset, memory }
+            | Self::I32Load8Signed { offset, memory }
+            | Self::I32Load8Unsigned { offset, memory }
+            | Self::I32Load16Signed { offset, memory }
+            | Self::I32Load16Unsigned { offset, memory }
+            | Self::I64Load8Signed { offset, memory }
+            | Self::I64Load8Unsigned { offset, memory }
+            | Self::I64Load16Signed { offset, memory }
+            | Self::I64Load16Unsigned { offset, memory }
+            | Self::I64Load32Signed { offset, memory }
+            | Self::I64Load32Unsigned { offset, memory }
+            | Self::I32Store { offset, memory }
+            | Self::I64Store { offset, memory }
+            | Self::F32Store { offset, memory }
+            | Self::F64Store { offset, memory }
+            | Self::I32Store8 { offset, memory }
+            | Self::I32Store16 { offset, memory }
+            | Self::I64Store8 { offset, memory }
+            | Self::I64Store16 { offset, memory }
+            | Self::I64Store32 { offset, memory }
+            | Self::V128Load { offset, memory }
+            | Self::V128Load8x8Signed { offset, memory }
+            | Self::V128Load8x8Unsigned { offset, memory }
+            | Self::V128Load16x4Signed { offset, memory }
+            | Self::V128Load16x4Unsigned { offset, memory }
+            | Self::V128Load32x2Signed { offset, memory }
+            | Self::V128Load32x2Unsigned { offset, memory }
+            | Self::V128Load8Splat { offset, memory }
+            | Self::V128Load16Splat { offset, memory }
+            | Self::V128Load32Splat { offset, memory }
+            | Self::V128Load64Splat { offset, memory }
+            | Self::V128Load32Zero { offset, memory }
+            | Self::V128Load64Zero { offset, memory }
+            | Self::V128Store { offset, memory } => {
+                field(offset, &mut copy);
+                field(memory, &mut copy);
+            }
+            Self::MemorySize { memory_idx }
+            | Self::MemoryGrow { memory_idx }
+            | Self::MemoryFill { memory_idx } => {
+                field(memory_idx, &mut copy);
+            }
+            Self::MemoryInit {
+                data_idx,
+                memory_idx,
+            } => {
+                field(data_idx, &mut copy);
+                field(memory_idx, &mut copy);
+            }
+            Self::DataDrop { data_idx } => {
+                field(data_idx, &mut copy);
+            }
+            Self::MemoryCopy {
+                dst_memory_idx,
+                src_memory_idx,
+            } => {
+                field(dst_memory_idx, &mut copy);
+                field(src_memory_idx, &mut copy);
+            }
+            Self::V128Load8Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Load16Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Load32Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Load64Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Store8Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Store16Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Store32Lane {
+                offset,
+                memory,
+                lane,
+            }
+            | Self::V128Store64Lane {
+                offset,
+                memory,
+                lane,
+            } => {
+                field(offset, &mut copy);
+                field(memory, &mut copy);
+                field(lane, &mut copy);
+            }
+            Self::I8x16ExtractLaneSigned(lane)
+            | Self::I8x16ExtractLaneUnsigned(lane)
+            | Self::I8x16ReplaceLane(lane)
+            | Self::I16x8ExtractLaneSigned(lane)
+            | Self::I16x8ExtractLaneUnsigned(lane)
+            | Self::I16x8ReplaceLane(lane)
+            | Self::I32x4ExtractLane(lane)
+            | Self::I32x4ReplaceLane(lane)
+            | Self::I64x2ExtractLane(lane)
+            | Self::I64x2ReplaceLane(lane)
+            | Self::F32x4ExtractLane(lane)
+            | Self::F32x4ReplaceLane(lane)
+            | Self::F64x2ExtractLane(lane)
+            | Self::F64x2ReplaceLane(lane) => {
+                field(lane, &mut copy);
+            }
+            Self::LocalGet2 {
+                local_idx_a,
+                local_idx_b,
+            } => {
+                field(local_idx_a, &mut copy);
+                field(local_idx_b, &mut copy);
+            }
+            Self::Unreachable
+            | Self::Nop
+            | Self::Return
+            | Self::Drop
+            | Self::Select
+            | Self::RefIsNull
+            | Self::RefEq
+            | Self::RefAsNonNull
+            | Self::ThrowRef
+            | Self::I32EqZero
+            | Self::I32Eq
+            | Self::I32Ne
+            | Self::I32LtSigned
+            | Self::I32LtUnsigned
+            | Self::I32GtSigned
+            | Self::I32GtUnsigned
+            | Self::I32LeSigned
+            | Self::I32LeUnsigned
+            | Self::I32GeSigned
+            | Self::I32GeUnsigned
+            | Self::I64EqZero
+            | Self::I64Eq
+            | Self::I64Ne
+            | Self::I64LtSigned
+            | Self::I64LtUnsigned
+            | Self::I64GtSigned
+            | Self::I64GtUnsigned
+            | Self::I64LeSigned
+            | Self::I64LeUnsigned
+            | Self::I64GeSigned
+            | Self::I64GeUnsigned
+            | Self::F32Eq
+            | Self::F32Ne
+            | Self::F32Lt
+            | Self::F32Gt
+            | Self::F32Le
+            | Self::F32Ge
+            | Self::F64Eq
+            | Self::F64Ne
+            | Self::F64Lt
+            | Self::F64Gt
+            | Self::F64Le
+            | Self::F64Ge
+            | Self::I32CountLeadingZeros
+            | Self::I32CountTrailingZeros
+            | Self::I32PopCount
+            | Self::I32Add
+            | Self::I32Sub
+            | Self::I32Mul
+            | Self::I32DivSigned
+            | Self::I32DivUnsigned
+            | Self::I32RemainderSigned
+            | Self::I32RemainderUnsigned
+            | Self::I32And
+            | Self::I32Or
+            | Self::I32Xor
+            | Self::I32Shl
+            | Self::I32ShrSigned
+            | Self::I32ShrUnsigned
+            | Self::I32RotateLeft
+            | Self::I32RotateRight
+            | Self::I64CountLeadingZeros
+            | Self::I64CountTrailingZeros
+            | Self::I64PopCount
+            | Self::I64Add
+            | Self::I64Sub
+            | Self::I64Mul
+            | Self::I64DivSigned
+            | Self::I64DivUnsigned
+            | Self::I64RemainderSigned
+            | Self::I64RemainderUnsigned
+            | Self::I64And
+            | Self::I64Or
+            | Self::I64Xor
+            | Self::I64Shl
+            | Self::I64ShrSigned
+            | Self::I64ShrUnsigned
+            | Self::I64RotateLeft
+            | Self::I64RotateRight
+            | Self::F32Abs
+            | Self::F32Neg
+            | Self::F32Ceil
+            | Self::F32Floor
+            | Self::F32Trunc
+            | Self::F32Nearest
+            | Self::F32Sqrt
+            | Self::F32Add
+            | Self::F32Sub
+            | Self::F32Mul
+            | Self::F32Div
+            | Self::F32Min
+            | Self::F32Max
+            | Self::F32CopySign
+            | Self::F64Abs
+            | Self::F64Neg
+            | Self::F64Ceil
+            | Self::F64Floor
+            | Self::F64Trunc
+            | Self::F64Nearest
+            | Self::F64Sqrt
+            | Self::F64Add
+            | Self::F64Sub
+            | Self::F64Mul
+            | Self::F64Div
+            | Self::F64Min
+            | Self::F64Max
+            | Self::F64CopySign
+            | Self::I32WrapI64
+            | Self::I32TruncF32Signed
+            | Self::I32TruncF32Unsigned
+            | Self::I32TruncF64Signed
+            | Self::I32TruncF64Unsigned
+            | Self::I64ExtendI32Signed
+            | Self::I64ExtendI32Unsigned
+            | Self::I64TruncF32Signed
+            | Self::I64TruncF32Unsigned
+            | Self::I64TruncF64Signed
+            | Self::I64TruncF64Unsigned
+            | Self::F32ConvertI32Signed
+            | Self::F32ConvertI32Unsigned
+            | Self::F32ConvertI64Signed
+            | Self::F32ConvertI64Unsigned
+            | Self::F32DemoteF64
+            | Self::F64ConvertI32Signed
+            | Self::F64ConvertI32Unsigned
+            | Self::F64ConvertI64Signed
+            | Self::F64ConvertI64Unsigned
+            | Self::F64PromoteF32
+            | Self::I32ReinterpretF32
+            | Self::I64ReinterpretF64
+            | Self::F32ReinterpretI32
+            | Self::F64ReinterpretI64
+            | Self::I32Extend8Signed
+            | Self::I32Extend16Signed
+            | Self::I64Extend8Signed
+            | Self::I64Extend16Signed
+            | Self::I64Extend32Signed
+            | Self::I32TruncSaturatedF32Signed
+            | Self::I32TruncSaturatedF32Unsigned
+            | Self::I32TruncSaturatedF64Signed
+            | Self::I32TruncSaturatedF64Unsigned
+            | Self::I64TruncSaturatedF32Signed
+            | Self::I64TruncSaturatedF32Unsigned
+            | Self::I64TruncSaturatedF64Signed
+            | Self::I64TruncSaturatedF64Unsigned
+            | Self::I8x16Swizzle
+            | Self::I8x16Splat
+            | Self::I16x8Splat
+            | Self::I32x4Splat
+            | Self::I64x2Splat
+            | Self::F32x4Splat
+            | Self::F64x2Splat
+            | Self::I8x16Eq
+            | Self::I8x16Ne
+            | Self::I8x16LtSigned
+            | Self::I8x16LtUnsigned
+            | Self::I8x16GtSigned
+            | Self::I8x16GtUnsigned
+            | Self::I8x16LeSigned
+            | Self::I8x16LeUnsigned
+            | Self::I8x16GeSigned
+            | Self::I8x16GeUnsigned
+            | Self::I16x8Eq
+            | Self::I16x8Ne
+            | Self::I16x8LtSigned
+            | Self::I16x8LtUnsigned
+            | Self::I16x8GtSigned
+            | Self::I16x8GtUnsigned
+            | Self::I16x8LeSigned
+            | Self::I16x8LeUnsigned
+            | Self::I16x8GeSigned
+            | Self::I16x8GeUnsigned
+            | Self::I32x4Eq
+            | Self::I32x4Ne
+            | Self::I32x4LtSigned
+            | Self::I32x4LtUnsigned
+            | Self::I32x4GtSigned
+            | Self::I32x4GtUnsigned
+            | Self::I32x4LeSigned
+            | Self::I32x4LeUnsigned
+            | Self::I32x4GeSigned
+            | Self::I32x4GeUnsigned
+            | Self::I64x2Eq
+            | Self::I64x2Ne
+            | Self::I64x2LtSigned
+            | Self::I64x2GtSigned
+            | Self::I64x2LeSigned
+            | Self::I64x2GeSigned
+            | Self::F32x4Eq
+            | Self::F32x4Ne
+            | Self::F32x4Lt
+            | Self::F32x4Gt
+            | Self::F32x4Le
+            | Self::F32x4Ge
+            | Self::F64x2Eq
+            | Self::F64x2Ne
+            | Self::F64x2Lt
+            | Self::F64x2Gt
+            | Self::F64x2Le
+            | Self::F64x2Ge
+            | Self::V128Not
+            | Self::V128And
+            | Self::V128AndNot
+            | Self::V128Or
+            | Self::V128Xor
+            | Self::V128BitSelect
+            | Self::V128AnyTrue
+            | Self::I8x16Abs
+            | Self::I8x16Neg
+            | Self::I8x16PopCount
+            | Self::I8x16AllTrue
+            | Self::I8x16BitMask
+            | Self::I8x16NarrowI16x8Signed
+            | Self::I8x16NarrowI16x8Unsigned
+            | Self::I8x16Shl
+            | Self::I8x16ShrSigned
+            | Self::I8x16ShrUnsigned
+            | Self::I8x16Add
+            | Self::I8x16AddSaturatedSigned
+            | Self::I8x16AddSaturatedUnsigned
+            | Self::I8x16Sub
+            | Self::I8x16SubSaturatedSigned
+            | Self::I8x16SubSaturatedUnsigned
+            | Self::I8x16MinSigned
+            | Self::I8x16MinUnsigned
+            | Self::I8x16MaxSigned
+            | Self::I8x16MaxUnsigned
+            | Self::I8x16AvgRangeUnsigned
+            | Self::I16x8ExtAddPairWiseI8x16Signed
+            | Self::I16x8ExtAddPairWiseI8x16Unsigned
+            | Self::I16x8Abs
+            | Self::I16x8Neg
+            | Self::I16xQ15MulRangeSaturatedSigned
+            | Self::I16x8AllTrue
+            | Self::I16x8BitMask
+            | Self::I16x8NarrowI32x4Signed
+            | Self::I16x8NarrowI32x4Unsigned
+            | Self::I16x8ExtendLowI8x16Unsigned
+            | Self::I16x8ExtendHighI8x16Unsigned
+            | Self::I16x8ExtendLowI8x16Signed
+            | Self::I16x8ExtendHighI8x16Signed
+            | Self::I16x8Shl
+            | Self::I16x8ShrSigned
+            | Self::I16x8ShrUnsigned
+            | Self::I16x8Add
+            | Self::I16x8AddSaturatedSigned
+            | Self::I16x8AddSaturatedUnsigned
+            | Self::I16x8Sub
+            | Self::I16x8SubSaturatedSigned
+            | Self::I16x8SubSaturatedUnsigned
+            | Self::I16x8Mul
+            | Self::I16x8MinSigned
+            | Self::I16x8MinUnsigned
+            | Self::I16x8MaxSigned
+            | Self::I16x8MaxUnsigned
+            | Self::I16x8AvgRangeUnsigned
+            | Self::I16x8ExtMulLowI8x16Signed
+            | Self::I16x8ExtMulHighI8x16Signed
+            | Self::I16x8ExtMulLowI8x16Unsigned
+            | Self::I16x8ExtMulHighI8x16Unsigned
+            | Self::I32x4ExtAddPairWiseI16x8Signed
+            | Self::I32x4ExtAddPairWiseI16x8Unsigned
+            | Self::I32x4Abs
+            | Self::I32x4Neg
+            | Self::I32x4AllTrue
+            | Self::I32x4BitMask
+            | Self::I32x4ExtendLowI16x8Signed
+            | Self::I32x4ExtendHighI16x8Signed
+            | Self::I32x4ExtendLowI16x8Unsigned
+            | Self::I32x4ExtendHighI16x8Unsigned
+            | Self::I32x4Shl
+            | Self::I32x4ShrSigned
+            | Self::I32x4ShrUnsigned
+            | Self::I32x4Add
+            | Self::I32x4Sub
+            | Self::I32x4Mul
+            | Self::I32x4MinSigned
+            | Self::I32x4MinUnsigned
+            | Self::I32x4MaxSigned
+            | Self::I32x4MaxUnsigned
+            | Self::I32x4DotI16x8Signed
+            | Self::I32x4ExtMulLowI16x8Signed
+            | Self::I32x4ExtMulHighI16x8Signed
+            | Self::I32x4ExtMulLowI16x8Unsigned
+            | Self::I32x4ExtMulHighI16x8Unsigned
+            | Self::I64x2Abs
+            | Self::I64x2Neg
+            | Self::I64x2AllTrue
+            | Self::I64x2BitMask
+            | Self::I64x2ExtendLowI32x4Signed
+            | Self::I64x2ExtendHighI32x4Signed
+            | Self::I64x2ExtendLowI32x4Unsigned
+            | Self::I64x2ExtendHighI32x4Unsigned
+            | Self::I64x2Shl
+            | Self::I64x2ShrSigned
+            | Self::I64x2ShrUnsigned
+            | Self::I64x2Add
+            | Self::I64x2Sub
+            | Self::I64x2Mul
+            | Self::I64x2ExtMulLowI32x4Signed
+            | Self::I64x2ExtMulHighI32x4Signed
+            | Self::I64x2ExtMulLowI32x4Unsigned
+            | Self::I64x2ExtMulHighI32x4Unsigned
+            | Self::F32x4Ceil
+            | Self::F32x4Floor
+            | Self::F32x4Trunc
+            | Self::F32x4Nearest
+            | Self::F32x4Abs
+            | Self::F32x4Neg
+            | Self::F32x4Sqrt
+            | Self::F32x4Add
+            | Self::F32x4Sub
+            | Self::F32x4Mul
+            | Self::F32x4Div
+            | Self::F32x4Min
+            | Self::F32x4Max
+            | Self::F32x4PMin
+            | Self::F32x4PMax
+            | Self::F64x2Ceil
+            | Self::F64x2Floor
+            | Self::F64x2Trunc
+            | Self::F64x2Nearest
+            | Self::F64x2Abs
+            | Self::F64x2Neg
+            | Self::F64x2Sqrt
+            | Self::F64x2Add
+            | Self::F64x2Sub
+            | Self::F64x2Mul
+            | Self::F64x2Div
+            | Self::F64x2Min
+            | Self::F64x2Max
+            | Self::F64x2PMin
+            | Self::F64x2PMax
+            | Self::I32x4TruncSaturatedF32x4Signed
+            | Self::I32x4TruncSaturatedF32x4Unsigned
+            | Self::F32x4ConvertI32x4Signed
+            | Self::F32x4ConvertI32x4Unsigned
+            | Self::I32x4TruncSaturatedF64x2SignedZero
+            | Self::I32x4TruncSaturatedF64x2UnsignedZero
+            | Self::F64x2ConvertLowI32x4Signed
+            | Self::F64x2ConvertLowI32x4Unsigned
+            | Self::F32x4DemoteF64x2Zero
+            | Self::F64x2PromoteLowF32x4
+            | Self::I8x16RelaxedSwizzle
+            | Self::I32x4RelaxedTruncF32x4Signed
+            | Self::I32x4RelaxedTruncF32x4Unsigned
+            | Self::I32x4RelaxedTruncF64x2SignedZero
+            | Self::I32x4RelaxedTruncF64x2UnsignedZero
+            | Self::F32x4RelaxedMadd
+            | Self::F32x4RelaxedNmadd
+            | Self::F64x2RelaxedMadd
+            | Self::F64x2RelaxedNmadd
+            | Self::I8x16RelaxedLaneselect
+            | Self::I16x8RelaxedLaneselect
+            | Self::I32x4RelaxedLaneselect
+            | Self::I64x2RelaxedLaneselect
+            | Self::F32x4RelaxedMin
+            | Self::F32x4RelaxedMax
+            | Self::F64x2RelaxedMin
+            | Self::F64x2RelaxedMax
+            | Self::I16x8RelaxedQ15mulrSigned
+            | Self::I16x8RelaxedDotI8x16I7x16Signed
+            | Self::I32x4RelaxedDotI8x16I7x16AddSigned => {}
+        }
+        bytes
+    }
+}
+
+impl Op {
+    /// The memory, static offset and width of a scalar load or store, and
+    /// whether it writes
+    pub const fn memory_access(&self) -> Option<(u32, u32, u64, bool)> {
+        let (offset, memory, width, write) = match *self {
+            Self::I32Load8Signed { offset, memory }
+            | Self::I32Load8Unsigned { offset, memory }
+            | Self::I64Load8Signed { offset, memory }
+            | Self::I64Load8Unsigned { offset, memory } => (offset, memory, 1, false),
+            Self::I32Load16Signed { offset, memory }
+            | Self::I32Load16Unsigned { offset, memory }
+            | Self::I64Load16Signed { offset, memory }
+            | Self::I64Load16Unsigned { offset, memory } => (offset, memory, 2, false),
+            Self::I32Load { offset, memory }
+            | Self::F32Load { offset, memory }
+            | Self::I64Load32Signed { offset, memory }
+            | Self::I64Load32Unsigned { offset, memory } => (offset, memory, 4, false),
+            Self::I64Load { offset, memory } | Self::F64Load { offset, memory } => {
+                (offset, memory, 8, false)
+            }
+            Self::I32Store8 { offset, memory } | Self::I64Store8 { offset, memory } => {
+                (offset, memory, 1, true)
+            }
+            Self::I32Store16 { offset, memory } | Self::I64Store16 { offset, memory } => {
+                (offset, memory, 2, true)
+            }
+            Self::I32Store { offset, memory }
+            | Self::F32Store { offset, memory }
+            | Self::I64Store32 { offset, memory } => (offset, memory, 4, true),
+            Self::I64Store { offset, memory } | Self::F64Store { offset, memory } => {
+                (offset, memory, 8, true)
+            }
+            _ => return None,
+        };
+
+        Some((memory, offset, width, write))
+    }
+
+    /// Relaxed SIMD ops, whose results may differ between hosts
+    pub const fn is_relaxed_simd(&self) -> bool {
+        matches!(
+            self,
+            Self::I8x16RelaxedSwizzle
+                | Self::I32x4RelaxedTruncF32x4Signed
+                | Self::I32x4RelaxedTruncF32x4Unsigned
+                | Self::I32x4RelaxedTruncF64x2SignedZero
+                | Self::I32x4RelaxedTruncF64x2UnsignedZero
+                | Self::F32x4RelaxedMadd
+                | Self::F32x4RelaxedNmadd
+                | Self::F64x2RelaxedMadd
+                | Self::F64x2RelaxedNmadd
+                | Self::I8x16RelaxedLaneselect
+                | Self::I16x8RelaxedLaneselect
+                | Self::I32x4RelaxedLaneselect
+                | Self::I64x2RelaxedLaneselect
+                | Self::F32x4RelaxedMin
+                | Self::F32x4RelaxedMax
+                | Self::F64x2RelaxedMin
+                | Self::F64x2RelaxedMax
+                | Self::I16x8RelaxedQ15mulrSigned
+                | Self::I16x8RelaxedDotI8x16I7x16Signed
+                | Self::I32x4RelaxedDotI8x16I7x16AddSigned
+        )
+    }
+
     pub const fn jump_target(&self) -> Option<u32> {
         match self {
             Self::Jump { target, .. }
@@ -1,5 +1,5 @@
-use crate::error::{Error, Result};
-use crate::{ensure, parse_err};
+use crate::ensure;
+use crate::error::{Error, ParseError, ParseErrorKind, Result};
 
 /// The maximum length of a leb128-encoded 32-bit integer
 pub const MAX_LEB128_LEN_32: usize = 5;
@@ -12,11 +12,26 @@ fn size_u32(x: u32) -> usize {
     ((9 * bits + 64) / 64) as usize
 }
 
+/// Error offsets are relative to the start of the encoded integer
+const fn invalid(offset: usize) -> Error {
+    Error::Parse(ParseError::new(offset, ParseErrorKind::InvalidLeb128))
+}
+
+/// A buffer ran out mid-integer: either it ended early, or the encoding is
+/// longer than `max_len`
+const fn unterminated(buf: &[u8], max_len: usize) -> Error {
+    if buf.len() < max_len {
+        Error::Parse(ParseError::new(buf.len(), ParseErrorKind::UnexpectedEof))
+    } else {
+        invalid(max_len - 1)
+    }
+}
+
 #[inline]
 pub fn write_u32(buf: &mut [u8], mut x: u32) -> Result<usize> {
     ensure!(
         size_u32(x) <= buf.len(),
-        Error::Parse("The number being read is too large for the provided buffer.".into())
+        Error::Parse(ParseError::new(buf.len(), ParseErrorKind::UnexpectedEof))
     );
 
     for (i, curr_byte) in buf.iter_mut().enumerate().take(MAX_LEB128_LEN_32) {
@@ -31,9 +46,7 @@ pub fn write_u32(buf: &mut [u8], mut x: u32) -> Result<usize> {
         }
     }
 
-    Err(Error::Parse(
-        "The number being encoded exceeds the maximum representable length.".into(),
-    ))
+    Err(invalid(MAX_LEB128_LEN_32))
 }
 
 #[inline]
@@ -42,16 +55,10 @@ pub fn read_u32(buf: &[u8]) -> Result<(u32, usize)> {
     let mut s: usize = 0;
 
     for (i, &b) in buf.iter().enumerate() {
-        ensure!(
-            i < MAX_LEB128_LEN_32,
-            Error::Parse("The number being decoded exceeds the maximum length of a leb128-encoded 32-bit integer.".into())
-        );
+        ensure!(i < MAX_LEB128_LEN_32, invalid(i));
 
         if b < 0x80 {
-            ensure!(
-                i != MAX_LEB128_LEN_32 || b <= 1,
-                Error::Parse("Invalid final byte for 32-bit leb128 decoding.".into())
-            );
+            ensure!(i != MAX_LEB128_LEN_32 || b <= 1, invalid(i));
 
             return Ok((x | (b as u32) << s, i + 1));
         }
@@ -60,9 +67,7 @@ pub fn read_u32(buf: &[u8]) -> Result<(u32, usize)> {
         s += 7
     }
 
-    parse_err!(
-        "The number being decoded exceeds the maximum length of a leb128-encoded 32-bit integer."
-    )
+    Err(unterminated(buf, MAX_LEB128_LEN_32))
 }
 
 #[inline]
@@ -71,7 +76,7 @@ pub fn write_i32(buf: &mut [u8], mut x: i32) -> Result<usize> {
     loop {
         ensure!(
             i < buf.len() && i < MAX_LEB128_LEN_32,
-            Error::Parse("buffer too small for signed LEB128 i32".into())
+            Error::Parse(ParseError::new(i, ParseErrorKind::UnexpectedEof))
         );
         let mut byte = (x & 0x7F) as u8;
         x >>= 7;
@@ -93,10 +98,7 @@ pub fn read_i32(buf: &[u8]) -> Result<(i32, usize)> {
     let mut shift: u32 = 0;
 
     for (i, &byte) in buf.iter().enumerate() {
-        ensure!(
-            i < MAX_LEB128_LEN_32,
-            Error::Parse("The number being decoded exceeds the maximum length of a signed leb128-encoded 32-bit integer.".into())
-        );
+        ensure!(i < MAX_LEB128_LEN_32, invalid(i));
 
         result |= ((byte & 0x7F) as i32) << shift;
         shift += 7;
@@ -110,7 +112,7 @@ pub fn read_i32(buf: &[u8]) -> Result<(i32, usize)> {
         }
     }
 
-    parse_err!("Unterminated signed leb128-encoded 32-bit integer.")
+    Err(unterminated(buf, MAX_LEB128_LEN_32))
 }
 
 #[inline]
@@ -119,16 +121,10 @@ pub fn read_u64(buf: &[u8]) -> Result<(u64, usize)> {
     let mut s: usize = 0;
 
     for (i, &b) in buf.iter().enumerate() {
-        ensure!(
-            i < MAX_LEB128_LEN_64,
-            Error::Parse("The number being decoded exceeds the maximum length of a leb128-encoded 64-bit integer.".into())
-        );
+        ensure!(i < MAX_LEB128_LEN_64, invalid(i));
 
         if b < 0x80 {
-            ensure!(
-                i != MAX_LEB128_LEN_64 || b <= 1,
-                Error::Parse("Invalid final byte for 64-bit leb128 decoding.".into())
-            );
+            ensure!(i != MAX_LEB128_LEN_64 || b <= 1, invalid(i));
 
             return Ok((x | (b as u64) << s, i + 1));
         }
@@ -137,9 +133,7 @@ pub fn read_u64(buf: &[u8]) -> Result<(u64, usize)> {
         s += 7
     }
 
-    parse_err!(
-        "The number being decoded exceeds the maximum length of a leb128-encoded 64-bit integer."
-    )
+    Err(unterminated(buf, MAX_LEB128_LEN_64))
 }
 
 #[inline]
@@ -148,10 +142,7 @@ pub fn read_i64(buf: &[u8]) -> Result<(i64, usize)> {
     let mut shift: u32 = 0;
 
     for (i, &byte) in buf.iter().enumerate() {
-        ensure!(
-            i < MAX_LEB128_LEN_64,
-            Error::Parse("The number being decoded exceeds the maximum length of a signed leb128-encoded 64-bit integer.".into())
-        );
+        ensure!(i < MAX_LEB128_LEN_64, invalid(i));
 
         result |= ((byte & 0x7F) as i64) << shift;
         shift += 7;
@@ -164,7 +155,7 @@ pub fn read_i64(buf: &[u8]) -> Result<(i64, usize)> {
         }
     }
 
-    parse_err!("Unterminated signed leb128-encoded 64-bit integer.")
+    Err(unterminated(buf, MAX_LEB128_LEN_64))
 }
 
 #[cfg(all(test, not(feature = "spec-tests")))]
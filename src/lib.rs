@@ -1,19 +1,48 @@
 #![warn(clippy::nursery)]
 
+mod backtrace;
 mod binary_grammar;
 pub mod compiler;
+mod coverage;
+mod dwarf;
 mod error;
 mod execution_grammar;
+mod fuel;
+mod gdbstub;
+mod host_log;
+mod interrupt;
 pub mod ir;
 pub mod leb128;
+mod limits;
+mod linker;
 mod module;
 pub mod parser;
+mod profiler;
+mod recording;
 pub mod snapshot;
 mod store;
+mod trace;
 pub mod value_stack;
+mod vfs;
+mod wasi;
+mod watchpoint;
 
+pub use backtrace::*;
 pub use binary_grammar::*;
+pub use coverage::{BlockCoverage, Coverage, FunctionCoverage};
+pub use dwarf::*;
 pub use error::*;
 pub use execution_grammar::*;
+pub use fuel::*;
+pub use gdbstub::*;
+pub use host_log::*;
+pub use interrupt::*;
+pub use limits::*;
+pub use linker::*;
 pub use module::*;
+pub use profiler::{FunctionCost, Profile, ProfileFrame};
 pub use store::*;
+pub use trace::*;
+pub use vfs::Vfs;
+pub use wasi::{Wasi, WASI_MODULE};
+pub use watchpoint::*;
@@ -0,0 +1,74 @@
+use crate::store::{MAX_CALL_DEPTH, MAX_STACK_VALUES};
+
+/// Caps on the resources a [`crate::Store`] hands out to guests
+///
+/// Memory and table limits are store-wide: `max_memory_bytes` bounds the sum of
+/// every linear memory and `max_table_elements` the sum of every table. They
+/// are consulted on allocation, instantiation, `memory.grow` and `table.grow`,
+/// and are recorded in snapshots so a restored store enforces the same policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoreLimits {
+    pub max_memory_bytes: u64,
+    pub max_table_elements: u64,
+    pub max_instances: usize,
+    pub max_call_depth: usize,
+    /// Upper bound on the operand stack, in values. The stack starts small and
+    /// grows on demand up to this size
+    pub max_stack_values: usize,
+    /// Trap with [`crate::Trap::ResourceLimitExceeded`] instead of returning
+    /// -1 when a limit refuses `memory.grow` or `table.grow`
+    pub trap_on_grow_failure: bool,
+}
+
+impl Default for StoreLimits {
+    fn default() -> Self {
+        Self {
+            max_memory_bytes: u64::MAX,
+            max_table_elements: u64::MAX,
+            max_instances: usize::MAX,
+            max_call_depth: MAX_CALL_DEPTH,
+            max_stack_values: MAX_STACK_VALUES,
+            trap_on_grow_failure: false,
+        }
+    }
+}
+
+impl StoreLimits {
+    pub const fn memory_bytes(mut self, max: u64) -> Self {
+        self.max_memory_bytes = max;
+        self
+    }
+
+    pub const fn table_elements(mut self, max: u64) -> Self {
+        self.max_table_elements = max;
+        self
+    }
+
+    pub const fn instances(mut self, max: usize) -> Self {
+        self.max_instances = max;
+        self
+    }
+
+    pub const fn call_depth(mut self, max: usize) -> Self {
+        self.max_call_depth = max;
+        self
+    }
+
+    pub const fn stack_values(mut self, max: usize) -> Self {
+        self.max_stack_values = max;
+        self
+    }
+
+    pub const fn trap_on_grow_failure(mut self, trap: bool) -> Self {
+        self.trap_on_grow_failure = trap;
+        self
+    }
+
+    pub(crate) const fn allows_memory(&self, current: u64, requested: u64) -> bool {
+        current.saturating_add(requested) <= self.max_memory_bytes
+    }
+
+    pub(crate) const fn allows_table_elements(&self, current: u64, requested: u64) -> bool {
+        current.saturating_add(requested) <= self.max_table_elements
+    }
+}
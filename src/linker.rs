@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use crate::binary_grammar::{
+    CompositeType, FunctionType, GlobalType, HeapType, ImportDeclaration, ImportDescription, Limit,
+    MemoryType, RefType, TableType, ValueType,
+};
+use crate::error::{ExternType, LinkErrorKind, Result};
+use crate::execution_grammar::{ExternalValue, FunctionInstance, RawValue, Ref};
+use crate::store::{Instance, Store, PAGE_SIZE};
+use crate::wasi::{self, Wasi, WASI_MODULE};
+use crate::{link_err, Module};
+
+/// Resolves a [`Module`]'s imports by `module::name` instead of by position
+///
+/// Definitions are either allocated in a [`Store`] by the linker itself (host
+/// functions, memories, tables and globals) or taken from the exports of an
+/// already instantiated module. Later definitions shadow earlier ones under the
+/// same name, the same way `(register ...)` behaves in the spec test suite.
+#[derive(Debug, Default, Clone)]
+pub struct Linker {
+    definitions: HashMap<(String, String), ExternalValue>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(&mut self, module: &str, name: &str, value: ExternalValue) -> &mut Self {
+        self.definitions
+            .insert((module.to_owned(), name.to_owned()), value);
+        self
+    }
+
+    /// Defines a host function. Calls to it suspend the guest with
+    /// [`crate::ExecutionState::Suspended`]
+    pub fn func(
+        &mut self,
+        store: &mut Store,
+        module: &str,
+        name: &str,
+        function_type: FunctionType,
+    ) -> &mut Self {
+        let addr = store.allocate_host_function(module, name, function_type);
+        self.define(module, name, ExternalValue::Function { addr })
+    }
+
+    /// Defines every `wasi_snapshot_preview1` function, answered by `wasi`
+    /// without suspending the guest
+    pub fn wasi(&mut self, store: &mut Store, wasi: Wasi) -> &mut Self {
+        for (name, function_type) in wasi::function_types() {
+            self.func(store, WASI_MODULE, name, function_type);
+        }
+        store.set_wasi(wasi);
+        self
+    }
+
+    pub fn memory(
+        &mut self,
+        store: &mut Store,
+        module: &str,
+        name: &str,
+        memory_type: MemoryType,
+    ) -> Result<&mut Self> {
+        let addr = store.allocate_memory(memory_type)?;
+        Ok(self.define(module, name, ExternalValue::Memory { addr }))
+    }
+
+    pub fn table(
+        &mut self,
+        store: &mut Store,
+        module: &str,
+        name: &str,
+        table_type: TableType,
+        initial_ref: Ref,
+    ) -> Result<&mut Self> {
+        let addr = store.allocate_table(table_type, initial_ref)?;
+        Ok(self.define(module, name, ExternalValue::Table { addr }))
+    }
+
+    pub fn global(
+        &mut self,
+        store: &mut Store,
+        module: &str,
+        name: &str,
+        global_type: GlobalType,
+        value: RawValue,
+    ) -> &mut Self {
+        let addr = store.allocate_global(global_type, value);
+        self.define(module, name, ExternalValue::Global { addr })
+    }
+
+    /// Registers every export of `instance` under `module`
+    pub fn instance(&mut self, store: &Store, module: &str, instance: Instance) -> &mut Self {
+        for export in store.exports(instance) {
+            self.define(module, &export.name, export.value.clone());
+        }
+        self
+    }
+
+    pub fn get(&self, module: &str, name: &str) -> Option<&ExternalValue> {
+        self.definitions.get(&(module.to_owned(), name.to_owned()))
+    }
+
+    /// Looks up and type checks every import of `module`, in declaration order
+    pub fn resolve(&self, store: &Store, module: &Module) -> Result<Vec<ExternalValue>> {
+        module
+            .import_declarations()
+            .iter()
+            .map(|import| {
+                let Some(value) = self.get(&import.module, &import.name) else {
+                    link_err!(import, LinkErrorKind::UnknownImport);
+                };
+                Self::check_import(store, module, import, value)?;
+                Ok(value.clone())
+            })
+            .collect()
+    }
+
+    pub fn instantiate(&self, store: &mut Store, module: &Module) -> Result<Instance> {
+        let imports = self.resolve(store, module)?;
+        store.instantiate(module, imports)
+    }
+
+    /// Checks that `value` matches the external type declared by `import`
+    pub(crate) fn check_import(
+        store: &Store,
+        module: &Module,
+        import: &ImportDeclaration,
+        value: &ExternalValue,
+    ) -> Result<()> {
+        let expected = import_type(module, import)?;
+        let actual = extern_type(store, import, value)?;
+
+        let matches = match (&expected, &actual) {
+            (ExternType::Func(expected), ExternType::Func(actual))
+            | (ExternType::Tag(expected), ExternType::Tag(actual)) => {
+                function_types_match(expected, actual)
+            }
+            (ExternType::Table(expected), ExternType::Table(actual)) => {
+                expected.addr_type == actual.addr_type
+                    && ref_types_match(
+                        expected.element_reference_type,
+                        actual.element_reference_type,
+                    )
+                    && limits_match(&actual.limit, &expected.limit)
+            }
+            (ExternType::Memory(expected), ExternType::Memory(actual)) => {
+                expected.addr_type == actual.addr_type
+                    && limits_match(&actual.limit, &expected.limit)
+            }
+            (ExternType::Global(expected), ExternType::Global(actual)) => {
+                expected.mutability == actual.mutability
+                    && value_types_match(&expected.value_type, &actual.value_type)
+            }
+            _ => false,
+        };
+
+        if !matches {
+            link_err!(import, LinkErrorKind::IncompatibleType { expected, actual });
+        }
+
+        Ok(())
+    }
+}
+
+fn function_type_at<'a>(
+    module: &'a Module,
+    import: &ImportDeclaration,
+    type_idx: u32,
+) -> Result<&'a FunctionType> {
+    match module
+        .types()
+        .get(type_idx as usize)
+        .map(|st| &st.composite_type)
+    {
+        Some(CompositeType::Func(ft)) => Ok(ft),
+        _ => link_err!(import, LinkErrorKind::NotAFunctionType(type_idx)),
+    }
+}
+
+/// The type `import` is declared with
+fn import_type(module: &Module, import: &ImportDeclaration) -> Result<ExternType> {
+    Ok(match &import.description {
+        ImportDescription::Func(type_idx) => {
+            ExternType::Func(function_type_at(module, import, *type_idx)?.clone())
+        }
+        ImportDescription::Table(table_type) => ExternType::Table(*table_type),
+        ImportDescription::Mem(memory_type) => ExternType::Memory(memory_type.clone()),
+        ImportDescription::Global(global_type) => ExternType::Global(global_type.clone()),
+        ImportDescription::Tag(type_idx) => {
+            ExternType::Tag(function_type_at(module, import, *type_idx)?.clone())
+        }
+    })
+}
+
+/// The type of `value`, with tables and memories at their current size
+fn extern_type(
+    store: &Store,
+    import: &ImportDeclaration,
+    value: &ExternalValue,
+) -> Result<ExternType> {
+    Ok(match value {
+        ExternalValue::Function { addr } => match store.functions.get(*addr) {
+            Some(
+                FunctionInstance::Local { function_type, .. }
+                | FunctionInstance::Host { function_type, .. },
+            ) => ExternType::Func(function_type.clone()),
+            None => link_err!(import, LinkErrorKind::UnknownImport),
+        },
+        ExternalValue::Table { addr } => {
+            let Some(table) = store.tables.get(*addr) else {
+                link_err!(import, LinkErrorKind::UnknownImport);
+            };
+            ExternType::Table(TableType {
+                limit: Limit {
+                    min: table.elem.len() as u64,
+                    max: table.table_type.limit.max,
+                },
+                ..table.table_type
+            })
+        }
+        ExternalValue::Memory { addr } => {
+            let Some(mem) = store.memories.get(*addr) else {
+                link_err!(import, LinkErrorKind::UnknownImport);
+            };
+            ExternType::Memory(MemoryType {
+                addr_type: mem.memory_type.addr_type,
+                limit: Limit {
+                    min: (mem.data.len() / PAGE_SIZE) as u64,
+                    max: mem.memory_type.limit.max,
+                },
+            })
+        }
+        ExternalValue::Global { addr } => {
+            let Some(global) = store.globals.get(*addr) else {
+                link_err!(import, LinkErrorKind::UnknownImport);
+            };
+            ExternType::Global(global.global_type.clone())
+        }
+        ExternalValue::Tag { addr } => {
+            let Some(tag) = store.tags.get(*addr) else {
+                link_err!(import, LinkErrorKind::UnknownImport);
+            };
+            ExternType::Tag(tag.tag_type.clone())
+        }
+    })
+}
+
+/// Limits subtyping: the provided limits must fit within the expected ones
+const fn limits_match(actual: &Limit, expected: &Limit) -> bool {
+    // `u64::MAX` stands in for "no maximum"
+    actual.min >= expected.min
+        && (expected.max == u64::MAX || (actual.max != u64::MAX && actual.max <= expected.max))
+}
+
+fn function_types_match(expected: &FunctionType, actual: &FunctionType) -> bool {
+    let types_match = |a: &[ValueType], b: &[ValueType]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| value_types_match(a, b))
+    };
+
+    types_match(&expected.0 .0, &actual.0 .0) && types_match(&expected.1 .0, &actual.1 .0)
+}
+
+fn value_types_match(expected: &ValueType, actual: &ValueType) -> bool {
+    match (expected, actual) {
+        (ValueType::Ref(a), ValueType::Ref(b)) => ref_types_match(*a, *b),
+        (a, b) => a == b,
+    }
+}
+
+fn ref_types_match(expected: RefType, actual: RefType) -> bool {
+    // `funcref` and `externref` are shorthands for their nullable forms
+    let normalize = |rt: RefType| match rt {
+        RefType::FuncRef => (true, HeapType::Func),
+        RefType::ExternRef => (true, HeapType::Extern),
+        RefType::Ref {
+            nullable,
+            heap_type,
+        } => (nullable, heap_type),
+    };
+
+    normalize(expected) == normalize(actual)
+}
@@ -1,8 +1,22 @@
-use gabagool::{Module, RawValue, Store, ValueType};
-use std::fs;
+use gabagool::{
+    Error, GdbStub, Linker, Module, RawValue, Store, Trap, ValueType, Wasi, WASI_MODULE,
+};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
 use std::path::PathBuf;
 use std::process;
 
+const USAGE: &str = "usage: gabagool [run [--profile <out.folded>] [--coverage <out.info>] \
+[--env <key=value>] [--dir <host[::guest]>]] <file.wasm> <func_name> [args...]\n       \
+gabagool run [options] <wasi_program.wasm> [args...]\n       \
+gabagool debug --gdb-port <port> <file.wasm> [func_name] [args...]\n       \
+gabagool debug --gdb-port <port> <wasi_program.wasm> [args...]";
+
+/// Ops between profiler samples, prime so samples don't keep landing on the
+/// same op of a loop
+const PROFILE_INTERVAL: u64 = 1009;
+
 fn main() {
     if let Err(e) = run() {
         eprintln!("error: {e}");
@@ -11,36 +25,159 @@ fn main() {
 }
 
 fn run() -> Result<(), Box<dyn std::error::Error>> {
-    let mut args = std::env::args().skip(1);
-
-    let wasm_file = args
-        .next()
-        .ok_or("usage: gabagool <file.wasm> <func_name> [args...]")?;
+    let mut args = std::env::args().skip(1).peekable();
 
-    let func_name = args
-        .next()
-        .ok_or("usage: gabagool <file.wasm> <func_name> [args...]")?;
+    let mut profile_path = None;
+    let mut coverage_path = None;
+    let mut wasi = Wasi::new();
+    match args.peek().map(String::as_str) {
+        Some("debug") => {
+            args.next();
+            return debug(args);
+        }
+        Some("run") => {
+            args.next();
+            while let Some(flag) = args.next_if(|arg| arg.starts_with("--")) {
+                let value = args.next().ok_or(USAGE)?;
+                match flag.as_str() {
+                    "--profile" => profile_path = Some(PathBuf::from(value)),
+                    "--coverage" => coverage_path = Some(PathBuf::from(value)),
+                    "--env" => {
+                        let (key, value) = value.split_once('=').ok_or(USAGE)?;
+                        wasi = wasi.env(key, value);
+                    }
+                    "--dir" => {
+                        let (host, guest) = value.split_once("::").unwrap_or((&value, &value));
+                        wasi = wasi.preopen_dir(host, guest);
+                    }
+                    _ => return Err(USAGE.into()),
+                }
+            }
+        }
+        _ => {}
+    }
 
-    let wasm_file = PathBuf::from(&wasm_file);
+    let wasm_arg = args.next().ok_or(USAGE)?;
+    let wasm_file = PathBuf::from(&wasm_arg);
     let wasm_bytes = fs::read(&wasm_file)?;
 
     let module = Module::new(&wasm_bytes)?;
     let mut store = Store::new();
-    let instance = store.instantiate(&module, vec![])?;
+    let mut linker = Linker::new();
 
-    let param_types = store.get_param_types(instance, &func_name)?;
+    // WASI programs start at `_start` and take the rest as their arguments
+    let is_wasi = module
+        .import_declarations()
+        .iter()
+        .any(|import| import.module == WASI_MODULE);
+    if is_wasi {
+        linker.wasi(&mut store, wasi.arg(wasm_arg).args(args.by_ref()));
+    }
+    let instance = linker.instantiate(&mut store, &module)?;
 
+    let func_name = match is_wasi {
+        true => "_start".to_owned(),
+        false => args.next().ok_or(USAGE)?,
+    };
+    let param_types = store.get_param_types(instance, &func_name)?;
     let values = param_types
         .iter()
         .zip(args)
         .map(|(vt, arg)| parse_value(vt, &arg))
         .collect::<Result<Vec<_>, _>>()?;
 
+    if profile_path.is_some() {
+        store.start_profiling(PROFILE_INTERVAL);
+    }
+    if coverage_path.is_some() {
+        store.start_coverage();
+    }
+
     let results = store
-        .invoke(instance, &func_name, values)?
-        .into_completed()?;
+        .invoke(instance, &func_name, values)
+        .and_then(|state| state.into_completed());
+
+    if let Some(path) = profile_path {
+        let profile = store.stop_profiling().unwrap();
+        let mut out = BufWriter::new(File::create(path)?);
+        profile.write_folded(&mut out)?;
+        out.flush()?;
+        profile.write_table(io::stderr().lock())?;
+    }
+
+    if let Some(path) = coverage_path {
+        let mut out = BufWriter::new(File::create(path)?);
+        store.stop_coverage().unwrap().write_lcov(&mut out)?;
+        out.flush()?;
+    }
+
+    match results {
+        Err(Error::Trap(Trap::Exit(code), _)) => process::exit(code as i32),
+        Ok(results) if !is_wasi => println!("{:?}", results),
+        results => {
+            results?;
+        }
+    }
+
+    Ok(())
+}
 
-    println!("{:?}", results);
+/// Serves one GDB remote protocol connection on `--gdb-port`, starting with
+/// `func_name` (`_start` by default, and always for WASI programs) paused at
+/// its first instruction
+fn debug(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn std::error::Error>> {
+    if args.next().as_deref() != Some("--gdb-port") {
+        return Err(USAGE.into());
+    }
+    let port = args.next().ok_or(USAGE)?.parse::<u16>()?;
+    let wasm_arg = args.next().ok_or(USAGE)?;
+    let wasm_file = PathBuf::from(&wasm_arg);
+
+    let wasm_bytes = fs::read(&wasm_file)?;
+    let module = Module::new(&wasm_bytes)?;
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+
+    // as with `run`, WASI programs take the rest as their arguments
+    let is_wasi = module
+        .import_declarations()
+        .iter()
+        .any(|import| import.module == WASI_MODULE);
+    if is_wasi {
+        linker.wasi(&mut store, Wasi::new().arg(wasm_arg).args(args.by_ref()));
+    }
+    let instance = linker.instantiate(&mut store, &module)?;
+
+    let func_name = match is_wasi {
+        true => "_start".to_owned(),
+        false => args.next().unwrap_or_else(|| "_start".to_owned()),
+    };
+
+    let param_types = store.get_param_types(instance, &func_name)?;
+    let values = param_types
+        .iter()
+        .zip(args)
+        .map(|(vt, arg)| parse_value(vt, &arg))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("waiting for a debugger on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+
+    let name = wasm_file
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let results = GdbStub::new(&mut store, instance, &module, &wasm_bytes, name)
+        .run(stream, &func_name, values)?;
+
+    match results {
+        Err(Error::Trap(Trap::Exit(code), _)) => process::exit(code as i32),
+        Ok(results) if !is_wasi => println!("{:?}", results),
+        results => {
+            results?;
+        }
+    }
 
     Ok(())
 }
@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
 use crate::binary_grammar::{
-    DataSegment, ElementSegment, Export, Function, Global, ImportDeclaration, MemoryType, SubType,
-    TableDef, Tag,
+    DataSegment, ElementSegment, Export, ExportDescription, Function, Global, ImportDeclaration,
+    MemoryType, NameMap, SubType, TableDef, Tag,
 };
 use crate::compiler::{self, ModuleCode};
+use crate::dwarf::SourceLocation;
 use crate::error::Result;
+use crate::ir::OffsetMap;
 use crate::parser::Parser;
 
 /// A parsed and compiled WASM module ready to be instantiated
@@ -52,4 +54,46 @@ impl Module {
     pub fn types(&self) -> &[SubType] {
         &self.code.types
     }
+
+    pub fn names(&self) -> &NameMap {
+        &self.code.names
+    }
+
+    pub fn function_name(&self, func_idx: u32) -> Option<&str> {
+        self.code.names.function(func_idx)
+    }
+
+    /// Where each op of a local function came from, `None` for imports
+    pub fn function_offsets(&self, func_idx: u32) -> Option<&OffsetMap> {
+        self.code
+            .compiled_funcs
+            .iter()
+            .find(|cf| cf.func_idx == Some(func_idx))
+            .map(|cf| &cf.offsets)
+    }
+
+    /// Index of the local function whose body holds `wasm_offset`
+    pub fn function_at(&self, wasm_offset: u32) -> Option<u32> {
+        self.code
+            .compiled_funcs
+            .iter()
+            .find(|cf| cf.offsets.contains(wasm_offset))?
+            .func_idx
+    }
+
+    /// Index of the function exported as `name`
+    pub fn exported_function(&self, name: &str) -> Option<u32> {
+        self.exports
+            .iter()
+            .find_map(|export| match export.description {
+                ExportDescription::Func(idx) if export.name == name => Some(idx),
+                _ => None,
+            })
+    }
+
+    /// Maps a byte offset in the module to a source line, if the module was
+    /// built with DWARF debug info
+    pub fn source_location(&self, wasm_offset: u32) -> Option<SourceLocation> {
+        self.code.source_location(wasm_offset)
+    }
 }
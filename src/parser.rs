@@ -1,7 +1,7 @@
 use std::cmp::min;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 
-use crate::error::{Error, Result};
+use crate::error::{Error, ParseError, ParseErrorKind, Result};
 use crate::{ensure, parse_err};
 
 use crate::binary_grammar::{
@@ -9,9 +9,9 @@ use crate::binary_grammar::{
     DataMode, DataSection, DataSegment, ElementMode, ElementSection, ElementSegment, Export,
     ExportDescription, ExportSection, FieldType, Function, FunctionSection, FunctionType, Global,
     GlobalSection, GlobalType, HeapType, ImportDeclaration, ImportDescription, ImportSection,
-    Instruction, Limit, Local, MemArg, MemorySection, MemoryType, Mutability, ParsedModule,
-    RefType, ResultType, Section, StorageType, StructType, SubType, TableDef, TableSection,
-    TableType, Tag, TagSection, TypeSection, ValueType, MAGIC_NUMBER, TERM_ELSE_BYTE,
+    IndexNames, Instruction, Limit, Local, MemArg, MemorySection, MemoryType, Mutability, NameMap,
+    ParsedModule, RefType, ResultType, Section, StorageType, StructType, SubType, TableDef,
+    TableSection, TableType, Tag, TagSection, TypeSection, ValueType, MAGIC_NUMBER, TERM_ELSE_BYTE,
     TERM_END_BYTE,
 };
 use crate::leb128::{self, MAX_LEB128_LEN_32, MAX_LEB128_LEN_64};
@@ -21,6 +21,8 @@ pub struct Parser<'a> {
     cursor: usize,
     buffer: &'a [u8],
     function_types: VecDeque<u32>,
+    /// Offsets of the instructions parsed so far, while inside a function body
+    instruction_offsets: Option<Vec<u32>>,
 }
 
 impl<'a> Parser<'a> {
@@ -29,6 +31,7 @@ impl<'a> Parser<'a> {
             buffer,
             cursor: 0,
             function_types: VecDeque::new(),
+            instruction_offsets: None,
         }
     }
 
@@ -39,9 +42,21 @@ impl<'a> Parser<'a> {
 
         while self.cursor < self.buffer.len() {
             let id = self.read_u8()?;
-
-            match self.parse_section(id)? {
-                Section::Custom(custom) => module.customs.push(custom),
+            let section = self.parse_section(id).map_err(|err| match err {
+                Error::Parse(err) => Error::Parse(ParseError {
+                    section: err.section.or(Some(id)),
+                    ..err
+                }),
+                err => err,
+            })?;
+
+            match section {
+                Section::Custom(custom) => {
+                    if custom.name == "name" {
+                        module.names = Parser::new(&custom.bytes).parse_name_section();
+                    }
+                    module.customs.push(custom)
+                }
                 Section::Type(TypeSection { mut types }) => module.types.append(&mut types),
                 Section::Import(ImportSection {
                     mut import_declarations,
@@ -63,7 +78,10 @@ impl<'a> Parser<'a> {
                 Section::Element(ElementSection { mut elements }) => {
                     module.element_segments.append(&mut elements)
                 }
-                Section::Code(CodeSection { mut codes }) => module.functions.append(&mut codes),
+                Section::Code(CodeSection { offset, mut codes }) => {
+                    module.code_offset = offset;
+                    module.functions.append(&mut codes)
+                }
                 Section::Data(DataSection { mut data_segments }) => {
                     module.data_segments.append(&mut data_segments)
                 }
@@ -75,11 +93,15 @@ impl<'a> Parser<'a> {
         if let Some(count) = data_count {
             ensure!(
                 count as usize == module.data_segments.len(),
-                Error::Parse(format!(
-                    "Data count {} does not match number of data segments {}",
-                    count,
-                    module.data_segments.len()
-                ))
+                ParseError::malformed(
+                    self.cursor,
+                    format!(
+                        "Data count {} does not match number of data segments {}",
+                        count,
+                        module.data_segments.len()
+                    )
+                )
+                .into()
             );
         }
 
@@ -89,17 +111,21 @@ impl<'a> Parser<'a> {
     fn parse_preamble(&mut self) -> Result<u8> {
         ensure!(
             self.read_slice(4)? == MAGIC_NUMBER,
-            Error::Parse("Expected magic number in preamble.".into())
+            ParseError::new(0, ParseErrorKind::BadMagic).into()
         );
 
         ensure!(
             self.read_slice(4)? == [1, 0, 0, 0],
-            Error::Parse("Expected version 1.".into())
+            ParseError::new(4, ParseErrorKind::UnsupportedVersion).into()
         );
 
         Ok(1)
     }
 
+    fn peek_u8(&self) -> Result<u8> {
+        Ok(self.peek_slice(1)?[0])
+    }
+
     fn read_u8(&mut self) -> Result<u8> {
         let b = self.read_slice(1)?[0];
 
@@ -109,7 +135,7 @@ impl<'a> Parser<'a> {
     fn read_u32(&mut self) -> Result<u32> {
         let buf = self.peek_leb_slice::<MAX_LEB128_LEN_32>()?;
 
-        let (out, seen) = leb128::read_u32(buf)?;
+        let (out, seen) = leb128::read_u32(buf).map_err(|err| self.relocate(err))?;
         self.cursor += seen;
 
         Ok(out)
@@ -118,7 +144,7 @@ impl<'a> Parser<'a> {
     fn read_i32(&mut self) -> Result<i32> {
         let buf = self.peek_leb_slice::<MAX_LEB128_LEN_32>()?;
 
-        let (out, seen) = leb128::read_i32(buf)?;
+        let (out, seen) = leb128::read_i32(buf).map_err(|err| self.relocate(err))?;
         self.cursor += seen;
 
         Ok(out)
@@ -127,7 +153,7 @@ impl<'a> Parser<'a> {
     fn read_u64(&mut self) -> Result<u64> {
         let buf = self.peek_leb_slice::<MAX_LEB128_LEN_64>()?;
 
-        let (out, seen) = leb128::read_u64(buf)?;
+        let (out, seen) = leb128::read_u64(buf).map_err(|err| self.relocate(err))?;
         self.cursor += seen;
 
         Ok(out)
@@ -136,18 +162,37 @@ impl<'a> Parser<'a> {
     fn read_i64(&mut self) -> Result<i64> {
         let buf = self.peek_leb_slice::<MAX_LEB128_LEN_64>()?;
 
-        let (out, seen) = leb128::read_i64(buf)?;
+        let (out, seen) = leb128::read_i64(buf).map_err(|err| self.relocate(err))?;
         self.cursor += seen;
 
         Ok(out)
     }
 
     fn read_f32(&mut self) -> Result<f32> {
-        Ok(f32::from_le_bytes(self.read_slice(4)?.try_into()?))
+        Ok(f32::from_le_bytes(self.read_array()?))
     }
 
     fn read_f64(&mut self) -> Result<f64> {
-        Ok(f64::from_le_bytes(self.read_slice(8)?.try_into()?))
+        Ok(f64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.read_slice(N)?);
+
+        Ok(out)
+    }
+
+    /// Moves an error from a decoder working on the rest of the buffer, such as
+    /// [`leb128`], to the cursor's position
+    fn relocate(&self, err: Error) -> Error {
+        match err {
+            Error::Parse(err) => Error::Parse(ParseError {
+                offset: self.cursor + err.offset,
+                ..err
+            }),
+            err => err,
+        }
     }
 
     fn peek_leb_slice<const MAX_LEB128_LEN: usize>(&self) -> Result<&'a [u8]> {
@@ -159,7 +204,7 @@ impl<'a> Parser<'a> {
     fn peek_slice(&self, len: usize) -> Result<&'a [u8]> {
         self.buffer
             .get(self.cursor..self.cursor + len)
-            .ok_or_else(|| Error::Parse("oob".into()))
+            .ok_or_else(|| ParseError::new(self.buffer.len(), ParseErrorKind::UnexpectedEof).into())
     }
 
     fn read_slice(&mut self, len: usize) -> Result<&'a [u8]> {
@@ -192,9 +237,13 @@ impl<'a> Parser<'a> {
     // 5.2: Values
     fn parse_name(&mut self) -> Result<String> {
         let n = self.read_u32()?;
+        let start = self.cursor;
         let slice = self.read_slice(n as usize)?;
 
-        Ok(std::str::from_utf8(slice)?.to_owned())
+        match std::str::from_utf8(slice) {
+            Ok(name) => Ok(name.to_owned()),
+            Err(err) => parse_err!(start + err.valid_up_to(), ParseErrorKind::MalformedUtf8),
+        }
     }
 
     // 5.3: Types
@@ -212,21 +261,30 @@ impl<'a> Parser<'a> {
             0x72 => HeapType::NoExtern,
             0x73 => HeapType::NoFunc,
             0x74 => HeapType::NoExn,
-            foreign => parse_err!("Unrecognized abstract heap type byte: {}", foreign),
+            foreign => parse_err!(
+                self.cursor - 1,
+                "Unrecognized abstract heap type byte: {}",
+                foreign
+            ),
         };
         Ok(ht)
     }
 
     fn parse_heap_type(&mut self) -> Result<HeapType> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         match byte {
             0x69..=0x74 => self.parse_abs_heap_type(),
             _ => {
                 // Type index encoded as s33 (positive signed integer)
+                let start = self.cursor;
                 let idx = self.read_i64()?;
                 ensure!(
                     idx >= 0,
-                    Error::Parse(format!("heap type index must be non-negative, got {}", idx))
+                    ParseError::malformed(
+                        start,
+                        format!("heap type index must be non-negative, got {}", idx)
+                    )
+                    .into()
                 );
                 Ok(HeapType::TypeIndex(idx as u32))
             }
@@ -234,7 +292,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_reference_type(&mut self) -> Result<RefType> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         let r = match byte {
             0x70 => {
                 self.cursor += 1;
@@ -268,13 +326,13 @@ impl<'a> Parser<'a> {
                     heap_type: ht,
                 }
             }
-            foreign => parse_err!("Unrecognized reference byte. Got: {}", foreign),
+            foreign => parse_err!(self.cursor, "Unrecognized reference byte. Got: {}", foreign),
         };
         Ok(r)
     }
 
     fn parse_value_type(&mut self) -> Result<ValueType> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         let value_type = match byte {
             0x7F => {
                 self.cursor += 1;
@@ -298,7 +356,7 @@ impl<'a> Parser<'a> {
             }
             // Reference types (includes 0x70, 0x6F, 0x63, 0x64, 0x69-0x6E, 0x71-0x74)
             0x63 | 0x64 | 0x69..=0x74 => ValueType::Ref(self.parse_reference_type()?),
-            foreign => parse_err!("Unrecognized type. Got: {}", foreign),
+            foreign => parse_err!(self.cursor, "Unrecognized type. Got: {}", foreign),
         };
         Ok(value_type)
     }
@@ -312,6 +370,7 @@ impl<'a> Parser<'a> {
             0x00 => Ok(Mutability::Const),
             0x01 => Ok(Mutability::Var),
             foreign => parse_err!(
+                self.cursor - 1,
                 "Unrecognized mutability byte. Expected 0x00 or 0x01, Got: {}",
                 foreign
             ),
@@ -319,7 +378,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_storage_type(&mut self) -> Result<StorageType> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         match byte {
             0x78 => {
                 self.cursor += 1;
@@ -359,15 +418,15 @@ impl<'a> Parser<'a> {
                 Ok(CompositeType::Struct(StructType { fields }))
             }
             _ => parse_err!(
-                "Expected composite type (0x5E/0x5F/0x60), got: 0x{:02X} at pos {}",
-                b,
-                self.cursor - 1
+                self.cursor - 1,
+                "Expected composite type (0x5E/0x5F/0x60), got: 0x{:02X}",
+                b
             ),
         }
     }
 
     fn parse_sub_type(&mut self) -> Result<SubType> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         match byte {
             0x4F => {
                 self.cursor += 1;
@@ -401,7 +460,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_rec_type(&mut self) -> Result<Vec<SubType>> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         if byte == 0x4E {
             self.cursor += 1;
             Ok(self.parse_vec(Self::parse_sub_type)?)
@@ -443,6 +502,7 @@ impl<'a> Parser<'a> {
                 },
             )),
             _ => parse_err!(
+                self.cursor - 1,
                 "Expected limit flag 0x00/0x01/0x04/0x05. Got: 0x{:02X}",
                 flag
             ),
@@ -471,6 +531,7 @@ impl<'a> Parser<'a> {
                 0x00 => Mutability::Const,
                 0x01 => Mutability::Var,
                 foreign => parse_err!(
+                    self.cursor - 1,
                     "Unrecognized mutability byte. Expected 0x00 or 0x01, Got: {}",
                     foreign
                 ),
@@ -481,7 +542,7 @@ impl<'a> Parser<'a> {
     // 5.4: Instructions
 
     fn parse_block_type(&mut self) -> Result<BlockType> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         if byte == 0x40 {
             self.cursor += 1;
             Ok(BlockType::Empty)
@@ -530,7 +591,7 @@ impl<'a> Parser<'a> {
             0x03 => Ok(CatchClause::CatchAllRef {
                 label: self.read_u32()?,
             }),
-            _ => parse_err!("Unknown catch clause kind: {}", kind),
+            _ => parse_err!(self.cursor - 1, "Unknown catch clause kind: {}", kind),
         }
     }
 
@@ -544,6 +605,7 @@ impl<'a> Parser<'a> {
                 break;
             }
 
+            self.record_offset();
             let instruction = self.parse_instruction(opcode)?;
 
             instructions.push(instruction);
@@ -569,6 +631,7 @@ impl<'a> Parser<'a> {
                 break;
             }
 
+            self.record_offset();
             let instruction = self.parse_instruction(opcode)?;
 
             if else_flag {
@@ -581,7 +644,15 @@ impl<'a> Parser<'a> {
         Ok(if_else)
     }
 
+    /// Records the offset of the opcode just read
+    fn record_offset(&mut self) {
+        if let Some(offsets) = &mut self.instruction_offsets {
+            offsets.push(self.cursor as u32 - 1);
+        }
+    }
+
     fn parse_instruction(&mut self, opcode: u8) -> Result<Instruction> {
+        let start = self.cursor - 1;
         let instr = match opcode {
             0x00 => Instruction::Unreachable,
             0x01 => Instruction::Nop,
@@ -711,7 +782,13 @@ impl<'a> Parser<'a> {
                 0x1C => Instruction::RefI31,
                 0x1D => Instruction::I31GetSigned,
                 0x1E => Instruction::I31GetUnsigned,
-                foreign => parse_err!("Encountered unknown GC opcode: 0xFB 0x{:02X}", foreign),
+                foreign => parse_err!(
+                    start,
+                    ParseErrorKind::InvalidOpcode {
+                        prefix: Some(0xFB),
+                        opcode: foreign
+                    }
+                ),
             },
             0xFC => match self.read_u32()? {
                 0 => Instruction::I32TruncSaturatedF32Signed,
@@ -743,7 +820,13 @@ impl<'a> Parser<'a> {
                 15 => Instruction::TableGrow(self.read_u32()?),
                 16 => Instruction::TableSize(self.read_u32()?),
                 17 => Instruction::TableFill(self.read_u32()?),
-                foreign => parse_err!("Encountered foreign table opcode: {}", foreign),
+                foreign => parse_err!(
+                    start,
+                    ParseErrorKind::InvalidOpcode {
+                        prefix: Some(0xFC),
+                        opcode: foreign
+                    }
+                ),
             },
             0x28 => Instruction::I32Load(self.parse_memarg()?),
             0x29 => Instruction::I64Load(self.parse_memarg()?),
@@ -922,10 +1005,7 @@ impl<'a> Parser<'a> {
                 0x09 => Instruction::V128Load32Splat(self.parse_memarg()?),
                 0x0A => Instruction::V128Load64Splat(self.parse_memarg()?),
                 0x0B => Instruction::V128Store(self.parse_memarg()?),
-                0x0C => {
-                    let bytes = self.read_slice(16)?;
-                    Instruction::V128Const(i128::from_le_bytes(bytes.try_into()?))
-                }
+                0x0C => Instruction::V128Const(i128::from_le_bytes(self.read_array()?)),
                 0x0D => {
                     let mut lanes = [0u8; 16];
                     lanes.copy_from_slice(self.read_slice(16)?);
@@ -1207,9 +1287,21 @@ impl<'a> Parser<'a> {
                 0x111 => Instruction::I16x8RelaxedQ15mulrSigned,
                 0x112 => Instruction::I16x8RelaxedDotI8x16I7x16Signed,
                 0x113 => Instruction::I32x4RelaxedDotI8x16I7x16AddSigned,
-                foreign => parse_err!("Encountered unknown SIMD opcode: 0xFD 0x{:X}", foreign),
+                foreign => parse_err!(
+                    start,
+                    ParseErrorKind::InvalidOpcode {
+                        prefix: Some(0xFD),
+                        opcode: foreign
+                    }
+                ),
             },
-            foreign => parse_err!("Encountered unknown opcode: {}", foreign),
+            foreign => parse_err!(
+                start,
+                ParseErrorKind::InvalidOpcode {
+                    prefix: None,
+                    opcode: foreign as u32
+                }
+            ),
         };
 
         Ok(instr)
@@ -1228,6 +1320,65 @@ impl<'a> Parser<'a> {
         Ok(CustomSection { name, bytes })
     }
 
+    /// Decodes as much of the `name` section as is well-formed. Custom sections
+    /// can't invalidate a module, so a malformed subsection only ends decoding
+    fn parse_name_section(&mut self) -> NameMap {
+        let mut names = NameMap::default();
+
+        while self.cursor < self.buffer.len() {
+            if self.parse_name_subsection(&mut names).is_err() {
+                break;
+            }
+        }
+
+        names
+    }
+
+    fn parse_name_subsection(&mut self, names: &mut NameMap) -> Result<()> {
+        let id = self.read_u8()?;
+        let size = self.read_u32()? as usize;
+        let end = self.cursor + size;
+
+        match id {
+            0 => names.module = Some(self.parse_name()?),
+            1 => names.functions = self.parse_name_map()?,
+            2 => names.locals = self.parse_indirect_name_map()?,
+            3 => names.labels = self.parse_indirect_name_map()?,
+            4 => names.types = self.parse_name_map()?,
+            5 => names.tables = self.parse_name_map()?,
+            6 => names.memories = self.parse_name_map()?,
+            7 => names.globals = self.parse_name_map()?,
+            8 => names.elems = self.parse_name_map()?,
+            9 => names.datas = self.parse_name_map()?,
+            // field and tag names, or a future extension
+            _ => {}
+        }
+
+        ensure!(
+            self.cursor <= end,
+            ParseError::malformed(
+                self.cursor,
+                format!("name subsection {id} overruns its size")
+            )
+            .into()
+        );
+        self.cursor = end;
+
+        Ok(())
+    }
+
+    fn parse_name_map(&mut self) -> Result<IndexNames> {
+        let entries = self.parse_vec(|p| Ok((p.read_u32()?, p.parse_name()?)))?;
+
+        Ok(entries.into_iter().collect())
+    }
+
+    fn parse_indirect_name_map(&mut self) -> Result<BTreeMap<u32, IndexNames>> {
+        let entries = self.parse_vec(|p| Ok((p.read_u32()?, p.parse_name_map()?)))?;
+
+        Ok(entries.into_iter().collect())
+    }
+
     fn parse_type_section(&mut self) -> Result<TypeSection> {
         let rec_types = self.parse_vec(Self::parse_rec_type)?;
         Ok(TypeSection {
@@ -1249,9 +1400,9 @@ impl<'a> Parser<'a> {
                     ImportDescription::Tag(self.read_u32()?)
                 }
                 foreign => parse_err!(
-                    "Unrecognized import description. Got: {}, at: {}",
-                    foreign,
-                    self.cursor
+                    self.cursor - 1,
+                    "Unrecognized import description. Got: {}",
+                    foreign
                 ),
             },
         })
@@ -1272,13 +1423,17 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_table_def(&mut self) -> Result<TableDef> {
-        let byte = self.buffer[self.cursor];
+        let byte = self.peek_u8()?;
         if byte == 0x40 {
             // table with init expression: 0x40 0x00 reftype limit expr
             self.cursor += 1;
             ensure!(
                 self.read_u8()? == 0x00,
-                Error::Parse("Expected 0x00 after 0x40 in table definition".into())
+                ParseError::malformed(
+                    self.cursor - 1,
+                    "Expected 0x00 after 0x40 in table definition"
+                )
+                .into()
             );
             let table_type = self.parse_table_type()?;
             let init = self.parse_expression()?;
@@ -1318,7 +1473,7 @@ impl<'a> Parser<'a> {
     fn parse_tag(&mut self) -> Result<Tag> {
         ensure!(
             self.read_u8()? == 0x00,
-            Error::Parse("Expected 0x00 attribute byte for tag.".into())
+            ParseError::malformed(self.cursor - 1, "Expected 0x00 attribute byte for tag.").into()
         );
         Ok(Tag {
             type_index: self.read_u32()?,
@@ -1341,6 +1496,7 @@ impl<'a> Parser<'a> {
                 0x03 => ExportDescription::Global(self.read_u32()?),
                 0x04 => ExportDescription::Tag(self.read_u32()?),
                 foreign => parse_err!(
+                    self.cursor - 1,
                     "Encountered foreign byte when parsing export description. Got: {}",
                     foreign
                 ),
@@ -1355,6 +1511,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_element_segement(&mut self) -> Result<ElementSegment> {
+        let start = self.cursor;
         let segment = match self.read_u32()? {
             0 => {
                 let offset = self.parse_expression()?;
@@ -1376,7 +1533,7 @@ impl<'a> Parser<'a> {
             1 => {
                 ensure!(
                     self.read_u8()? == 0x00,
-                    Error::Parse("Expected elemkind 0x00.".into())
+                    ParseError::malformed(self.cursor - 1, "Expected elemkind 0x00.").into()
                 );
 
                 let expression = self
@@ -1396,7 +1553,7 @@ impl<'a> Parser<'a> {
                 let offset = self.parse_expression()?;
                 ensure!(
                     self.read_u8()? == 0x00,
-                    Error::Parse("Expected elemkind 0x00.".into())
+                    ParseError::malformed(self.cursor - 1, "Expected elemkind 0x00.").into()
                 );
 
                 let expression = self
@@ -1417,7 +1574,7 @@ impl<'a> Parser<'a> {
             3 => {
                 ensure!(
                     self.read_u8()? == 0x00,
-                    Error::Parse("Expected elemkind 0x00.".into())
+                    ParseError::malformed(self.cursor - 1, "Expected elemkind 0x00.").into()
                 );
 
                 let expression = self
@@ -1470,7 +1627,11 @@ impl<'a> Parser<'a> {
                 expression: self.parse_vec(Self::parse_expression)?,
                 mode: ElementMode::Declarative,
             },
-            foreign => parse_err!("Encountered foreign element segement kind: {}", foreign),
+            foreign => parse_err!(
+                start,
+                "Encountered foreign element segement kind: {}",
+                foreign
+            ),
         };
 
         Ok(segment)
@@ -1496,17 +1657,28 @@ impl<'a> Parser<'a> {
         let type_index = self
             .function_types
             .pop_front()
-            .ok_or_else(|| Error::Parse("Function type list empty".into()))?;
+            .ok_or_else(|| ParseError::malformed(start, "Function type list empty"))?;
+
+        let locals = self.parse_vec(Self::parse_local)?;
+
+        self.instruction_offsets = Some(Vec::new());
+        let body = self.parse_expression();
+        let mut offsets = self.instruction_offsets.take().unwrap_or_default();
+        let body = body?;
+        // the closing `end`
+        offsets.push(self.cursor as u32 - 1);
 
         let func = Function {
             type_index,
-            locals: self.parse_vec(Self::parse_local)?,
-            body: self.parse_expression()?,
+            locals,
+            body,
+            offsets,
         };
 
         let consumed = self.cursor - start;
         if consumed != size as usize {
             parse_err!(
+                start,
                 "parse_code: expected {} bytes but consumed {} (type_index={}, start=0x{:x})",
                 size,
                 consumed,
@@ -1520,11 +1692,13 @@ impl<'a> Parser<'a> {
 
     fn parse_code_section(&mut self) -> Result<CodeSection> {
         Ok(CodeSection {
+            offset: self.cursor as u32,
             codes: self.parse_vec(Self::parse_code)?,
         })
     }
 
     fn parse_data_segment(&mut self) -> Result<DataSegment> {
+        let start = self.cursor;
         let segment = match self.read_u32()? {
             0 => {
                 let offset = self.parse_expression()?;
@@ -1555,7 +1729,7 @@ impl<'a> Parser<'a> {
                     mode: DataMode::Active { memory, offset },
                 }
             }
-            foreign => parse_err!("Encountered foreign data kind. Got: {}", foreign),
+            foreign => parse_err!(start, "Encountered foreign data kind. Got: {}", foreign),
         };
 
         Ok(segment)
@@ -1570,6 +1744,7 @@ impl<'a> Parser<'a> {
     fn parse_section(&mut self, id: u8) -> Result<Section> {
         use crate::binary_grammar::section_id::*;
 
+        let start = self.cursor - 1;
         let size = self.read_u32()?;
 
         let section = match id {
@@ -1587,7 +1762,7 @@ impl<'a> Parser<'a> {
             DATA_ID => Section::Data(self.parse_data_section()?),
             DATA_COUNT_ID => Section::DataCount(self.read_u32()?),
             TAG_ID => Section::Tag(self.parse_tag_section()?),
-            foreign_id => parse_err!("Encountered foreign section id: {}", foreign_id),
+            foreign_id => parse_err!(start, ParseErrorKind::UnknownSection(foreign_id)),
         };
 
         Ok(section)
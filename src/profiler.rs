@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::dwarf::SourceLocation;
+
+/// Call stack samples taken every `interval` ops, see
+/// [`crate::Store::start_profiling`]
+#[derive(Debug)]
+pub struct Profiler {
+    pub(crate) interval: u64,
+    countdown: u64,
+    /// Sample counts by stack of (instance_idx, compiled_func_idx), outermost
+    /// frame first, and by the pc of the innermost frame
+    pub(crate) stacks: HashMap<Vec<(u16, u32)>, HashMap<usize, u64>>,
+    scratch: Vec<(u16, u32)>,
+}
+
+impl Profiler {
+    pub(crate) fn new(interval: u64) -> Self {
+        let interval = interval.max(1);
+        Self {
+            interval,
+            countdown: interval,
+            stacks: HashMap::new(),
+            scratch: vec![],
+        }
+    }
+
+    /// Counts one op, sampling `frames` and the op's `pc` when the interval
+    /// is up
+    #[inline]
+    pub(crate) fn tick(&mut self, frames: impl Iterator<Item = (u16, u32)>, pc: usize) {
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return;
+        }
+
+        self.countdown = self.interval;
+        self.scratch.clear();
+        self.scratch.extend(frames);
+        match self.stacks.get_mut(&self.scratch) {
+            Some(pcs) => *pcs.entry(pc).or_default() += 1,
+            None => {
+                self.stacks
+                    .insert(self.scratch.clone(), HashMap::from([(pc, 1)]));
+            }
+        }
+    }
+}
+
+/// Call stack samples of a profiled store, see [`crate::Store::profile`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    /// Ops between samples
+    pub interval: u64,
+    /// Sampled stacks, outermost frame first, with how often each was seen,
+    /// sorted by stack
+    pub stacks: Vec<(Vec<ProfileFrame>, u64)>,
+}
+
+/// A function on a sampled stack
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProfileFrame {
+    /// Index of the instance in the store
+    pub module_idx: usize,
+    /// Index in the module's function index space, `None` for segment
+    /// initializers
+    pub func_idx: Option<u32>,
+    pub name: Option<String>,
+    /// Source line of the sampled op, for innermost frames of modules with
+    /// DWARF
+    pub location: Option<SourceLocation>,
+}
+
+impl ProfileFrame {
+    /// The function qualified by its module, like `module0.wasm!main`
+    pub fn function(&self) -> String {
+        let module = format!("module{}.wasm", self.module_idx);
+        match (&self.name, self.func_idx) {
+            (Some(name), _) => format!("{module}!{name}"),
+            (None, Some(idx)) => format!("{module}!wasm-function[{idx}]"),
+            (None, None) => format!("{module}!<init>"),
+        }
+    }
+}
+
+impl fmt::Display for ProfileFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.function())?;
+        if let Some(location) = &self.location {
+            write!(f, " ({}:{})", location.file, location.line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimated ops spent in a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCost {
+    pub module_idx: usize,
+    pub func_idx: Option<u32>,
+    /// See [`ProfileFrame::function`]
+    pub name: String,
+    /// Ops run by the function itself
+    pub self_ops: u64,
+    /// Ops run while the function was on the stack, counted once for
+    /// recursive calls
+    pub total_ops: u64,
+    /// Source line the function ran the most ops at, with DWARF
+    pub hottest_line: Option<SourceLocation>,
+}
+
+impl Profile {
+    pub fn samples(&self) -> u64 {
+        self.stacks.iter().map(|(_, count)| count).sum()
+    }
+
+    /// Writes the stacks in the folded format read by `flamegraph.pl` and
+    /// inferno, weighted by estimated op count
+    pub fn write_folded(&self, mut out: impl Write) -> io::Result<()> {
+        for (stack, count) in &self.stacks {
+            let frames = stack
+                .iter()
+                .map(|frame| frame.to_string().replace([';', ' '], "_"))
+                .collect::<Vec<_>>();
+            writeln!(out, "{} {}", frames.join(";"), count * self.interval)?;
+        }
+        Ok(())
+    }
+
+    /// Self and total op counts of every sampled function, hottest first
+    pub fn functions(&self) -> Vec<FunctionCost> {
+        let mut costs: HashMap<(usize, Option<u32>), FunctionCost> = HashMap::new();
+        let mut lines: HashMap<(usize, Option<u32>), HashMap<&SourceLocation, u64>> =
+            HashMap::new();
+        let mut seen = HashSet::new();
+        for (stack, count) in &self.stacks {
+            let ops = count * self.interval;
+            seen.clear();
+            for (depth, frame) in stack.iter().enumerate() {
+                let key = (frame.module_idx, frame.func_idx);
+                let cost = costs.entry(key).or_insert_with(|| FunctionCost {
+                    module_idx: frame.module_idx,
+                    func_idx: frame.func_idx,
+                    name: frame.function(),
+                    self_ops: 0,
+                    total_ops: 0,
+                    hottest_line: None,
+                });
+                if seen.insert(key) {
+                    cost.total_ops += ops;
+                }
+                if depth + 1 == stack.len() {
+                    cost.self_ops += ops;
+                    if let Some(location) = &frame.location {
+                        *lines.entry(key).or_default().entry(location).or_default() += ops;
+                    }
+                }
+            }
+        }
+
+        let mut functions = costs
+            .into_iter()
+            .map(|(key, mut function)| {
+                function.hottest_line = lines.get(&key).and_then(|lines| {
+                    lines
+                        .iter()
+                        .max_by(|a, b| (a.1, b.0).cmp(&(b.1, a.0)))
+                        .map(|(location, _)| (*location).clone())
+                });
+                function
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            (b.self_ops, b.total_ops, &a.name).cmp(&(a.self_ops, a.total_ops, &b.name))
+        });
+        functions
+    }
+
+    /// Writes [`Profile::functions`] as a table, with the line each function
+    /// spent the most ops at where DWARF has it
+    pub fn write_table(&self, mut out: impl Write) -> io::Result<()> {
+        let total = (self.samples() * self.interval).max(1);
+        writeln!(
+            out,
+            "{:>7} {:>14} {:>14}  function",
+            "self%", "self ops", "total ops"
+        )?;
+        for function in self.functions() {
+            write!(
+                out,
+                "{:>6.2}% {:>14} {:>14}  {}",
+                function.self_ops as f64 * 100.0 / total as f64,
+                function.self_ops,
+                function.total_ops,
+                function.name
+            )?;
+            if let Some(location) = &function.hottest_line {
+                write!(out, " ({}:{})", location.file, location.line)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::RawValue;
+
+/// Execution history kept for time travel, see
+/// [`crate::Store::start_recording`]
+///
+/// Positions count the fuel the recorded invocation has consumed, whether or
+/// not the store has a fuel limit, so they're stable across replays.
+#[derive(Debug)]
+pub struct Recording {
+    pub(crate) interval: u64,
+    pub(crate) position: u64,
+    /// The furthest position the invocation has reached
+    pub(crate) end: u64,
+    pub(crate) next_checkpoint: u64,
+    pub(crate) checkpoints: Vec<Checkpoint>,
+    /// Results of host calls, answered by the embedder or by WASI, in call
+    /// order
+    pub(crate) host_results: Vec<HostResult>,
+    /// Host calls made up to `position`
+    pub(crate) host_calls: usize,
+    /// Set while the guest waits on a host call
+    pub(crate) awaiting_host: bool,
+    pub(crate) seek: Option<Seek>,
+}
+
+/// What a host call returned, and the guest memory a WASI call wrote
+#[derive(Debug, Clone)]
+pub struct HostResult {
+    pub(crate) values: Vec<RawValue>,
+    /// Offset and contents of each write
+    pub(crate) writes: Vec<(usize, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+pub struct Checkpoint {
+    pub(crate) position: u64,
+    pub(crate) host_calls: usize,
+    pub(crate) snapshot: Vec<u8>,
+}
+
+/// A replay towards `target`, noting the last position before it where
+/// `search` matched
+#[derive(Debug)]
+pub struct Seek {
+    pub(crate) target: u64,
+    pub(crate) search: Option<Search>,
+    pub(crate) found: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Search {
+    /// The start of a wasm instruction
+    Instruction,
+    Breakpoint,
+}
+
+impl Recording {
+    pub(crate) fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            position: 0,
+            end: 0,
+            next_checkpoint: 0,
+            checkpoints: vec![],
+            host_results: vec![],
+            host_calls: 0,
+            awaiting_host: false,
+            seek: None,
+        }
+    }
+
+    /// Forgets the recorded history, ready for a new invocation
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.interval);
+    }
+
+    pub(crate) fn checkpoint(&mut self, snapshot: Vec<u8>) {
+        self.checkpoints.push(Checkpoint {
+            position: self.position,
+            host_calls: self.host_calls,
+            snapshot,
+        });
+        self.next_checkpoint = self.position + self.interval;
+    }
+
+    /// The index of the last checkpoint at or before `position`
+    pub(crate) fn checkpoint_before(&self, position: u64) -> Option<usize> {
+        self.checkpoints
+            .partition_point(|checkpoint| checkpoint.position <= position)
+            .checked_sub(1)
+    }
+
+    /// The result of the next host call, if it was already answered
+    pub(crate) fn next_host_result(&mut self) -> Option<HostResult> {
+        let values = self.host_results.get(self.host_calls)?.clone();
+        self.host_calls += 1;
+        Some(values)
+    }
+
+    pub(crate) fn record_host_result(&mut self, values: &[RawValue]) {
+        if !self.awaiting_host {
+            return;
+        }
+
+        self.awaiting_host = false;
+        self.push_host_result(HostResult {
+            values: values.to_vec(),
+            writes: vec![],
+        });
+    }
+
+    pub(crate) fn push_host_result(&mut self, result: HostResult) {
+        if self.host_calls == self.host_results.len() {
+            self.host_results.push(result);
+        }
+        self.host_calls += 1;
+    }
+}
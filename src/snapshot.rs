@@ -1,18 +1,29 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{mem, slice};
 
 use crate::binary_grammar::{
     AddrType, ArrayType, CompositeType, FieldType, FunctionType, GlobalType, HeapType, Limit,
-    MemoryType, Mutability, RefType, ResultType, StorageType, StructType, SubType, TableType,
-    ValueType,
+    MemoryType, Mutability, NameMap, RefType, ResultType, StorageType, StructType, SubType,
+    TableType, ValueType,
 };
 use crate::compiler::ModuleCode;
+use crate::coverage::CoverageCounters;
+use crate::dwarf::{LineRow, LineTable};
+use crate::error::Trap;
 use crate::execution_grammar::{ExportInstance, ExternalValue, RawValue, Ref};
-use crate::ir::{CompiledFunction, JumpTableEntry, Op};
-use crate::store::{CallFrame, InstantiatedModule};
+use crate::fuel::FuelCosts;
+use crate::ir::{CompiledFunction, JumpTableEntry, OffsetMap, Op};
+use crate::limits::StoreLimits;
+use crate::store::{CallFrame, Execution, ExecutionContext, InstantiatedModule, Nesting, StepKind};
+use crate::value_stack::ValueStack;
+use crate::vfs::{Node, Vfs};
+use crate::wasi::{Descriptor, Seeded, Wasi};
+use crate::watchpoint::{WatchKind, Watchpoint};
 
 pub const SNAPSHOT_MAGIC: &[u8; 4] = b"gaba";
-pub const SNAPSHOT_VERSION: u32 = 1;
+pub const SNAPSHOT_VERSION: u32 = 19;
 
 pub trait Snapshot: Sized {
     fn encode(&self, buf: &mut Vec<u8>);
@@ -205,6 +216,53 @@ impl<T: Snapshot> Snapshot for Option<T> {
     }
 }
 
+impl<T: Snapshot> Snapshot for Box<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (**self).encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self::new(T::decode(buf))
+    }
+}
+
+impl<K: Snapshot + Ord, V: Snapshot> Snapshot for BTreeMap<K, V> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        for (k, v) in self {
+            k.encode(buf);
+            v.encode(buf);
+        }
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        let len = u32::decode(buf) as usize;
+        (0..len).map(|_| (K::decode(buf), V::decode(buf))).collect()
+    }
+}
+
+impl<T: Snapshot + Ord> Snapshot for BTreeSet<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (self.len() as u32).encode(buf);
+        for v in self {
+            v.encode(buf);
+        }
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        let len = u32::decode(buf) as usize;
+        (0..len).map(|_| T::decode(buf)).collect()
+    }
+}
+
+impl<A: Snapshot, B: Snapshot, C: Snapshot> Snapshot for (A, B, C) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+        self.2.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        (A::decode(buf), B::decode(buf), C::decode(buf))
+    }
+}
+
 impl<A: Snapshot, B: Snapshot> Snapshot for (A, B) {
     fn encode(&self, buf: &mut Vec<u8>) {
         self.0.encode(buf);
@@ -625,19 +683,29 @@ impl Snapshot for JumpTableEntry {
 
 impl Snapshot for CompiledFunction {
     fn encode(&self, buf: &mut Vec<u8>) {
-        encode_bulk(&self.ops, buf);
+        // in the layout `decode_bulk` reads, minus the padding
+        (self.ops.len() as u32).encode(buf);
+        for op in &self.ops {
+            buf.extend_from_slice(&op.canonical_bytes());
+        }
         self.type_index.encode(buf);
         self.num_args.encode(buf);
+        self.func_idx.encode(buf);
         self.local_types.encode(buf);
         self.max_stack_height.encode(buf);
+        self.offsets.entries.encode(buf);
     }
     fn decode(buf: &mut &[u8]) -> Self {
         Self {
             ops: decode_bulk::<Op>(buf),
             type_index: u32::decode(buf),
             num_args: u32::decode(buf),
+            func_idx: Option::<u32>::decode(buf),
             local_types: Vec::<ValueType>::decode(buf),
             max_stack_height: u32::decode(buf),
+            offsets: OffsetMap {
+                entries: Vec::decode(buf),
+            },
         }
     }
 }
@@ -663,6 +731,8 @@ impl Snapshot for ModuleCode {
             table.encode(buf);
         }
         self.shuffle_masks.encode(buf);
+        self.names.encode(buf);
+        self.line_table.encode(buf);
     }
     fn decode(buf: &mut &[u8]) -> Self {
         let compiled_funcs = Vec::<CompiledFunction>::decode(buf);
@@ -673,12 +743,16 @@ impl Snapshot for ModuleCode {
             .map(|_| Vec::<JumpTableEntry>::decode(buf))
             .collect();
         let shuffle_masks = Vec::<[u8; 16]>::decode(buf);
+        let names = NameMap::decode(buf);
+        let line_table = Option::<LineTable>::decode(buf);
         Self {
             compiled_funcs,
             types,
             v128_constants,
             jump_tables,
             shuffle_masks,
+            names,
+            line_table,
         }
     }
 }
@@ -704,6 +778,118 @@ impl Snapshot for CallFrame {
     }
 }
 
+impl Snapshot for Execution {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self(usize::decode(buf))
+    }
+}
+
+impl Snapshot for Trap {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Self::Unreachable => 0,
+            Self::IntegerDivideByZero => 1,
+            Self::IntegerOverflow => 2,
+            Self::InvalidConversionToInteger => 3,
+            Self::OutOfBoundsMemoryAccess => 4,
+            Self::OutOfBoundsTableAccess => 5,
+            Self::UndefinedElement => 6,
+            Self::OutOfBoundsDataAccess => 7,
+            Self::IndirectCallTypeMismatch => 8,
+            Self::NullReference => 9,
+            Self::CastFailure => 10,
+            Self::OutOfBoundsArrayAccess => 11,
+            Self::CallStackExhausted => 12,
+            Self::ResourceLimitExceeded => 13,
+            Self::Exit(code) => {
+                14u8.encode(buf);
+                code.encode(buf);
+                return;
+            }
+            // only the message of an embedder's error survives
+            Self::Host(err) => {
+                15u8.encode(buf);
+                err.to_string().encode(buf);
+                return;
+            }
+        };
+        tag.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        match u8::decode(buf) {
+            0 => Self::Unreachable,
+            1 => Self::IntegerDivideByZero,
+            2 => Self::IntegerOverflow,
+            3 => Self::InvalidConversionToInteger,
+            4 => Self::OutOfBoundsMemoryAccess,
+            5 => Self::OutOfBoundsTableAccess,
+            6 => Self::UndefinedElement,
+            7 => Self::OutOfBoundsDataAccess,
+            8 => Self::IndirectCallTypeMismatch,
+            9 => Self::NullReference,
+            10 => Self::CastFailure,
+            11 => Self::OutOfBoundsArrayAccess,
+            12 => Self::CallStackExhausted,
+            13 => Self::ResourceLimitExceeded,
+            14 => Self::Exit(u32::decode(buf)),
+            15 => Self::host(String::decode(buf)),
+            d => panic!("invalid Trap discriminant: {d}"),
+        }
+    }
+}
+
+impl Snapshot for Nesting {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.arity.encode(buf);
+        self.host_func.encode(buf);
+        self.depth.encode(buf);
+        self.stack_height.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            arity: usize::decode(buf),
+            host_func: usize::decode(buf),
+            depth: usize::decode(buf),
+            stack_height: usize::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for ExecutionContext {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (stack_data, stack_cursor) = self.stack.snapshot_data();
+        encode_bulk(stack_data, buf);
+        stack_cursor.encode(buf);
+        self.call_stack.encode(buf);
+        self.pending_arity.encode(buf);
+        self.awaiting_host.encode(buf);
+        self.nested.encode(buf);
+        self.step.encode(buf);
+        self.resuming.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        let stack_data = decode_bulk(buf);
+        let stack_cursor = usize::decode(buf);
+        let call_stack = Vec::decode(buf);
+        let pending_arity = Option::decode(buf);
+        let awaiting_host = Option::decode(buf);
+        let nested: Vec<Nesting> = Vec::decode(buf);
+        Self {
+            stack: ValueStack::from_snapshot(stack_data, stack_cursor),
+            call_stack,
+            pending_arity,
+            awaiting_host,
+            base_depth: nested.last().map_or(0, |nesting| nesting.depth),
+            nested,
+            step: Option::decode(buf),
+            resuming: bool::decode(buf),
+        }
+    }
+}
+
 impl Snapshot for InstantiatedModule {
     fn encode(&self, buf: &mut Vec<u8>) {
         self.code.as_ref().encode(buf);
@@ -730,3 +916,320 @@ impl Snapshot for InstantiatedModule {
         }
     }
 }
+
+impl Snapshot for StoreLimits {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.max_memory_bytes.encode(buf);
+        self.max_table_elements.encode(buf);
+        self.max_instances.encode(buf);
+        self.max_call_depth.encode(buf);
+        self.max_stack_values.encode(buf);
+        self.trap_on_grow_failure.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            max_memory_bytes: u64::decode(buf),
+            max_table_elements: u64::decode(buf),
+            max_instances: usize::decode(buf),
+            max_call_depth: usize::decode(buf),
+            max_stack_values: usize::decode(buf),
+            trap_on_grow_failure: bool::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for FuelCosts {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.instruction.encode(buf);
+        self.memory_access.encode(buf);
+        self.call.encode(buf);
+        self.host_call.encode(buf);
+        self.grow.encode(buf);
+        self.bulk.encode(buf);
+        self.bulk_unit.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            instruction: u64::decode(buf),
+            memory_access: u64::decode(buf),
+            call: u64::decode(buf),
+            host_call: u64::decode(buf),
+            grow: u64::decode(buf),
+            bulk: u64::decode(buf),
+            bulk_unit: u64::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for StepKind {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Self::Into => 0,
+            Self::Over => 1,
+            Self::Out => 2,
+        };
+        tag.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        match u8::decode(buf) {
+            0 => Self::Into,
+            1 => Self::Over,
+            2 => Self::Out,
+            d => panic!("invalid StepKind discriminant: {d}"),
+        }
+    }
+}
+
+impl Snapshot for WatchKind {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Self::Read => 0,
+            Self::Write => 1,
+            Self::ReadWrite => 2,
+        };
+        tag.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        match u8::decode(buf) {
+            0 => Self::Read,
+            1 => Self::Write,
+            2 => Self::ReadWrite,
+            d => panic!("invalid WatchKind discriminant: {d}"),
+        }
+    }
+}
+
+impl Snapshot for Watchpoint {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.instance.encode(buf);
+        self.memory_idx.encode(buf);
+        self.mem_addr.encode(buf);
+        self.start.encode(buf);
+        self.end.encode(buf);
+        self.kind.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            instance: usize::decode(buf),
+            memory_idx: u32::decode(buf),
+            mem_addr: usize::decode(buf),
+            start: u64::decode(buf),
+            end: u64::decode(buf),
+            kind: WatchKind::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for LineRow {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.address.encode(buf);
+        self.file.encode(buf);
+        self.line.encode(buf);
+        self.column.encode(buf);
+        self.end_sequence.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            address: u32::decode(buf),
+            file: u32::decode(buf),
+            line: u32::decode(buf),
+            column: u32::decode(buf),
+            end_sequence: bool::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for LineTable {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.code_offset.encode(buf);
+        self.files.encode(buf);
+        self.rows.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            code_offset: u32::decode(buf),
+            files: Vec::decode(buf),
+            rows: Vec::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for NameMap {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.module.encode(buf);
+        self.functions.encode(buf);
+        self.locals.encode(buf);
+        self.labels.encode(buf);
+        self.types.encode(buf);
+        self.tables.encode(buf);
+        self.memories.encode(buf);
+        self.globals.encode(buf);
+        self.elems.encode(buf);
+        self.datas.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            module: Option::decode(buf),
+            functions: BTreeMap::decode(buf),
+            locals: BTreeMap::decode(buf),
+            labels: BTreeMap::decode(buf),
+            types: BTreeMap::decode(buf),
+            tables: BTreeMap::decode(buf),
+            memories: BTreeMap::decode(buf),
+            globals: BTreeMap::decode(buf),
+            elems: BTreeMap::decode(buf),
+            datas: BTreeMap::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for CoverageCounters {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.counts.encode(buf);
+        self.calls.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        Self {
+            counts: Vec::decode(buf),
+            calls: Vec::decode(buf),
+        }
+    }
+}
+
+impl Snapshot for Node {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::File(data) => {
+                0u8.encode(buf);
+                encode_bulk(data, buf);
+            }
+            Self::Dir(entries) => {
+                1u8.encode(buf);
+                entries.encode(buf);
+            }
+        }
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        match u8::decode(buf) {
+            0 => Self::File(decode_bulk(buf)),
+            _ => Self::Dir(BTreeMap::decode(buf)),
+        }
+    }
+}
+
+impl Snapshot for Vfs {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.nodes.encode(buf);
+        self.max_bytes.encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        let nodes: Vec<Node> = Vec::decode(buf);
+        let bytes = nodes
+            .iter()
+            .map(|node| match node {
+                Node::File(data) => data.len() as u64,
+                Node::Dir(_) => 0,
+            })
+            .sum();
+        Self {
+            nodes,
+            max_bytes: u64::decode(buf),
+            bytes,
+        }
+    }
+}
+
+/// Streams and host files stay behind: restored guests get the host's
+/// standard streams and find host files closed
+impl Snapshot for Wasi {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.args.encode(buf);
+        self.env.encode(buf);
+
+        (self.fds.len() as u32).encode(buf);
+        for descriptor in &self.fds {
+            match descriptor {
+                None => 0u8.encode(buf),
+                Some(Descriptor::Stdin) => 1u8.encode(buf),
+                Some(Descriptor::Stdout) => 2u8.encode(buf),
+                Some(Descriptor::Stderr) => 3u8.encode(buf),
+                Some(Descriptor::Dir { host, preopen }) => {
+                    4u8.encode(buf);
+                    host.to_string_lossy().into_owned().encode(buf);
+                    preopen.encode(buf);
+                }
+                Some(Descriptor::VfsDir { inode, preopen }) => {
+                    5u8.encode(buf);
+                    inode.encode(buf);
+                    preopen.encode(buf);
+                }
+                Some(Descriptor::VfsFile {
+                    inode,
+                    offset,
+                    rights,
+                    append,
+                }) => {
+                    6u8.encode(buf);
+                    inode.encode(buf);
+                    offset.encode(buf);
+                    rights.encode(buf);
+                    append.encode(buf);
+                }
+                // host files can't be restored, but their numbers stay taken
+                Some(Descriptor::File(_) | Descriptor::Closed) => 7u8.encode(buf),
+            }
+        }
+
+        self.vfs.encode(buf);
+        // deterministic stores don't read the host clock, so identical runs
+        // snapshot to identical bytes
+        let elapsed = match self.seeded {
+            Some(_) => 0,
+            None => self.started.elapsed().as_nanos() as u64,
+        };
+        elapsed.encode(buf);
+        self.seeded
+            .map(|seeded| (seeded.now, seeded.rng))
+            .encode(buf);
+    }
+    fn decode(buf: &mut &[u8]) -> Self {
+        let mut wasi = Self::new();
+        wasi.args = Vec::decode(buf);
+        wasi.env = Vec::decode(buf);
+
+        let num_fds = u32::decode(buf) as usize;
+        wasi.fds = (0..num_fds)
+            .map(|_| match u8::decode(buf) {
+                0 => None,
+                1 => Some(Descriptor::Stdin),
+                2 => Some(Descriptor::Stdout),
+                3 => Some(Descriptor::Stderr),
+                4 => Some(Descriptor::Dir {
+                    host: String::decode(buf).into(),
+                    preopen: Option::decode(buf),
+                }),
+                5 => Some(Descriptor::VfsDir {
+                    inode: usize::decode(buf),
+                    preopen: Option::decode(buf),
+                }),
+                6 => Some(Descriptor::VfsFile {
+                    inode: usize::decode(buf),
+                    offset: u64::decode(buf),
+                    rights: u64::decode(buf),
+                    append: bool::decode(buf),
+                }),
+                7 => Some(Descriptor::Closed),
+                tag => panic!("invalid descriptor tag {tag}"),
+            })
+            .collect();
+
+        wasi.vfs = Option::decode(buf);
+        // the monotonic clocks carry on from where they were
+        let elapsed = Duration::from_nanos(u64::decode(buf));
+        wasi.started = Instant::now()
+            .checked_sub(elapsed)
+            .unwrap_or_else(Instant::now);
+        wasi.seeded = Option::decode(buf).map(|(now, rng)| Seeded { now, rng });
+        wasi
+    }
+}
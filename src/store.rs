@@ -1,35 +1,58 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Debug;
-use std::ops::Neg;
+use std::mem;
+use std::ops::{Neg, Range};
 use std::rc::Rc;
 use std::sync::Arc;
 
 use crate::compiler::ModuleCode;
-use crate::error::{Error, Result};
+use crate::coverage::{Coverage, CoverageCounters};
+use crate::error::{Error, InstantiationError, Result};
 use crate::{
-    compiler, ensure, instantiation_err, trap, AddrType, DataMode, ElementMode, ImportDescription,
-    Instruction, Module, Mutability, Trap,
+    compiler, ensure, instantiation_err, trap, AddrType, DataMode, ElementMode, Instruction,
+    Linker, Module, Mutability, Trap,
 };
 
+use crate::backtrace::{Backtrace, FrameInfo};
 use crate::binary_grammar::{
-    CompositeType, DataSegment, ElementSegment, ExportDescription, Function, FunctionType, Global,
+    CompositeType, DataSegment, ElementSegment, ExportDescription, Function, FunctionType,
     GlobalType, MemoryType, ParsedModule, RefType, SubType, TableType, ValueType,
 };
 use crate::execution_grammar::{
     AddressMap, DataInstance, ElementInstance, ExportInstance, ExternalValue, FunctionInstance,
-    GlobalInstance, MemoryInstance, Ref, TableInstance, TagInstance,
+    GlobalInstance, MemoryInstance, Ref, TableInstance, TagInstance, Value,
 };
-use crate::ir::{CompiledFunction, Op};
+use crate::fuel::FuelCosts;
+use crate::host_log::{HostEvent, HostLog};
+use crate::interrupt::InterruptHandle;
+use crate::ir::{CompiledFunction, OffsetMap, Op};
+use crate::limits::StoreLimits;
+use crate::profiler::{Profile, ProfileFrame, Profiler};
+use crate::recording::{HostResult, Recording, Search, Seek};
 use crate::snapshot::{decode_bulk, encode_bulk, Snapshot, SNAPSHOT_MAGIC, SNAPSHOT_VERSION};
+use crate::trace::{TracedFunction, Tracer};
 use crate::value_stack::ValueStack;
+use crate::wasi::{Wasi, WASI_MODULE};
+use crate::watchpoint::{WatchHit, WatchKind, Watchpoint};
 use crate::RawValue;
 
 pub const PAGE_SIZE: usize = 65536;
 pub const MAX_CALL_DEPTH: usize = 1024;
+pub const MAX_STACK_VALUES: usize = 1 << 20;
+
+/// The positive quiet NaNs with no payload that deterministic stores produce
+const CANONICAL_NAN_F32: u32 = 0x7FC0_0000;
+const CANONICAL_NAN_F64: u64 = 0x7FF8_0000_0000_0000;
 
 #[derive(Debug, Clone)]
 pub enum ExecutionState {
     Completed(Vec<RawValue>),
     FuelExhausted,
+    Interrupted,
+    /// Paused at a breakpoint or at the end of a [`Store::step`]
+    Breakpoint,
+    /// Paused after an op that accessed watched memory
+    Watchpoint(Box<WatchHit>),
     Suspended {
         module_name: String,
         func_name: String,
@@ -37,11 +60,25 @@ pub enum ExecutionState {
     },
 }
 
+/// How far [`Store::step`] runs before pausing again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    /// Stops at the next instruction, entering calls
+    Into,
+    /// Stops at the next instruction of the current frame or a caller
+    Over,
+    /// Stops once the current frame has returned
+    Out,
+}
+
 impl ExecutionState {
     pub fn into_completed(self) -> Result<Vec<RawValue>> {
         match self {
             Self::Completed(v) => Ok(v),
             Self::FuelExhausted => instantiation_err!("execution paused: fuel exhausted"),
+            Self::Interrupted => instantiation_err!("execution paused: interrupted"),
+            Self::Breakpoint => instantiation_err!("execution paused: breakpoint"),
+            Self::Watchpoint(_) => instantiation_err!("execution paused: watchpoint"),
             Self::Suspended { func_name, .. } => {
                 instantiation_err!("execution suspended on host function: {}", func_name)
             }
@@ -67,12 +104,27 @@ macro_rules! pop_val {
     };
 }
 
+/// Takes a branch, pausing on back-edges if an interrupt was requested
+macro_rules! branch {
+    ($self:expr, $depth:expr, $target:expr) => {{
+        let frame = &mut $self.call_stack[$depth];
+        let target = $target as usize;
+        // pc already points past the branch, so a loop jumps strictly backwards
+        let back_edge = target < frame.pc;
+        frame.pc = target;
+
+        if back_edge && $self.interrupt.take() {
+            return Ok(RunOutcome::Interrupted);
+        }
+    }};
+}
+
 macro_rules! cmp_branch_zero {
     ($self:expr, $depth:expr, $ty:ident, $target:expr, $keep:expr, $drop:expr, $op:tt) => {{
         let a = pop_val!($self, $ty);
         if a $op 0 {
             $self.stack.keep_top($keep as usize, $drop as usize);
-            $self.call_stack[$depth].pc = $target as usize;
+            branch!($self, $depth, $target);
         }
     }};
 }
@@ -83,7 +135,7 @@ macro_rules! cmp_branch {
         let b = pop_val!($self, $ty);
         if b $op a {
             $self.stack.keep_top($keep as usize, $drop as usize);
-            $self.call_stack[$depth].pc = $target as usize;
+            branch!($self, $depth, $target);
         }
     }};
     ($self:expr, $depth:expr, $ty:ident, $target:expr, $keep:expr, $drop:expr, $cast:ty, $op:tt) => {{
@@ -91,7 +143,7 @@ macro_rules! cmp_branch {
         let b = pop_val!($self, $ty);
         if (b as $cast) $op (a as $cast) {
             $self.stack.keep_top($keep as usize, $drop as usize);
-            $self.call_stack[$depth].pc = $target as usize;
+            branch!($self, $depth, $target);
         }
     }};
 }
@@ -104,6 +156,15 @@ macro_rules! binop {
     }};
 }
 
+/// Like `binop!`, with NaN results canonicalized in deterministic stores
+macro_rules! float_binop {
+    ($self:expr, $variant:ident, $push:ident, |$b:ident, $a:ident| $expr:expr) => {{
+        let $a = pop_val!($self, $variant);
+        let $b = pop_val!($self, $variant);
+        $self.$push($expr);
+    }};
+}
+
 macro_rules! cmpop {
     ($self:expr, $variant:ident, |$b:ident, $a:ident| $expr:expr) => {{
         let $a = pop_val!($self, $variant);
@@ -151,7 +212,12 @@ macro_rules! mem_store_c {
 enum RunOutcome {
     Completed,
     FuelExhausted,
+    Interrupted,
+    Breakpoint,
+    Watchpoint(Box<WatchHit>),
     Suspended,
+    /// A recording checkpoint is due before the next op
+    Checkpoint,
 }
 
 /// A handle to an instantiated WASM module in the store
@@ -160,6 +226,53 @@ enum RunOutcome {
 #[derive(Debug, Copy, Clone)]
 pub struct Instance(pub(crate) usize);
 
+/// A handle to an invocation started with [`Store::spawn`]
+///
+/// Handles aren't reused, so one whose invocation has finished stays invalid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Execution(pub(crate) usize);
+
+/// The stacks and pause state of a paused [`Execution`]. The one that runs
+/// lives in the store's own fields
+pub struct ExecutionContext {
+    pub(crate) stack: ValueStack,
+    pub(crate) call_stack: Vec<CallFrame>,
+    pub(crate) pending_arity: Option<usize>,
+    pub(crate) awaiting_host: Option<usize>,
+    pub(crate) nested: Vec<Nesting>,
+    pub(crate) base_depth: usize,
+    pub(crate) step: Option<(StepKind, usize)>,
+    pub(crate) resuming: bool,
+}
+
+/// An invocation waiting on a host call while the embedder invokes the
+/// guest again on top of it
+#[derive(Debug, Clone, Copy)]
+pub struct Nesting {
+    /// Results the waiting invocation returns
+    pub(crate) arity: usize,
+    /// The host function it waits on
+    pub(crate) host_func: usize,
+    /// Its call stack and value stack heights
+    pub(crate) depth: usize,
+    pub(crate) stack_height: usize,
+}
+
+impl ExecutionContext {
+    fn new() -> Self {
+        Self {
+            stack: ValueStack::with_capacity(1024),
+            call_stack: Vec::new(),
+            pending_arity: None,
+            awaiting_host: None,
+            nested: vec![],
+            base_depth: 0,
+            step: None,
+            resuming: false,
+        }
+    }
+}
+
 pub struct CallFrame {
     pub module_idx: u16,
     pub compiled_func_idx: u32,
@@ -205,8 +318,52 @@ pub struct Store {
     stack: ValueStack,
     call_stack: Vec<CallFrame>,
     fuel: Option<u64>,
+    fuel_costs: FuelCosts,
     pending_arity: Option<usize>,
-    pending_suspension: Option<(String, String, Vec<RawValue>)>,
+    /// The host function the guest just called and its arguments
+    pending_suspension: Option<(usize, Vec<RawValue>)>,
+    /// The host function the guest waits on the embedder to answer
+    awaiting_host: Option<usize>,
+    /// Invocations made while the guest waited on a host call, innermost last
+    nested: Vec<Nesting>,
+    /// The call depth the innermost invocation returns at
+    base_depth: usize,
+    /// Why the last invocation trapped
+    last_trap: Option<Trap>,
+    /// Paused executions other than the store's own invocation
+    executions: BTreeMap<usize, ExecutionContext>,
+    next_execution: usize,
+    /// The execution in the store's fields, `None` for its own invocation
+    running: Option<Execution>,
+
+    limits: StoreLimits,
+    interrupt: InterruptHandle,
+
+    // debugging
+    /// (instance_idx, compiled_func_idx, pc) of every breakpoint
+    breakpoints: BTreeSet<(u16, u32, usize)>,
+    /// The pending step and the call depth it started at
+    step: Option<(StepKind, usize)>,
+    /// Set while paused at a debug stop, so resuming runs the op instead of
+    /// stopping on it again
+    resuming: bool,
+    watchpoints: Vec<Watchpoint>,
+    /// A watched access by the op that just ran, reported before the next
+    watch_hit: Option<Box<WatchHit>>,
+    /// Whether `run` checks for debug stops at all
+    debugging: bool,
+    recording: Option<Box<Recording>>,
+    host_log: Option<Box<HostLog>>,
+
+    tracer: Option<Box<dyn Tracer>>,
+    /// Cached [`Tracer::traces_ops`] of the installed tracer
+    trace_ops: bool,
+    profiler: Option<Box<Profiler>>,
+    coverage: Option<CoverageCounters>,
+    /// Answers `wasi_snapshot_preview1` imports without suspending
+    wasi: Option<Box<Wasi>>,
+    /// The seed of deterministic stores, see [`Store::deterministic`]
+    deterministic: Option<u64>,
 }
 
 impl Default for Store {
@@ -240,13 +397,88 @@ impl Store {
             stack: ValueStack::with_capacity(1024),
             call_stack: Vec::new(),
             fuel: None,
+            fuel_costs: FuelCosts::default(),
             pending_arity: None,
             pending_suspension: None,
+            awaiting_host: None,
+            nested: vec![],
+            base_depth: 0,
+            last_trap: None,
+            executions: BTreeMap::new(),
+            next_execution: 0,
+            running: None,
             instances: vec![],
             func_addr_to_module: vec![],
+            limits: StoreLimits::default(),
+            interrupt: InterruptHandle::default(),
+            breakpoints: BTreeSet::new(),
+            step: None,
+            resuming: false,
+            watchpoints: vec![],
+            watch_hit: None,
+            debugging: false,
+            recording: None,
+            host_log: None,
+            tracer: None,
+            trace_ops: false,
+            profiler: None,
+            coverage: None,
+            wasi: None,
+            deterministic: None,
         }
     }
 
+    pub fn with_limits(limits: StoreLimits) -> Self {
+        let mut store = Self::new();
+        store.set_limits(limits);
+        store
+    }
+
+    /// A store whose runs give bit-identical results on every host, so that
+    /// snapshots resume the same anywhere
+    ///
+    /// Float ops return canonical NaNs, WASI clocks are virtual and advance
+    /// only as the guest reads or waits on them, and WASI random bytes come
+    /// from a generator seeded with `seed`. All of it is part of snapshots.
+    /// Modules using relaxed SIMD are refused; threads aren't supported at
+    /// all. Host functions, host files and stdin remain up to the embedder.
+    pub fn deterministic(seed: u64) -> Self {
+        let mut store = Self::new();
+        store.deterministic = Some(seed);
+        store
+    }
+
+    /// Makes the store deterministic from now on, see [`Store::deterministic`].
+    /// Fails if an instance already uses non-deterministic features
+    pub fn set_deterministic(&mut self, seed: u64) -> Result<()> {
+        for instance in &self.instances {
+            Self::check_deterministic(&instance.code)?;
+        }
+
+        self.deterministic = Some(seed);
+        if let Some(wasi) = &mut self.wasi {
+            wasi.seed(seed);
+        }
+        Ok(())
+    }
+
+    pub const fn is_deterministic(&self) -> bool {
+        self.deterministic.is_some()
+    }
+
+    fn check_deterministic(code: &ModuleCode) -> Result<()> {
+        let relaxed = code
+            .compiled_funcs
+            .iter()
+            .flat_map(|cf| &cf.ops)
+            .any(Op::is_relaxed_simd);
+        ensure!(
+            !relaxed,
+            Error::Instantiation("relaxed SIMD is not allowed in deterministic stores".into())
+        );
+        Ok(())
+    }
+
     pub fn instance(&self, index: usize) -> Instance {
         assert!(index < self.instances.len(), "instance index out of bounds");
         Instance(index)
@@ -273,7 +505,7 @@ impl Store {
         let fi = self
             .functions
             .get(addr)
-            .ok_or_else(|| Error::Instantiation(format!("function addr {} oob", addr)))?;
+            .ok_or_else(|| Error::Instantiation(format!("function addr {} oob", addr).into()))?;
         match fi {
             FunctionInstance::Local { function_type, .. }
             | FunctionInstance::Host { function_type, .. } => Ok(function_type.0 .0.clone()),
@@ -292,13 +524,228 @@ impl Store {
         self.fuel
     }
 
+    pub const fn set_fuel_costs(&mut self, fuel_costs: FuelCosts) {
+        self.fuel_costs = fuel_costs;
+    }
+
+    pub const fn fuel_costs(&self) -> &FuelCosts {
+        &self.fuel_costs
+    }
+
+    /// Fuel needed to execute `op` against the current stack
+    fn fuel_cost(&self, mi: usize, op: &Op) -> u64 {
+        let len = match *op {
+            Op::MemoryCopy { dst_memory_idx, .. } => {
+                let ma = self.instances[mi].mem_addrs[dst_memory_idx as usize];
+                self.stack
+                    .peek_address(self.memories[ma].memory_type.addr_type)
+            }
+            Op::MemoryFill { memory_idx } => {
+                let ma = self.instances[mi].mem_addrs[memory_idx as usize];
+                self.stack
+                    .peek_address(self.memories[ma].memory_type.addr_type)
+            }
+            Op::TableCopy {
+                dst_table_idx,
+                src_table_idx,
+            } => {
+                let dst = self.instances[mi].table_addrs[dst_table_idx as usize];
+                let src = self.instances[mi].table_addrs[src_table_idx as usize];
+                let at = match (
+                    self.tables[dst].table_type.addr_type,
+                    self.tables[src].table_type.addr_type,
+                ) {
+                    (AddrType::I64, AddrType::I64) => AddrType::I64,
+                    _ => AddrType::I32,
+                };
+                self.stack.peek_address(at)
+            }
+            Op::TableFill { table_idx } => {
+                let ta = self.instances[mi].table_addrs[table_idx as usize];
+                self.stack
+                    .peek_address(self.tables[ta].table_type.addr_type)
+            }
+            Op::MemoryInit { .. } | Op::TableInit { .. } => self.stack.peek_address(AddrType::I32),
+            Op::Call { func_idx } | Op::ReturnCall { func_idx } => {
+                let func_addr = self.instances[mi].function_addrs[func_idx as usize];
+                return self.call_cost(op, Some(func_addr));
+            }
+            Op::CallIndirect { table_idx, .. } | Op::ReturnCallIndirect { table_idx, .. } => {
+                let table = &self.tables[self.instances[mi].table_addrs[table_idx as usize]];
+                let i = self.stack.peek_address(table.table_type.addr_type);
+                let func_addr = match table.elem.get(i) {
+                    Some(Ref::FunctionAddr(func_addr)) => Some(*func_addr),
+                    _ => None,
+                };
+                return self.call_cost(op, func_addr);
+            }
+            Op::CallRef { .. } | Op::ReturnCallRef { .. } => {
+                let func_addr = match self.stack.last().as_ref() {
+                    Ref::FunctionAddr(func_addr) => Some(func_addr),
+                    _ => None,
+                };
+                return self.call_cost(op, func_addr);
+            }
+            _ => return self.fuel_costs.op_cost(op),
+        };
+
+        self.fuel_costs
+            .op_cost(op)
+            .saturating_add(self.fuel_costs.bulk_len_cost(len as u64))
+    }
+
+    /// A call's cost, with the host call surcharge if it reaches the host.
+    /// Calls that are about to trap pay the plain cost
+    fn call_cost(&self, op: &Op, func_addr: Option<usize>) -> u64 {
+        let cost = self.fuel_costs.op_cost(op);
+        match func_addr.and_then(|func_addr| self.functions.get(func_addr)) {
+            Some(FunctionInstance::Host { .. }) => cost.saturating_add(self.fuel_costs.host_call),
+            _ => cost,
+        }
+    }
+
+    pub const fn limits(&self) -> &StoreLimits {
+        &self.limits
+    }
+
+    /// Replaces the store's limits. Resources already allocated past the new
+    /// limits are kept, but they can no longer grow
+    pub const fn set_limits(&mut self, limits: StoreLimits) {
+        self.limits = limits;
+    }
+
+    /// A handle that pauses this store's execution from any thread
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Installs `tracer` to observe execution, replacing any previous one.
+    /// Unless it traces ops, the run loop does no per-op work for it
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.trace_ops = tracer.traces_ops();
+        self.tracer = Some(tracer);
+        self.sync_debugging();
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.trace_ops = false;
+        self.sync_debugging();
+        self.tracer.take()
+    }
+
+    /// Answers WASI imports with `wasi`, replacing any previous state. The
+    /// imports themselves are defined by [`crate::Linker::wasi`]
+    pub fn set_wasi(&mut self, mut wasi: Wasi) {
+        if let Some(seed) = self.deterministic {
+            wasi.seed(seed);
+        }
+        self.wasi = Some(Box::new(wasi));
+    }
+
+    /// WASI state, restored along with the rest of the store by
+    /// [`Store::from_snapshot`]
+    pub fn wasi(&self) -> Option<&Wasi> {
+        self.wasi.as_deref()
+    }
+
+    pub fn wasi_mut(&mut self) -> Option<&mut Wasi> {
+        self.wasi.as_deref_mut()
+    }
+
+    pub fn take_wasi(&mut self) -> Option<Wasi> {
+        self.wasi.take().map(|wasi| *wasi)
+    }
+
+    /// Samples the guest call stack every `interval` ops from now on,
+    /// discarding any previous samples
+    pub fn start_profiling(&mut self, interval: u64) {
+        self.profiler = Some(Box::new(Profiler::new(interval)));
+    }
+
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        let profile = self.profile();
+        self.profiler = None;
+        profile
+    }
+
+    /// The samples taken so far, `None` when not profiling. Functions are
+    /// named from the name section where it has them, and innermost frames
+    /// are placed on a source line where the module has DWARF
+    pub fn profile(&self) -> Option<Profile> {
+        let profiler = self.profiler.as_deref()?;
+        let mut stacks: HashMap<Vec<ProfileFrame>, u64> = HashMap::new();
+        for (frames, pcs) in &profiler.stacks {
+            for (&pc, &count) in pcs {
+                let stack = frames
+                    .iter()
+                    .enumerate()
+                    .map(|(depth, &(module_idx, compiled_idx))| {
+                        let function = traced_function(&self.instances, module_idx, compiled_idx);
+                        let code = &self.instances[module_idx as usize].code;
+                        let location = match depth + 1 == frames.len() {
+                            true => code.compiled_funcs[compiled_idx as usize]
+                                .offsets
+                                .wasm_offset(pc)
+                                .and_then(|offset| code.source_location(offset)),
+                            false => None,
+                        };
+                        ProfileFrame {
+                            module_idx: function.module_idx,
+                            func_idx: function.func_idx,
+                            name: function.name.map(str::to_owned),
+                            location,
+                        }
+                    })
+                    .collect();
+                *stacks.entry(stack).or_default() += count;
+            }
+        }
+
+        let mut stacks = stacks.into_iter().collect::<Vec<_>>();
+        stacks.sort();
+        Some(Profile {
+            interval: profiler.interval,
+            stacks,
+        })
+    }
+
+    /// Counts how often each op runs from now on, discarding earlier counts.
+    /// The counts are kept in snapshots
+    pub fn start_coverage(&mut self) {
+        self.coverage = Some(CoverageCounters::default());
+    }
+
+    pub fn stop_coverage(&mut self) -> Option<Coverage> {
+        let coverage = self.coverage();
+        self.coverage = None;
+        coverage
+    }
+
+    /// The blocks run so far in every instance, `None` when not collecting
+    /// coverage
+    pub fn coverage(&self) -> Option<Coverage> {
+        let counters = self.coverage.as_ref()?;
+        Some(counters.report(self.instances.iter().map(|instance| &*instance.code)))
+    }
+
+    fn memory_bytes(&self) -> u64 {
+        self.memories.iter().map(|m| m.data.len() as u64).sum()
+    }
+
+    fn table_elements(&self) -> u64 {
+        self.tables.iter().map(|t| t.elem.len() as u64).sum()
+    }
+
     fn extract_function_type(types: &[SubType], type_index: u32) -> Result<FunctionType> {
         let sub_type = types.get(type_index as usize).ok_or_else(|| {
-            Error::Instantiation(format!(
-                "Type index {} too large to index into types. Len: {}",
-                type_index,
-                types.len()
-            ))
+            Error::Instantiation(
+                format!(
+                    "Type index {} too large to index into types. Len: {}",
+                    type_index,
+                    types.len()
+                )
+                .into(),
+            )
         })?;
 
         match &sub_type.composite_type {
@@ -326,9 +773,17 @@ impl Store {
         Ok(f_address)
     }
 
-    fn allocate_table(&mut self, table_type: TableType, initial_ref: Ref) -> usize {
+    pub(crate) fn allocate_table(
+        &mut self,
+        table_type: TableType,
+        initial_ref: Ref,
+    ) -> Result<usize> {
         let n = table_type.limit.min;
 
+        if !self.limits.allows_table_elements(self.table_elements(), n) {
+            instantiation_err!("table of {} elements exceeds the store's table limit", n);
+        }
+
         let table_address = self.tables.len();
 
         self.tables.push(TableInstance {
@@ -336,32 +791,57 @@ impl Store {
             elem: vec![initial_ref; n as usize],
         });
 
-        table_address
+        Ok(table_address)
     }
 
-    fn allocate_memory(&mut self, memory_type: MemoryType) -> usize {
+    pub(crate) fn allocate_memory(&mut self, memory_type: MemoryType) -> Result<usize> {
         let memory_address = self.memories.len();
         let n = memory_type.limit.min as usize * PAGE_SIZE;
 
+        if !self.limits.allows_memory(self.memory_bytes(), n as u64) {
+            instantiation_err!("memory of {} bytes exceeds the store's memory limit", n);
+        }
+
         self.memories.push(MemoryInstance {
             memory_type,
             data: vec![0u8; n],
         });
 
-        memory_address
+        Ok(memory_address)
     }
 
-    fn allocate_global(&mut self, global: Global, initializer_value: RawValue) -> usize {
+    pub(crate) fn allocate_global(
+        &mut self,
+        global_type: GlobalType,
+        initializer_value: RawValue,
+    ) -> usize {
         let global_address = self.globals.len();
 
         self.globals.push(GlobalInstance {
-            global_type: global.global_type,
+            global_type,
             value: initializer_value,
         });
 
         global_address
     }
 
+    pub(crate) fn allocate_host_function(
+        &mut self,
+        module_name: &str,
+        function_name: &str,
+        function_type: FunctionType,
+    ) -> usize {
+        let addr = self.functions.len();
+
+        self.functions.push(FunctionInstance::Host {
+            function_type,
+            module_name: module_name.to_owned(),
+            function_name: function_name.to_owned(),
+        });
+
+        addr
+    }
+
     fn allocate_element_segment(
         &mut self,
         element_segment: ElementSegment,
@@ -433,22 +913,20 @@ impl Store {
                 .globals
                 .into_iter()
                 .zip(initial_global_values)
-                .map(|(global, init_val)| self.allocate_global(global, init_val)),
+                .map(|(global, init_val)| self.allocate_global(global.global_type, init_val)),
         );
 
         // step 29-30
-        address_map
-            .mem_addrs
-            .extend(module.mems.into_iter().map(|m| self.allocate_memory(m)));
+        for mem in module.mems {
+            let addr = self.allocate_memory(mem)?;
+            address_map.mem_addrs.push(addr);
+        }
 
         // step 31-32
-        address_map.table_addrs.extend(
-            module
-                .tables
-                .into_iter()
-                .zip(initial_table_refs)
-                .map(|(td, ref_t)| self.allocate_table(td.table_type, ref_t)),
-        );
+        for (td, ref_t) in module.tables.into_iter().zip(initial_table_refs) {
+            let addr = self.allocate_table(td.table_type, ref_t)?;
+            address_map.table_addrs.push(addr);
+        }
 
         // step 35-36
         address_map.data_addrs.extend(
@@ -477,36 +955,29 @@ impl Store {
 
         // step 33-34
         for export in &module.exports {
+            let addr = |addrs: &[usize], idx: u32| {
+                addrs.get(idx as usize).copied().ok_or_else(|| {
+                    InstantiationError::ExportIndexOutOfBounds {
+                        name: export.name.clone(),
+                        description: export.description.clone(),
+                    }
+                })
+            };
             let extern_value = match export.description {
                 ExportDescription::Func(x) => ExternalValue::Function {
-                    addr: *address_map
-                        .function_addrs
-                        .get(x as usize)
-                        .ok_or_else(|| Error::Instantiation("oob".into()))?,
+                    addr: addr(&address_map.function_addrs, x)?,
                 },
                 ExportDescription::Table(x) => ExternalValue::Table {
-                    addr: *address_map
-                        .table_addrs
-                        .get(x as usize)
-                        .ok_or_else(|| Error::Instantiation("oob".into()))?,
+                    addr: addr(&address_map.table_addrs, x)?,
                 },
                 ExportDescription::Mem(x) => ExternalValue::Memory {
-                    addr: *address_map
-                        .mem_addrs
-                        .get(x as usize)
-                        .ok_or_else(|| Error::Instantiation("oob".into()))?,
+                    addr: addr(&address_map.mem_addrs, x)?,
                 },
                 ExportDescription::Global(x) => ExternalValue::Global {
-                    addr: *address_map
-                        .global_addrs
-                        .get(x as usize)
-                        .ok_or_else(|| Error::Instantiation("oob".into()))?,
+                    addr: addr(&address_map.global_addrs, x)?,
                 },
                 ExportDescription::Tag(x) => ExternalValue::Tag {
-                    addr: *address_map
-                        .tag_addrs
-                        .get(x as usize)
-                        .ok_or_else(|| Error::Instantiation("oob".into()))?,
+                    addr: addr(&address_map.tag_addrs, x)?,
                 },
             };
 
@@ -529,40 +1000,38 @@ impl Store {
         module: &Module,
         external_addresses: Vec<ExternalValue>,
     ) -> Result<Instance> {
-        // step 4
         ensure!(
-            module.import_declarations.len() == external_addresses.len(),
-            Error::Instantiation(format!(
-                "Expected {} imports, got {}",
-                module.import_declarations.len(),
-                external_addresses.len()
-            ))
+            self.instances.len() < self.limits.max_instances,
+            Error::Instantiation(
+                format!("instance limit of {} reached", self.limits.max_instances).into()
+            )
         );
 
-        // step 5
-        let is_compatible = |external_address: &ExternalValue,
-                             import_description: &ImportDescription| {
-            matches!(
-                (external_address, &import_description),
-                (ExternalValue::Function { .. }, ImportDescription::Func(_))
-                    | (ExternalValue::Table { .. }, ImportDescription::Table(_))
-                    | (ExternalValue::Memory { .. }, ImportDescription::Mem(_))
-                    | (ExternalValue::Global { .. }, ImportDescription::Global(_))
-                    | (ExternalValue::Tag { .. }, ImportDescription::Tag(_))
-            )
-        };
+        if self.deterministic.is_some() {
+            Self::check_deterministic(&module.code)?;
+        }
 
+        // step 4
         ensure!(
-            external_addresses
-                .iter()
-                .zip(module.import_declarations.iter())
-                .all(|(extern_addr, import_decl)| is_compatible(
-                    extern_addr,
-                    &import_decl.description
-                )),
-            Error::Instantiation("import type mismatch".into())
+            module.import_declarations.len() == external_addresses.len(),
+            Error::Instantiation(
+                format!(
+                    "Expected {} imports, got {}",
+                    module.import_declarations.len(),
+                    external_addresses.len()
+                )
+                .into()
+            )
         );
 
+        // step 5
+        for (extern_addr, import_decl) in external_addresses
+            .iter()
+            .zip(module.import_declarations.iter())
+        {
+            Linker::check_import(self, module, import_decl, extern_addr)?;
+        }
+
         // step 6
         let data_instructions = module
             .data_segments
@@ -664,6 +1133,8 @@ impl Store {
             exports: module.exports.clone(),
             tags: module.tags.clone(),
             customs: vec![],
+            names: crate::binary_grammar::NameMap::new(),
+            code_offset: 0,
         };
         let module_instance = self.allocate_module(
             parsed_clone,
@@ -705,7 +1176,7 @@ impl Store {
 
         // compile any imported local functions not yet in the compiled set
         let types_for_compile = entity.code.types.clone();
-        for &addr in &module_instance.function_addrs {
+        for (func_idx, &addr) in module_instance.function_addrs.iter().enumerate() {
             if self
                 .func_addr_to_module
                 .get(addr)
@@ -715,7 +1186,11 @@ impl Store {
             }
             if let FunctionInstance::Local { code, .. } = &self.functions[addr] {
                 let code_mut = Arc::make_mut(&mut entity.code);
-                let cf = compiler::compile_function_into_code(&types_for_compile, code, code_mut);
+                let mut cf =
+                    compiler::compile_function_into_code(&types_for_compile, code, code_mut);
+                cf.func_idx = Some(func_idx as u32);
+                // the body's offsets point into the module that defined it
+                cf.offsets = OffsetMap::default();
                 let idx = code_mut.compiled_funcs.len();
                 code_mut.compiled_funcs.push(cf);
                 if addr < self.func_addr_to_module.len() {
@@ -724,122 +1199,986 @@ impl Store {
             }
         }
 
-        self.instances.push(entity);
-        self.ensure_stack_capacity();
-        let instance = Instance(instance_idx as usize);
+        self.instances.push(entity);
+        let instance = Instance(instance_idx as usize);
+
+        // step 27 - execute element segment initialization
+        // step 28 - execute data segment initialization
+        let init_instructions = [element_instructions, data_instructions].concat();
+        if !init_instructions.is_empty() {
+            let init = self.run_init_instructions(&init_instructions, instance_idx);
+            if let Err(Error::Trap(
+                Trap::OutOfBoundsTableAccess | Trap::OutOfBoundsMemoryAccess,
+                _,
+            )) = init
+            {
+                if let Some(err) = self.segment_out_of_bounds(module, &module_instance) {
+                    return Err(err.into());
+                }
+            }
+            init?;
+        }
+
+        // step 29: invoke start function if present
+        if let Some(start_idx) = start_func_idx {
+            let func_addr = *module_instance
+                .function_addrs
+                .get(start_idx as usize)
+                .ok_or_else(|| {
+                    Error::Instantiation(format!("start function index {} oob", start_idx).into())
+                })?;
+            if self.push_function_call(func_addr)? {
+                instantiation_err!("start function cannot be a host import");
+            }
+
+            // the start function can't be paused, there's no instance to resume
+            let outcome = self.run_detached().map_err(|e| self.unwind(e))?;
+            if !matches!(outcome, RunOutcome::Completed) {
+                self.stack.clear();
+                self.call_stack.clear();
+                instantiation_err!("start function did not run to completion");
+            }
+        }
+
+        // step 31
+        Ok(instance)
+    }
+
+    /// The first active segment, in initialization order, that doesn't fit
+    fn segment_out_of_bounds(
+        &self,
+        module: &Module,
+        address_map: &AddressMap,
+    ) -> Option<InstantiationError> {
+        let eval_offset = |offset: &[Instruction], addr_type| {
+            let value = eval_const_expr_with_module(offset, self, address_map).ok()?;
+            Some(match addr_type {
+                AddrType::I32 => value.as_i32() as u32 as u64,
+                AddrType::I64 => value.as_i64() as u64,
+            })
+        };
+
+        for (i, segment) in module.element_segments.iter().enumerate() {
+            let ElementMode::Active {
+                table_index,
+                offset,
+            } = &segment.mode
+            else {
+                continue;
+            };
+            let table = &self.tables[*address_map.table_addrs.get(*table_index as usize)?];
+            let offset = eval_offset(offset, table.table_type.addr_type)?;
+            let len = segment.expression.len() as u64;
+            let table_size = table.elem.len() as u64;
+
+            if offset > table_size || len > table_size - offset {
+                return Some(InstantiationError::ElementSegmentOutOfBounds {
+                    segment: i as u32,
+                    offset,
+                    len,
+                    table_size,
+                });
+            }
+        }
+
+        for (i, segment) in module.data_segments.iter().enumerate() {
+            let DataMode::Active { memory, offset } = &segment.mode else {
+                continue;
+            };
+            let mem = &self.memories[*address_map.mem_addrs.get(*memory as usize)?];
+            let offset = eval_offset(offset, mem.memory_type.addr_type)?;
+            let len = segment.bytes.len() as u64;
+            let memory_size = mem.data.len() as u64;
+
+            if offset > memory_size || len > memory_size - offset {
+                return Some(InstantiationError::DataSegmentOutOfBounds {
+                    segment: i as u32,
+                    offset,
+                    len,
+                    memory_size,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Makes room for `n` more values, trapping once the stack limit is reached
+    fn reserve_stack(&mut self, n: usize) -> Result<()> {
+        ensure!(
+            self.stack.len() + n <= self.limits.max_stack_values,
+            Error::from(Trap::CallStackExhausted)
+        );
+
+        self.stack.reserve(n);
+        Ok(())
+    }
+
+    /// Stack slots a frame of `cf` may use above its base. The compiler counts
+    /// a v128 as one value, but it takes two slots
+    const fn frame_height(cf: &CompiledFunction) -> usize {
+        2 * cf.max_stack_height as usize
+    }
+
+    /// Calls the export `name` of `instance`
+    ///
+    /// While the guest waits on a host call, the call runs nested on top of
+    /// the waiting frames, which can be answered once it has completed or
+    /// trapped.
+    pub fn invoke(
+        &mut self,
+        instance: Instance,
+        name: &str,
+        args: Vec<RawValue>,
+    ) -> Result<ExecutionState> {
+        let addr = self.get_func(instance, name)?;
+        self.invoke_by_addr(addr, args)
+    }
+
+    pub fn resume(&mut self) -> Result<ExecutionState> {
+        let arity = self
+            .pending_arity
+            .ok_or_else(|| Error::Instantiation("no pending execution to resume".into()))?;
+        self.check_host_results(&[])?;
+
+        self.finish_run(arity)
+    }
+
+    /// Answers the pending host call with `return_values`, which must be as
+    /// many as the import returns. Resuming from other pauses takes none
+    pub fn resume_with(&mut self, return_values: &[RawValue]) -> Result<ExecutionState> {
+        let arity = self
+            .pending_arity
+            .ok_or_else(|| Error::Instantiation("no pending execution to resume".into()))?;
+        self.check_host_results(return_values)?;
+
+        self.reserve_stack(return_values.len())?;
+        for val in return_values {
+            self.stack.push(*val);
+        }
+
+        if let Some(recording) = &mut self.recording {
+            recording.record_host_result(return_values);
+        }
+        self.log_host_event(|| HostEvent::Return(return_values.to_vec()));
+        if let Some(tracer) = &mut self.tracer {
+            tracer.host_return(return_values);
+        }
+
+        self.finish_run(arity)
+    }
+
+    /// Invokes `name` as an execution of its own, with its own stacks, so
+    /// that it can stay paused while the store runs other invocations
+    ///
+    /// The handle is for resuming it with [`Store::resume_execution`] and
+    /// [`Store::resume_execution_with`] until it completes or traps. Time
+    /// travel only covers invocations made with [`Store::invoke`].
+    pub fn spawn(
+        &mut self,
+        instance: Instance,
+        name: &str,
+        args: Vec<RawValue>,
+    ) -> Result<(Execution, ExecutionState)> {
+        let addr = self.get_func(instance, name)?;
+        self.spawn_by_addr(addr, args)
+    }
+
+    pub(crate) fn spawn_by_addr(
+        &mut self,
+        function_addr: usize,
+        args: Vec<RawValue>,
+    ) -> Result<(Execution, ExecutionState)> {
+        let execution = Execution(self.next_execution);
+        self.next_execution += 1;
+        self.executions.insert(execution.0, ExecutionContext::new());
+
+        let state =
+            self.in_execution(execution, |store| store.invoke_by_addr(function_addr, args))?;
+        Ok((execution, state))
+    }
+
+    pub fn resume_execution(&mut self, execution: Execution) -> Result<ExecutionState> {
+        self.in_execution(execution, Self::resume)
+    }
+
+    pub fn resume_execution_with(
+        &mut self,
+        execution: Execution,
+        return_values: &[RawValue],
+    ) -> Result<ExecutionState> {
+        self.in_execution(execution, |store| store.resume_with(return_values))
+    }
+
+    pub fn resume_execution_with_trap(
+        &mut self,
+        execution: Execution,
+        trap: Trap,
+    ) -> Result<ExecutionState> {
+        self.in_execution(execution, |store| store.resume_with_trap(trap))
+    }
+
+    /// Abandons a paused execution
+    pub fn cancel(&mut self, execution: Execution) -> Result<()> {
+        match self.executions.remove(&execution.0) {
+            Some(_) => Ok(()),
+            None => instantiation_err!("no paused execution {}", execution.0),
+        }
+    }
+
+    /// The paused executions, oldest first
+    pub fn executions(&self) -> Vec<Execution> {
+        self.executions.keys().copied().map(Execution).collect()
+    }
+
+    /// Runs `f` with `execution` in place of the store's own invocation,
+    /// keeping the execution only while it's paused
+    fn in_execution(
+        &mut self,
+        execution: Execution,
+        f: impl FnOnce(&mut Self) -> Result<ExecutionState>,
+    ) -> Result<ExecutionState> {
+        let Some(mut context) = self.executions.remove(&execution.0) else {
+            instantiation_err!("no paused execution {}", execution.0);
+        };
+
+        self.swap_context(&mut context);
+        let running = self.running.replace(execution);
+        let recording = self.recording.take();
+        self.sync_debugging();
+
+        let state = f(self);
+
+        self.recording = recording;
+        self.running = running;
+        self.swap_context(&mut context);
+        self.sync_debugging();
+
+        if context.pending_arity.is_some() {
+            self.executions.insert(execution.0, context);
+        }
+        state
+    }
+
+    fn swap_context(&mut self, context: &mut ExecutionContext) {
+        mem::swap(&mut self.stack, &mut context.stack);
+        mem::swap(&mut self.call_stack, &mut context.call_stack);
+        mem::swap(&mut self.pending_arity, &mut context.pending_arity);
+        mem::swap(&mut self.awaiting_host, &mut context.awaiting_host);
+        mem::swap(&mut self.nested, &mut context.nested);
+        mem::swap(&mut self.base_depth, &mut context.base_depth);
+        mem::swap(&mut self.step, &mut context.step);
+        mem::swap(&mut self.resuming, &mut context.resuming);
+        self.watch_hit = None;
+    }
+
+    /// Answers the pending host call like [`Store::resume_with`], also
+    /// checking the values have the types the import returns
+    pub fn resume_with_values(&mut self, return_values: &[Value]) -> Result<ExecutionState> {
+        let result_types = self.host_result_types()?;
+        ensure!(
+            return_values.len() == result_types.len()
+                && return_values
+                    .iter()
+                    .zip(result_types)
+                    .all(|(value, value_type)| value.has_type(value_type)),
+            Error::Instantiation(
+                format!(
+                    "host call returns {:?}, got {:?}",
+                    result_types, return_values
+                )
+                .into()
+            )
+        );
+
+        let mut raw = Vec::with_capacity(return_values.len());
+        for value in return_values {
+            value.push_raw(&mut raw);
+        }
+        self.resume_with(&raw)
+    }
+
+    /// Makes the pending host call trap with `trap`, unwinding the guest as
+    /// if the import itself had trapped
+    pub fn resume_with_trap(&mut self, trap: Trap) -> Result<ExecutionState> {
+        ensure!(
+            self.awaiting_host.is_some(),
+            Error::Instantiation("not waiting on a host call".into())
+        );
+        self.log_host_event(|| HostEvent::Trap(trap.clone()));
+
+        self.awaiting_host = None;
+        self.pending_arity = None;
+        Err(self.unwind(trap.into()))
+    }
+
+    /// Why the last invocation trapped, until the next one starts. Kept in
+    /// snapshots, where an embedder's error is reduced to its message
+    pub const fn last_trap(&self) -> Option<&Trap> {
+        self.last_trap.as_ref()
+    }
+
+    /// The types the host call the guest waits on returns
+    pub fn host_result_types(&self) -> Result<&[ValueType]> {
+        match self.awaiting_host.map(|addr| &self.functions[addr]) {
+            Some(FunctionInstance::Host { function_type, .. }) => Ok(&function_type.1 .0),
+            _ => instantiation_err!("not waiting on a host call"),
+        }
+    }
+
+    /// Checks `return_values` fill the results of the awaited host call,
+    /// counting a v128 as two values
+    fn check_host_results(&self, return_values: &[RawValue]) -> Result<()> {
+        let expected = match self.awaiting_host {
+            Some(_) => self
+                .host_result_types()?
+                .iter()
+                .map(|value_type| match value_type {
+                    ValueType::V128 => 2,
+                    _ => 1,
+                })
+                .sum(),
+            None => 0,
+        };
+        ensure!(
+            return_values.len() == expected,
+            Error::Instantiation(
+                format!(
+                    "expected {} host results, got {}",
+                    expected,
+                    return_values.len()
+                )
+                .into()
+            )
+        );
+        Ok(())
+    }
+
+    /// Resumes a paused execution until it reaches the next instruction
+    /// selected by `kind`, a breakpoint or the end of the invocation
+    pub fn step(&mut self, kind: StepKind) -> Result<ExecutionState> {
+        ensure!(
+            self.pending_arity.is_some(),
+            Error::Instantiation("no pending execution to step".into())
+        );
+
+        self.step = Some((kind, self.call_stack.len()));
+        self.resuming = true;
+        self.debugging = true;
+        self.resume()
+    }
+
+    /// Pauses execution before the instruction at `wasm_offset` in function
+    /// `func_idx` of `instance`. The offset is a byte offset in the module
+    /// that defines the function
+    pub fn add_breakpoint(
+        &mut self,
+        instance: Instance,
+        func_idx: u32,
+        wasm_offset: u32,
+    ) -> Result<()> {
+        let key = self.breakpoint_key(instance, func_idx, wasm_offset)?;
+        self.breakpoints.insert(key);
+        self.debugging = true;
+        Ok(())
+    }
+
+    /// Returns whether a breakpoint was set at that instruction
+    pub fn remove_breakpoint(
+        &mut self,
+        instance: Instance,
+        func_idx: u32,
+        wasm_offset: u32,
+    ) -> Result<bool> {
+        let key = self.breakpoint_key(instance, func_idx, wasm_offset)?;
+        let removed = self.breakpoints.remove(&key);
+        self.sync_debugging();
+        Ok(removed)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.sync_debugging();
+    }
+
+    /// Pauses execution after any op that makes a `kind` access to `range`
+    /// of memory `memory_idx` of `instance`
+    pub fn add_watchpoint(
+        &mut self,
+        instance: Instance,
+        memory_idx: u32,
+        range: Range<u64>,
+        kind: WatchKind,
+    ) -> Result<()> {
+        let watchpoint = self.watchpoint(instance, memory_idx, range, kind)?;
+        self.watchpoints.push(watchpoint);
+        self.debugging = true;
+        Ok(())
+    }
+
+    /// Returns whether that exact watchpoint was set
+    pub fn remove_watchpoint(
+        &mut self,
+        instance: Instance,
+        memory_idx: u32,
+        range: Range<u64>,
+        kind: WatchKind,
+    ) -> Result<bool> {
+        let watchpoint = self.watchpoint(instance, memory_idx, range, kind)?;
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|wp| *wp != watchpoint);
+        self.sync_debugging();
+        Ok(self.watchpoints.len() != len)
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+        self.sync_debugging();
+    }
+
+    fn watchpoint(
+        &self,
+        instance: Instance,
+        memory_idx: u32,
+        range: Range<u64>,
+        kind: WatchKind,
+    ) -> Result<Watchpoint> {
+        ensure!(
+            !range.is_empty(),
+            Error::Instantiation(format!("empty watch range {:?}", range).into())
+        );
+
+        Ok(Watchpoint {
+            instance: instance.0,
+            memory_idx,
+            mem_addr: self.memory_addr(instance, memory_idx)?,
+            start: range.start,
+            end: range.end,
+            kind,
+        })
+    }
+
+    fn breakpoint_key(
+        &self,
+        instance: Instance,
+        func_idx: u32,
+        wasm_offset: u32,
+    ) -> Result<(u16, u32, usize)> {
+        let func_addr = *self.instances[instance.0]
+            .function_addrs
+            .get(func_idx as usize)
+            .ok_or_else(|| {
+                Error::Instantiation(format!("function index {} oob", func_idx).into())
+            })?;
+        let Some((module_idx, compiled_idx)) = self.compiled_func_index(func_addr) else {
+            instantiation_err!("function {} is a host function", func_idx);
+        };
+
+        let cf = &self.instances[module_idx as usize].code.compiled_funcs[compiled_idx as usize];
+        let Some(pc) = cf.offsets.pc(wasm_offset) else {
+            instantiation_err!(
+                "no instruction at offset {:#x} in function {}",
+                wasm_offset,
+                func_idx
+            );
+        };
+
+        Ok((module_idx, compiled_idx, pc))
+    }
+
+    /// The paused call stack, innermost frame first. The innermost frame is
+    /// at the next op to run, its callers at their calls
+    pub fn frames(&self) -> Vec<FrameInfo> {
+        let innermost = self.call_stack.len().saturating_sub(1);
+        self.call_stack
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, frame)| match i == innermost {
+                true => self.frame_info(frame, frame.pc),
+                false => self.frame_info(frame, frame.pc.saturating_sub(1)),
+            })
+            .collect()
+    }
+
+    /// Position in `call_stack` of `frame`, counted from the innermost frame
+    fn frame_position(&self, frame: usize) -> Result<usize> {
+        self.call_stack
+            .len()
+            .checked_sub(frame + 1)
+            .ok_or_else(|| Error::Instantiation(format!("no frame {}", frame).into()))
+    }
+
+    /// Arguments followed by declared locals
+    pub fn locals(&self, frame: usize) -> Result<&[RawValue]> {
+        Ok(&self.call_stack[self.frame_position(frame)?].locals)
+    }
+
+    pub fn set_local(&mut self, frame: usize, local_idx: u32, value: RawValue) -> Result<()> {
+        let position = self.frame_position(frame)?;
+        let Some(local) = self.call_stack[position].locals.get_mut(local_idx as usize) else {
+            instantiation_err!("local index {} oob", local_idx);
+        };
+
+        *local = value;
+        Ok(())
+    }
+
+    /// Range of the value stack holding the operands of `frame`
+    fn operand_range(&self, frame: usize) -> Result<std::ops::Range<usize>> {
+        let position = self.frame_position(frame)?;
+        let start = self.call_stack[position].stack_base;
+        let end = self
+            .call_stack
+            .get(position + 1)
+            .map_or(self.stack.len(), |callee| callee.stack_base);
+
+        Ok(start..end)
+    }
+
+    /// Operands of `frame`, bottom first. A v128 takes two slots
+    pub fn operand_stack(&self, frame: usize) -> Result<&[RawValue]> {
+        let range = self.operand_range(frame)?;
+        Ok(&self.stack.as_slice()[range])
+    }
+
+    pub fn set_operand(&mut self, frame: usize, idx: usize, value: RawValue) -> Result<()> {
+        let range = self.operand_range(frame)?;
+        ensure!(
+            idx < range.len(),
+            Error::Instantiation(format!("operand index {} oob", idx).into())
+        );
+
+        self.stack.as_mut_slice()[range.start + idx] = value;
+        self.log_host_event(|| HostEvent::SetOperand { frame, idx, value });
+        Ok(())
+    }
+
+    fn global_addr(&self, instance: Instance, global_idx: u32) -> Result<usize> {
+        self.instances[instance.0]
+            .global_addrs
+            .get(global_idx as usize)
+            .copied()
+            .ok_or_else(|| Error::Instantiation(format!("global index {} oob", global_idx).into()))
+    }
+
+    pub fn global(&self, instance: Instance, global_idx: u32) -> Result<RawValue> {
+        Ok(self.globals[self.global_addr(instance, global_idx)?].value)
+    }
+
+    /// Overwrites a global, including immutable ones
+    pub fn set_global(
+        &mut self,
+        instance: Instance,
+        global_idx: u32,
+        value: RawValue,
+    ) -> Result<()> {
+        let addr = self.global_addr(instance, global_idx)?;
+        self.globals[addr].value = value;
+        self.log_host_event(|| HostEvent::SetGlobal {
+            global_addr: addr,
+            value,
+        });
+        Ok(())
+    }
+
+    fn memory_addr(&self, instance: Instance, memory_idx: u32) -> Result<usize> {
+        self.instances[instance.0]
+            .mem_addrs
+            .get(memory_idx as usize)
+            .copied()
+            .ok_or_else(|| Error::Instantiation(format!("memory index {} oob", memory_idx).into()))
+    }
+
+    pub fn memory(&self, instance: Instance, memory_idx: u32) -> Result<&[u8]> {
+        Ok(&self.memories[self.memory_addr(instance, memory_idx)?].data)
+    }
+
+    /// Guest memory for the embedder to write to. While logging host
+    /// interactions, the memory is copied so the writes can be logged
+    pub fn memory_mut(&mut self, instance: Instance, memory_idx: u32) -> Result<&mut [u8]> {
+        let addr = self.memory_addr(instance, memory_idx)?;
+        if let Some(log) = &mut self.host_log {
+            if log.lent.iter().all(|(lent, _)| *lent != addr) {
+                log.lent.push((addr, self.memories[addr].data.clone()));
+            }
+        }
+        Ok(&mut self.memories[addr].data)
+    }
+
+    /// Records the following invocations for time travel, checkpointing the
+    /// store every `interval` units of fuel
+    ///
+    /// Each invocation starts a new recording, which lasts until the next one
+    /// so a trapped or completed invocation can still be traveled through.
+    /// Travel replays from the nearest checkpoint and answers host calls with
+    /// the results they gave the first time, so changing guest state from the
+    /// embedder while recording makes replays diverge. Replayed WASI calls
+    /// also write what they wrote to guest memory the first time, without
+    /// repeating their I/O.
+    pub fn start_recording(&mut self, interval: u64) {
+        self.recording = Some(Box::new(Recording::new(interval)));
+        self.debugging = true;
+    }
 
-        // step 27 - execute element segment initialization
-        // step 28 - execute data segment initialization
-        let init_instructions = [element_instructions, data_instructions].concat();
-        if !init_instructions.is_empty() {
-            self.run_init_instructions(&init_instructions, instance_idx)?;
+    pub fn stop_recording(&mut self) {
+        self.recording = None;
+        self.sync_debugging();
+    }
+
+    /// Logs host interactions from now on, starting from a snapshot of the
+    /// store, so a [`crate::Replayer`] can reproduce them without the host
+    ///
+    /// Logged are invocations, the host calls the guest suspends on, the
+    /// results they're resumed with and what the embedder writes to guest
+    /// memories, globals and operands, see [`HostLog`].
+    pub fn start_host_log(&mut self) {
+        self.host_log = Some(Box::new(HostLog::new(self.snapshot())));
+    }
+
+    pub fn stop_host_log(&mut self) -> Option<HostLog> {
+        self.log_memory_writes();
+        self.host_log.take().map(|log| *log)
+    }
+
+    /// The interactions logged so far, `None` when not logging
+    pub fn host_log(&self) -> Option<&HostLog> {
+        self.host_log.as_deref()
+    }
+
+    fn log_host_event(&mut self, event: impl FnOnce() -> HostEvent) {
+        self.log_memory_writes();
+        if let Some(log) = &mut self.host_log {
+            log.push(self.running, event());
         }
+    }
 
-        // step 29: invoke start function if present
-        if let Some(start_idx) = start_func_idx {
-            let func_addr = *module_instance
-                .function_addrs
-                .get(start_idx as usize)
-                .ok_or_else(|| {
-                    Error::Instantiation(format!("start function index {} oob", start_idx))
-                })?;
-            if self.push_function_call(func_addr)? {
-                instantiation_err!("start function cannot be a host import");
+    /// Logs what the embedder wrote to the memories it got from
+    /// [`Self::memory_mut`]
+    fn log_memory_writes(&mut self) {
+        if let Some(log) = &mut self.host_log {
+            for (mem_addr, before) in mem::take(&mut log.lent) {
+                log.push_memory_writes(
+                    self.running,
+                    mem_addr,
+                    &before,
+                    &self.memories[mem_addr].data,
+                );
             }
-            self.run()?;
         }
-
-        // step 31
-        Ok(instance)
+    }
+    /// Fuel the recorded invocation has consumed so far, `None` when not
+    /// recording. Positions are counted even if the store has no fuel limit
+    pub fn position(&self) -> Option<u64> {
+        self.recording.as_ref().map(|recording| recording.position)
     }
 
-    fn ensure_stack_capacity(&mut self) {
-        let max_func_stack = self
-            .instances
-            .iter()
-            .flat_map(|inst| inst.code.compiled_funcs.iter())
-            .map(|f| f.max_stack_height as usize)
-            .max()
-            .unwrap_or(1_024);
+    /// Travels to the first op at or after `position`, which must be within
+    /// the recorded history, and pauses there. Replaying doesn't use fuel
+    pub fn seek(&mut self, position: u64) -> Result<ExecutionState> {
+        let recording = self.recording()?;
+        ensure!(
+            position <= recording.end,
+            Error::Instantiation(
+                format!(
+                    "position {} is past the end of the recording ({})",
+                    position, recording.end
+                )
+                .into()
+            )
+        );
+        let Some(checkpoint) = recording.checkpoint_before(position) else {
+            instantiation_err!("nothing has been recorded");
+        };
 
-        let needed = max_func_stack.saturating_mul(MAX_CALL_DEPTH).max(1024);
-        if needed > self.stack.capacity() {
-            self.stack = ValueStack::with_capacity(needed);
-        }
+        self.replay(checkpoint, position, None).0
     }
 
-    pub fn invoke(
-        &mut self,
-        instance: Instance,
-        name: &str,
-        args: Vec<RawValue>,
-    ) -> Result<ExecutionState> {
-        let addr = self.get_func(instance, name)?;
-        self.invoke_by_addr(addr, args)
+    /// Travels back to the previous wasm instruction
+    pub fn reverse_step(&mut self) -> Result<ExecutionState> {
+        let position = self.recording()?.position;
+        match self.search_back(Search::Instruction)? {
+            Some(previous) => self.seek(previous),
+            None => {
+                self.seek(position)?;
+                instantiation_err!("already at the start of the recording")
+            }
+        }
     }
 
-    pub fn resume(&mut self) -> Result<ExecutionState> {
-        let arity = self
-            .pending_arity
-            .ok_or_else(|| Error::Instantiation("no pending execution to resume".into()))?;
+    /// Travels back to the last breakpoint hit before the current position,
+    /// or to the start of the recording if there is none
+    pub fn reverse_continue(&mut self) -> Result<ExecutionState> {
+        let position = self.search_back(Search::Breakpoint)?.unwrap_or(0);
+        self.seek(position)
+    }
 
-        self.finish_run(arity)
+    fn recording(&self) -> Result<&Recording> {
+        match &self.recording {
+            Some(recording) => Ok(recording),
+            None => instantiation_err!("not recording"),
+        }
     }
 
-    pub fn resume_with(&mut self, return_values: &[RawValue]) -> Result<ExecutionState> {
-        let arity = self
-            .pending_arity
-            .ok_or_else(|| Error::Instantiation("no pending execution to resume".into()))?;
+    /// The last position before the current one where `search` matches,
+    /// replaying one checkpoint interval at a time from the most recent
+    fn search_back(&mut self, search: Search) -> Result<Option<u64>> {
+        let recording = self.recording()?;
+        let mut target = recording.position;
+        let Some(mut checkpoint) = recording.checkpoint_before(target) else {
+            return Ok(None);
+        };
 
-        for val in return_values {
-            self.stack.push(*val);
+        loop {
+            if let (_, Some(found)) = self.replay(checkpoint, target, Some(search)) {
+                return Ok(Some(found));
+            }
+            if checkpoint == 0 {
+                return Ok(None);
+            }
+
+            target = self.recording()?.checkpoints[checkpoint].position;
+            checkpoint -= 1;
         }
+    }
 
-        self.finish_run(arity)
+    /// Restores `checkpoint` and replays up to `target`, returning where the
+    /// replay stopped and the last match for `search` on the way
+    fn replay(
+        &mut self,
+        checkpoint: usize,
+        target: u64,
+        search: Option<Search>,
+    ) -> (Result<ExecutionState>, Option<u64>) {
+        let mut recording = self.recording.take().unwrap();
+        let checkpoint = &recording.checkpoints[checkpoint];
+        let mut restored = Self::from_snapshot(&checkpoint.snapshot);
+        recording.position = checkpoint.position;
+        recording.host_calls = checkpoint.host_calls;
+        recording.awaiting_host = false;
+        recording.seek = Some(Seek {
+            target,
+            search,
+            found: None,
+        });
+
+        // debugger and embedder settings aren't part of the history
+        restored.breakpoints = mem::take(&mut self.breakpoints);
+        restored.watchpoints = mem::take(&mut self.watchpoints);
+        restored.watch_hit = None;
+        restored.step = None;
+        restored.resuming = false;
+        restored.limits = self.limits;
+        restored.fuel_costs = self.fuel_costs;
+        restored.interrupt = self.interrupt.clone();
+        // guest files rewind with the rest, output past the end of the
+        // recording keeps going to the same streams
+        if let (Some(wasi), Some(current)) = (&mut restored.wasi, self.wasi.take()) {
+            wasi.adopt_streams(*current);
+        }
+        restored.fuel = None;
+        restored.coverage = None;
+        restored.recording = Some(recording);
+        restored.debugging = true;
+
+        // nor are the tracer, host log and profiler, which already saw this
+        // history
+        let fuel = self.fuel;
+        let tracer = self.tracer.take();
+        let host_log = self.host_log.take();
+        let profiler = self.profiler.take();
+        let coverage = self.coverage.take();
+        let trace_ops = self.trace_ops;
+        *self = restored;
+
+        let state = self.resume();
+        let found = self
+            .recording
+            .as_mut()
+            .and_then(|recording| recording.seek.take())
+            .and_then(|seek| seek.found);
+        self.fuel = fuel;
+        self.tracer = tracer;
+        self.host_log = host_log;
+        self.trace_ops = trace_ops;
+        self.profiler = profiler;
+        self.coverage = coverage;
+        self.sync_debugging();
+
+        (state, found)
     }
 
     fn finish_run(&mut self, num_results: usize) -> Result<ExecutionState> {
-        match self.run() {
+        // before the guest's own writes mix in
+        self.log_memory_writes();
+        self.awaiting_host = None;
+        let outcome = loop {
+            let outcome = self.run();
+            let Some(recording) = self.recording.as_deref_mut() else {
+                break outcome;
+            };
+            recording.end = recording.end.max(recording.position);
+
+            match outcome {
+                Ok(RunOutcome::Checkpoint) => {
+                    self.pending_arity = Some(num_results);
+                    let snapshot = self.snapshot();
+                    self.recording.as_mut().unwrap().checkpoint(snapshot);
+                }
+                // host calls the embedder already answered are replayed
+                Ok(RunOutcome::Suspended) => match recording.next_host_result() {
+                    Some(HostResult { values, .. }) => {
+                        self.pending_suspension = None;
+                        if let Some(tracer) = &mut self.tracer {
+                            tracer.host_return(&values);
+                        }
+                        self.reserve_stack(values.len())?;
+                        self.stack.extend_from_slice(&values);
+                    }
+                    None => {
+                        recording.awaiting_host = true;
+                        break outcome;
+                    }
+                },
+                outcome => break outcome,
+            }
+        };
+
+        let state = match outcome {
             Ok(RunOutcome::Completed) => {
-                let results = self.stack.pop_n(num_results);
+                self.end_step();
+                let results = self.stack.pop_n(num_results).to_vec();
                 self.pending_arity = None;
-                Ok(ExecutionState::Completed(results.to_vec()))
+                self.unnest();
+                ExecutionState::Completed(results)
             }
             Ok(RunOutcome::FuelExhausted) => {
                 self.pending_arity = Some(num_results);
-                Ok(ExecutionState::FuelExhausted)
+                ExecutionState::FuelExhausted
+            }
+            Ok(RunOutcome::Interrupted) => {
+                self.pending_arity = Some(num_results);
+                ExecutionState::Interrupted
+            }
+            Ok(RunOutcome::Breakpoint) => {
+                self.pending_arity = Some(num_results);
+                ExecutionState::Breakpoint
+            }
+            Ok(RunOutcome::Watchpoint(hit)) => {
+                self.pending_arity = Some(num_results);
+                ExecutionState::Watchpoint(hit)
             }
             Ok(RunOutcome::Suspended) => {
                 self.pending_arity = Some(num_results);
-                let (module_name, func_name, args) = self.pending_suspension.take().unwrap();
-                Ok(ExecutionState::Suspended {
-                    module_name,
-                    func_name,
-                    args,
-                })
+                self.take_suspension()
             }
+            Ok(RunOutcome::Checkpoint) => unreachable!("checkpoints are taken above"),
             Err(e) => {
-                self.stack.clear();
-                self.call_stack.clear();
                 self.pending_arity = None;
-                Err(e)
+                return Err(self.unwind(e));
+            }
+        };
+
+        if !matches!(
+            state,
+            ExecutionState::Completed(_) | ExecutionState::Suspended { .. }
+        ) {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.suspended(&state);
+            }
+        }
+        Ok(state)
+    }
+
+    /// Abandons the current execution, attaching a backtrace to traps
+    fn unwind(&mut self, mut err: Error) -> Error {
+        if let Error::Trap(trap, backtrace) = &mut err {
+            *backtrace = self.backtrace();
+            if let Some(tracer) = &mut self.tracer {
+                tracer.trap(trap, backtrace);
             }
+            self.last_trap = Some(trap.clone());
+        }
+
+        // only the innermost invocation is abandoned, a nested one leaves
+        // the invocation it was made from waiting on its host call
+        let (depth, stack_height) = self
+            .nested
+            .last()
+            .map_or((0, 0), |nesting| (nesting.depth, nesting.stack_height));
+        self.stack.truncate(stack_height);
+        self.call_stack.truncate(depth);
+        self.watch_hit = None;
+        self.end_step();
+        self.unnest();
+        err
+    }
+
+    /// Returns to the invocation the finished nested one was made from
+    fn unnest(&mut self) {
+        if let Some(nesting) = self.nested.pop() {
+            self.pending_arity = Some(nesting.arity);
+            self.awaiting_host = Some(nesting.host_func);
+            self.base_depth = self.nested.last().map_or(0, |nesting| nesting.depth);
+        }
+    }
+
+    /// Runs code that has no caller to pause for: start functions and
+    /// segment initializers
+    fn run_detached(&mut self) -> Result<RunOutcome> {
+        let debugging = mem::replace(&mut self.debugging, false);
+        let outcome = self.run();
+        self.debugging = debugging;
+        outcome
+    }
+
+    fn backtrace(&self) -> Backtrace {
+        let frames = self
+            .call_stack
+            .iter()
+            .rev()
+            // frames have already stepped past the faulting op or call
+            .map(|frame| self.frame_info(frame, frame.pc.saturating_sub(1)))
+            .collect();
+
+        Backtrace { frames }
+    }
+
+    fn frame_info(&self, frame: &CallFrame, pc: usize) -> FrameInfo {
+        let module_idx = frame.module_idx as usize;
+        let code = &self.instances[module_idx].code;
+        let cf = &code.compiled_funcs[frame.compiled_func_idx as usize];
+        let wasm_offset = cf.offsets.wasm_offset(pc);
+
+        FrameInfo {
+            module_idx,
+            func_idx: cf.func_idx,
+            name: cf
+                .func_idx
+                .and_then(|idx| code.names.function(idx))
+                .map(str::to_owned),
+            pc,
+            wasm_offset,
+            location: wasm_offset.and_then(|offset| code.source_location(offset)),
         }
     }
 
-    fn invoke_by_addr(
+    pub(crate) fn invoke_by_addr(
         &mut self,
         function_addr: usize,
         args: Vec<RawValue>,
     ) -> Result<ExecutionState> {
-        if self.pending_arity.is_some() {
+        if self.pending_arity.is_some() && self.awaiting_host.is_none() {
             instantiation_err!("cannot invoke while execution is paused; call resume() first");
         }
+        self.log_host_event(|| HostEvent::Invoke {
+            func_addr: function_addr,
+            args: args.clone(),
+        });
 
-        let fi = self
-            .functions
-            .get(function_addr)
-            .ok_or_else(|| Error::Instantiation(format!("function addr {} oob", function_addr)))?;
+        let fi = self.functions.get(function_addr).ok_or_else(|| {
+            Error::Instantiation(format!("function addr {} oob", function_addr).into())
+        })?;
 
         let (num_args, num_results) = match fi {
             FunctionInstance::Local { function_type, .. }
@@ -850,32 +2189,91 @@ impl Store {
 
         ensure!(
             num_args == args.len(),
-            Error::Instantiation(format!("expected {} args, got {}", num_args, args.len()))
+            Error::Instantiation(format!("expected {} args, got {}", num_args, args.len()).into())
         );
 
-        self.stack.extend_from_slice(&args);
-        let suspended = self.push_function_call(function_addr)?;
+        // invoking from a host call runs on top of the waiting frames, which
+        // carry on once the nested invocation returns
+        match (self.pending_arity.take(), self.awaiting_host.take()) {
+            (Some(arity), Some(host_func)) => {
+                self.nested.push(Nesting {
+                    arity,
+                    host_func,
+                    depth: self.call_stack.len(),
+                    stack_height: self.stack.len(),
+                });
+                self.base_depth = self.call_stack.len();
+            }
+            _ => {
+                self.last_trap = None;
+                if let Some(recording) = &mut self.recording {
+                    recording.reset();
+                }
+            }
+        }
+
+        let suspended = self
+            .reserve_stack(args.len())
+            .and_then(|()| {
+                self.stack.extend_from_slice(&args);
+                self.push_function_call(function_addr)
+            })
+            .map_err(|e| self.unwind(e))?;
 
         if suspended {
-            let (module_name, func_name, host_args) = self.pending_suspension.take().unwrap();
-            return Ok(ExecutionState::Suspended {
-                module_name,
-                func_name,
-                args: host_args,
-            });
+            self.pending_arity = Some(num_results);
+            return Ok(self.take_suspension());
         }
 
         self.finish_run(num_results)
     }
 
+    fn take_suspension(&mut self) -> ExecutionState {
+        let (host_func, args) = self.pending_suspension.take().unwrap();
+        let FunctionInstance::Host {
+            module_name,
+            function_name: func_name,
+            ..
+        } = &self.functions[host_func]
+        else {
+            unreachable!("suspended on a local function");
+        };
+        let (module_name, func_name) = (module_name.clone(), func_name.clone());
+        self.awaiting_host = Some(host_func);
+        self.log_host_event(|| HostEvent::Call {
+            module_name: module_name.clone(),
+            func_name: func_name.clone(),
+            args: args.clone(),
+        });
+        ExecutionState::Suspended {
+            module_name,
+            func_name,
+            args,
+        }
+    }
+
     fn compiled_func_index(&self, func_addr: usize) -> Option<(u16, u32)> {
         self.func_addr_to_module.get(func_addr).copied().flatten()
     }
 
+    /// Calls `func_addr` from inside `run`, returning why execution has to
+    /// stop, if it does
+    fn enter_function(&mut self, func_addr: usize) -> Result<Option<RunOutcome>> {
+        if self.push_function_call(func_addr)? {
+            return Ok(Some(RunOutcome::Suspended));
+        }
+
+        if self.interrupt.take() {
+            return Ok(Some(RunOutcome::Interrupted));
+        }
+
+        Ok(None)
+    }
+
     fn push_function_call(&mut self, func_addr: usize) -> Result<bool> {
         ensure!(
-            self.call_stack.len() < MAX_CALL_DEPTH,
-            Error::Trap(Trap::CallStackExhausted)
+            self.call_stack.len() < self.limits.max_call_depth,
+            Error::from(Trap::CallStackExhausted)
         );
 
         let fi = &self.functions[func_addr];
@@ -899,8 +2297,17 @@ impl Store {
                     );
 
                     let args = self.stack.pop_n(num_args).to_vec();
-                    self.pending_suspension =
-                        Some((module_name.clone(), function_name.clone(), args));
+
+                    if let Some(tracer) = &mut self.tracer {
+                        tracer.host_call(module_name, function_name, &args);
+                    }
+
+                    if self.wasi.is_some() && module_name == WASI_MODULE {
+                        let function_name = function_name.clone();
+                        return self.call_wasi(&function_name, &args).map(|()| false);
+                    }
+
+                    self.pending_suspension = Some((func_addr, args));
 
                     return Ok(true);
                 }
@@ -921,8 +2328,20 @@ impl Store {
             locals.push(RawValue::default());
         }
 
+        let frame_height = Self::frame_height(cf);
+        self.reserve_stack(frame_height)?;
+
+        if let Some(tracer) = &mut self.tracer {
+            let function = traced_function(&self.instances, module_idx, compiled_idx);
+            tracer.function_entry(function, &locals[..num_args]);
+        }
+
         let stack_base = self.stack.len();
 
+        if let Some(coverage) = &mut self.coverage {
+            coverage.call(module_idx as usize, compiled_idx as usize);
+        }
+
         self.call_stack.push(CallFrame {
             module_idx,
             compiled_func_idx: compiled_idx,
@@ -935,10 +2354,78 @@ impl Store {
         Ok(false)
     }
 
+    /// Pushes a float op's result, canonicalizing NaNs in deterministic stores
+    #[inline]
+    fn push_f32(&mut self, value: f32) {
+        match self.deterministic.is_some() && value.is_nan() {
+            true => self.stack.push(f32::from_bits(CANONICAL_NAN_F32)),
+            false => self.stack.push(value),
+        }
+    }
+
+    #[inline]
+    fn push_f64(&mut self, value: f64) {
+        match self.deterministic.is_some() && value.is_nan() {
+            true => self.stack.push(f64::from_bits(CANONICAL_NAN_F64)),
+            false => self.stack.push(value),
+        }
+    }
+
+    /// Answers a WASI import in place, against the calling instance's memory
+    fn call_wasi(&mut self, name: &str, args: &[RawValue]) -> Result<()> {
+        let memory = self
+            .call_stack
+            .last()
+            .and_then(|frame| self.instances[frame.module_idx as usize].mem_addrs.first())
+            .map_or(&mut [][..], |&addr| &mut self.memories[addr].data[..]);
+
+        let wasi = self.wasi.as_mut().expect("WASI is linked");
+
+        // calls already in the recording keep the guest's files in step, but
+        // their I/O isn't repeated and the guest sees what it saw the first
+        // time
+        let mut recording = self.recording.as_deref_mut();
+        let results = match recording
+            .as_deref_mut()
+            .and_then(Recording::next_host_result)
+        {
+            Some(recorded) => {
+                wasi.call_muted(name, args, memory)?;
+                for (offset, bytes) in recorded.writes {
+                    if let Some(dest) = memory.get_mut(offset..offset + bytes.len()) {
+                        dest.copy_from_slice(&bytes);
+                    }
+                }
+                recorded.values
+            }
+            None => {
+                let call = wasi.call(name, args, memory)?;
+                if let Some(recording) = recording {
+                    let writes = call.written.into_iter();
+                    recording.push_host_result(HostResult {
+                        values: call.results.clone(),
+                        writes: writes
+                            .map(|range| (range.start, memory[range].to_vec()))
+                            .collect(),
+                    });
+                }
+                call.results
+            }
+        };
+
+        if let Some(tracer) = &mut self.tracer {
+            tracer.host_return(&results);
+        }
+        for value in results {
+            self.stack.push(value);
+        }
+        Ok(())
+    }
+
     fn run(&mut self) -> Result<RunOutcome> {
         loop {
             let depth = match self.call_stack.len() {
-                0 => return Ok(RunOutcome::Completed),
+                n if n == self.base_depth => return Ok(RunOutcome::Completed),
                 n => n - 1,
             };
             assert!(depth < self.call_stack.len());
@@ -959,14 +2446,48 @@ impl Store {
             );
 
             let op = func_ops[pc];
+
+            if self.debugging {
+                if let Some(outcome) = self.debug_stop(depth, mi, func_idx, pc, &op) {
+                    return Ok(outcome);
+                }
+            }
+
             self.call_stack[depth].pc += 1;
 
-            if let Some(ref mut fuel) = self.fuel {
-                if *fuel == 0 {
-                    self.call_stack[depth].pc -= 1;
-                    return Ok(RunOutcome::FuelExhausted);
+            if self.fuel.is_some()
+                || self.recording.is_some()
+                || self.profiler.is_some()
+                || self.coverage.is_some()
+            {
+                let cost = self.fuel_cost(mi, &op);
+                if let Some(fuel) = self.fuel {
+                    if fuel < cost {
+                        self.call_stack[depth].pc -= 1;
+                        // this op has already been checked for debug stops,
+                        // and its watched accesses are found again on resume
+                        self.resuming = self.debugging;
+                        self.watch_hit = None;
+                        return Ok(RunOutcome::FuelExhausted);
+                    }
+                    self.fuel = Some(fuel - cost);
+                }
+                if let Some(recording) = &mut self.recording {
+                    recording.position += cost;
+                }
+                if let Some(profiler) = self.profiler.as_deref_mut() {
+                    let frames = self.call_stack.iter();
+                    profiler.tick(
+                        frames.map(|frame| (frame.module_idx, frame.compiled_func_idx)),
+                        pc,
+                    );
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    let num_ops = self.instances[mi].code.compiled_funcs[func_idx as usize]
+                        .ops
+                        .len();
+                    coverage.hit(mi, func_idx as usize, pc, num_ops);
                 }
-                *fuel -= 1;
             }
 
             match op {
@@ -975,20 +2496,20 @@ impl Store {
                 Op::Return => self.do_return(depth),
                 Op::Jump { target, keep, drop } => {
                     self.stack.keep_top(keep as usize, drop as usize);
-                    self.call_stack[depth].pc = target as usize;
+                    branch!(self, depth, target);
                 }
                 Op::JumpIf { target, keep, drop } => {
                     let cond = pop_val!(self, I32);
                     if cond != 0 {
                         self.stack.keep_top(keep as usize, drop as usize);
-                        self.call_stack[depth].pc = target as usize;
+                        branch!(self, depth, target);
                     }
                 }
                 Op::JumpIfNot { target, keep, drop } => {
                     let cond = pop_val!(self, I32);
                     if cond == 0 {
                         self.stack.keep_top(keep as usize, drop as usize);
-                        self.call_stack[depth].pc = target as usize;
+                        branch!(self, depth, target);
                     }
                 }
                 Op::JumpTable { index, keep } => {
@@ -1001,13 +2522,13 @@ impl Store {
                     };
                     self.stack
                         .keep_top(keep as usize, entry_target.drop as usize);
-                    self.call_stack[depth].pc = entry_target.target as usize;
+                    branch!(self, depth, entry_target.target);
                 }
                 Op::BrOnNull { target, keep, drop } => {
                     let val = self.stack.pop();
                     if matches!(val.as_ref(), Ref::Null) {
                         self.stack.keep_top(keep as usize, drop as usize);
-                        self.call_stack[depth].pc = target as usize;
+                        branch!(self, depth, target);
                     } else {
                         self.stack.push(val);
                     }
@@ -1017,13 +2538,13 @@ impl Store {
                     if !matches!(val.as_ref(), Ref::Null) {
                         self.stack.push(val);
                         self.stack.keep_top(keep as usize, drop as usize);
-                        self.call_stack[depth].pc = target as usize;
+                        branch!(self, depth, target);
                     }
                 }
                 Op::Call { func_idx } => {
                     let func_addr = self.instances[mi].function_addrs[func_idx as usize];
-                    if self.push_function_call(func_addr)? {
-                        return Ok(RunOutcome::Suspended);
+                    if let Some(outcome) = self.enter_function(func_addr)? {
+                        return Ok(outcome);
                     }
                 }
                 Op::CallIndirect {
@@ -1038,7 +2559,7 @@ impl Store {
                     let elem = self.tables[table_addr]
                         .elem
                         .get(i)
-                        .ok_or(Error::Trap(Trap::UndefinedElement))?;
+                        .ok_or_else(|| Error::from(Trap::UndefinedElement))?;
 
                     let Ref::FunctionAddr(func_addr) = elem else {
                         trap!(Trap::UndefinedElement);
@@ -1058,11 +2579,11 @@ impl Store {
                     ensure!(
                         expected.0 .0.len() == actual.0 .0.len()
                             && expected.1 .0.len() == actual.1 .0.len(),
-                        Error::Trap(Trap::IndirectCallTypeMismatch)
+                        Error::from(Trap::IndirectCallTypeMismatch)
                     );
 
-                    if self.push_function_call(*func_addr)? {
-                        return Ok(RunOutcome::Suspended);
+                    if let Some(outcome) = self.enter_function(*func_addr)? {
+                        return Ok(outcome);
                     }
                 }
                 Op::ReturnCall { func_idx } => {
@@ -1074,10 +2595,13 @@ impl Store {
 
                     self.stack.copy_within(len - num_args..len, old_base);
                     self.stack.truncate(old_base + num_args);
+                    if self.tracer.is_some() {
+                        self.trace_exit(depth, self.stack.len());
+                    }
                     self.call_stack.pop();
 
-                    if self.push_function_call(func_addr)? {
-                        return Ok(RunOutcome::Suspended);
+                    if let Some(outcome) = self.enter_function(func_addr)? {
+                        return Ok(outcome);
                     }
                 }
                 Op::ReturnCallIndirect {
@@ -1091,7 +2615,7 @@ impl Store {
                     let elem = self.tables[table_addr]
                         .elem
                         .get(i)
-                        .ok_or(Error::Trap(Trap::UndefinedElement))?;
+                        .ok_or_else(|| Error::from(Trap::UndefinedElement))?;
 
                     let Ref::FunctionAddr(func_addr) = elem else {
                         trap!(Trap::UndefinedElement);
@@ -1111,7 +2635,7 @@ impl Store {
                     ensure!(
                         expected.0 .0.len() == actual.0 .0.len()
                             && expected.1 .0.len() == actual.1 .0.len(),
-                        Error::Trap(Trap::IndirectCallTypeMismatch)
+                        Error::from(Trap::IndirectCallTypeMismatch)
                     );
 
                     let func_addr = *func_addr;
@@ -1121,9 +2645,12 @@ impl Store {
 
                     self.stack.copy_within(len - num_args..len, old_base);
                     self.stack.truncate(old_base + num_args);
+                    if self.tracer.is_some() {
+                        self.trace_exit(depth, self.stack.len());
+                    }
                     self.call_stack.pop();
-                    if self.push_function_call(func_addr)? {
-                        return Ok(RunOutcome::Suspended);
+                    if let Some(outcome) = self.enter_function(func_addr)? {
+                        return Ok(outcome);
                     }
                 }
                 Op::CallRef { .. } => {
@@ -1132,8 +2659,8 @@ impl Store {
                         Ref::FunctionAddr(f) => f,
                         _ => instantiation_err!("expected function or null ref"),
                     };
-                    if self.push_function_call(func_addr)? {
-                        return Ok(RunOutcome::Suspended);
+                    if let Some(outcome) = self.enter_function(func_addr)? {
+                        return Ok(outcome);
                     }
                 }
                 Op::ReturnCallRef { .. } => {
@@ -1148,9 +2675,12 @@ impl Store {
                     let len = self.stack.len();
                     self.stack.copy_within(len - num_args..len, old_base);
                     self.stack.truncate(old_base + num_args);
+                    if self.tracer.is_some() {
+                        self.trace_exit(depth, self.stack.len());
+                    }
                     self.call_stack.pop();
-                    if self.push_function_call(func_addr)? {
-                        return Ok(RunOutcome::Suspended);
+                    if let Some(outcome) = self.enter_function(func_addr)? {
+                        return Ok(outcome);
                     }
                 }
                 Op::I32Const { value } => self.stack.push(value),
@@ -1218,7 +2748,7 @@ impl Store {
                     let val = self.stack.pop();
                     ensure!(
                         !matches!(val.as_ref(), Ref::Null),
-                        Error::Trap(Trap::NullReference)
+                        Error::from(Trap::NullReference)
                     );
                     self.stack.push(val);
                 }
@@ -1233,7 +2763,7 @@ impl Store {
                     let elem = self.tables[ta]
                         .elem
                         .get(i)
-                        .ok_or(Error::Trap(Trap::OutOfBoundsTableAccess))?;
+                        .ok_or_else(|| Error::from(Trap::OutOfBoundsTableAccess))?;
 
                     self.stack.push(RawValue::from_ref(*elem));
                 }
@@ -1247,7 +2777,7 @@ impl Store {
                     let elem = self.tables[ta]
                         .elem
                         .get_mut(i)
-                        .ok_or(Error::Trap(Trap::OutOfBoundsTableAccess))?;
+                        .ok_or_else(|| Error::from(Trap::OutOfBoundsTableAccess))?;
 
                     *elem = r;
                 }
@@ -1328,7 +2858,17 @@ impl Store {
                     let old_size = self.tables[ta].elem.len();
                     let new_size = (old_size as u64).checked_add(n as u64);
 
-                    if new_size.is_none_or(|s| s > self.tables[ta].table_type.limit.max) {
+                    let refused_by_limits = !self
+                        .limits
+                        .allows_table_elements(self.table_elements(), n as u64);
+
+                    if refused_by_limits && self.limits.trap_on_grow_failure {
+                        trap!(Trap::ResourceLimitExceeded);
+                    }
+
+                    if refused_by_limits
+                        || new_size.is_none_or(|s| s > self.tables[ta].table_type.limit.max)
+                    {
                         match at {
                             AddrType::I32 => self.stack.push(-1i32),
                             AddrType::I64 => self.stack.push(-1i64),
@@ -1453,20 +2993,35 @@ impl Store {
                 }
                 Op::MemoryGrow { memory_idx } => {
                     let ma = self.instances[mi].mem_addrs[memory_idx as usize];
-                    let mem = &mut self.memories[ma];
-                    let at = mem.memory_type.addr_type;
+                    let at = self.memories[ma].memory_type.addr_type;
                     let page_count = self.stack.pop_address(at);
 
+                    let refused_by_limits = !self.limits.allows_memory(
+                        self.memory_bytes(),
+                        (page_count as u64).saturating_mul(PAGE_SIZE as u64),
+                    );
+
+                    if refused_by_limits && self.limits.trap_on_grow_failure {
+                        trap!(Trap::ResourceLimitExceeded);
+                    }
+
+                    let mem = &mut self.memories[ma];
                     let old_size = mem.data.len() / PAGE_SIZE;
                     let new_size = old_size + page_count;
 
                     const MAX_PAGES: usize = 65536;
 
-                    if new_size > MAX_PAGES || new_size as u64 > mem.memory_type.limit.max {
+                    if refused_by_limits
+                        || new_size > MAX_PAGES
+                        || new_size as u64 > mem.memory_type.limit.max
+                    {
                         match at {
                             AddrType::I32 => self.stack.push(-1i32),
                             AddrType::I64 => self.stack.push(-1i64),
                         }
+                        if let Some(tracer) = &mut self.tracer {
+                            tracer.memory_grow(memory_idx, old_size, None);
+                        }
                         continue;
                     }
                     mem.data.resize(new_size * PAGE_SIZE, 0);
@@ -1474,6 +3029,9 @@ impl Store {
                     mem.memory_type.limit.min = new_size as u64;
 
                     self.stack.push_address(old_size, at);
+                    if let Some(tracer) = &mut self.tracer {
+                        tracer.memory_grow(memory_idx, old_size, Some(new_size));
+                    }
                 }
                 Op::MemoryInit {
                     data_idx,
@@ -1582,10 +3140,10 @@ impl Store {
                     let a = pop_val!(self, I32);
                     let b = pop_val!(self, I32);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
                     ensure!(
                         !(b == i32::MIN && a == -1),
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
 
                     self.stack.push(b.wrapping_div(a));
@@ -1594,21 +3152,21 @@ impl Store {
                     let a = pop_val!(self, I32);
                     let b = pop_val!(self, I32);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
                     self.stack.push(((b as u32) / (a as u32)) as i32);
                 }
                 Op::I32RemainderSigned => {
                     let a = pop_val!(self, I32);
                     let b = pop_val!(self, I32);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
                     self.stack.push(b.wrapping_rem(a));
                 }
                 Op::I32RemainderUnsigned => {
                     let a = pop_val!(self, I32);
                     let b = pop_val!(self, I32);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
                     self.stack.push(((b as u32) % (a as u32)) as i32);
                 }
                 Op::I32And => binop!(self, I32, |b, a| b & a),
@@ -1655,10 +3213,10 @@ impl Store {
                     let a = pop_val!(self, I64);
                     let b = pop_val!(self, I64);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
                     ensure!(
                         !(b == i64::MIN && a == -1),
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
 
                     self.stack.push(b.wrapping_div(a));
@@ -1667,7 +3225,7 @@ impl Store {
                     let a = pop_val!(self, I64);
                     let b = pop_val!(self, I64);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
 
                     self.stack.push(((b as u64) / (a as u64)) as i64);
                 }
@@ -1675,7 +3233,7 @@ impl Store {
                     let a = pop_val!(self, I64);
                     let b = pop_val!(self, I64);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
 
                     self.stack.push(b.wrapping_rem(a));
                 }
@@ -1683,7 +3241,7 @@ impl Store {
                     let a = pop_val!(self, I64);
                     let b = pop_val!(self, I64);
 
-                    ensure!(a != 0, Error::Trap(Trap::IntegerDivideByZero));
+                    ensure!(a != 0, Error::from(Trap::IntegerDivideByZero));
                     self.stack.push(((b as u64) % (a as u64)) as i64);
                 }
                 Op::I64And => binop!(self, I64, |b, a| b & a),
@@ -1713,33 +3271,33 @@ impl Store {
                 }
                 Op::F32Ceil => {
                     let a = pop_val!(self, F32);
-                    self.stack.push(a.ceil());
+                    self.push_f32(a.ceil());
                 }
                 Op::F32Floor => {
                     let a = pop_val!(self, F32);
-                    self.stack.push(a.floor());
+                    self.push_f32(a.floor());
                 }
                 Op::F32Trunc => {
                     let a = pop_val!(self, F32);
-                    self.stack.push(a.trunc());
+                    self.push_f32(a.trunc());
                 }
                 Op::F32Nearest => {
                     let a = pop_val!(self, F32);
-                    self.stack.push(a.round_ties_even());
+                    self.push_f32(a.round_ties_even());
                 }
                 Op::F32Sqrt => {
                     let a = pop_val!(self, F32);
-                    self.stack.push(a.sqrt());
+                    self.push_f32(a.sqrt());
                 }
-                Op::F32Add => binop!(self, F32, |b, a| b + a),
-                Op::F32Sub => binop!(self, F32, |b, a| b - a),
-                Op::F32Mul => binop!(self, F32, |b, a| b * a),
-                Op::F32Div => binop!(self, F32, |b, a| b / a),
+                Op::F32Add => float_binop!(self, F32, push_f32, |b, a| b + a),
+                Op::F32Sub => float_binop!(self, F32, push_f32, |b, a| b - a),
+                Op::F32Mul => float_binop!(self, F32, push_f32, |b, a| b * a),
+                Op::F32Div => float_binop!(self, F32, push_f32, |b, a| b / a),
                 Op::F32Min => {
                     let a = pop_val!(self, F32);
                     let b = pop_val!(self, F32);
                     let r = if a.is_nan() || b.is_nan() {
-                        f32::from_bits(0x7FC0_0000)
+                        f32::from_bits(CANONICAL_NAN_F32)
                     } else if a == b {
                         f32::from_bits(a.to_bits() | b.to_bits())
                     } else {
@@ -1751,7 +3309,7 @@ impl Store {
                     let a = pop_val!(self, F32);
                     let b = pop_val!(self, F32);
                     let r = if a.is_nan() || b.is_nan() {
-                        f32::from_bits(0x7FC0_0000)
+                        f32::from_bits(CANONICAL_NAN_F32)
                     } else if a == b {
                         f32::from_bits(a.to_bits() & b.to_bits())
                     } else {
@@ -1776,33 +3334,33 @@ impl Store {
                 }
                 Op::F64Ceil => {
                     let a = pop_val!(self, F64);
-                    self.stack.push(a.ceil());
+                    self.push_f64(a.ceil());
                 }
                 Op::F64Floor => {
                     let a = pop_val!(self, F64);
-                    self.stack.push(a.floor());
+                    self.push_f64(a.floor());
                 }
                 Op::F64Trunc => {
                     let a = pop_val!(self, F64);
-                    self.stack.push(a.trunc());
+                    self.push_f64(a.trunc());
                 }
                 Op::F64Nearest => {
                     let a = pop_val!(self, F64);
-                    self.stack.push(a.round_ties_even());
+                    self.push_f64(a.round_ties_even());
                 }
                 Op::F64Sqrt => {
                     let a = pop_val!(self, F64);
-                    self.stack.push(a.sqrt());
+                    self.push_f64(a.sqrt());
                 }
-                Op::F64Add => binop!(self, F64, |b, a| b + a),
-                Op::F64Sub => binop!(self, F64, |b, a| b - a),
-                Op::F64Mul => binop!(self, F64, |b, a| b * a),
-                Op::F64Div => binop!(self, F64, |b, a| b / a),
+                Op::F64Add => float_binop!(self, F64, push_f64, |b, a| b + a),
+                Op::F64Sub => float_binop!(self, F64, push_f64, |b, a| b - a),
+                Op::F64Mul => float_binop!(self, F64, push_f64, |b, a| b * a),
+                Op::F64Div => float_binop!(self, F64, push_f64, |b, a| b / a),
                 Op::F64Min => {
                     let a = pop_val!(self, F64);
                     let b = pop_val!(self, F64);
                     let r = if a.is_nan() || b.is_nan() {
-                        f64::from_bits(0x7FF8_0000_0000_0000)
+                        f64::from_bits(CANONICAL_NAN_F64)
                     } else if a == b {
                         f64::from_bits(a.to_bits() | b.to_bits())
                     } else {
@@ -1814,7 +3372,7 @@ impl Store {
                     let a = pop_val!(self, F64);
                     let b = pop_val!(self, F64);
                     let r = if a.is_nan() || b.is_nan() {
-                        f64::from_bits(0x7FF8_0000_0000_0000)
+                        f64::from_bits(CANONICAL_NAN_F64)
                     } else if a == b {
                         f64::from_bits(a.to_bits() & b.to_bits())
                     } else {
@@ -1829,41 +3387,41 @@ impl Store {
                 }
                 Op::I32TruncF32Signed => {
                     let a = pop_val!(self, F32);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= i32::MIN as f32 && t < i32::MAX as f32,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as i32);
                 }
                 Op::I32TruncF32Unsigned => {
                     let a = pop_val!(self, F32);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= 0.0 && t < u32::MAX as f32,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as u32 as i32);
                 }
                 Op::I32TruncF64Signed => {
                     let a = pop_val!(self, F64);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= i32::MIN as f64 && t <= i32::MAX as f64,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as i32);
                 }
                 Op::I32TruncF64Unsigned => {
                     let a = pop_val!(self, F64);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= 0.0 && t <= u32::MAX as f64,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as u32 as i32);
                 }
@@ -1877,41 +3435,41 @@ impl Store {
                 }
                 Op::I64TruncF32Signed => {
                     let a = pop_val!(self, F32);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= i64::MIN as f32 && t < i64::MAX as f32,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as i64);
                 }
                 Op::I64TruncF32Unsigned => {
                     let a = pop_val!(self, F32);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= 0.0 && t < u64::MAX as f32,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as u64 as i64);
                 }
                 Op::I64TruncF64Signed => {
                     let a = pop_val!(self, F64);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= i64::MIN as f64 && t < i64::MAX as f64,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as i64);
                 }
                 Op::I64TruncF64Unsigned => {
                     let a = pop_val!(self, F64);
-                    ensure!(!a.is_nan(), Error::Trap(Trap::InvalidConversionToInteger));
+                    ensure!(!a.is_nan(), Error::from(Trap::InvalidConversionToInteger));
                     let t = a.trunc();
                     ensure!(
                         t >= 0.0 && t < u64::MAX as f64,
-                        Error::Trap(Trap::IntegerOverflow)
+                        Error::from(Trap::IntegerOverflow)
                     );
                     self.stack.push(t as u64 as i64);
                 }
@@ -1933,7 +3491,7 @@ impl Store {
                 }
                 Op::F32DemoteF64 => {
                     let a = pop_val!(self, F64);
-                    self.stack.push(a as f32);
+                    self.push_f32(a as f32);
                 }
                 Op::F64ConvertI32Signed => {
                     let a = pop_val!(self, I32);
@@ -1953,7 +3511,7 @@ impl Store {
                 }
                 Op::F64PromoteF32 => {
                     let a = pop_val!(self, F32);
-                    self.stack.push(a as f64);
+                    self.push_f64(a as f64);
                 }
                 Op::I32ReinterpretF32 => {
                     let a = pop_val!(self, F32);
@@ -2201,6 +3759,180 @@ impl Store {
         }
     }
 
+    /// Whether execution pauses before the op at `pc` of the innermost frame
+    fn debug_stop(
+        &mut self,
+        depth: usize,
+        mi: usize,
+        func_idx: u32,
+        pc: usize,
+        op: &Op,
+    ) -> Option<RunOutcome> {
+        if let Some(recording) = self.recording.as_deref_mut() {
+            if recording.position >= recording.next_checkpoint {
+                return Some(RunOutcome::Checkpoint);
+            }
+
+            // seeks replay history without stopping for breakpoints or steps
+            if let Some(seek) = &mut recording.seek {
+                if recording.position >= seek.target {
+                    self.resuming = true;
+                    return Some(RunOutcome::Breakpoint);
+                }
+
+                let matched = match seek.search {
+                    None => false,
+                    Some(Search::Instruction) => self.instances[mi].code.compiled_funcs
+                        [func_idx as usize]
+                        .offsets
+                        .starts_instruction(pc),
+                    Some(Search::Breakpoint) => {
+                        self.breakpoints.contains(&(mi as u16, func_idx, pc))
+                    }
+                };
+                if matched {
+                    seek.found = Some(recording.position);
+                }
+                return None;
+            }
+        }
+
+        if let Some(mut hit) = self.watch_hit.take() {
+            let ma = self.instances[hit.instance.0].mem_addrs[hit.memory_idx as usize];
+            let start = hit.address as usize;
+            hit.new = self.memories[ma].data[start..start + hit.old.len()].to_vec();
+            return Some(RunOutcome::Watchpoint(hit));
+        }
+
+        if !mem::take(&mut self.resuming) {
+            let at_breakpoint = self.breakpoints.contains(&(mi as u16, func_idx, pc));
+            let step_done = self.step.is_some_and(|(kind, from)| {
+                let offsets = &self.instances[mi].code.compiled_funcs[func_idx as usize].offsets;
+                let frames = depth + 1;
+
+                offsets.starts_instruction(pc)
+                    && match kind {
+                        StepKind::Into => true,
+                        StepKind::Over => frames <= from,
+                        StepKind::Out => frames < from,
+                    }
+            });
+
+            if at_breakpoint || step_done {
+                // hitting a breakpoint also ends the step that ran into it
+                self.end_step();
+                self.resuming = true;
+                return Some(RunOutcome::Breakpoint);
+            }
+        }
+
+        if !self.watchpoints.is_empty() {
+            self.watch_hit = self.watched_access(depth, mi, pc, op);
+        }
+
+        // ops that will pause for lack of fuel are traced once they run
+        if self.trace_ops && self.fuel.is_none_or(|fuel| fuel >= self.fuel_cost(mi, op)) {
+            if let Some(tracer) = &mut self.tracer {
+                tracer.op(
+                    traced_function(&self.instances, mi as u16, func_idx),
+                    pc,
+                    op,
+                );
+            }
+        }
+
+        None
+    }
+
+    /// The first watched access `op` is about to make, peeking at its
+    /// operands. Accesses out of bounds trap instead
+    fn watched_access(&self, depth: usize, mi: usize, pc: usize, op: &Op) -> Option<Box<WatchHit>> {
+        let operands = self.stack.as_slice();
+        let operand = |from_top: usize, addr_type: AddrType| {
+            let value = operands[operands.len() - 1 - from_top];
+            match addr_type {
+                AddrType::I32 => value.as_i32() as u32 as u64,
+                AddrType::I64 => value.as_i64() as u64,
+            }
+        };
+        let memory = |idx: u32| {
+            let ma = self.instances[mi].mem_addrs[idx as usize];
+            (ma, self.memories[ma].memory_type.addr_type)
+        };
+
+        // (memory address, start, length, write), writes first
+        let accesses = match *op {
+            Op::MemoryFill { memory_idx } => {
+                let (ma, at) = memory(memory_idx);
+                [Some((ma, operand(2, at), operand(0, at), true)), None]
+            }
+            Op::MemoryCopy {
+                dst_memory_idx,
+                src_memory_idx,
+            } => {
+                let (dst, at) = memory(dst_memory_idx);
+                let (src, _) = memory(src_memory_idx);
+                let n = operand(0, at);
+                [
+                    Some((dst, operand(2, at), n, true)),
+                    Some((src, operand(1, at), n, false)),
+                ]
+            }
+            Op::MemoryInit { memory_idx, .. } => {
+                let (ma, at) = memory(memory_idx);
+                [
+                    Some((ma, operand(2, at), operand(0, AddrType::I32), true)),
+                    None,
+                ]
+            }
+            _ => {
+                let (memory_idx, offset, width, write) = op.memory_access()?;
+                let (ma, at) = memory(memory_idx);
+                let base = operand(usize::from(write), at);
+                [
+                    Some((ma, base.checked_add(offset.into())?, width, write)),
+                    None,
+                ]
+            }
+        };
+
+        accesses
+            .into_iter()
+            .flatten()
+            .find_map(|(ma, start, len, write)| {
+                let end = start.saturating_add(len);
+                let (watchpoint, (from, to)) = self
+                    .watchpoints
+                    .iter()
+                    .find_map(|wp| Some((wp, wp.overlap(ma, start, end, write)?)))?;
+                let old = self.memories[ma].data.get(from as usize..to as usize)?;
+
+                Some(Box::new(WatchHit {
+                    frame: self.frame_info(&self.call_stack[depth], pc),
+                    instance: Instance(watchpoint.instance),
+                    memory_idx: watchpoint.memory_idx,
+                    write,
+                    address: from,
+                    old: old.to_vec(),
+                    new: vec![],
+                }))
+            })
+    }
+
+    fn end_step(&mut self) {
+        self.step = None;
+        self.resuming = false;
+        self.sync_debugging();
+    }
+
+    fn sync_debugging(&mut self) {
+        self.debugging = !self.breakpoints.is_empty()
+            || !self.watchpoints.is_empty()
+            || self.step.is_some()
+            || self.recording.is_some()
+            || self.trace_ops;
+    }
+
     fn do_local_get(&mut self, local_idx: usize, depth: usize) {
         let locals = &self.call_stack[depth].locals;
         assert!(
@@ -2220,9 +3952,22 @@ impl Store {
             self.stack.copy_within(len - arity..len, base);
         }
         self.stack.truncate(base + arity);
+        if self.tracer.is_some() {
+            self.trace_exit(depth, base);
+        }
         self.call_stack.pop();
     }
 
+    /// Reports the exit of the frame at `depth`, with the values from
+    /// `results_start` up as its results
+    fn trace_exit(&mut self, depth: usize, results_start: usize) {
+        let frame = &self.call_stack[depth];
+        let function = traced_function(&self.instances, frame.module_idx, frame.compiled_func_idx);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.function_exit(function, self.stack.slice_from(results_start));
+        }
+    }
+
     fn func_num_params(&self, func_addr: usize) -> usize {
         match &self.functions[func_addr] {
             FunctionInstance::Local { function_type, .. }
@@ -2272,10 +4017,14 @@ impl Store {
             ops,
             type_index: 0,
             num_args: 0,
+            func_idx: None,
             local_types: vec![],
             max_stack_height,
+            offsets: OffsetMap::default(),
         };
 
+        self.reserve_stack(Self::frame_height(&cf))?;
+
         let code = Arc::make_mut(&mut self.instances[module_idx as usize].code);
         let compiled_func_idx = code.compiled_funcs.len();
         code.compiled_funcs.push(cf);
@@ -2288,7 +4037,7 @@ impl Store {
             stack_base: self.stack.len(),
             arity: 0,
         });
-        self.run()?;
+        self.run_detached().map_err(|e| self.unwind(e))?;
         Ok(())
     }
 }
@@ -2352,18 +4101,19 @@ fn eval_const_expr_with_module(
                 let addr = *address_map
                     .function_addrs
                     .get(*idx as usize)
-                    .ok_or_else(|| Error::Instantiation(format!("ref.func index {} oob", idx)))?;
+                    .ok_or_else(|| {
+                        Error::Instantiation(format!("ref.func index {} oob", idx).into())
+                    })?;
                 stack.push(RawValue::from_ref(Ref::FunctionAddr(addr)));
             }
             Instruction::GlobalGet(idx) => {
                 let store_idx = *address_map.global_addrs.get(*idx as usize).ok_or_else(|| {
-                    Error::Instantiation(format!("global index {} oob in const expr", idx))
+                    Error::Instantiation(format!("global index {} oob in const expr", idx).into())
                 })?;
                 let global = store.globals.get(store_idx).ok_or_else(|| {
-                    Error::Instantiation(format!(
-                        "global store index {} oob in const expr",
-                        store_idx
-                    ))
+                    Error::Instantiation(
+                        format!("global store index {} oob in const expr", store_idx).into(),
+                    )
                 })?;
                 stack.push(global.value);
             }
@@ -2505,10 +4255,47 @@ impl Store {
         // fuel + pending_arity
         self.fuel.encode(&mut buf);
         self.pending_arity.encode(&mut buf);
+        self.awaiting_host.encode(&mut buf);
+        self.nested.encode(&mut buf);
+        self.last_trap.encode(&mut buf);
+
+        self.limits.encode(&mut buf);
+        self.fuel_costs.encode(&mut buf);
+
+        // debugging
+        self.breakpoints.encode(&mut buf);
+        self.step.encode(&mut buf);
+        self.resuming.encode(&mut buf);
+        self.watchpoints.encode(&mut buf);
+
+        self.coverage.encode(&mut buf);
+        self.wasi.encode(&mut buf);
+        self.deterministic.encode(&mut buf);
+        self.executions.encode(&mut buf);
+        self.next_execution.encode(&mut buf);
 
         buf
     }
 
+    /// Reserves the stack space `call_stack` had in the store the snapshot
+    /// was taken from
+    fn reserve_frames(
+        instances: &[InstantiatedModule],
+        stack: &mut ValueStack,
+        call_stack: &[CallFrame],
+    ) {
+        let stack_top = call_stack
+            .iter()
+            .map(|frame| {
+                let code = &instances[frame.module_idx as usize].code;
+                frame.stack_base
+                    + Self::frame_height(&code.compiled_funcs[frame.compiled_func_idx as usize])
+            })
+            .max()
+            .unwrap_or(0);
+        stack.reserve(stack_top.saturating_sub(stack.len()));
+    }
+
     pub fn from_snapshot(bytes: &[u8]) -> Self {
         let buf = &mut &bytes[..];
 
@@ -2529,6 +4316,7 @@ impl Store {
             type_index: 0,
             locals: vec![],
             body: vec![],
+            offsets: vec![],
         };
 
         for _ in 0..num_funcs {
@@ -2613,7 +4401,7 @@ impl Store {
         }
 
         // instances
-        let instances = Vec::decode(buf);
+        let instances: Vec<InstantiatedModule> = Vec::decode(buf);
 
         // func_addr_to_module
         let func_addr_to_module = Vec::decode(buf);
@@ -2622,14 +4410,39 @@ impl Store {
         let _stack_capacity = u32::decode(buf) as usize;
         let stack_data = decode_bulk(buf);
         let stack_cursor = usize::decode(buf);
-        let stack = ValueStack::from_snapshot(stack_data, stack_cursor);
+        let mut stack = ValueStack::from_snapshot(stack_data, stack_cursor);
 
         // call stack
-        let call_stack = Vec::decode(buf);
+        let call_stack: Vec<CallFrame> = Vec::decode(buf);
+
+        Self::reserve_frames(&instances, &mut stack, &call_stack);
 
         // fuel + pending_arity
         let fuel = Option::decode(buf);
         let pending_arity = Option::decode(buf);
+        let awaiting_host = Option::decode(buf);
+        let nested: Vec<Nesting> = Vec::decode(buf);
+        let base_depth = nested.last().map_or(0, |nesting| nesting.depth);
+        let last_trap = Option::decode(buf);
+
+        let limits = StoreLimits::decode(buf);
+        let fuel_costs = FuelCosts::decode(buf);
+
+        // debugging
+        let breakpoints: BTreeSet<(u16, u32, usize)> = BTreeSet::decode(buf);
+        let step: Option<(StepKind, usize)> = Option::decode(buf);
+        let resuming = bool::decode(buf);
+        let watchpoints: Vec<Watchpoint> = Vec::decode(buf);
+
+        let coverage = Option::decode(buf);
+        let wasi = Option::decode(buf);
+        let deterministic = Option::decode(buf);
+        let mut executions: BTreeMap<usize, ExecutionContext> = BTreeMap::decode(buf);
+        for context in executions.values_mut() {
+            Self::reserve_frames(&instances, &mut context.stack, &context.call_stack);
+        }
+        let next_execution = usize::decode(buf);
+        let debugging = !breakpoints.is_empty() || !watchpoints.is_empty() || step.is_some();
 
         Self {
             functions,
@@ -2644,8 +4457,49 @@ impl Store {
             stack,
             call_stack,
             fuel,
+            fuel_costs,
             pending_arity,
             pending_suspension: None,
+            awaiting_host,
+            nested,
+            base_depth,
+            last_trap,
+            executions,
+            next_execution,
+            running: None,
+            limits,
+            interrupt: InterruptHandle::default(),
+            breakpoints,
+            step,
+            resuming,
+            watchpoints,
+            watch_hit: None,
+            debugging,
+            recording: None,
+            host_log: None,
+            tracer: None,
+            trace_ops: false,
+            profiler: None,
+            coverage,
+            wasi,
+            deterministic,
         }
     }
 }
+
+/// Identifies compiled function `compiled_idx` of instance `module_idx` for a
+/// tracer, borrowing only the instances so the tracer can be borrowed too
+fn traced_function(
+    instances: &[InstantiatedModule],
+    module_idx: u16,
+    compiled_idx: u32,
+) -> TracedFunction<'_> {
+    let code = &instances[module_idx as usize].code;
+    let func_idx = code.compiled_funcs[compiled_idx as usize].func_idx;
+
+    TracedFunction {
+        module_idx: module_idx as usize,
+        func_idx,
+        name: func_idx.and_then(|idx| code.names.function(idx)),
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt::Write as _;
+use std::io::Write;
+
+use crate::backtrace::Backtrace;
+use crate::error::Trap;
+use crate::ir::Op;
+use crate::store::ExecutionState;
+use crate::RawValue;
+
+/// The guest function a trace event happened in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TracedFunction<'a> {
+    /// Index of the instance in the store
+    pub module_idx: usize,
+    /// Index in the module's function index space, `None` for segment
+    /// initializers
+    pub func_idx: Option<u32>,
+    pub name: Option<&'a str>,
+}
+
+/// Receives execution events from a [`crate::Store`], see
+/// [`crate::Store::set_tracer`]
+///
+/// Every method defaults to doing nothing. Tracers observe execution but
+/// can't change it, and they aren't part of snapshots.
+pub trait Tracer {
+    /// A guest function was entered, `args` are its parameters
+    fn function_entry(&mut self, _function: TracedFunction<'_>, _args: &[RawValue]) {}
+
+    /// A guest function returned. Frames replaced by a tail call exit with
+    /// no results
+    fn function_exit(&mut self, _function: TracedFunction<'_>, _results: &[RawValue]) {}
+
+    /// The guest called a host import and suspends until it's answered
+    fn host_call(&mut self, _module_name: &str, _func_name: &str, _args: &[RawValue]) {}
+
+    /// The embedder answered the pending host call
+    fn host_return(&mut self, _results: &[RawValue]) {}
+
+    /// Execution paused for fuel, an interrupt or a debug stop
+    fn suspended(&mut self, _state: &ExecutionState) {}
+
+    /// `memory.grow` ran, `new_pages` is `None` when it failed
+    fn memory_grow(&mut self, _memory_idx: u32, _old_pages: usize, _new_pages: Option<usize>) {}
+
+    /// The invocation trapped
+    fn trap(&mut self, _trap: &Trap, _backtrace: &Backtrace) {}
+
+    /// Whether [`Tracer::op`] is called, read when the tracer is installed.
+    /// Tracing ops puts the store on its slower debugging path
+    fn traces_ops(&self) -> bool {
+        false
+    }
+
+    /// The op at `pc` of `function` is about to run
+    fn op(&mut self, _function: TracedFunction<'_>, _pc: usize, _op: &Op) {}
+}
+
+/// Writes every event as a JSON object on its own line
+///
+/// Values are written as their raw bits reinterpreted as `i64`. Write errors
+/// are ignored so tracing never changes the outcome of a run.
+#[derive(Debug)]
+pub struct JsonTracer<W: Write> {
+    out: W,
+    ops: bool,
+    line: String,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub const fn new(out: W) -> Self {
+        Self {
+            out,
+            ops: false,
+            line: String::new(),
+        }
+    }
+
+    /// Also writes an event for every op
+    pub const fn with_ops(mut self) -> Self {
+        self.ops = true;
+        self
+    }
+
+    fn start(&mut self, event: &str) {
+        self.line.clear();
+        let _ = write!(self.line, "{{\"event\":\"{event}\"");
+    }
+
+    fn field(&mut self, key: &str, value: impl std::fmt::Display) {
+        let _ = write!(self.line, ",\"{key}\":{value}");
+    }
+
+    fn string(&mut self, key: &str, value: &str) {
+        let _ = write!(self.line, ",\"{key}\":");
+        push_json_string(&mut self.line, value);
+    }
+
+    fn values(&mut self, key: &str, values: &[RawValue]) {
+        let _ = write!(self.line, ",\"{key}\":[");
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            let _ = write!(self.line, "{}", value.as_i64());
+        }
+        self.line.push(']');
+    }
+
+    fn function(&mut self, function: TracedFunction<'_>) {
+        self.field("module", function.module_idx);
+        match function.func_idx {
+            Some(idx) => self.field("func", idx),
+            None => self.field("func", "null"),
+        }
+        if let Some(name) = function.name {
+            self.string("name", name);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.line.push_str("}\n");
+        let _ = self.out.write_all(self.line.as_bytes());
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn function_entry(&mut self, function: TracedFunction<'_>, args: &[RawValue]) {
+        self.start("enter");
+        self.function(function);
+        self.values("args", args);
+        self.finish();
+    }
+
+    fn function_exit(&mut self, function: TracedFunction<'_>, results: &[RawValue]) {
+        self.start("exit");
+        self.function(function);
+        self.values("results", results);
+        self.finish();
+    }
+
+    fn host_call(&mut self, module_name: &str, func_name: &str, args: &[RawValue]) {
+        self.start("host_call");
+        self.string("module", module_name);
+        self.string("name", func_name);
+        self.values("args", args);
+        self.finish();
+    }
+
+    fn host_return(&mut self, results: &[RawValue]) {
+        self.start("host_return");
+        self.values("results", results);
+        self.finish();
+    }
+
+    fn suspended(&mut self, state: &ExecutionState) {
+        let reason = match state {
+            ExecutionState::FuelExhausted => "fuel_exhausted",
+            ExecutionState::Interrupted => "interrupted",
+            ExecutionState::Breakpoint => "breakpoint",
+            ExecutionState::Watchpoint(_) => "watchpoint",
+            ExecutionState::Completed(_) | ExecutionState::Suspended { .. } => return,
+        };
+        self.start("suspended");
+        self.string("reason", reason);
+        self.finish();
+    }
+
+    fn memory_grow(&mut self, memory_idx: u32, old_pages: usize, new_pages: Option<usize>) {
+        self.start("memory_grow");
+        self.field("memory", memory_idx);
+        self.field("old_pages", old_pages);
+        match new_pages {
+            Some(pages) => self.field("new_pages", pages),
+            None => self.field("new_pages", "null"),
+        }
+        self.finish();
+    }
+
+    fn trap(&mut self, trap: &Trap, backtrace: &Backtrace) {
+        self.start("trap");
+        self.string("trap", &trap.to_string());
+        self.line.push_str(",\"frames\":[");
+        for (i, frame) in backtrace.frames.iter().enumerate() {
+            if i > 0 {
+                self.line.push(',');
+            }
+            let _ = write!(self.line, "{{\"module\":{}", frame.module_idx);
+            match frame.func_idx {
+                Some(idx) => self.field("func", idx),
+                None => self.field("func", "null"),
+            }
+            match frame.wasm_offset {
+                Some(offset) => self.field("offset", offset),
+                None => self.field("offset", "null"),
+            }
+            self.line.push('}');
+        }
+        self.line.push(']');
+        self.finish();
+    }
+
+    fn traces_ops(&self) -> bool {
+        self.ops
+    }
+
+    fn op(&mut self, function: TracedFunction<'_>, pc: usize, op: &Op) {
+        self.start("op");
+        self.function(function);
+        self.field("pc", pc);
+        self.string("op", &format!("{op:?}"));
+        self.finish();
+    }
+}
+
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
@@ -8,7 +8,8 @@ use crate::execution_grammar::RawValue;
 ///
 /// safety:
 ///     - wasm validation guarantees every instruction sequence is stack-safe
-///     - we track the max stack height when we compile so we can never overflow
+///     - we track the max stack height when we compile, and the store reserves
+///       it on function entry so we can never overflow
 pub struct ValueStack {
     inner: Box<[RawValue]>,
     cursor: usize,
@@ -54,6 +55,24 @@ impl ValueStack {
         self.inner.len()
     }
 
+    /// Grows the stack so at least `additional` more values fit above the
+    /// cursor
+    #[inline]
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.cursor + additional;
+        if needed > self.inner.len() {
+            self.grow(needed);
+        }
+    }
+
+    #[cold]
+    fn grow(&mut self, needed: usize) {
+        let capacity = needed.max(self.inner.len() * 2);
+        let mut inner = vec![RawValue::default(); capacity].into_boxed_slice();
+        inner[..self.cursor].copy_from_slice(&self.inner[..self.cursor]);
+        self.inner = inner;
+    }
+
     pub const fn clear(&mut self) {
         self.cursor = 0;
     }
@@ -110,6 +129,13 @@ impl ValueStack {
         }
     }
 
+    pub fn peek_address(&self, addr_type: AddrType) -> usize {
+        match addr_type {
+            AddrType::I32 => self.last().as_i32() as u32 as usize,
+            AddrType::I64 => self.last().as_i64() as usize,
+        }
+    }
+
     pub fn push_address(&mut self, value: usize, addr_type: AddrType) {
         match addr_type {
             AddrType::I32 => self.push(value as i32),
@@ -129,6 +155,14 @@ impl ValueStack {
         unsafe { self.inner.get_unchecked(range) }
     }
 
+    pub fn as_slice(&self) -> &[RawValue] {
+        &self.inner[..self.cursor]
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [RawValue] {
+        &mut self.inner[..self.cursor]
+    }
+
     pub fn snapshot_data(&self) -> (&[RawValue], usize) {
         (&self.inner[..self.cursor], self.cursor)
     }
@@ -0,0 +1,288 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::wasi::{
+    Errno, ERRNO_EXIST, ERRNO_FBIG, ERRNO_ISDIR, ERRNO_NOENT, ERRNO_NOSPC, ERRNO_NOTCAPABLE,
+    ERRNO_NOTDIR, OFLAGS_CREAT, OFLAGS_DIRECTORY, OFLAGS_EXCL, OFLAGS_TRUNC,
+};
+
+/// An in-memory filesystem WASI guests can be given in place of host
+/// directories, see [`crate::Wasi::preopen_vfs`]
+///
+/// Unlike host files, its contents and the guest's descriptors into it are
+/// part of [`crate::Store`] snapshots, so a restored guest carries on reading
+/// the same file at the same offset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vfs {
+    /// Indexed by inode, the root directory being inode 0
+    pub(crate) nodes: Vec<Node>,
+    /// Guest writes that would take the files past this many bytes in total
+    /// fail with `ERRNO_NOSPC`
+    pub(crate) max_bytes: u64,
+    /// Bytes in all files
+    pub(crate) bytes: u64,
+}
+
+const DEFAULT_MAX_BYTES: u64 = 1 << 30;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, usize>),
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vfs {
+    pub(crate) const ROOT: usize = 0;
+
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![Node::Dir(BTreeMap::new())],
+            max_bytes: DEFAULT_MAX_BYTES,
+            bytes: 0,
+        }
+    }
+
+    /// Caps the bytes guests can store in the files, 1 GiB by default. Files
+    /// the embedder writes count towards it but aren't refused
+    pub const fn max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = max;
+        self
+    }
+
+    /// Copies the tree below the host directory `dir`, following symlinks
+    /// to files. Symlinked directories, which could loop, and anything that
+    /// isn't a regular file, like devices and pipes, are skipped
+    pub fn from_host_dir(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let mut vfs = Self::new();
+        vfs.copy_host_dir(dir.as_ref(), Self::ROOT)?;
+        Ok(vfs)
+    }
+
+    fn copy_host_dir(&mut self, host: &Path, dir: usize) -> io::Result<()> {
+        for entry in fs::read_dir(host)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // `file_type` doesn't follow symlinks, `metadata` does
+            let node = if entry.file_type()?.is_dir() {
+                Node::Dir(BTreeMap::new())
+            } else if fs::metadata(entry.path()).is_ok_and(|meta| meta.is_file()) {
+                Node::File(fs::read(entry.path())?)
+            } else {
+                continue;
+            };
+
+            let inode = self.insert(dir, name, node);
+            if matches!(self.nodes[inode], Node::Dir(_)) {
+                self.copy_host_dir(&entry.path(), inode)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the directory `path` and any missing parents
+    pub fn create_dir_all(&mut self, path: &str) -> io::Result<()> {
+        self.dir_all(path).map(|_| ())
+    }
+
+    /// Creates or replaces the file at `path`, creating missing parents
+    pub fn write_file(&mut self, path: &str, contents: impl Into<Vec<u8>>) -> io::Result<()> {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let dir = self.dir_all(parent)?;
+        match self.child(dir, name) {
+            Some(inode) => match &mut self.nodes[inode] {
+                Node::File(data) => {
+                    let contents = contents.into();
+                    self.bytes = self.bytes - data.len() as u64 + contents.len() as u64;
+                    *data = contents;
+                }
+                Node::Dir(_) => return Err(io::ErrorKind::IsADirectory.into()),
+            },
+            None => {
+                self.insert(dir, name.to_owned(), Node::File(contents.into()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_file(&self, path: &str) -> Option<&[u8]> {
+        let (parent, name) = self.walk(Self::ROOT, path).ok()?;
+        match &self.nodes[self.child(parent, name?)?] {
+            Node::File(data) => Some(data),
+            Node::Dir(_) => None,
+        }
+    }
+
+    /// Names in the directory at `path`, in order
+    pub fn read_dir(&self, path: &str) -> Option<Vec<&str>> {
+        let (parent, name) = self.walk(Self::ROOT, path).ok()?;
+        let inode = match name {
+            Some(name) => self.child(parent, name)?,
+            None => parent,
+        };
+        match &self.nodes[inode] {
+            Node::Dir(entries) => Some(entries.keys().map(String::as_str).collect()),
+            Node::File(_) => None,
+        }
+    }
+
+    fn dir_all(&mut self, path: &str) -> io::Result<usize> {
+        let mut dir = Self::ROOT;
+        for name in path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+        {
+            dir = match self.child(dir, name) {
+                Some(inode) if matches!(self.nodes[inode], Node::Dir(_)) => inode,
+                Some(_) => return Err(io::ErrorKind::NotADirectory.into()),
+                None => self.insert(dir, name.to_owned(), Node::Dir(BTreeMap::new())),
+            };
+        }
+        Ok(dir)
+    }
+
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        match &self.nodes[dir] {
+            Node::Dir(entries) => entries.get(name).copied(),
+            Node::File(_) => None,
+        }
+    }
+
+    fn insert(&mut self, dir: usize, name: String, node: Node) -> usize {
+        if let Node::File(data) = &node {
+            self.bytes += data.len() as u64;
+        }
+        let inode = self.nodes.len();
+        self.nodes.push(node);
+        if let Node::Dir(entries) = &mut self.nodes[dir] {
+            entries.insert(name, inode);
+        }
+        inode
+    }
+
+    /// Walks `path` from directory `dir` to the directory holding its last
+    /// component, returned with that component's name. Paths naming `dir`
+    /// itself have no last component, and paths leaving it are refused
+    fn walk<'p>(&self, dir: usize, path: &'p str) -> Result<(usize, Option<&'p str>), Errno> {
+        if path.starts_with('/') {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+
+        let mut names = vec![];
+        for name in path.split('/') {
+            match name {
+                "" | "." => {}
+                ".." => {
+                    names.pop().ok_or(ERRNO_NOTCAPABLE)?;
+                }
+                name => names.push(name),
+            }
+        }
+
+        let Some(last) = names.pop() else {
+            return Ok((dir, None));
+        };
+        let mut parent = dir;
+        for name in names {
+            parent = self.child(parent, name).ok_or(ERRNO_NOENT)?;
+            if !matches!(self.nodes[parent], Node::Dir(_)) {
+                return Err(ERRNO_NOTDIR);
+            }
+        }
+        Ok((parent, Some(last)))
+    }
+
+    /// Resolves `path` below directory `dir` the way `path_open` does,
+    /// creating or truncating the file as `oflags` ask
+    pub(crate) fn open(&mut self, dir: usize, path: &str, oflags: u16) -> Result<usize, Errno> {
+        let (parent, name) = self.walk(dir, path)?;
+        let creat = oflags & OFLAGS_CREAT != 0;
+        let directory = oflags & OFLAGS_DIRECTORY != 0;
+
+        let existing = name.map_or(Some(parent), |name| self.child(parent, name));
+        let inode = match existing {
+            Some(_) if creat && oflags & OFLAGS_EXCL != 0 => return Err(ERRNO_EXIST),
+            Some(inode) => inode,
+            None if creat && !directory => {
+                return Ok(self.insert(parent, name.unwrap().to_owned(), Node::File(vec![])));
+            }
+            None => return Err(ERRNO_NOENT),
+        };
+
+        match &mut self.nodes[inode] {
+            Node::File(_) if directory => Err(ERRNO_NOTDIR),
+            Node::File(data) => {
+                if oflags & OFLAGS_TRUNC != 0 {
+                    self.bytes -= data.len() as u64;
+                    data.clear();
+                }
+                Ok(inode)
+            }
+            Node::Dir(_) if oflags & OFLAGS_TRUNC != 0 => Err(ERRNO_ISDIR),
+            Node::Dir(_) => Ok(inode),
+        }
+    }
+
+    pub(crate) fn is_dir(&self, inode: usize) -> bool {
+        matches!(self.nodes[inode], Node::Dir(_))
+    }
+
+    /// Size in bytes of a file, or number of entries of a directory
+    pub(crate) fn len(&self, inode: usize) -> u64 {
+        match &self.nodes[inode] {
+            Node::File(data) => data.len() as u64,
+            Node::Dir(entries) => entries.len() as u64,
+        }
+    }
+
+    /// Reads from file `inode` at `offset`, returning the number of bytes read
+    pub(crate) fn read_at(&self, inode: usize, offset: u64, buf: &mut [u8]) -> usize {
+        let Node::File(data) = &self.nodes[inode] else {
+            return 0;
+        };
+        let start = (offset as usize).min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        n
+    }
+
+    /// Writes to file `inode` at `offset`, zero filling any gap past its end
+    ///
+    /// Files can't grow past `isize::MAX` bytes, nor all of them together
+    /// past [`Self::max_bytes`], and a file the host can't allocate is refused
+    /// rather than aborting it.
+    pub(crate) fn write_at(
+        &mut self,
+        inode: usize,
+        offset: u64,
+        bytes: &[u8],
+    ) -> Result<(), Errno> {
+        let Node::File(data) = &mut self.nodes[inode] else {
+            return Ok(());
+        };
+        let end = offset
+            .checked_add(bytes.len() as u64)
+            .filter(|&end| end <= isize::MAX as u64)
+            .ok_or(ERRNO_FBIG)?;
+        let (start, end) = (offset as usize, end as usize);
+        if data.len() < end {
+            let growth = (end - data.len()) as u64;
+            if self.bytes.saturating_add(growth) > self.max_bytes {
+                return Err(ERRNO_NOSPC);
+            }
+            data.try_reserve(end - data.len())
+                .map_err(|_| ERRNO_NOSPC)?;
+            data.resize(end, 0);
+            self.bytes += growth;
+        }
+        data[start..end].copy_from_slice(bytes);
+        Ok(())
+    }
+}
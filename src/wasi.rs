@@ -0,0 +1,1027 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::binary_grammar::{FunctionType, ResultType, ValueType};
+use crate::error::{Result, Trap};
+use crate::execution_grammar::RawValue;
+use crate::trap;
+use crate::vfs::Vfs;
+
+/// The import module WASI preview 1 functions are defined under
+pub const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+pub type Errno = u16;
+
+pub const ERRNO_ACCES: Errno = 2;
+pub const ERRNO_BADF: Errno = 8;
+pub const ERRNO_EXIST: Errno = 20;
+pub const ERRNO_FAULT: Errno = 21;
+pub const ERRNO_FBIG: Errno = 22;
+pub const ERRNO_INVAL: Errno = 28;
+pub const ERRNO_IO: Errno = 29;
+pub const ERRNO_ISDIR: Errno = 31;
+pub const ERRNO_NAMETOOLONG: Errno = 37;
+pub const ERRNO_NOENT: Errno = 44;
+pub const ERRNO_NOSPC: Errno = 51;
+pub const ERRNO_NOSYS: Errno = 52;
+pub const ERRNO_NOTDIR: Errno = 54;
+pub const ERRNO_NOTSUP: Errno = 58;
+pub const ERRNO_OVERFLOW: Errno = 61;
+pub const ERRNO_SPIPE: Errno = 70;
+pub const ERRNO_NOTCAPABLE: Errno = 76;
+
+const FILETYPE_CHARACTER_DEVICE: u8 = 2;
+const FILETYPE_DIRECTORY: u8 = 3;
+const FILETYPE_REGULAR_FILE: u8 = 4;
+
+const RIGHTS_FD_READ: u64 = 1 << 1;
+const RIGHTS_FD_WRITE: u64 = 1 << 6;
+const RIGHTS_ALL: u64 = (1 << 30) - 1;
+
+pub const OFLAGS_CREAT: u16 = 1;
+pub const OFLAGS_DIRECTORY: u16 = 2;
+pub const OFLAGS_EXCL: u16 = 4;
+pub const OFLAGS_TRUNC: u16 = 8;
+const FDFLAGS_APPEND: u16 = 1;
+
+const EVENTTYPE_CLOCK: u8 = 0;
+const SUBCLOCKFLAGS_ABSTIME: u16 = 1;
+
+/// Every preview 1 function and its parameters, `i` for i32 and `I` for i64.
+/// All but `proc_exit` return an errno
+const FUNCTIONS: &[(&str, &str)] = &[
+    ("args_get", "ii"),
+    ("args_sizes_get", "ii"),
+    ("environ_get", "ii"),
+    ("environ_sizes_get", "ii"),
+    ("clock_res_get", "ii"),
+    ("clock_time_get", "iIi"),
+    ("fd_advise", "iIIi"),
+    ("fd_allocate", "iII"),
+    ("fd_close", "i"),
+    ("fd_datasync", "i"),
+    ("fd_fdstat_get", "ii"),
+    ("fd_fdstat_set_flags", "ii"),
+    ("fd_fdstat_set_rights", "iII"),
+    ("fd_filestat_get", "ii"),
+    ("fd_filestat_set_size", "iI"),
+    ("fd_filestat_set_times", "iIIi"),
+    ("fd_pread", "iiiIi"),
+    ("fd_prestat_get", "ii"),
+    ("fd_prestat_dir_name", "iii"),
+    ("fd_pwrite", "iiiIi"),
+    ("fd_read", "iiii"),
+    ("fd_readdir", "iiiIi"),
+    ("fd_renumber", "ii"),
+    ("fd_seek", "iIii"),
+    ("fd_sync", "i"),
+    ("fd_tell", "ii"),
+    ("fd_write", "iiii"),
+    ("path_create_directory", "iii"),
+    ("path_filestat_get", "iiiii"),
+    ("path_filestat_set_times", "iiiiIIi"),
+    ("path_link", "iiiiiii"),
+    ("path_open", "iiiiiIIii"),
+    ("path_readlink", "iiiiii"),
+    ("path_remove_directory", "iii"),
+    ("path_rename", "iiiiii"),
+    ("path_symlink", "iiiii"),
+    ("path_unlink_file", "iii"),
+    ("poll_oneoff", "iiii"),
+    ("proc_exit", "i"),
+    ("proc_raise", "i"),
+    ("random_get", "ii"),
+    ("sched_yield", ""),
+    ("sock_accept", "iii"),
+    ("sock_recv", "iiiiii"),
+    ("sock_send", "iiiii"),
+    ("sock_shutdown", "ii"),
+];
+
+/// The name and type of every `wasi_snapshot_preview1` function
+pub fn function_types() -> impl Iterator<Item = (&'static str, FunctionType)> {
+    FUNCTIONS.iter().map(|&(name, params)| {
+        let params = params
+            .chars()
+            .map(|c| match c {
+                'I' => ValueType::I64,
+                _ => ValueType::I32,
+            })
+            .collect();
+        let results = match name {
+            "proc_exit" => vec![],
+            _ => vec![ValueType::I32],
+        };
+        (name, FunctionType(ResultType(params), ResultType(results)))
+    })
+}
+
+pub enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    /// `preopen` is the name preopened directories are known by
+    Dir {
+        host: PathBuf,
+        preopen: Option<String>,
+    },
+    File(File),
+    VfsDir {
+        inode: usize,
+        preopen: Option<String>,
+    },
+    VfsFile {
+        inode: usize,
+        offset: u64,
+        rights: u64,
+        append: bool,
+    },
+    /// A host file lost to a snapshot. Its number stays taken until the guest
+    /// closes it, so it can't end up naming another file
+    Closed,
+}
+
+/// Host state for guests importing WASI preview 1, see [`crate::Linker::wasi`]
+///
+/// Calls are answered synchronously instead of suspending the guest. Files
+/// are opened below the preopened host directories or in a [`Vfs`], standard
+/// streams default to the host's own. Unimplemented functions return
+/// `ENOSYS`.
+///
+/// Store snapshots include the arguments, environment, descriptor table and
+/// the `Vfs`, but not the streams or the host files: restored guests write to
+/// the host's standard streams, and their host file descriptors fail with
+/// `EBADF` until they're closed.
+pub struct Wasi {
+    pub(crate) args: Vec<String>,
+    pub(crate) env: Vec<(String, String)>,
+    pub(crate) fds: Vec<Option<Descriptor>>,
+    pub(crate) vfs: Option<Vfs>,
+    stdin: Box<dyn Read>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    pub(crate) started: Instant,
+    /// Virtual clock and entropy in deterministic stores
+    pub(crate) seeded: Option<Seeded>,
+    /// Set while replaying calls whose I/O already happened
+    muted: bool,
+}
+
+/// The results of a WASI call and the guest memory it may have written
+pub struct WasiCall {
+    pub(crate) results: Vec<RawValue>,
+    pub(crate) written: Vec<Range<usize>>,
+}
+
+/// Virtual time advances by this much on every clock read, so guests timing
+/// loops still see time pass
+const SEEDED_CLOCK_TICK: u64 = 1_000;
+/// Where the virtual realtime clock starts, 2020-01-01T00:00:00Z
+const SEEDED_EPOCH: u64 = 1_577_836_800 * 1_000_000_000;
+
+/// The clock and entropy source of a deterministic store, see
+/// [`crate::Store::deterministic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seeded {
+    /// Nanoseconds on the monotonic clock
+    pub(crate) now: u64,
+    /// SplitMix64 state
+    pub(crate) rng: u64,
+}
+
+impl Seeded {
+    const fn next_u64(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+impl Default for Wasi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Wasi {
+    pub fn new() -> Self {
+        Self {
+            args: vec![],
+            env: vec![],
+            fds: vec![
+                Some(Descriptor::Stdin),
+                Some(Descriptor::Stdout),
+                Some(Descriptor::Stderr),
+            ],
+            vfs: None,
+            stdin: Box::new(io::stdin()),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            started: Instant::now(),
+            seeded: None,
+            muted: false,
+        }
+    }
+
+    /// Appends to the guest's arguments, the first being the program name
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<S: Into<String>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Gives the guest access to the host directory `host` under the name
+    /// `guest`
+    pub fn preopen_dir(mut self, host: impl Into<PathBuf>, guest: impl Into<String>) -> Self {
+        self.fds.push(Some(Descriptor::Dir {
+            host: host.into(),
+            preopen: Some(guest.into()),
+        }));
+        self
+    }
+
+    /// Gives the guest `vfs` under the name `guest`, replacing any previous
+    /// one
+    pub fn preopen_vfs(mut self, vfs: Vfs, guest: impl Into<String>) -> Self {
+        self.vfs = Some(vfs);
+        self.fds.push(Some(Descriptor::VfsDir {
+            inode: Vfs::ROOT,
+            preopen: Some(guest.into()),
+        }));
+        self
+    }
+
+    pub fn stdin(mut self, stdin: impl Read + 'static) -> Self {
+        self.stdin = Box::new(stdin);
+        self
+    }
+
+    pub fn stdout(mut self, stdout: impl Write + 'static) -> Self {
+        self.stdout = Box::new(stdout);
+        self
+    }
+
+    pub fn stderr(mut self, stderr: impl Write + 'static) -> Self {
+        self.stderr = Box::new(stderr);
+        self
+    }
+
+    pub const fn vfs(&self) -> Option<&Vfs> {
+        self.vfs.as_ref()
+    }
+
+    pub const fn vfs_mut(&mut self) -> Option<&mut Vfs> {
+        self.vfs.as_mut()
+    }
+
+    /// Switches clocks and randomness to sources seeded with `seed`, unless
+    /// they already are
+    pub(crate) fn seed(&mut self, seed: u64) {
+        self.seeded.get_or_insert(Seeded { now: 0, rng: seed });
+    }
+
+    /// Takes over the standard streams of `other`
+    pub(crate) fn adopt_streams(&mut self, other: Self) {
+        self.stdin = other.stdin;
+        self.stdout = other.stdout;
+        self.stderr = other.stderr;
+    }
+
+    /// Runs `name` like [`Self::call`], but with the standard streams
+    /// disconnected and host files out of reach, so only guest state changes
+    pub(crate) fn call_muted(
+        &mut self,
+        name: &str,
+        args: &[RawValue],
+        memory: &mut [u8],
+    ) -> Result<WasiCall> {
+        let stdin = mem::replace(&mut self.stdin, Box::new(io::empty()));
+        let stdout = mem::replace(&mut self.stdout, Box::new(io::sink()));
+        let stderr = mem::replace(&mut self.stderr, Box::new(io::sink()));
+        self.muted = true;
+
+        let call = self.call(name, args, memory);
+
+        self.muted = false;
+        self.stdin = stdin;
+        self.stdout = stdout;
+        self.stderr = stderr;
+        call
+    }
+
+    /// Runs the WASI function `name` against the caller's `memory`
+    pub(crate) fn call(
+        &mut self,
+        name: &str,
+        args: &[RawValue],
+        memory: &mut [u8],
+    ) -> Result<WasiCall> {
+        let arg = |i: usize| args[i].as_i32() as u32;
+        let arg64 = |i: usize| args[i].as_i64() as u64;
+        let mem = &mut Memory {
+            data: memory,
+            written: vec![],
+        };
+
+        let result = match name {
+            "args_get" => {
+                let args = self.args.iter().map(|arg| arg.as_bytes().to_vec());
+                write_strings(mem, args, arg(0), arg(1))
+            }
+            "args_sizes_get" => {
+                let args = self.args.iter().map(|arg| arg.len());
+                write_sizes(mem, args, arg(0), arg(1))
+            }
+            "environ_get" => {
+                let vars = self
+                    .env
+                    .iter()
+                    .map(|(k, v)| format!("{k}={v}").into_bytes());
+                write_strings(mem, vars, arg(0), arg(1))
+            }
+            "environ_sizes_get" => {
+                let vars = self.env.iter().map(|(k, v)| k.len() + 1 + v.len());
+                write_sizes(mem, vars, arg(0), arg(1))
+            }
+            "clock_res_get" => match arg(0) {
+                0..=3 => mem.write_u64(arg(1), 1),
+                _ => Err(ERRNO_INVAL),
+            },
+            "clock_time_get" => self.now(arg(0)).and_then(|now| mem.write_u64(arg(2), now)),
+            "fd_close" => self.fd_close(arg(0)),
+            "fd_fdstat_get" => self.fd_fdstat_get(mem, arg(0), arg(1)),
+            "fd_filestat_get" => self.fd_filestat_get(mem, arg(0), arg(1)),
+            "fd_prestat_get" => self.fd_prestat_get(mem, arg(0), arg(1)),
+            "fd_prestat_dir_name" => self.fd_prestat_dir_name(mem, arg(0), arg(1), arg(2)),
+            "fd_read" => self.fd_read(mem, arg(0), arg(1), arg(2), arg(3)),
+            "fd_seek" => self.fd_seek(mem, arg(0), arg64(1) as i64, arg(2), arg(3)),
+            "fd_tell" => self.fd_seek(mem, arg(0), 0, 1, arg(1)),
+            "fd_write" => self.fd_write(mem, arg(0), arg(1), arg(2), arg(3)),
+            "path_open" => self.path_open(
+                mem,
+                arg(0),
+                (arg(2), arg(3)),
+                arg(4) as u16,
+                arg64(5),
+                arg(7) as u16,
+                arg(8),
+            ),
+            "poll_oneoff" => self.poll_oneoff(mem, arg(0), arg(1), arg(2), arg(3)),
+            "proc_exit" => trap!(Trap::Exit(arg(0))),
+            "random_get" => mem
+                .slice_mut(arg(0), arg(1))
+                .map(|buf| match &mut self.seeded {
+                    Some(seeded) => seeded.fill(buf),
+                    None => fill_random(buf),
+                }),
+            "sched_yield" => Ok(()),
+            _ => Err(ERRNO_NOSYS),
+        };
+
+        let errno = match result {
+            Ok(()) => 0,
+            Err(errno) => errno,
+        };
+        Ok(WasiCall {
+            results: vec![RawValue::from(i32::from(errno))],
+            written: mem::take(&mut mem.written),
+        })
+    }
+
+    /// Nanoseconds on clock `id`
+    fn now(&mut self, id: u32) -> std::result::Result<u64, Errno> {
+        if let Some(seeded) = &mut self.seeded {
+            let now = seeded.now;
+            seeded.now = seeded.now.saturating_add(SEEDED_CLOCK_TICK);
+            return match id {
+                0 => Ok(SEEDED_EPOCH.saturating_add(now)),
+                1..=3 => Ok(now),
+                _ => Err(ERRNO_INVAL),
+            };
+        }
+
+        match id {
+            0 => Ok(SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_nanos() as u64)),
+            1..=3 => Ok(self.started.elapsed().as_nanos() as u64),
+            _ => Err(ERRNO_INVAL),
+        }
+    }
+
+    fn descriptor(&mut self, fd: u32) -> std::result::Result<&mut Descriptor, Errno> {
+        self.fds
+            .get_mut(fd as usize)
+            .and_then(Option::as_mut)
+            .filter(|descriptor| !matches!(descriptor, Descriptor::Closed))
+            .ok_or(ERRNO_BADF)
+    }
+
+    fn fd_close(&mut self, fd: u32) -> std::result::Result<(), Errno> {
+        // the only call that releases a closed descriptor
+        match self.fds.get_mut(fd as usize) {
+            Some(slot @ Some(_)) => {
+                *slot = None;
+                Ok(())
+            }
+            _ => Err(ERRNO_BADF),
+        }
+    }
+
+    fn fd_fdstat_get(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let filetype = match self.descriptor(fd)? {
+            Descriptor::Stdin | Descriptor::Stdout | Descriptor::Stderr => {
+                FILETYPE_CHARACTER_DEVICE
+            }
+            Descriptor::Dir { .. } | Descriptor::VfsDir { .. } => FILETYPE_DIRECTORY,
+            Descriptor::File(_) | Descriptor::VfsFile { .. } => FILETYPE_REGULAR_FILE,
+            Descriptor::Closed => return Err(ERRNO_BADF),
+        };
+        let (rights, append) = match self.descriptor(fd)? {
+            Descriptor::VfsFile { rights, append, .. } => (*rights, *append),
+            _ => (RIGHTS_ALL, false),
+        };
+
+        let stat = mem.slice_mut(ptr, 24)?;
+        stat.fill(0);
+        stat[0] = filetype;
+        if append {
+            stat[2..4].copy_from_slice(&FDFLAGS_APPEND.to_le_bytes());
+        }
+        stat[8..16].copy_from_slice(&rights.to_le_bytes());
+        stat[16..24].copy_from_slice(&RIGHTS_ALL.to_le_bytes());
+        Ok(())
+    }
+
+    fn fd_filestat_get(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let vfs_inode = match self.descriptor(fd)? {
+            Descriptor::VfsDir { inode, .. } | Descriptor::VfsFile { inode, .. } => Some(*inode),
+            _ => None,
+        };
+        if let (Some(inode), Some(vfs)) = (vfs_inode, &self.vfs) {
+            let filetype = match vfs.is_dir(inode) {
+                true => FILETYPE_DIRECTORY,
+                false => FILETYPE_REGULAR_FILE,
+            };
+            let stat = mem.slice_mut(ptr, 64)?;
+            stat.fill(0);
+            stat[8..16].copy_from_slice(&(inode as u64).to_le_bytes());
+            stat[16] = filetype;
+            stat[24..32].copy_from_slice(&1u64.to_le_bytes());
+            stat[32..40].copy_from_slice(&vfs.len(inode).to_le_bytes());
+            return Ok(());
+        }
+
+        let (filetype, metadata) = match self.descriptor(fd)? {
+            Descriptor::Stdin | Descriptor::Stdout | Descriptor::Stderr => {
+                (FILETYPE_CHARACTER_DEVICE, None)
+            }
+            Descriptor::VfsDir { .. } | Descriptor::VfsFile { .. } | Descriptor::Closed => {
+                return Err(ERRNO_BADF)
+            }
+            Descriptor::Dir { host, .. } => {
+                (FILETYPE_DIRECTORY, Some(fs::metadata(host).map_err(errno)?))
+            }
+            Descriptor::File(file) => {
+                (FILETYPE_REGULAR_FILE, Some(file.metadata().map_err(errno)?))
+            }
+        };
+
+        let nanos = |time: io::Result<SystemTime>| {
+            time.ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_nanos() as u64)
+        };
+        let stat = mem.slice_mut(ptr, 64)?;
+        stat.fill(0);
+        stat[16] = filetype;
+        stat[24..32].copy_from_slice(&1u64.to_le_bytes());
+        if let Some(metadata) = metadata {
+            stat[32..40].copy_from_slice(&metadata.len().to_le_bytes());
+            stat[40..48].copy_from_slice(&nanos(metadata.accessed()).to_le_bytes());
+            stat[48..56].copy_from_slice(&nanos(metadata.modified()).to_le_bytes());
+            stat[56..64].copy_from_slice(&nanos(metadata.modified()).to_le_bytes());
+        }
+        Ok(())
+    }
+
+    fn preopen_name(&mut self, fd: u32) -> std::result::Result<&str, Errno> {
+        match self.descriptor(fd)? {
+            Descriptor::Dir {
+                preopen: Some(name),
+                ..
+            }
+            | Descriptor::VfsDir {
+                preopen: Some(name),
+                ..
+            } => Ok(name),
+            _ => Err(ERRNO_BADF),
+        }
+    }
+
+    fn fd_prestat_get(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let name = self.preopen_name(fd)?;
+
+        let len = name.len() as u32;
+        mem.write_u32(ptr, 0)?;
+        mem.write_u32(ptr + 4, len)
+    }
+
+    fn fd_prestat_dir_name(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        ptr: u32,
+        len: u32,
+    ) -> std::result::Result<(), Errno> {
+        let name = self.preopen_name(fd)?;
+
+        if (len as usize) < name.len() {
+            return Err(ERRNO_NAMETOOLONG);
+        }
+        mem.slice_mut(ptr, name.len() as u32)?
+            .copy_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    fn fd_read(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        iovs: u32,
+        iovs_len: u32,
+        nread_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let reader: &mut dyn Read = match self.descriptor(fd)? {
+            Descriptor::Stdin => &mut self.stdin,
+            Descriptor::File(file) => file,
+            Descriptor::VfsFile { .. } => return self.vfs_read(mem, fd, iovs, iovs_len, nread_ptr),
+            Descriptor::Dir { .. } | Descriptor::VfsDir { .. } => return Err(ERRNO_ISDIR),
+            Descriptor::Stdout | Descriptor::Stderr | Descriptor::Closed => return Err(ERRNO_BADF),
+        };
+
+        let mut nread = 0u32;
+        for i in 0..iovs_len {
+            let (buf, len) = mem.iovec(iovs, i)?;
+            let n = reader.read(mem.slice_mut(buf, len)?).map_err(errno)?;
+            nread = nread.checked_add(n as u32).ok_or(ERRNO_OVERFLOW)?;
+            if n < len as usize {
+                break;
+            }
+        }
+        mem.write_u32(nread_ptr, nread)
+    }
+
+    fn fd_write(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        iovs: u32,
+        iovs_len: u32,
+        nwritten_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let writer: &mut dyn Write = match self.descriptor(fd)? {
+            Descriptor::Stdout => &mut self.stdout,
+            Descriptor::Stderr => &mut self.stderr,
+            Descriptor::File(file) => file,
+            Descriptor::VfsFile { .. } => {
+                return self.vfs_write(mem, fd, iovs, iovs_len, nwritten_ptr)
+            }
+            Descriptor::Dir { .. } | Descriptor::VfsDir { .. } => return Err(ERRNO_ISDIR),
+            Descriptor::Stdin | Descriptor::Closed => return Err(ERRNO_BADF),
+        };
+
+        let mut nwritten = 0u32;
+        for i in 0..iovs_len {
+            let (buf, len) = mem.iovec(iovs, i)?;
+            nwritten = nwritten.checked_add(len).ok_or(ERRNO_OVERFLOW)?;
+            writer.write_all(mem.slice(buf, len)?).map_err(errno)?;
+        }
+        writer.flush().map_err(errno)?;
+        mem.write_u32(nwritten_ptr, nwritten)
+    }
+
+    fn vfs_read(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        iovs: u32,
+        iovs_len: u32,
+        nread_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let Some(Descriptor::VfsFile {
+            inode,
+            offset,
+            rights,
+            ..
+        }) = &mut self.fds[fd as usize]
+        else {
+            return Err(ERRNO_BADF);
+        };
+        if *rights & RIGHTS_FD_READ == 0 {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        let vfs = self.vfs.as_ref().ok_or(ERRNO_BADF)?;
+
+        let mut nread = 0u32;
+        for i in 0..iovs_len {
+            let (buf, len) = mem.iovec(iovs, i)?;
+            let n = vfs.read_at(*inode, *offset, mem.slice_mut(buf, len)?);
+            *offset += n as u64;
+            nread = nread.checked_add(n as u32).ok_or(ERRNO_OVERFLOW)?;
+            if n < len as usize {
+                break;
+            }
+        }
+        mem.write_u32(nread_ptr, nread)
+    }
+
+    fn vfs_write(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        iovs: u32,
+        iovs_len: u32,
+        nwritten_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let Some(Descriptor::VfsFile {
+            inode,
+            offset,
+            rights,
+            append,
+        }) = &mut self.fds[fd as usize]
+        else {
+            return Err(ERRNO_BADF);
+        };
+        if *rights & RIGHTS_FD_WRITE == 0 {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        let vfs = self.vfs.as_mut().ok_or(ERRNO_BADF)?;
+        if *append {
+            *offset = vfs.len(*inode);
+        }
+
+        let mut nwritten = 0u32;
+        for i in 0..iovs_len {
+            let (buf, len) = mem.iovec(iovs, i)?;
+            nwritten = nwritten.checked_add(len).ok_or(ERRNO_OVERFLOW)?;
+            vfs.write_at(*inode, *offset, mem.slice(buf, len)?)?;
+            *offset += u64::from(len);
+        }
+        mem.write_u32(nwritten_ptr, nwritten)
+    }
+
+    fn fd_seek(
+        &mut self,
+        mem: &mut Memory<'_>,
+        fd: u32,
+        offset: i64,
+        whence: u32,
+        new_offset_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let len = match self.descriptor(fd)? {
+            &mut Descriptor::VfsFile { inode, .. } => self.vfs.as_ref().map(|vfs| vfs.len(inode)),
+            _ => None,
+        };
+        if let Some(Descriptor::VfsFile {
+            offset: current, ..
+        }) = &mut self.fds[fd as usize]
+        {
+            let base = match whence {
+                0 => 0,
+                1 => *current,
+                2 => len.unwrap_or(0),
+                _ => return Err(ERRNO_INVAL),
+            };
+            *current = base.checked_add_signed(offset).ok_or(ERRNO_INVAL)?;
+            return mem.write_u64(new_offset_ptr, *current);
+        }
+
+        let Descriptor::File(file) = self.descriptor(fd)? else {
+            return Err(ERRNO_SPIPE);
+        };
+
+        let pos = match whence {
+            0 => SeekFrom::Start(u64::try_from(offset).map_err(|_| ERRNO_INVAL)?),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(ERRNO_INVAL),
+        };
+        let new_offset = file.seek(pos).map_err(errno)?;
+        mem.write_u64(new_offset_ptr, new_offset)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn path_open(
+        &mut self,
+        mem: &mut Memory<'_>,
+        dir_fd: u32,
+        (path, path_len): (u32, u32),
+        oflags: u16,
+        rights: u64,
+        fdflags: u16,
+        fd_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let path = std::str::from_utf8(mem.slice(path, path_len)?).map_err(|_| ERRNO_INVAL)?;
+        let muted = self.muted;
+        let host = match self.descriptor(dir_fd)? {
+            Descriptor::Dir { .. } if muted => return Err(ERRNO_NOTCAPABLE),
+            Descriptor::Dir { host, .. } => resolve(host, path)?,
+            &mut Descriptor::VfsDir { inode: dir, .. } => {
+                let vfs = self.vfs.as_mut().ok_or(ERRNO_BADF)?;
+                let inode = vfs.open(dir, path, oflags)?;
+                let descriptor = match vfs.is_dir(inode) {
+                    true => Descriptor::VfsDir {
+                        inode,
+                        preopen: None,
+                    },
+                    false => Descriptor::VfsFile {
+                        inode,
+                        offset: 0,
+                        rights,
+                        append: fdflags & FDFLAGS_APPEND != 0,
+                    },
+                };
+                return self.allocate_fd(mem, descriptor, fd_ptr);
+            }
+            _ => return Err(ERRNO_NOTDIR),
+        };
+
+        let descriptor = if oflags & OFLAGS_DIRECTORY != 0 || host.is_dir() {
+            if !host.is_dir() {
+                return Err(if host.exists() {
+                    ERRNO_NOTDIR
+                } else {
+                    ERRNO_NOENT
+                });
+            }
+            Descriptor::Dir {
+                host,
+                preopen: None,
+            }
+        } else {
+            let write = rights & RIGHTS_FD_WRITE != 0;
+            let file = OpenOptions::new()
+                .read(rights & RIGHTS_FD_READ != 0 || !write)
+                .write(write && fdflags & FDFLAGS_APPEND == 0)
+                .append(write && fdflags & FDFLAGS_APPEND != 0)
+                .create(oflags & OFLAGS_CREAT != 0)
+                .create_new(oflags & OFLAGS_CREAT != 0 && oflags & OFLAGS_EXCL != 0)
+                .truncate(oflags & OFLAGS_TRUNC != 0)
+                .open(&host)
+                .map_err(errno)?;
+            Descriptor::File(file)
+        };
+        self.allocate_fd(mem, descriptor, fd_ptr)
+    }
+
+    /// Adds `descriptor` at the lowest free descriptor, written to `fd_ptr`.
+    /// Closed ones aren't free
+    fn allocate_fd(
+        &mut self,
+        mem: &mut Memory<'_>,
+        descriptor: Descriptor,
+        fd_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        let fd = match self.fds.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.fds[fd] = Some(descriptor);
+                fd
+            }
+            None => {
+                self.fds.push(Some(descriptor));
+                self.fds.len() - 1
+            }
+        };
+        mem.write_u32(fd_ptr, fd as u32)
+    }
+
+    /// Waits for the earliest clock subscription, or skips ahead to it on a
+    /// virtual clock. Subscriptions to file descriptors aren't supported and
+    /// complete at once with `ENOTSUP`
+    fn poll_oneoff(
+        &mut self,
+        mem: &mut Memory<'_>,
+        subscriptions: u32,
+        events: u32,
+        count: u32,
+        nevents_ptr: u32,
+    ) -> std::result::Result<(), Errno> {
+        if count == 0 {
+            return Err(ERRNO_INVAL);
+        }
+
+        // (userdata, tag, time left or an error)
+        let mut pending = vec![];
+        for i in 0..count {
+            let sub = mem.slice(subscriptions + i * 48, 48)?;
+            let userdata = u64::from_le_bytes(sub[0..8].try_into().unwrap());
+            let tag = sub[8];
+            if tag != EVENTTYPE_CLOCK {
+                pending.push((userdata, tag, Err(ERRNO_NOTSUP)));
+                continue;
+            }
+
+            let id = u32::from_le_bytes(sub[16..20].try_into().unwrap());
+            let timeout = u64::from_le_bytes(sub[24..32].try_into().unwrap());
+            let flags = u16::from_le_bytes(sub[40..42].try_into().unwrap());
+            let wait = match self.now(id) {
+                Ok(now) if flags & SUBCLOCKFLAGS_ABSTIME != 0 => Ok(timeout.saturating_sub(now)),
+                Ok(_) => Ok(timeout),
+                Err(errno) => Err(errno),
+            };
+            pending.push((userdata, tag, wait));
+        }
+
+        let ready_now = pending.iter().any(|(_, _, wait)| wait.is_err());
+        let wait = pending
+            .iter()
+            .filter_map(|(_, _, wait)| wait.ok())
+            .min()
+            .filter(|_| !ready_now);
+        match (wait, &mut self.seeded) {
+            (Some(wait), Some(seeded)) => seeded.now = seeded.now.saturating_add(wait),
+            (Some(wait), None) => thread::sleep(Duration::from_nanos(wait)),
+            (None, _) => {}
+        }
+
+        let mut nevents = 0;
+        for (userdata, tag, result) in pending {
+            let error = match result {
+                Ok(left) if wait.is_some_and(|wait| left <= wait) => 0,
+                Ok(_) => continue,
+                Err(errno) => errno,
+            };
+            let event = mem.slice_mut(events + nevents * 32, 32)?;
+            event.fill(0);
+            event[0..8].copy_from_slice(&userdata.to_le_bytes());
+            event[8..10].copy_from_slice(&error.to_le_bytes());
+            event[10] = tag;
+            nevents += 1;
+        }
+        mem.write_u32(nevents_ptr, nevents)
+    }
+}
+
+/// Guest linear memory, with out of bounds accesses failing with `EFAULT`
+struct Memory<'a> {
+    data: &'a mut [u8],
+    /// Ranges handed out for writing
+    written: Vec<Range<usize>>,
+}
+
+impl Memory<'_> {
+    fn slice(&self, ptr: u32, len: u32) -> std::result::Result<&[u8], Errno> {
+        let start = ptr as usize;
+        self.data
+            .get(start..start + len as usize)
+            .ok_or(ERRNO_FAULT)
+    }
+
+    fn slice_mut(&mut self, ptr: u32, len: u32) -> std::result::Result<&mut [u8], Errno> {
+        let range = ptr as usize..ptr as usize + len as usize;
+        let slice = self.data.get_mut(range.clone()).ok_or(ERRNO_FAULT)?;
+        self.written.push(range);
+        Ok(slice)
+    }
+
+    fn write_u32(&mut self, ptr: u32, value: u32) -> std::result::Result<(), Errno> {
+        self.slice_mut(ptr, 4)?
+            .copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn write_u64(&mut self, ptr: u32, value: u64) -> std::result::Result<(), Errno> {
+        self.slice_mut(ptr, 8)?
+            .copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    /// The (buf, len) of the `i`th iovec of the array at `iovs`
+    fn iovec(&self, iovs: u32, i: u32) -> std::result::Result<(u32, u32), Errno> {
+        let iovec = self.slice(iovs + i * 8, 8)?;
+        Ok((
+            u32::from_le_bytes(iovec[0..4].try_into().unwrap()),
+            u32::from_le_bytes(iovec[4..8].try_into().unwrap()),
+        ))
+    }
+}
+
+/// Writes NUL terminated `strings` to `buf` and pointers to them to `ptrs`
+fn write_strings(
+    mem: &mut Memory<'_>,
+    strings: impl Iterator<Item = Vec<u8>>,
+    mut ptrs: u32,
+    mut buf: u32,
+) -> std::result::Result<(), Errno> {
+    for string in strings {
+        mem.write_u32(ptrs, buf)?;
+        let len = string.len() as u32;
+        mem.slice_mut(buf, len)?.copy_from_slice(&string);
+        mem.slice_mut(buf + len, 1)?[0] = 0;
+        ptrs += 4;
+        buf += len + 1;
+    }
+    Ok(())
+}
+
+/// Writes the number of strings and their size with NUL terminators
+fn write_sizes(
+    mem: &mut Memory<'_>,
+    lens: impl Iterator<Item = usize>,
+    count_ptr: u32,
+    size_ptr: u32,
+) -> std::result::Result<(), Errno> {
+    let (count, size) = lens.fold((0, 0), |(count, size), len| (count + 1, size + len + 1));
+    mem.write_u32(count_ptr, count)?;
+    mem.write_u32(size_ptr, size as u32)
+}
+
+/// Resolves the guest `path` below the host directory `dir`, refusing paths
+/// that leave it
+fn resolve(dir: &Path, path: &str) -> std::result::Result<PathBuf, Errno> {
+    let mut resolved = dir.to_path_buf();
+    let mut depth = 0usize;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(name) => {
+                resolved.push(name);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => {
+                resolved.pop();
+                depth -= 1;
+            }
+            _ => return Err(ERRNO_NOTCAPABLE),
+        }
+    }
+    Ok(resolved)
+}
+
+fn errno(err: io::Error) -> Errno {
+    match err.kind() {
+        io::ErrorKind::NotFound => ERRNO_NOENT,
+        io::ErrorKind::PermissionDenied => ERRNO_ACCES,
+        io::ErrorKind::AlreadyExists => ERRNO_EXIST,
+        io::ErrorKind::InvalidInput => ERRNO_INVAL,
+        _ => ERRNO_IO,
+    }
+}
+
+/// Fills `buf` from the host's entropy source, falling back to the standard
+/// library's per-process hash keys
+fn fill_random(buf: &mut [u8]) {
+    if File::open("/dev/urandom")
+        .and_then(|mut urandom| urandom.read_exact(buf))
+        .is_ok()
+    {
+        return;
+    }
+
+    let state = RandomState::new();
+    for (i, chunk) in buf.chunks_mut(8).enumerate() {
+        let mut hasher = state.build_hasher();
+        hasher.write_usize(i);
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes()[..chunk.len()]);
+    }
+}
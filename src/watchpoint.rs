@@ -0,0 +1,64 @@
+use crate::backtrace::FrameInfo;
+use crate::store::Instance;
+
+/// The memory accesses that trigger a watchpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    pub(crate) const fn matches(self, write: bool) -> bool {
+        match self {
+            Self::Read => !write,
+            Self::Write => write,
+            Self::ReadWrite => true,
+        }
+    }
+}
+
+/// A memory access that hit a watchpoint, reported once the op that made it
+/// has run
+#[derive(Debug, Clone)]
+pub struct WatchHit {
+    /// The frame of the op that made the access, at that op
+    pub frame: FrameInfo,
+    pub instance: Instance,
+    pub memory_idx: u32,
+    pub write: bool,
+    /// Start of the watched bytes the op accessed
+    pub address: u64,
+    /// The watched bytes before and after the op, the same for reads
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub(crate) instance: usize,
+    pub(crate) memory_idx: u32,
+    /// The memory's address in the store, shared by every instance that
+    /// imports it
+    pub(crate) mem_addr: usize,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) kind: WatchKind,
+}
+
+impl Watchpoint {
+    /// The part of `start..end` of memory `mem_addr` that this watchpoint
+    /// covers, if a `write` there triggers it
+    pub(crate) fn overlap(
+        &self,
+        mem_addr: usize,
+        start: u64,
+        end: u64,
+        write: bool,
+    ) -> Option<(u64, u64)> {
+        let (start, end) = (start.max(self.start), end.min(self.end));
+        (self.mem_addr == mem_addr && self.kind.matches(write) && start < end)
+            .then_some((start, end))
+    }
+}
@@ -0,0 +1,128 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{Error, FunctionType, Linker, Module, ResultType, Store, Trap};
+
+use common::module;
+
+#[test]
+fn trap_carries_call_stack() {
+    let bytes = wat::parse_str(
+        r#"(module
+            (import "env" "unused" (func))
+            (memory 1)
+            (func $load (param i32) (result i32)
+                (i32.load (local.get 0)))
+            (func $middle (result i32)
+                (call $load (i32.const 65536)))
+            (func (export "outer") (result i32)
+                (i32.add (i32.const 1) (call $middle))))"#,
+    )
+    .unwrap();
+    let module = Module::new(&bytes).unwrap();
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+    linker.func(
+        &mut store,
+        "env",
+        "unused",
+        FunctionType(ResultType(vec![]), ResultType(vec![])),
+    );
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    let err = store.invoke(instance, "outer", vec![]).unwrap_err();
+    let Error::Trap(trap, backtrace) = &err else {
+        panic!("expected trap, got {err}");
+    };
+    assert_eq!(*trap, Trap::OutOfBoundsMemoryAccess);
+
+    // function indices count the import
+    let funcs = backtrace
+        .frames
+        .iter()
+        .map(|frame| frame.func_idx)
+        .collect::<Vec<_>>();
+    assert_eq!(funcs, [Some(1), Some(2), Some(3)]);
+    assert!(backtrace.frames.iter().all(|frame| frame.module_idx == 0));
+
+    // offsets point at the i32.load and the two calls
+    let opcodes = backtrace
+        .frames
+        .iter()
+        .map(|frame| bytes[frame.wasm_offset.unwrap() as usize])
+        .collect::<Vec<_>>();
+    assert_eq!(opcodes, [0x28, 0x10, 0x10]);
+
+    let message = err.to_string();
+    assert!(message.starts_with("trap: out of bounds memory access\nwasm backtrace:\n"));
+    assert!(message.contains("0: load (module 0, func 1) at 0x"));
+    assert!(message.contains("2: <func 3> (module 0) at 0x"));
+}
+
+#[test]
+fn store_is_usable_after_trap() {
+    let module = module(
+        r#"(module
+            (func (export "trap") (unreachable))
+            (func (export "ok") (result i32) (i32.const 1)))"#,
+    );
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    let err = store.invoke(instance, "trap", vec![]).unwrap_err();
+    assert!(matches!(&err, Error::Trap(Trap::Unreachable, bt) if bt.frames.len() == 1));
+
+    let results = store
+        .invoke(instance, "ok", vec![])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i32(), 1);
+}
+
+#[test]
+fn start_function_trap_has_backtrace() {
+    let module = module(
+        r#"(module
+            (func $start (unreachable))
+            (start $start))"#,
+    );
+    let mut store = Store::new();
+
+    let err = store.instantiate(&module, vec![]).unwrap_err();
+    let Error::Trap(Trap::Unreachable, backtrace) = err else {
+        panic!("expected trap, got {err}");
+    };
+    assert_eq!(backtrace.frames[0].func_idx, Some(0));
+}
+
+#[test]
+fn restored_frames_keep_offsets() {
+    let bytes = wat::parse_str(
+        r#"(module
+            (func $count (export "count") (param i32)
+                (loop $l
+                    (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                    (br_if $l (local.get 0)))
+                (unreachable)))"#,
+    )
+    .unwrap();
+    let module = Module::new(&bytes).unwrap();
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    store.set_fuel(20);
+    let state = store
+        .invoke(instance, "count", vec![gabagool::RawValue::from(100i32)])
+        .unwrap();
+    assert!(matches!(state, gabagool::ExecutionState::FuelExhausted));
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    restored.set_fuel(u64::MAX);
+    let Err(Error::Trap(Trap::Unreachable, backtrace)) = restored.resume() else {
+        panic!("expected trap");
+    };
+    let offset = backtrace.frames[0].wasm_offset.unwrap();
+    assert_eq!(bytes[offset as usize], 0x00);
+}
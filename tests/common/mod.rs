@@ -0,0 +1,32 @@
+// each test binary uses only some of these
+#![allow(dead_code)]
+
+use gabagool::{FunctionType, Instance, Linker, Module, ResultType, Store, ValueType};
+
+pub fn module(wat: &str) -> Module {
+    Module::new(&wat::parse_str(wat).unwrap()).unwrap()
+}
+
+pub fn func_type(params: &[ValueType], results: &[ValueType]) -> FunctionType {
+    FunctionType(ResultType(params.to_vec()), ResultType(results.to_vec()))
+}
+
+/// Instantiates `module` in `store`, with `host` functions defined under
+/// `env` for it to import
+pub fn instantiate(
+    mut store: Store,
+    module: &Module,
+    host: &[(&str, FunctionType)],
+) -> (Store, Instance) {
+    let mut linker = Linker::new();
+    for (name, function_type) in host {
+        linker.func(&mut store, "env", name, function_type.clone());
+    }
+    let instance = linker.instantiate(&mut store, module).unwrap();
+    (store, instance)
+}
+
+/// Instantiates `wat` in a new store, see [`instantiate`]
+pub fn setup(wat: &str, host: &[(&str, FunctionType)]) -> (Store, Instance) {
+    instantiate(Store::new(), &module(wat), host)
+}
@@ -0,0 +1,174 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{Coverage, ExecutionState, FunctionCoverage, Instance, RawValue, Store};
+
+const WAT: &str = r#"(module
+    (func $classify (export "classify") (param $n i32) (result i32)
+        (if (result i32) (i32.lt_s (local.get $n) (i32.const 0))
+            (then (i32.const -1))
+            (else
+                (block $two
+                    (block $one
+                        (block $zero
+                            (br_table $zero $one $two (local.get $n)))
+                        (return (i32.const 0)))
+                    (return (i32.const 1)))
+                (i32.const 2))))
+    (func $countdown (export "countdown") (param $n i32)
+        (loop $l
+            (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+            (br_if $l (local.get $n))))
+    (func $unused (export "unused")))"#;
+
+fn setup() -> (Store, Instance) {
+    common::setup(WAT, &[])
+}
+
+fn call(store: &mut Store, instance: Instance, name: &str, args: &[i32]) {
+    let args = args.iter().map(|&arg| RawValue::from(arg)).collect();
+    store
+        .invoke(instance, name, args)
+        .unwrap()
+        .into_completed()
+        .unwrap();
+}
+
+fn function<'a>(coverage: &'a Coverage, name: &str) -> &'a FunctionCoverage {
+    coverage
+        .functions
+        .iter()
+        .find(|f| f.name.as_deref() == Some(name))
+        .unwrap()
+}
+
+#[test]
+fn blocks_and_calls() {
+    let (mut store, instance) = setup();
+    assert!(store.coverage().is_none());
+    store.start_coverage();
+
+    for n in [-3, 0, 1, 1, 7] {
+        call(&mut store, instance, "classify", &[n]);
+    }
+    call(&mut store, instance, "countdown", &[5]);
+
+    let coverage = store.stop_coverage().unwrap();
+    assert!(store.coverage().is_none());
+    assert_eq!(coverage.functions.len(), 3);
+
+    // every arm ran but the negative one is the only way through `then`
+    let classify = function(&coverage, "classify");
+    assert_eq!(classify.calls, 5);
+    assert_eq!(classify.blocks[0].pc, 0);
+    assert_eq!(classify.blocks[0].hits, 5);
+    let mut hits = classify
+        .blocks
+        .iter()
+        .map(|block| block.hits)
+        .collect::<Vec<_>>();
+    hits.sort_unstable();
+    assert!(hits.starts_with(&[1, 1, 1]), "{hits:?}");
+    assert!(hits.contains(&2) && hits.contains(&4));
+    assert!(classify
+        .blocks
+        .iter()
+        .all(|block| !block.wasm_offsets.is_empty() && block.locations.is_empty()));
+
+    // the loop body starts the function, so it's entered more than it's called
+    let countdown = function(&coverage, "countdown");
+    assert_eq!(countdown.calls, 1);
+    assert_eq!(countdown.blocks[0].hits, 5);
+
+    let unused = function(&coverage, "unused");
+    assert_eq!(unused.calls, 0);
+    assert!(unused.blocks.iter().all(|block| block.hits == 0));
+}
+
+#[test]
+fn counters_survive_snapshots() {
+    let (mut expected, instance) = setup();
+    expected.start_coverage();
+    call(&mut expected, instance, "classify", &[1]);
+    call(&mut expected, instance, "countdown", &[50]);
+
+    let (mut store, instance) = setup();
+    store.start_coverage();
+    call(&mut store, instance, "classify", &[1]);
+    store.set_fuel(40);
+    let state = store
+        .invoke(instance, "countdown", vec![RawValue::from(50i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    restored.set_fuel(u64::MAX);
+    restored.resume().unwrap().into_completed().unwrap();
+    assert_eq!(restored.coverage(), expected.coverage());
+
+    // stores without coverage stay that way
+    let (store, _) = setup();
+    assert!(Store::from_snapshot(&store.snapshot()).coverage().is_none());
+}
+
+#[test]
+fn merge_and_lcov() {
+    let (mut a, instance) = setup();
+    a.start_coverage();
+    call(&mut a, instance, "classify", &[-1]);
+
+    let (mut b, instance) = setup();
+    b.start_coverage();
+    call(&mut b, instance, "classify", &[2]);
+
+    let mut coverage = a.coverage().unwrap();
+    coverage.merge(&b.coverage().unwrap());
+    let classify = function(&coverage, "classify");
+    assert_eq!(classify.calls, 2);
+    assert_eq!(classify.blocks[0].hits, 2);
+
+    let mut lcov = vec![];
+    coverage.write_lcov(&mut lcov).unwrap();
+    let lcov = String::from_utf8(lcov).unwrap();
+    let lines = lcov.lines().collect::<Vec<_>>();
+
+    // without DWARF, lines are instruction offsets in the module
+    assert_eq!(lines[..2], ["TN:", "SF:module0.wasm"]);
+    assert!(lines.contains(&"FNDA:2,classify"));
+    assert!(lines.contains(&"FNDA:0,countdown"));
+    assert!(lines.contains(&"FNF:3"));
+    assert!(lines.contains(&"FNH:1"));
+    assert_eq!(lines.last(), Some(&"end_of_record"));
+
+    let found = lines.iter().filter(|line| line.starts_with("DA:")).count();
+    let hit = lines
+        .iter()
+        .filter(|line| line.starts_with("DA:") && !line.ends_with(",0"))
+        .count();
+    assert!(hit > 0 && hit < found);
+    assert!(lines.contains(&format!("LF:{found}").as_str()));
+    assert!(lines.contains(&format!("LH:{hit}").as_str()));
+}
+
+#[test]
+fn forks_merge_without_their_shared_prefix() {
+    let (mut store, instance) = setup();
+    store.start_coverage();
+    call(&mut store, instance, "classify", &[0]);
+    let snapshot = store.snapshot();
+    let base = store.coverage().unwrap();
+
+    let mut coverage = base.clone();
+    for n in [-1, 2] {
+        let mut fork = Store::from_snapshot(&snapshot);
+        call(&mut fork, instance, "classify", &[n]);
+        coverage.merge(&fork.coverage().unwrap().since(&base));
+    }
+
+    // one run before the fork and one in each
+    let classify = function(&coverage, "classify");
+    assert_eq!(classify.calls, 3);
+    assert_eq!(classify.blocks[0].hits, 3);
+    assert_eq!(function(&coverage, "countdown").calls, 0);
+}
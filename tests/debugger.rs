@@ -0,0 +1,148 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{ExecutionState, Instance, Module, RawValue, StepKind, Store};
+
+const WAT: &str = r#"(module
+    (global $g (mut i32) (i32.const 0))
+    (memory 1)
+    (func $double (param i32) (result i32)
+        (i32.mul (local.get 0) (i32.const 2)))
+    (func (export "run") (param i32) (result i32)
+        (global.set $g (call $double (local.get 0)))
+        (i32.add (global.get $g) (i32.const 1)))
+    (func (export "load") (param i32) (result i32)
+        (i32.load (local.get 0))))"#;
+
+fn setup() -> (Vec<u8>, Store, Instance) {
+    let bytes = wat::parse_str(WAT).unwrap();
+    let (store, instance) = common::instantiate(Store::new(), &Module::new(&bytes).unwrap(), &[]);
+    (bytes, store, instance)
+}
+
+/// Pauses `name` before its first instruction
+fn pause_at_entry(store: &mut Store, instance: Instance, name: &str, arg: i32) {
+    store.set_fuel(0);
+    let state = store
+        .invoke(instance, name, vec![RawValue::from(arg)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+    store.set_fuel(u64::MAX);
+}
+
+fn completed(state: ExecutionState) -> i32 {
+    state.into_completed().unwrap()[0].as_i32()
+}
+
+#[test]
+fn step_into_and_out() {
+    let (_, mut store, instance) = setup();
+    pause_at_entry(&mut store, instance, "run", 5);
+
+    // local.get 0, then stop at the call
+    let state = store.step(StepKind::Into).unwrap();
+    assert!(matches!(state, ExecutionState::Breakpoint));
+    assert_eq!(store.frames()[0].func_idx, Some(1));
+    assert_eq!(store.operand_stack(0).unwrap().len(), 1);
+
+    store.step(StepKind::Into).unwrap();
+    let frames = store.frames();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].func_idx, Some(0));
+    assert_eq!(store.locals(0).unwrap()[0].as_i32(), 5);
+
+    // back in the caller, before global.set
+    store.step(StepKind::Out).unwrap();
+    assert_eq!(store.frames().len(), 1);
+    assert_eq!(store.operand_stack(0).unwrap()[0].as_i32(), 10);
+
+    store.set_operand(0, 0, RawValue::from(20i32)).unwrap();
+    assert_eq!(completed(store.resume().unwrap()), 21);
+    assert_eq!(store.global(instance, 0).unwrap().as_i32(), 20);
+}
+
+#[test]
+fn step_over_call() {
+    let (_, mut store, instance) = setup();
+    pause_at_entry(&mut store, instance, "run", 5);
+
+    store.step(StepKind::Into).unwrap();
+    store.step(StepKind::Over).unwrap();
+    assert_eq!(store.frames().len(), 1);
+    assert_eq!(store.operand_stack(0).unwrap()[0].as_i32(), 10);
+
+    // stepping out of the outermost frame finishes the invocation
+    assert_eq!(completed(store.step(StepKind::Out).unwrap()), 11);
+    assert!(!store.is_paused());
+}
+
+#[test]
+fn breakpoint_pauses_and_survives_snapshot() {
+    let (bytes, mut store, instance) = setup();
+
+    // step onto $double's i32.mul to find its offset
+    pause_at_entry(&mut store, instance, "run", 5);
+    for _ in 0..4 {
+        store.step(StepKind::Into).unwrap();
+    }
+    let offset = store.frames()[0].wasm_offset.unwrap();
+    assert_eq!(bytes[offset as usize], 0x6c);
+    store.resume().unwrap();
+
+    store.add_breakpoint(instance, 0, offset).unwrap();
+
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(5i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Breakpoint));
+    assert_eq!(store.frames()[0].wasm_offset, Some(offset));
+    let operands = store
+        .operand_stack(0)
+        .unwrap()
+        .iter()
+        .map(|v| v.as_i32())
+        .collect::<Vec<_>>();
+    assert_eq!(operands, [5, 2]);
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    assert_eq!(completed(restored.resume().unwrap()), 11);
+    assert_eq!(completed(store.resume().unwrap()), 11);
+
+    // the breakpoint stays set in both stores
+    for store in [&mut store, &mut restored] {
+        let state = store
+            .invoke(instance, "run", vec![RawValue::from(1i32)])
+            .unwrap();
+        assert!(matches!(state, ExecutionState::Breakpoint));
+        assert!(store.remove_breakpoint(instance, 0, offset).unwrap());
+        assert_eq!(completed(store.resume().unwrap()), 3);
+    }
+}
+
+#[test]
+fn locals_and_memory_are_writable() {
+    let (_, mut store, instance) = setup();
+    pause_at_entry(&mut store, instance, "load", 0);
+
+    store.set_local(0, 0, RawValue::from(8i32)).unwrap();
+    store.memory_mut(instance, 0).unwrap()[8..12].copy_from_slice(&42i32.to_le_bytes());
+    assert_eq!(
+        &store.memory(instance, 0).unwrap()[8..12],
+        &42i32.to_le_bytes()
+    );
+
+    assert_eq!(completed(store.resume().unwrap()), 42);
+}
+
+#[test]
+fn invalid_requests() {
+    let (_, mut store, instance) = setup();
+
+    assert!(store.add_breakpoint(instance, 0, 0).is_err());
+    assert!(store.add_breakpoint(instance, 9, 0).is_err());
+    assert!(store.step(StepKind::Into).is_err());
+    assert!(store.frames().is_empty());
+    assert!(store.locals(0).is_err());
+    assert!(store.global(instance, 1).is_err());
+}
@@ -0,0 +1,106 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{Error, Instance, Module, RawValue, Store};
+
+// results are returned as bits so NaN payloads can be compared
+const WAT: &str = r#"(module
+    (func (export "f32_div") (param f32 f32) (result i32)
+        (i32.reinterpret_f32 (f32.div (local.get 0) (local.get 1))))
+    (func (export "f32_add") (param f32 f32) (result i32)
+        (i32.reinterpret_f32 (f32.add (local.get 0) (local.get 1))))
+    (func (export "f32_neg") (param f32) (result i32)
+        (i32.reinterpret_f32 (f32.neg (local.get 0))))
+    (func (export "f64_sqrt") (param f64) (result i64)
+        (i64.reinterpret_f64 (f64.sqrt (local.get 0))))
+    (func (export "f64_promote") (param f32) (result i64)
+        (i64.reinterpret_f64 (f64.promote_f32 (local.get 0)))))"#;
+
+const RELAXED_WAT: &str = r#"(module
+    (func (export "swizzle") (param v128 v128) (result v128)
+        (i8x16.relaxed_swizzle (local.get 0) (local.get 1))))"#;
+
+/// A NaN with a payload
+const PAYLOAD_NAN: u32 = 0x7FA0_0001;
+
+fn setup(store: Store) -> (Store, Instance) {
+    common::instantiate(store, &common::module(WAT), &[])
+}
+
+fn call(store: &mut Store, instance: Instance, name: &str, args: &[RawValue]) -> RawValue {
+    store
+        .invoke(instance, name, args.to_vec())
+        .unwrap()
+        .into_completed()
+        .unwrap()[0]
+}
+
+#[test]
+fn canonical_nans() {
+    let nan = RawValue::from(f32::from_bits(PAYLOAD_NAN));
+    let one = RawValue::from(1.0f32);
+    let zero = RawValue::from(0.0f32);
+
+    let (mut store, instance) = setup(Store::deterministic(7));
+    let mut f32_bits =
+        |name: &str, args: &[RawValue]| call(&mut store, instance, name, args).as_i32() as u32;
+    assert_eq!(f32_bits("f32_div", &[zero, zero]), 0x7FC0_0000);
+    assert_eq!(f32_bits("f32_add", &[nan, one]), 0x7FC0_0000);
+    assert_eq!(f32_bits("f32_add", &[one, one]), 2.0f32.to_bits());
+    // sign and payload are untouched by bitwise ops
+    assert_eq!(f32_bits("f32_neg", &[nan]), PAYLOAD_NAN | 0x8000_0000);
+
+    let f64_bits = |store: &mut Store, name: &str, arg: RawValue| {
+        call(store, instance, name, &[arg]).as_i64() as u64
+    };
+    assert_eq!(
+        f64_bits(&mut store, "f64_sqrt", RawValue::from(-1.0f64)),
+        0x7FF8_0000_0000_0000
+    );
+    assert_eq!(
+        f64_bits(&mut store, "f64_promote", nan),
+        0x7FF8_0000_0000_0000
+    );
+    assert_eq!(
+        f64_bits(&mut store, "f64_sqrt", RawValue::from(4.0f64)),
+        2.0f64.to_bits()
+    );
+
+    // other stores keep whatever the host produces, here a quieted payload
+    let (mut store, instance) = setup(Store::new());
+    let bits = call(&mut store, instance, "f32_add", &[nan, one]).as_i32() as u32;
+    assert_eq!(bits, PAYLOAD_NAN | 0x0040_0000);
+}
+
+#[test]
+fn survives_snapshots() {
+    let (store, _) = setup(Store::deterministic(7));
+    assert!(store.is_deterministic());
+    assert!(Store::from_snapshot(&store.snapshot()).is_deterministic());
+
+    let (store, _) = setup(Store::new());
+    assert!(!Store::from_snapshot(&store.snapshot()).is_deterministic());
+}
+
+#[test]
+fn relaxed_simd_refused() {
+    let bytes = wat::parse_str(RELAXED_WAT).unwrap();
+    let module = Module::new(&bytes).unwrap();
+
+    let err = Store::deterministic(7)
+        .instantiate(&module, vec![])
+        .unwrap_err();
+    assert!(matches!(err, Error::Instantiation(_)), "{err}");
+    assert!(err.to_string().contains("relaxed SIMD"));
+
+    // nor can a store become deterministic once it runs such a module
+    let mut store = Store::new();
+    store.instantiate(&module, vec![]).unwrap();
+    assert!(store.set_deterministic(7).is_err());
+    assert!(!store.is_deterministic());
+
+    let (mut store, _) = setup(Store::new());
+    store.set_deterministic(7).unwrap();
+    assert!(store.instantiate(&module, vec![]).is_err());
+}
@@ -0,0 +1,336 @@
+#![cfg(all(not(feature = "spec-tests"), feature = "dwarf"))]
+
+use gabagool::{Module, SourceLocation, Store};
+
+const WAT: &str = r#"(module
+    (func (export "f") (result i32) (i32.add (i32.const 1) (i32.const 2))))"#;
+
+fn uleb(mut value: usize, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn with_customs(customs: &[(&str, Vec<u8>)]) -> (Vec<u8>, u32) {
+    let mut bytes = wat::parse_str(WAT).unwrap();
+
+    // find where the code section's contents start
+    let mut cursor = 8;
+    let code_offset = loop {
+        let id = bytes[cursor];
+        let (size, seen) = gabagool::leb128::read_u32(&bytes[cursor + 1..]).unwrap();
+        cursor += 1 + seen;
+        if id == 10 {
+            break cursor as u32;
+        }
+        cursor += size as usize;
+    };
+
+    for (name, contents) in customs {
+        let mut payload = Vec::new();
+        uleb(name.len(), &mut payload);
+        payload.extend_from_slice(name.as_bytes());
+        payload.extend_from_slice(contents);
+
+        bytes.push(0);
+        uleb(payload.len(), &mut bytes);
+        bytes.extend(payload);
+    }
+
+    (bytes, code_offset)
+}
+
+/// Wraps a header (everything after `header_length`) and a program into a
+/// 32-bit DWARF line number program unit
+fn line_unit(version: u16, prefix: &[u8], header: &[u8], program: &[u8]) -> Vec<u8> {
+    let mut unit = Vec::new();
+    unit.extend_from_slice(&version.to_le_bytes());
+    unit.extend_from_slice(prefix);
+    unit.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    unit.extend_from_slice(header);
+    unit.extend_from_slice(program);
+
+    let mut out = (unit.len() as u32).to_le_bytes().to_vec();
+    out.extend(unit);
+    out
+}
+
+// min_inst_length, max_ops_per_inst, default_is_stmt, line_base -5,
+// line_range 14, opcode_base 13 and the standard opcode lengths
+const HEADER_FIELDS: [u8; 18] = [1, 1, 1, 0xfb, 14, 13, 0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+fn location(file: &str, line: u32, column: u32) -> Option<SourceLocation> {
+    Some(SourceLocation {
+        file: file.to_owned(),
+        line,
+        column,
+    })
+}
+
+#[test]
+fn dwarf4_line_table() {
+    let mut header = HEADER_FIELDS.to_vec();
+    header.extend_from_slice(b"src\0\0");
+    header.extend_from_slice(b"main.c\0\x01\0\0\0");
+
+    let program = [
+        0, 5, 2, 0x10, 0, 0, 0, // DW_LNE_set_address 0x10
+        3, 9,  // DW_LNS_advance_line 9
+        1,  // DW_LNS_copy
+        75, // special: address += 4, line += 1
+        5, 7, // DW_LNS_set_column 7
+        2, 4, // DW_LNS_advance_pc 4
+        1, // DW_LNS_copy
+        2, 2, // DW_LNS_advance_pc 2
+        0, 1, 1, // DW_LNE_end_sequence
+    ];
+
+    let (bytes, code) = with_customs(&[(".debug_line", line_unit(4, &[], &header, &program))]);
+    let module = Module::new(&bytes).unwrap();
+
+    assert_eq!(module.source_location(code + 0x0f), None);
+    assert_eq!(
+        module.source_location(code + 0x10),
+        location("src/main.c", 10, 0)
+    );
+    assert_eq!(
+        module.source_location(code + 0x13),
+        location("src/main.c", 10, 0)
+    );
+    assert_eq!(
+        module.source_location(code + 0x14),
+        location("src/main.c", 11, 0)
+    );
+    assert_eq!(
+        module.source_location(code + 0x18),
+        location("src/main.c", 11, 7)
+    );
+    assert_eq!(module.source_location(code + 0x1a), None);
+    assert_eq!(
+        location("src/main.c", 11, 7).unwrap().to_string(),
+        "src/main.c:11:7"
+    );
+}
+
+#[test]
+fn lcov_source_lines() {
+    let mut header = HEADER_FIELDS.to_vec();
+    header.extend_from_slice(b"src\0\0");
+    header.extend_from_slice(b"main.c\0\x01\0\0\0");
+
+    let program = [
+        0, 5, 2, 0, 0, 0, 0, // DW_LNE_set_address 0
+        3, 4, // DW_LNS_advance_line 4
+        1, // DW_LNS_copy
+        2, 0x40, // DW_LNS_advance_pc 0x40
+        0, 1, 1, // DW_LNE_end_sequence
+    ];
+
+    let (bytes, _) = with_customs(&[(".debug_line", line_unit(4, &[], &header, &program))]);
+    let module = Module::new(&bytes).unwrap();
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+    store.start_coverage();
+    store.invoke(instance, "f", vec![]).unwrap();
+
+    let coverage = store.stop_coverage().unwrap();
+    assert_eq!(
+        coverage.functions[0].blocks[0].locations,
+        [location("src/main.c", 5, 0).unwrap()]
+    );
+
+    let mut lcov = vec![];
+    coverage.write_lcov(&mut lcov).unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "TN:\nSF:src/main.c\nFN:5,wasm-function[0]\nFNDA:1,wasm-function[0]\nFNF:1\nFNH:1\n\
+         DA:5,1\nLF:1\nLH:1\nend_of_record\n"
+    );
+}
+
+#[test]
+fn profile_source_lines() {
+    let mut header = HEADER_FIELDS.to_vec();
+    header.extend_from_slice(b"src\0\0");
+    header.extend_from_slice(b"main.c\0\x01\0\0\0");
+
+    let program = [
+        0, 5, 2, 0, 0, 0, 0, // DW_LNE_set_address 0
+        3, 4, // DW_LNS_advance_line 4
+        1, // DW_LNS_copy
+        2, 0x40, // DW_LNS_advance_pc 0x40
+        0, 1, 1, // DW_LNE_end_sequence
+    ];
+
+    let (bytes, _) = with_customs(&[(".debug_line", line_unit(4, &[], &header, &program))]);
+    let module = Module::new(&bytes).unwrap();
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+    store.start_profiling(1);
+    store.invoke(instance, "f", vec![]).unwrap();
+
+    let profile = store.stop_profiling().unwrap();
+    let functions = profile.functions();
+    assert_eq!(functions[0].hottest_line, location("src/main.c", 5, 0));
+
+    let mut folded = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(
+        folded.starts_with("module0.wasm!wasm-function[0]_(src/main.c:5) "),
+        "{folded}"
+    );
+
+    let mut table = vec![];
+    profile.write_table(&mut table).unwrap();
+    let table = String::from_utf8(table).unwrap();
+    assert!(
+        table.ends_with("  module0.wasm!wasm-function[0] (src/main.c:5)\n"),
+        "{table}"
+    );
+}
+
+#[test]
+fn dwarf5_line_table() {
+    let mut header = HEADER_FIELDS.to_vec();
+    // directories: DW_LNCT_path as DW_FORM_line_strp
+    header.extend_from_slice(&[1, 1, 0x1f, 1, 0, 0, 0, 0]);
+    // files: DW_LNCT_path as DW_FORM_string, DW_LNCT_directory_index as
+    // DW_FORM_data1
+    header.extend_from_slice(&[2, 1, 0x08, 2, 0x0b, 1]);
+    header.extend_from_slice(b"lib.c\0\0");
+
+    let program = [
+        0, 5, 2, 0x20, 0, 0, 0, // DW_LNE_set_address 0x20
+        3, 41, // DW_LNS_advance_line 41
+        4, 0, // DW_LNS_set_file 0
+        1, // DW_LNS_copy
+        2, 3, // DW_LNS_advance_pc 3
+        0, 1, 1, // DW_LNE_end_sequence
+    ];
+
+    let (bytes, code) = with_customs(&[
+        (".debug_line", line_unit(5, &[4, 0], &header, &program)),
+        (".debug_line_str", b"/work\0".to_vec()),
+    ]);
+    let module = Module::new(&bytes).unwrap();
+
+    assert_eq!(
+        module.source_location(code + 0x21),
+        location("/work/lib.c", 42, 0)
+    );
+    assert_eq!(module.source_location(code + 0x23), None);
+}
+
+#[test]
+fn missing_or_malformed_debug_info() {
+    let (bytes, code) = with_customs(&[]);
+    assert_eq!(Module::new(&bytes).unwrap().source_location(code), None);
+
+    let (bytes, code) = with_customs(&[(".debug_line", vec![0xff, 0xff])]);
+    assert_eq!(Module::new(&bytes).unwrap().source_location(code), None);
+}
+
+/// Wraps a unit header (everything after `version`) and its root DIE into a
+/// 32-bit DWARF `.debug_info` unit
+fn info_unit(version: u16, header: &[u8], die: &[u8]) -> Vec<u8> {
+    let mut unit = version.to_le_bytes().to_vec();
+    unit.extend_from_slice(header);
+    unit.extend_from_slice(die);
+
+    let mut out = (unit.len() as u32).to_le_bytes().to_vec();
+    out.extend(unit);
+    out
+}
+
+#[test]
+fn comp_dir_from_debug_info() {
+    let mut header = HEADER_FIELDS.to_vec();
+    header.extend_from_slice(b"src\0\0");
+    header.extend_from_slice(b"main.c\0\x01\0\0util.c\0\0\0\0\0");
+
+    let program = [
+        0, 5, 2, 0x10, 0, 0, 0, // DW_LNE_set_address 0x10
+        1, // DW_LNS_copy
+        4, 2, // DW_LNS_set_file 2
+        2, 4, // DW_LNS_advance_pc 4
+        1, // DW_LNS_copy
+        2, 2, // DW_LNS_advance_pc 2
+        0, 1, 1, // DW_LNE_end_sequence
+    ];
+    let debug_line = line_unit(4, &[], &header, &program);
+
+    // DW_TAG_compile_unit with DW_AT_stmt_list as DW_FORM_sec_offset and
+    // DW_AT_comp_dir as DW_FORM_string
+    let debug_abbrev = vec![1, 0x11, 0, 0x10, 0x17, 0x1b, 0x08, 0, 0, 0];
+    let mut die = vec![1, 0, 0, 0, 0];
+    die.extend_from_slice(b"/work\0");
+    let debug_info = info_unit(4, &[0, 0, 0, 0, 4], &die);
+
+    let (bytes, code) = with_customs(&[
+        (".debug_line", debug_line.clone()),
+        (".debug_info", debug_info),
+        (".debug_abbrev", debug_abbrev),
+    ]);
+    let module = Module::new(&bytes).unwrap();
+    assert_eq!(
+        module.source_location(code + 0x10),
+        location("/work/src/main.c", 1, 0)
+    );
+    assert_eq!(
+        module.source_location(code + 0x14),
+        location("/work/util.c", 1, 0)
+    );
+
+    // a DWARF 5 unit naming its directory through DW_FORM_strx1, based at
+    // DW_AT_str_offsets_base
+    let debug_abbrev = vec![1, 0x11, 0, 0x72, 0x17, 0x10, 0x17, 0x1b, 0x25, 0, 0, 0];
+    let die = [1, 8, 0, 0, 0, 0, 0, 0, 0, 1];
+    let debug_info = info_unit(5, &[1, 4, 0, 0, 0, 0], &die);
+    let mut debug_str_offsets = vec![12, 0, 0, 0, 5, 0, 0, 0];
+    debug_str_offsets.extend_from_slice(&[0, 0, 0, 0, 2, 0, 0, 0]);
+
+    let (bytes, code) = with_customs(&[
+        (".debug_line", debug_line),
+        (".debug_info", debug_info),
+        (".debug_abbrev", debug_abbrev),
+        (".debug_str", b"c\0/build\0".to_vec()),
+        (".debug_str_offsets", debug_str_offsets),
+    ]);
+    let module = Module::new(&bytes).unwrap();
+    assert_eq!(
+        module.source_location(code + 0x10),
+        location("/build/src/main.c", 1, 0)
+    );
+}
+
+#[test]
+fn overflowing_line_programs() {
+    let mut header = HEADER_FIELDS.to_vec();
+    header.extend_from_slice(b"\0main.c\0\0\0\0\0");
+
+    let max_sleb = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0];
+    let max_uleb = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 1];
+
+    let mut lines = vec![3];
+    lines.extend(max_sleb);
+    lines.push(3);
+    lines.extend(max_sleb);
+
+    let mut addresses = vec![2];
+    addresses.extend(max_uleb);
+    addresses.extend([8, 8]); // DW_LNS_const_add_pc twice
+
+    for program in [lines, addresses] {
+        let mut program = program;
+        program.extend([1, 0, 1, 1]);
+        let (bytes, code) = with_customs(&[(".debug_line", line_unit(4, &[], &header, &program))]);
+        assert_eq!(Module::new(&bytes).unwrap().source_location(code), None);
+    }
+}
@@ -0,0 +1,172 @@
+#![cfg(not(feature = "spec-tests"))]
+
+use gabagool::{
+    Error, ExportDescription, ExternType, InstantiationError, LinkErrorKind, Linker, Module,
+    ParseError, ParseErrorKind, Store,
+};
+
+fn parse_err(bytes: &[u8]) -> ParseError {
+    match Module::new(bytes) {
+        Err(Error::Parse(err)) => err,
+        other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+    }
+}
+
+fn instantiate(wat: &str) -> Result<(), Error> {
+    let module = Module::new(&wat::parse_str(wat).unwrap()).unwrap();
+    Linker::new().instantiate(&mut Store::new(), &module)?;
+    Ok(())
+}
+
+/// Index of the last occurrence of `needle` in `bytes`
+fn find(bytes: &[u8], needle: &[u8]) -> usize {
+    bytes
+        .windows(needle.len())
+        .rposition(|w| w == needle)
+        .unwrap()
+}
+
+#[test]
+fn preamble_errors() {
+    let err = parse_err(b"\0wsm\x01\0\0\0");
+    assert_eq!(err, ParseError::new(0, ParseErrorKind::BadMagic));
+
+    let err = parse_err(b"\0asm\x02\0\0\0");
+    assert_eq!(err, ParseError::new(4, ParseErrorKind::UnsupportedVersion));
+
+    let err = parse_err(b"\0as");
+    assert_eq!(err, ParseError::new(3, ParseErrorKind::UnexpectedEof));
+}
+
+#[test]
+fn errors_point_at_the_failing_byte() {
+    let bytes = wat::parse_str(r#"(module (func (export "f") nop))"#).unwrap();
+
+    // an unknown opcode in place of the `nop`
+    let mut patched = bytes.clone();
+    let nop = find(&patched, &[0x01, 0x0B]);
+    patched[nop] = 0xFF;
+    let err = parse_err(&patched);
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::InvalidOpcode {
+            prefix: None,
+            opcode: 0xFF
+        }
+    );
+    assert_eq!((err.offset, err.section), (nop, Some(10)));
+    assert!(err.to_string().contains("in section 10"), "{err}");
+
+    // an export name that isn't UTF-8
+    let mut patched = bytes.clone();
+    let name = find(&patched, b"f");
+    patched[name] = 0xFF;
+    let err = parse_err(&patched);
+    assert_eq!(err.kind, ParseErrorKind::MalformedUtf8);
+    assert_eq!((err.offset, err.section), (name, Some(7)));
+
+    // a section size that never terminates
+    let mut patched = bytes[..8].to_vec();
+    patched.extend([1, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80]);
+    let err = parse_err(&patched);
+    assert_eq!(err.kind, ParseErrorKind::InvalidLeb128);
+    assert_eq!((err.offset, err.section), (13, Some(1)));
+
+    // the module ends mid-section
+    let err = parse_err(&bytes[..bytes.len() - 1]);
+    assert_eq!(err.kind, ParseErrorKind::UnexpectedEof);
+    assert_eq!((err.offset, err.section), (bytes.len() - 1, Some(10)));
+}
+
+#[test]
+fn link_errors_name_the_import() {
+    let err = instantiate(r#"(module (import "env" "missing" (func)))"#).unwrap_err();
+    let Error::Link(err) = err else {
+        panic!("expected a link error, got {err}");
+    };
+    assert_eq!((err.module.as_str(), err.name.as_str()), ("env", "missing"));
+    assert!(matches!(err.kind, LinkErrorKind::UnknownImport));
+
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+    let exporter = wat::parse_str(r#"(module (global (export "g") i32 (i32.const 0)))"#).unwrap();
+    let exporter = linker
+        .instantiate(&mut store, &Module::new(&exporter).unwrap())
+        .unwrap();
+    linker.instance(&store, "env", exporter);
+
+    let importer = wat::parse_str(r#"(module (import "env" "g" (func)))"#).unwrap();
+    let Err(Error::Link(err)) = linker.instantiate(&mut store, &Module::new(&importer).unwrap())
+    else {
+        panic!("expected a link error");
+    };
+    assert_eq!(err.name, "g");
+    assert!(matches!(
+        err.kind,
+        LinkErrorKind::IncompatibleType {
+            expected: ExternType::Func(_),
+            actual: ExternType::Global(_),
+        }
+    ));
+}
+
+#[test]
+fn segments_out_of_bounds() {
+    let err = instantiate(
+        r#"(module
+            (memory 1)
+            (data (i32.const 0) "ok")
+            (data (i32.const 65535) "ab"))"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Instantiation(InstantiationError::DataSegmentOutOfBounds {
+            segment: 1,
+            offset: 65535,
+            len: 2,
+            memory_size: 65536,
+        })
+    ));
+
+    let err = instantiate(
+        r#"(module
+            (table 1 funcref)
+            (func $f)
+            (elem (i32.const -1) $f))"#,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Instantiation(InstantiationError::ElementSegmentOutOfBounds {
+            segment: 0,
+            offset: 0xFFFF_FFFF,
+            len: 1,
+            table_size: 1,
+        })
+    ));
+}
+
+#[test]
+fn exports_out_of_bounds() {
+    let mut bytes = wat::parse_str(r#"(module (func (export "f")))"#).unwrap();
+    let desc = find(&bytes, b"\x01f\x00\x00");
+    bytes[desc + 3] = 5;
+
+    let module = Module::new(&bytes).unwrap();
+    let err = Linker::new()
+        .instantiate(&mut Store::new(), &module)
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("export \"f\" refers to function 5"),
+        "{err}"
+    );
+    assert!(matches!(
+        err,
+        Error::Instantiation(InstantiationError::ExportIndexOutOfBounds {
+            name,
+            description: ExportDescription::Func(5),
+        }) if name == "f"
+    ));
+}
@@ -0,0 +1,151 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{Error, Execution, ExecutionState, Instance, RawValue, Replayer, Store, ValueType};
+
+use common::func_type;
+
+// each call asks the host for `n` values and sums them, keeping a running
+// total in memory so executions also share state
+const WAT: &str = r#"(module
+    (import "env" "next" (func $next (param i32) (result i32)))
+    (memory 1)
+    (func (export "sum") (param $n i32) (result i32) (local $acc i32)
+        (loop $l
+            (local.set $acc (i32.add (local.get $acc) (call $next (local.get $n))))
+            (i32.store (i32.const 0) (i32.add (i32.load (i32.const 0)) (i32.const 1)))
+            (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+            (br_if $l (local.get $n)))
+        (local.get $acc)))"#;
+
+fn setup() -> (Store, Instance) {
+    common::setup(
+        WAT,
+        &[("next", func_type(&[ValueType::I32], &[ValueType::I32]))],
+    )
+}
+
+fn spawn(store: &mut Store, instance: Instance, n: i32) -> Execution {
+    let (execution, state) = store
+        .spawn(instance, "sum", vec![RawValue::from(n)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    execution
+}
+
+fn answer(store: &mut Store, execution: Execution, value: i32) -> ExecutionState {
+    store
+        .resume_execution_with(execution, &[RawValue::from(value)])
+        .unwrap()
+}
+
+fn calls(store: &Store, instance: Instance) -> i32 {
+    let memory = store.memory(instance, 0).unwrap();
+    i32::from_le_bytes(memory[..4].try_into().unwrap())
+}
+
+#[test]
+fn interleaved_executions() {
+    let (mut store, instance) = setup();
+    let a = spawn(&mut store, instance, 2);
+    let b = spawn(&mut store, instance, 3);
+    assert_eq!(store.executions(), vec![a, b]);
+
+    // the store's own invocation runs while both are suspended
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    let own = store.resume_with(&[RawValue::from(1000i32)]).unwrap();
+
+    let ExecutionState::Suspended { args, .. } = answer(&mut store, b, 100) else {
+        panic!("b should ask again");
+    };
+    assert_eq!(args[0].as_i32(), 2);
+    assert!(matches!(
+        answer(&mut store, a, 1),
+        ExecutionState::Suspended { .. }
+    ));
+    assert!(matches!(
+        answer(&mut store, b, 200),
+        ExecutionState::Suspended { .. }
+    ));
+    let ExecutionState::Completed(a_results) = answer(&mut store, a, 2) else {
+        panic!("a should be done");
+    };
+    let ExecutionState::Completed(b_results) = answer(&mut store, b, 300) else {
+        panic!("b should be done");
+    };
+
+    assert_eq!(own.into_completed().unwrap()[0].as_i32(), 1000);
+    assert_eq!(a_results[0].as_i32(), 3);
+    assert_eq!(b_results[0].as_i32(), 600);
+    assert_eq!(calls(&store, instance), 6);
+    assert!(store.executions().is_empty());
+}
+
+#[test]
+fn finished_and_cancelled_handles_are_invalid() {
+    let (mut store, instance) = setup();
+    let a = spawn(&mut store, instance, 1);
+    let b = spawn(&mut store, instance, 1);
+    assert!(matches!(
+        answer(&mut store, a, 5),
+        ExecutionState::Completed(_)
+    ));
+    assert!(matches!(
+        store.resume_execution(a),
+        Err(Error::Instantiation(_))
+    ));
+
+    store.cancel(b).unwrap();
+    assert!(store.cancel(b).is_err());
+    assert!(store.executions().is_empty());
+}
+
+#[test]
+fn snapshots_keep_paused_executions() {
+    let (mut store, instance) = setup();
+    let a = spawn(&mut store, instance, 2);
+    let b = spawn(&mut store, instance, 2);
+    answer(&mut store, a, 10);
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    assert_eq!(restored.executions(), vec![a, b]);
+    for store in [&mut store, &mut restored] {
+        answer(store, b, 1);
+        let b_results = answer(store, b, 2).into_completed().unwrap();
+        let a_results = answer(store, a, 20).into_completed().unwrap();
+        assert_eq!(a_results[0].as_i32(), 30);
+        assert_eq!(b_results[0].as_i32(), 3);
+        assert_eq!(calls(store, instance), 4);
+
+        // handles aren't reused after a restore either
+        let c = spawn(store, instance, 1);
+        assert!(c != a && c != b);
+    }
+}
+
+#[test]
+fn host_log_replays_executions() {
+    let (mut store, instance) = setup();
+    let a = spawn(&mut store, instance, 2);
+    store.start_host_log();
+
+    let b = spawn(&mut store, instance, 1);
+    answer(&mut store, a, 7);
+    answer(&mut store, b, 11);
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    store.resume_with(&[RawValue::from(13i32)]).unwrap();
+    answer(&mut store, a, 17);
+
+    let log = store.stop_host_log().unwrap();
+    let mut replayer = Replayer::new(&log);
+    let state = replayer.run().unwrap();
+    assert_eq!(state.into_completed().unwrap()[0].as_i32(), 24);
+    assert_eq!(calls(replayer.store(), instance), 4);
+}
@@ -0,0 +1,141 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{ExecutionState, FuelCosts, FunctionType, Linker, RawValue, ResultType, Store};
+
+use common::module;
+
+fn fuel_used(store: &mut Store, instance: gabagool::Instance, func: &str) -> u64 {
+    store.set_fuel(1_000_000);
+    store
+        .invoke(
+            instance,
+            func,
+            vec![RawValue::from(1i32), RawValue::from(2i32)],
+        )
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    1_000_000 - store.fuel().unwrap()
+}
+
+#[test]
+fn fused_ops_cost_their_parts() {
+    // `local.get; local.get` and `i32.lt_s; br_if` are both fused
+    let module = module(
+        r#"(module
+            (func (export "f") (param i32 i32) (result i32)
+                (block (br_if 0 (i32.lt_s (local.get 0) (local.get 1))))
+                (i32.const 7)))"#,
+    );
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    // local.get, local.get, i32.lt_s, br_if, i32.const, return
+    assert_eq!(fuel_used(&mut store, instance, "f"), 6);
+}
+
+#[test]
+fn bulk_ops_cost_by_size() {
+    let module = module(
+        r#"(module
+            (memory 1)
+            (func (export "small") (param i32 i32)
+                (memory.fill (i32.const 0) (i32.const 1) (i32.const 1)))
+            (func (export "large") (param i32 i32)
+                (memory.fill (i32.const 0) (i32.const 1) (i32.const 65536))))"#,
+    );
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    let small = fuel_used(&mut store, instance, "small");
+    let large = fuel_used(&mut store, instance, "large");
+    assert_eq!(large - small, 65536 / 64 - 1);
+
+    store.set_fuel_costs(FuelCosts::uniform());
+    let small = fuel_used(&mut store, instance, "small");
+    let large = fuel_used(&mut store, instance, "large");
+    assert_eq!(large, small);
+}
+
+#[test]
+fn ops_only_run_when_paid_in_full() {
+    let module = module(
+        r#"(module
+            (memory 1)
+            (func (export "fill") (result i32)
+                (memory.fill (i32.const 0) (i32.const 1) (i32.const 65536))
+                (i32.load8_u (i32.const 65535))))"#,
+    );
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    store.set_fuel(100);
+    let state = store.invoke(instance, "fill", vec![]).unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+    assert!(store.memories[0].data.iter().all(|&b| b == 0));
+
+    // costs are part of the snapshot
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    assert_eq!(restored.fuel_costs(), &FuelCosts::default());
+
+    restored.set_fuel(2_000);
+    let results = restored.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 1);
+}
+
+#[test]
+fn host_calls_are_surcharged() {
+    let module = module(
+        r#"(module
+            (import "env" "host" (func $host))
+            (func (export "f") (param i32 i32) (result i32)
+                (call $host)
+                (i32.const 0)))"#,
+    );
+    let mut store = Store::new();
+    store.set_fuel_costs(FuelCosts {
+        host_call: 10,
+        ..FuelCosts::default()
+    });
+
+    let mut linker = Linker::new();
+    linker.func(
+        &mut store,
+        "env",
+        "host",
+        FunctionType(ResultType(vec![]), ResultType(vec![])),
+    );
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    store.set_fuel(1_000);
+    let state = store
+        .invoke(
+            instance,
+            "f",
+            vec![RawValue::from(0i32), RawValue::from(0i32)],
+        )
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+
+    // call + surcharge
+    assert_eq!(store.fuel(), Some(1_000 - 1 - 10));
+
+    store.resume_with(&[]).unwrap().into_completed().unwrap();
+    assert_eq!(store.fuel(), Some(1_000 - 1 - 10 - 2));
+
+    // the call waits until the tank covers the surcharge too
+    store.set_fuel(10);
+    let args = vec![RawValue::from(0i32), RawValue::from(0i32)];
+    let state = store.invoke(instance, "f", args).unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+    assert_eq!(store.fuel(), Some(10));
+
+    store.set_fuel(11);
+    assert!(matches!(
+        store.resume().unwrap(),
+        ExecutionState::Suspended { .. }
+    ));
+    assert_eq!(store.fuel(), Some(0));
+}
@@ -0,0 +1,230 @@
+#![cfg(not(feature = "spec-tests"))]
+
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use gabagool::{Error, GdbStub, Module, RawValue, Result, Store};
+
+const WAT: &str = r#"(module
+    (global $g (mut i32) (i32.const 0))
+    (memory 1)
+    (func $double (param i32) (result i32)
+        (i32.mul (local.get 0) (i32.const 2)))
+    (func (export "run") (param i32) (result i32)
+        (global.set $g (call $double (local.get 0)))
+        (i32.add (global.get $g) (i32.load (i32.const 0)))))"#;
+
+const OBJECT_SPACE: u64 = 1 << 62;
+
+struct Client {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    ack: bool,
+}
+
+impl Client {
+    fn request(&mut self, payload: &str) -> String {
+        let checksum = payload.bytes().fold(0u8, u8::wrapping_add);
+        write!(self.stream, "${payload}#{checksum:02x}").unwrap();
+
+        let mut bytes = self.reader.by_ref().bytes().map(|b| b.unwrap());
+        if self.ack {
+            assert_eq!(bytes.next(), Some(b'+'));
+        }
+        assert_eq!(bytes.next(), Some(b'$'));
+        let reply = bytes
+            .by_ref()
+            .take_while(|&b| b != b'#')
+            .collect::<Vec<_>>();
+        let sent = u8::from_str_radix(
+            &String::from_utf8(bytes.by_ref().take(2).collect()).unwrap(),
+            16,
+        )
+        .unwrap();
+        assert_eq!(sent, reply.iter().fold(0u8, |a, &b| a.wrapping_add(b)));
+
+        if self.ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(reply).unwrap()
+    }
+}
+
+/// Serves `run(arg)` on a loopback port and connects a client to it
+fn serve(arg: i32) -> (Client, JoinHandle<Result<Vec<RawValue>>>) {
+    let (mut client, server) = connect(arg, "test.wasm");
+    assert_eq!(client.request("QStartNoAckMode"), "OK");
+    client.ack = false;
+    (client, server)
+}
+
+/// Like [`serve`] with the module called `name`, leaving acks on
+fn connect(arg: i32, name: &'static str) -> (Client, JoinHandle<Result<Vec<RawValue>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let bytes = wat::parse_str(WAT).unwrap();
+        let module = Module::new(&bytes).unwrap();
+        let mut store = Store::new();
+        let instance = store.instantiate(&module, vec![]).unwrap();
+
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        GdbStub::new(&mut store, instance, &module, &bytes, name)
+            .run(stream, "run", vec![RawValue::from(arg)])
+            .unwrap()
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let client = Client {
+        reader: BufReader::new(stream.try_clone().unwrap()),
+        stream,
+        ack: true,
+    };
+    (client, server)
+}
+
+fn unhex(s: &str) -> Vec<u8> {
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn call_stack(client: &mut Client) -> Vec<u64> {
+    unhex(&client.request("qWasmCallStack"))
+        .chunks(8)
+        .map(|pc| u64::from_le_bytes(pc.try_into().unwrap()))
+        .collect()
+}
+
+fn value(reply: &str) -> i64 {
+    i64::from_le_bytes(unhex(reply).try_into().unwrap())
+}
+
+#[test]
+fn inspect_and_step() {
+    let (mut client, server) = serve(5);
+
+    assert!(client
+        .request("qSupported")
+        .contains("qXfer:libraries:read+"));
+    assert!(client
+        .request("qXfer:libraries:read::0,1000")
+        .starts_with("l<library-list><library name=\"test.wasm\">"));
+    assert!(client.request("?").starts_with("T05thread:1;"));
+
+    // paused on local.get 0, which the pc reads back from the module
+    let pcs = call_stack(&mut client);
+    assert_eq!(pcs.len(), 1);
+    assert_eq!(pcs[0] & OBJECT_SPACE, OBJECT_SPACE);
+    assert_eq!(client.request(&format!("m{:x},1", pcs[0])), "20");
+    assert_eq!(
+        u64::from_le_bytes(unhex(&client.request("p0")).try_into().unwrap()),
+        pcs[0]
+    );
+    assert_eq!(value(&client.request("qWasmLocal:0;0")), 5);
+
+    // into $double
+    assert!(client.request("s").starts_with("T05"));
+    assert!(client.request("s").starts_with("T05"));
+    assert_eq!(call_stack(&mut client).len(), 2);
+    assert_eq!(value(&client.request("qWasmLocal:0;0")), 5);
+    assert_eq!(value(&client.request("qWasmLocal:1;0")), 5);
+    assert_eq!(client.request("qWasmLocal:0;9"), "E01");
+
+    // linear memory through both the memory space and qWasmMem
+    assert_eq!(client.request("M0,4:07000000"), "OK");
+    assert_eq!(client.request("qWasmMem:0;0;4"), "07000000");
+    assert_eq!(client.request("m0,2"), "0700");
+    assert_eq!(value(&client.request("qWasmGlobal:0;0")), 0);
+
+    assert_eq!(client.request("vCont?"), "");
+    assert_eq!(client.request("c"), "W00");
+    assert_eq!(server.join().unwrap().unwrap()[0].as_i32(), 17);
+}
+
+#[test]
+fn breakpoints_and_detach() {
+    let bytes = wat::parse_str(WAT).unwrap();
+    let module = Module::new(&bytes).unwrap();
+    let (_, entry) = module.function_offsets(0).unwrap().iter().next().unwrap();
+    let addr = OBJECT_SPACE | u64::from(entry);
+
+    let (mut client, server) = serve(5);
+    assert_eq!(client.request(&format!("Z0,{addr:x},1")), "OK");
+    assert_eq!(client.request("Z0,1,1"), "E01");
+
+    assert!(client.request("c").starts_with("T05"));
+    assert_eq!(call_stack(&mut client)[0], addr);
+
+    // detaching clears the breakpoint and runs to completion
+    assert_eq!(client.request(&format!("z0,{addr:x},1")), "OK");
+    assert_eq!(client.request("D"), "OK");
+    assert_eq!(server.join().unwrap().unwrap()[0].as_i32(), 10);
+}
+
+#[test]
+fn kill() {
+    let (mut client, server) = serve(1);
+    client.stream.write_all(b"$k#6b").unwrap();
+    assert!(matches!(
+        server.join().unwrap(),
+        Err(Error::Instantiation(_))
+    ));
+}
+
+#[test]
+fn watchpoints() {
+    let (mut client, server) = serve(5);
+    assert_eq!(client.request("Z3,0,4"), "OK");
+    assert_eq!(client.request("Z3,100000000,4"), "E01");
+    assert_eq!(client.request("Z3,10,ffffffffffffffff"), "E01");
+
+    let reply = client.request("c");
+    assert!(reply.starts_with("T05"));
+    assert!(reply.ends_with(";rwatch:0;"));
+
+    assert_eq!(client.request("z3,0,4"), "OK");
+    assert_eq!(client.request("c"), "W00");
+    assert_eq!(server.join().unwrap().unwrap()[0].as_i32(), 10);
+}
+
+#[test]
+fn checksums_and_library_names() {
+    let (mut client, server) = connect(1, "<é>.wasm");
+
+    // a corrupted packet is refused and then answered when sent again
+    client.stream.write_all(b"$?#00").unwrap();
+    let mut nak = [0];
+    client.reader.read_exact(&mut nak).unwrap();
+    assert_eq!(&nak, b"-");
+    assert!(client.request("?").starts_with("T05"));
+
+    // the XML is read in chunks of bytes that don't line up with characters
+    let mut xml = String::new();
+    loop {
+        let reply = client.request(&format!("qXfer:libraries:read::{:x},5", xml.len()));
+        // `#` comes escaped as `}` followed by it xor 0x20
+        let mut chars = reply[1..].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '}' => xml.push((chars.next().unwrap() as u8 ^ 0x20) as char),
+                c => xml.push(c),
+            }
+        }
+        if reply.starts_with('l') {
+            break;
+        }
+    }
+    assert!(
+        xml.starts_with("<library-list><library name=\"&lt;&#xe9;&gt;.wasm\">"),
+        "{xml}"
+    );
+
+    assert_eq!(client.request("D"), "OK");
+    server.join().unwrap().unwrap();
+}
@@ -0,0 +1,103 @@
+#![cfg(not(feature = "spec-tests"))]
+
+use std::error::Error as _;
+use std::fmt;
+
+use gabagool::{
+    ExecutionState, FunctionType, Instance, Linker, Module, RawValue, ResultType, Store, Trap,
+    ValueType,
+};
+
+const WAT: &str = r#"(module
+    (import "env" "fetch" (func $fetch (param i32) (result i32)))
+    (func (export "run") (param i32) (result i32)
+        (call $fetch (local.get 0))))"#;
+
+#[derive(Debug, PartialEq)]
+struct Denied {
+    key: i32,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "access to key {} denied", self.key)
+    }
+}
+
+impl std::error::Error for Denied {}
+
+fn suspended() -> (Store, Instance) {
+    let module = Module::new(&wat::parse_str(WAT).unwrap()).unwrap();
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+    linker.func(
+        &mut store,
+        "env",
+        "fetch",
+        FunctionType(
+            ResultType(vec![ValueType::I32]),
+            ResultType(vec![ValueType::I32]),
+        ),
+    );
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(9i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    (store, instance)
+}
+
+#[test]
+fn host_error_comes_back_intact() {
+    let (mut store, _) = suspended();
+    let err = store
+        .resume_with_trap(Trap::host(Denied { key: 9 }))
+        .unwrap_err();
+
+    assert_eq!(err.downcast_ref::<Denied>(), Some(&Denied { key: 9 }));
+    assert!(err.downcast_ref::<fmt::Error>().is_none());
+    assert_eq!(err.source().unwrap().to_string(), "access to key 9 denied");
+    assert!(err
+        .to_string()
+        .starts_with("trap: host error: access to key 9 denied"));
+    assert_eq!(err.exit_code(), None);
+}
+
+#[test]
+fn exit_code_traps() {
+    let (mut store, _) = suspended();
+    let err = store.resume_with_trap(Trap::Exit(3)).unwrap_err();
+    assert_eq!(err.exit_code(), Some(3));
+    assert!(err.downcast_ref::<Denied>().is_none());
+}
+
+#[test]
+fn snapshots_record_trap_reason() {
+    let (mut store, instance) = suspended();
+    assert!(store.last_trap().is_none());
+    store
+        .resume_with_trap(Trap::host(Denied { key: 9 }))
+        .unwrap_err();
+    assert!(store
+        .last_trap()
+        .unwrap()
+        .downcast_ref::<Denied>()
+        .is_some());
+
+    // the restored reason keeps the message but not the type
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    let trap = restored.last_trap().unwrap();
+    assert_eq!(trap.to_string(), "host error: access to key 9 denied");
+    assert!(trap.downcast_ref::<Denied>().is_none());
+
+    // cleared by the next invocation
+    restored
+        .invoke(instance, "run", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert!(restored.last_trap().is_none());
+
+    let mut store = Store::from_snapshot(&restored.snapshot());
+    store.resume_with_trap(Trap::Exit(0)).unwrap_err();
+    let restored = Store::from_snapshot(&store.snapshot());
+    assert_eq!(restored.last_trap(), Some(&Trap::Exit(0)));
+}
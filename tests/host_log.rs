@@ -0,0 +1,178 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{
+    DivergenceKind, Error, ExecutionState, HostEvent, HostLog, Instance, RawValue, Replayer, Store,
+    ValueType,
+};
+
+use common::func_type;
+
+const WAT: &str = r#"(module
+    (import "env" "next" (func $next (param i32) (result i32)))
+    (func (export "sum") (param $n i32) (result i32) (local $acc i32)
+        (loop $l
+            (local.set $acc (i32.add (local.get $acc) (call $next (local.get $n))))
+            (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+            (br_if $l (local.get $n)))
+        (local.get $acc)))"#;
+
+fn setup() -> (Store, Instance) {
+    common::setup(
+        WAT,
+        &[("next", func_type(&[ValueType::I32], &[ValueType::I32]))],
+    )
+}
+
+/// Answers every `next(n)` with `n * 10` until the guest completes
+fn drive(store: &mut Store, mut state: ExecutionState) -> i32 {
+    loop {
+        state = match state {
+            ExecutionState::Completed(results) => return results[0].as_i32(),
+            ExecutionState::Suspended { args, .. } => store
+                .resume_with(&[RawValue::from(args[0].as_i32() * 10)])
+                .unwrap(),
+            ExecutionState::FuelExhausted => {
+                store.set_fuel(1000);
+                store.resume().unwrap()
+            }
+            state => panic!("unexpected {state:?}"),
+        }
+    }
+}
+
+fn completed(state: ExecutionState) -> i32 {
+    state.into_completed().unwrap()[0].as_i32()
+}
+
+#[test]
+fn replays_without_host() {
+    let (mut store, instance) = setup();
+    store.start_host_log();
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(3i32)])
+        .unwrap();
+    assert_eq!(drive(&mut store, state), 60);
+
+    let log = store.stop_host_log().unwrap();
+    assert_eq!(log.events.len(), 7);
+    assert!(matches!(
+        &log.events[1],
+        HostEvent::Call { func_name, args, .. } if func_name == "next" && args[0].as_i32() == 3
+    ));
+
+    let log = HostLog::from_bytes(&log.to_bytes());
+    let mut replayer = Replayer::new(&log);
+    assert_eq!(completed(replayer.run().unwrap()), 60);
+}
+
+#[test]
+fn starts_mid_invocation() {
+    let (mut store, instance) = setup();
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(5i32)])
+        .unwrap();
+    let ExecutionState::Suspended { .. } = state else {
+        panic!("expected a host call");
+    };
+
+    // logging starts with the guest waiting on the host, and stops before
+    // its last call is answered
+    store.start_host_log();
+    store.set_fuel(4);
+    let mut state = store.resume_with(&[RawValue::from(50i32)]).unwrap();
+    let mut calls = 0;
+    while calls < 3 {
+        state = match state {
+            ExecutionState::Suspended { args, .. } => {
+                calls += 1;
+                store
+                    .resume_with(&[RawValue::from(args[0].as_i32() * 10)])
+                    .unwrap()
+            }
+            ExecutionState::FuelExhausted => {
+                store.set_fuel(4);
+                store.resume().unwrap()
+            }
+            state => panic!("unexpected {state:?}"),
+        };
+    }
+    let log = store.stop_host_log().unwrap();
+    assert!(matches!(log.events[0], HostEvent::Return(_)));
+    assert_eq!(drive(&mut store, state), 150);
+
+    let mut replayer = Replayer::new(&log);
+    let state = replayer.run().unwrap();
+    let ExecutionState::Suspended { ref args, .. } = state else {
+        panic!("expected the call past the log, got {state:?}");
+    };
+    assert_eq!(args[0].as_i32(), 1);
+    assert_eq!(drive(replayer.store_mut(), state), 150);
+}
+
+#[test]
+fn divergence_is_an_error() {
+    let (mut store, instance) = setup();
+    store.start_host_log();
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(3i32)])
+        .unwrap();
+    drive(&mut store, state);
+    let mut log = store.stop_host_log().unwrap();
+
+    // the guest now finishes with host calls still logged
+    log.events[0] = HostEvent::Invoke {
+        func_addr: match log.events[0] {
+            HostEvent::Invoke { func_addr, .. } => func_addr,
+            _ => unreachable!(),
+        },
+        args: vec![RawValue::from(2i32)],
+    };
+    let mut replayer = Replayer::new(&log);
+    let Err(Error::Replay(err)) = replayer.run() else {
+        panic!("expected the replay to diverge");
+    };
+    assert_eq!(err.event, 1);
+    assert!(matches!(
+        &err.kind,
+        DivergenceKind::HostCall {
+            args,
+            actual: ExecutionState::Suspended { args: actual, .. },
+            ..
+        } if args[0].as_i32() == 3 && actual[0].as_i32() == 2
+    ));
+    assert!(
+        err.to_string().starts_with("replay diverged at event 1"),
+        "{err}"
+    );
+}
+
+#[test]
+fn replays_embedder_writes() {
+    let wat = r#"(module
+        (import "env" "next" (func $next (param i32) (result i32)))
+        (memory (export "memory") 1)
+        (global $bias (mut i32) (i32.const 0))
+        (func (export "run") (result i32)
+            (drop (call $next (i32.const 0)))
+            (i32.add (i32.load (i32.const 64)) (global.get $bias))))"#;
+    let host = [("next", func_type(&[ValueType::I32], &[ValueType::I32]))];
+    let (mut store, instance) = common::setup(wat, &host);
+
+    store.start_host_log();
+    store.invoke(instance, "run", vec![]).unwrap();
+    // the host answers through guest memory and state instead
+    store.memory_mut(instance, 0).unwrap()[64..68].copy_from_slice(&40i32.to_le_bytes());
+    store.set_global(instance, 0, RawValue::from(2i32)).unwrap();
+    let state = store.resume_with(&[RawValue::from(0i32)]).unwrap();
+    assert_eq!(completed(state), 42);
+
+    let log = HostLog::from_bytes(&store.stop_host_log().unwrap().to_bytes());
+    assert!(log.events.iter().any(|event| matches!(
+        event,
+        HostEvent::WriteMemory { offset: 64, bytes, .. } if bytes[..] == [40]
+    )));
+    let mut replayer = Replayer::new(&log);
+    assert_eq!(completed(replayer.run().unwrap()), 42);
+}
@@ -0,0 +1,114 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{
+    Error, ExecutionState, HostEvent, HostLog, Instance, RawValue, Replayer, Store, Trap, Value,
+    ValueType,
+};
+
+use common::func_type;
+
+const WAT: &str = r#"(module
+    (import "env" "pair" (func $pair (param i32) (result i32 i64)))
+    (import "env" "log" (func $log (param i32)))
+    (global $after (export "after") (mut i32) (i32.const 0))
+    (func (export "run") (param i32) (result i64)
+        (call $log (local.get 0))
+        (call $pair (local.get 0))
+        (i64.extend_i32_s)
+        (i64.add)
+        (global.set $after (i32.const 1))))"#;
+
+fn setup() -> (Store, Instance) {
+    let host = [
+        (
+            "pair",
+            func_type(&[ValueType::I32], &[ValueType::I32, ValueType::I64]),
+        ),
+        ("log", func_type(&[ValueType::I32], &[])),
+    ];
+    common::setup(WAT, &host)
+}
+
+/// Runs `run` up to its `pair` call
+fn suspend_on_pair(store: &mut Store, instance: Instance) {
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(5i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { ref func_name, .. } if func_name == "log"));
+    let state = store.resume().unwrap();
+    assert!(
+        matches!(state, ExecutionState::Suspended { ref func_name, .. } if func_name == "pair")
+    );
+}
+
+#[test]
+fn result_count_is_checked() {
+    let (mut store, instance) = setup();
+    suspend_on_pair(&mut store, instance);
+    assert_eq!(
+        store.host_result_types().unwrap(),
+        &[ValueType::I32, ValueType::I64]
+    );
+
+    assert!(matches!(
+        store.resume_with(&[RawValue::from(1i32)]),
+        Err(Error::Instantiation(_))
+    ));
+    assert!(matches!(store.resume(), Err(Error::Instantiation(_))));
+
+    // the call is still pending after a refused answer
+    let results = store
+        .resume_with(&[RawValue::from(1i32), RawValue::from(2i64)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i64(), 3);
+    assert!(store.host_result_types().is_err());
+}
+
+#[test]
+fn typed_results_are_checked() {
+    let (mut store, instance) = setup();
+    suspend_on_pair(&mut store, instance);
+
+    assert!(matches!(
+        store.resume_with_values(&[Value::I64(1), Value::I32(2)]),
+        Err(Error::Instantiation(_))
+    ));
+    let results = store
+        .resume_with_values(&[Value::I32(-1), Value::I64(10)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i64(), 9);
+}
+
+#[test]
+fn host_trap_unwinds_guest() {
+    let (mut store, instance) = setup();
+    suspend_on_pair(&mut store, instance);
+    store.start_host_log();
+
+    let err = store.resume_with_trap(Trap::Unreachable).unwrap_err();
+    let Error::Trap(Trap::Unreachable, backtrace) = err else {
+        panic!("expected the injected trap, got {err:?}");
+    };
+    assert_eq!(backtrace.frames.len(), 1);
+    assert_eq!(store.global(instance, 0).unwrap().as_i32(), 0);
+
+    // the store is ready for the next invocation
+    assert!(store.resume_with_trap(Trap::Unreachable).is_err());
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+
+    let log = store.stop_host_log().unwrap();
+    assert!(matches!(log.events[0], HostEvent::Trap(Trap::Unreachable)));
+    let state = Replayer::new(&HostLog::from_bytes(&log.to_bytes()))
+        .run()
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { ref func_name, .. } if func_name == "log"));
+}
@@ -0,0 +1,95 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use gabagool::{ExecutionState, Module, RawValue, Store};
+
+use common::module;
+
+fn countdown() -> Module {
+    module(
+        r#"(module
+            (func (export "countdown") (param i32) (result i32)
+                (local i32)
+                (loop $l
+                    (local.set 1 (i32.add (local.get 1) (i32.const 1)))
+                    (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))
+                (local.get 1)))"#,
+    )
+}
+
+#[test]
+fn interrupt_from_another_thread() {
+    let module = module(
+        r#"(module
+            (func (export "spin")
+                (loop $l (br $l))))"#,
+    );
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    let handle = store.interrupt_handle();
+    let interrupter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.interrupt();
+    });
+
+    let state = store.invoke(instance, "spin", vec![]).unwrap();
+    interrupter.join().unwrap();
+
+    assert!(matches!(state, ExecutionState::Interrupted));
+    assert!(store.is_paused());
+}
+
+#[test]
+fn interrupted_store_resumes() {
+    let mut store = Store::new();
+    let instance = store.instantiate(&countdown(), vec![]).unwrap();
+
+    // a request made before invoking is observed at the first back-edge
+    store.interrupt_handle().interrupt();
+    let state = store
+        .invoke(instance, "countdown", vec![RawValue::from(1000i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Interrupted));
+
+    let results = store.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 1000);
+}
+
+#[test]
+fn interrupted_store_snapshots() {
+    let mut store = Store::new();
+    let instance = store.instantiate(&countdown(), vec![]).unwrap();
+
+    store.interrupt_handle().interrupt();
+    let state = store
+        .invoke(instance, "countdown", vec![RawValue::from(1000i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Interrupted));
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    let results = restored.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 1000);
+}
+
+#[test]
+fn interrupt_at_function_entry() {
+    let module = module(
+        r#"(module
+            (func $inner (result i32) (i32.const 42))
+            (func (export "outer") (result i32) (call $inner)))"#,
+    );
+    let mut store = Store::new();
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    store.interrupt_handle().interrupt();
+    let state = store.invoke(instance, "outer", vec![]).unwrap();
+    assert!(matches!(state, ExecutionState::Interrupted));
+
+    let results = store.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 42);
+}
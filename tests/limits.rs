@@ -0,0 +1,177 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{Error, Module, RawValue, Store, StoreLimits, Trap, PAGE_SIZE};
+
+use common::module;
+
+fn grow_memory() -> Module {
+    module(
+        r#"(module
+            (memory 1)
+            (func (export "grow") (param i32) (result i32)
+                (memory.grow (local.get 0))))"#,
+    )
+}
+
+fn call_grow(store: &mut Store, instance: gabagool::Instance, pages: i32) -> Result<i32, Error> {
+    let results = store
+        .invoke(instance, "grow", vec![RawValue::from(pages)])?
+        .into_completed()?;
+    Ok(results[0].as_i32())
+}
+
+#[test]
+fn memory_grow_fails_past_limit() {
+    let limits = StoreLimits::default().memory_bytes(3 * PAGE_SIZE as u64);
+    let mut store = Store::with_limits(limits);
+    let instance = store.instantiate(&grow_memory(), vec![]).unwrap();
+
+    assert_eq!(call_grow(&mut store, instance, 2).unwrap(), 1);
+    assert_eq!(call_grow(&mut store, instance, 1).unwrap(), -1);
+    assert_eq!(call_grow(&mut store, instance, 0).unwrap(), 3);
+}
+
+#[test]
+fn memory_grow_traps_past_limit() {
+    let limits = StoreLimits::default()
+        .memory_bytes(2 * PAGE_SIZE as u64)
+        .trap_on_grow_failure(true);
+    let mut store = Store::with_limits(limits);
+    let instance = store.instantiate(&grow_memory(), vec![]).unwrap();
+
+    let err = call_grow(&mut store, instance, 2).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::ResourceLimitExceeded, _)));
+}
+
+#[test]
+fn table_grow_fails_past_limit() {
+    let module = module(
+        r#"(module
+            (table 4 funcref)
+            (func (export "grow") (param i32) (result i32)
+                (table.grow (ref.null func) (local.get 0))))"#,
+    );
+    let mut store = Store::with_limits(StoreLimits::default().table_elements(8));
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    assert_eq!(call_grow(&mut store, instance, 4).unwrap(), 4);
+    assert_eq!(call_grow(&mut store, instance, 1).unwrap(), -1);
+}
+
+#[test]
+fn instantiation_respects_limits() {
+    let mut store = Store::with_limits(StoreLimits::default().memory_bytes(PAGE_SIZE as u64));
+    let err = store
+        .instantiate(&module("(module (memory 2))"), vec![])
+        .unwrap_err();
+    assert!(matches!(err, Error::Instantiation(_)));
+
+    let mut store = Store::with_limits(StoreLimits::default().instances(1));
+    store.instantiate(&module("(module)"), vec![]).unwrap();
+    let err = store.instantiate(&module("(module)"), vec![]).unwrap_err();
+    assert!(matches!(err, Error::Instantiation(_)));
+}
+
+#[test]
+fn call_depth_limit() {
+    let module = module(
+        r#"(module
+            (func $recurse (export "recurse") (param i32) (result i32)
+                (if (result i32) (i32.eqz (local.get 0))
+                    (then (i32.const 0))
+                    (else (call $recurse (i32.sub (local.get 0) (i32.const 1)))))))"#,
+    );
+    let mut store = Store::with_limits(StoreLimits::default().call_depth(16));
+    let instance = store.instantiate(&module, vec![]).unwrap();
+
+    let ok = store.invoke(instance, "recurse", vec![RawValue::from(10i32)]);
+    assert!(ok.is_ok());
+
+    let err = store
+        .invoke(instance, "recurse", vec![RawValue::from(100i32)])
+        .unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::CallStackExhausted, _)));
+}
+
+#[test]
+fn limits_survive_snapshot() {
+    let limits = StoreLimits::default()
+        .memory_bytes(2 * PAGE_SIZE as u64)
+        .trap_on_grow_failure(true);
+    let mut store = Store::with_limits(limits);
+    let instance = store.instantiate(&grow_memory(), vec![]).unwrap();
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    assert_eq!(restored.limits(), &limits);
+
+    assert_eq!(call_grow(&mut restored, instance, 1).unwrap(), 1);
+    let err = call_grow(&mut restored, instance, 1).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::ResourceLimitExceeded, _)));
+}
+
+fn recursive_sum() -> Module {
+    module(
+        r#"(module
+            (func $sum (export "sum") (param i32) (result i32)
+                (if (result i32) (i32.eqz (local.get 0))
+                    (then (i32.const 0))
+                    (else
+                        (i32.add
+                            (local.get 0)
+                            (call $sum (i32.sub (local.get 0) (i32.const 1))))))))"#,
+    )
+}
+
+#[test]
+fn stack_grows_on_demand() {
+    let mut store = Store::with_limits(StoreLimits::default().call_depth(100_000));
+    let instance = store.instantiate(&recursive_sum(), vec![]).unwrap();
+
+    let results = store
+        .invoke(instance, "sum", vec![RawValue::from(50_000i32)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i32(), 1_250_025_000);
+}
+
+#[test]
+fn stack_limit_traps() {
+    let limits = StoreLimits::default()
+        .call_depth(100_000)
+        .stack_values(1_000);
+    let mut store = Store::with_limits(limits);
+    let instance = store.instantiate(&recursive_sum(), vec![]).unwrap();
+
+    let err = store
+        .invoke(instance, "sum", vec![RawValue::from(50_000i32)])
+        .unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::CallStackExhausted, _)));
+
+    // the store is still usable after the trap
+    let results = store
+        .invoke(instance, "sum", vec![RawValue::from(10i32)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i32(), 55);
+}
+
+#[test]
+fn restored_store_reserves_live_frames() {
+    let mut store = Store::with_limits(StoreLimits::default().call_depth(10_000));
+    let instance = store.instantiate(&recursive_sum(), vec![]).unwrap();
+
+    store.set_fuel(5_000);
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(5_000i32)])
+        .unwrap();
+    assert!(matches!(state, gabagool::ExecutionState::FuelExhausted));
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    restored.set_fuel(u64::MAX);
+    let results = restored.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 12_502_500);
+}
@@ -0,0 +1,164 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{
+    AddrType, Error, ExecutionState, ExternalValue, FunctionType, Limit, Linker, MemoryType,
+    RawValue, ResultType, Store, ValueType,
+};
+
+use common::module;
+
+fn i32_to_i32() -> FunctionType {
+    FunctionType(
+        ResultType(vec![ValueType::I32]),
+        ResultType(vec![ValueType::I32]),
+    )
+}
+
+#[test]
+fn resolves_imports_by_name() {
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+    linker
+        .memory(
+            &mut store,
+            "env",
+            "memory",
+            MemoryType {
+                addr_type: AddrType::I32,
+                limit: Limit { min: 1, max: 2 },
+            },
+        )
+        .unwrap()
+        .func(&mut store, "env", "double", i32_to_i32());
+
+    // imports are declared in a different order than they were defined
+    let module = module(
+        r#"(module
+            (import "env" "double" (func $double (param i32) (result i32)))
+            (import "env" "memory" (memory 1))
+            (func (export "run") (param i32) (result i32)
+                (i32.store (i32.const 0) (call $double (local.get 0)))
+                (i32.load (i32.const 0))))"#,
+    );
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(21i32)])
+        .unwrap();
+    let ExecutionState::Suspended {
+        module_name,
+        func_name,
+        args,
+    } = state
+    else {
+        panic!("expected suspension, got {state:?}");
+    };
+    assert_eq!(
+        (module_name.as_str(), func_name.as_str()),
+        ("env", "double")
+    );
+
+    let doubled = RawValue::from(args[0].as_i32() * 2);
+    let results = store
+        .resume_with(&[doubled])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i32(), 42);
+}
+
+#[test]
+fn links_against_registered_instance() {
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+
+    let provider = module(
+        r#"(module
+            (global (export "base") i32 (i32.const 40))
+            (func (export "add") (param i32 i32) (result i32)
+                (i32.add (local.get 0) (local.get 1))))"#,
+    );
+    let provider = linker.instantiate(&mut store, &provider).unwrap();
+    linker.instance(&store, "provider", provider);
+
+    let consumer = module(
+        r#"(module
+            (import "provider" "add" (func $add (param i32 i32) (result i32)))
+            (import "provider" "base" (global $base i32))
+            (func (export "run") (result i32)
+                (call $add (global.get $base) (i32.const 2))))"#,
+    );
+    let consumer = linker.instantiate(&mut store, &consumer).unwrap();
+
+    let results = store
+        .invoke(consumer, "run", vec![])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i32(), 42);
+}
+
+#[test]
+fn rejects_mismatched_imports() {
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+    linker
+        .func(&mut store, "env", "f", i32_to_i32())
+        .memory(
+            &mut store,
+            "env",
+            "memory",
+            MemoryType {
+                addr_type: AddrType::I32,
+                limit: Limit {
+                    min: 1,
+                    max: u64::MAX,
+                },
+            },
+        )
+        .unwrap();
+
+    let unlinkable = [
+        r#"(module (import "env" "f" (func (param i64) (result i32))))"#,
+        r#"(module (import "env" "f" (global i32)))"#,
+        r#"(module (import "env" "memory" (memory 2)))"#,
+        r#"(module (import "env" "memory" (memory 1 4)))"#,
+        r#"(module (import "env" "missing" (func)))"#,
+    ];
+
+    for wat in unlinkable {
+        let err = linker.instantiate(&mut store, &module(wat)).expect_err(wat);
+        assert!(matches!(err, Error::Link(_)), "{wat}: {err}");
+    }
+}
+
+#[test]
+fn rejects_dangling_addresses() {
+    let imports = [
+        (
+            r#"(module (import "env" "f" (func)))"#,
+            ExternalValue::Function { addr: 99 },
+        ),
+        (
+            r#"(module (import "env" "t" (table 1 funcref)))"#,
+            ExternalValue::Table { addr: 99 },
+        ),
+        (
+            r#"(module (import "env" "m" (memory 1)))"#,
+            ExternalValue::Memory { addr: 99 },
+        ),
+        (
+            r#"(module (import "env" "g" (global i32)))"#,
+            ExternalValue::Global { addr: 99 },
+        ),
+    ];
+
+    for (wat, value) in imports {
+        let err = Store::new()
+            .instantiate(&module(wat), vec![value])
+            .expect_err(wat);
+        assert!(matches!(err, Error::Link(_)), "{wat}: {err}");
+    }
+}
@@ -0,0 +1,74 @@
+#![cfg(not(feature = "spec-tests"))]
+
+use gabagool::{Error, FunctionType, Linker, Module, ResultType, Store, Trap, ValueType};
+
+const WAT: &str = r#"(module $demo
+    (import "env" "log" (func $log (param i32)))
+    (memory $mem 1)
+    (global $counter (mut i32) (i32.const 0))
+    (func $fault (param $addr i32) (result i32)
+        (i32.load (local.get $addr)))
+    (func $entry (export "entry") (result i32)
+        (call $fault (i32.const 65536))))"#;
+
+#[test]
+fn decodes_name_section() {
+    let module = Module::new(&wat::parse_str(WAT).unwrap()).unwrap();
+    let names = module.names();
+
+    assert_eq!(names.module.as_deref(), Some("demo"));
+    assert_eq!(module.function_name(0), Some("log"));
+    assert_eq!(module.function_name(1), Some("fault"));
+    assert_eq!(module.function_name(2), Some("entry"));
+    assert_eq!(module.function_name(3), None);
+    assert_eq!(names.local(1, 0), Some("addr"));
+    assert_eq!(names.memories.get(&0).map(String::as_str), Some("mem"));
+    assert_eq!(names.globals.get(&0).map(String::as_str), Some("counter"));
+}
+
+#[test]
+fn malformed_name_section_is_ignored() {
+    let mut wasm = wat::parse_str("(module (func))").unwrap();
+
+    // a custom `name` section whose function subsection claims more entries
+    // than it holds
+    let payload = [&[4][..], b"name", &[1, 3, 5, 0, 1]].concat();
+    wasm.push(0);
+    wasm.push(payload.len() as u8);
+    wasm.extend_from_slice(&payload);
+
+    let module = Module::new(&wasm).unwrap();
+    assert_eq!(module.function_name(0), None);
+}
+
+#[test]
+fn backtraces_use_names() {
+    let module = Module::new(&wat::parse_str(WAT).unwrap()).unwrap();
+    let mut store = Store::new();
+    let mut linker = Linker::new();
+    linker.func(
+        &mut store,
+        "env",
+        "log",
+        FunctionType(ResultType(vec![ValueType::I32]), ResultType(vec![])),
+    );
+    let instance = linker.instantiate(&mut store, &module).unwrap();
+
+    // names are part of the compiled code, so they survive a snapshot
+    let mut store = Store::from_snapshot(&store.snapshot());
+
+    let err = store.invoke(instance, "entry", vec![]).unwrap_err();
+    let Error::Trap(Trap::OutOfBoundsMemoryAccess, backtrace) = &err else {
+        panic!("expected trap, got {err}");
+    };
+
+    let names = backtrace
+        .frames
+        .iter()
+        .map(|frame| frame.name.as_deref())
+        .collect::<Vec<_>>();
+    assert_eq!(names, [Some("fault"), Some("entry")]);
+    assert!(err
+        .to_string()
+        .contains("0: fault (module 0, func 1) at 0x"));
+}
@@ -0,0 +1,141 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{ExecutionState, FunctionCost, Instance, Module, Store};
+
+// $count recurses through an unnamed function
+const WAT: &str = r#"(module
+    (func $spin (param $n i32)
+        (loop $l
+            (br_if $l (local.tee $n (i32.sub (local.get $n) (i32.const 1))))))
+    (func $count (param $n i32)
+        (if (local.get $n)
+            (then
+                (call $spin (i32.const 100))
+                (call 2 (i32.sub (local.get $n) (i32.const 1))))))
+    (func (param i32)
+        (call $count (local.get 0)))
+    (func $main (export "main")
+        (call $spin (i32.const 1000))
+        (call $count (i32.const 3))))"#;
+
+fn setup() -> (Store, Instance) {
+    common::setup(WAT, &[])
+}
+
+fn cost<'a>(functions: &'a [FunctionCost], name: &str) -> &'a FunctionCost {
+    let name = format!("module0.wasm!{name}");
+    functions.iter().find(|f| f.name == name).unwrap()
+}
+
+#[test]
+fn stacks_and_costs() {
+    let (mut store, instance) = setup();
+    assert!(store.profile().is_none());
+
+    store.start_profiling(1);
+    store
+        .invoke(instance, "main", vec![])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    let profile = store.stop_profiling().unwrap();
+    assert!(store.profile().is_none());
+
+    // with every op sampled, the counts are exact
+    let ops = profile.samples();
+    let functions = profile.functions();
+    assert_eq!(functions[0].name, "module0.wasm!spin");
+    assert!(functions.iter().all(|f| f.hottest_line.is_none()));
+    assert_eq!(cost(&functions, "main").total_ops, ops);
+    assert_eq!(
+        functions.iter().map(|f| f.self_ops).sum::<u64>(),
+        ops,
+        "every op is in exactly one function"
+    );
+
+    // recursion is counted once towards the total
+    let count = cost(&functions, "count");
+    let unnamed = cost(&functions, "wasm-function[2]");
+    assert!(count.total_ops > unnamed.total_ops);
+    assert!(count.total_ops < ops);
+
+    let spin = cost(&functions, "spin");
+    assert_eq!(spin.self_ops, spin.total_ops);
+    assert!(spin.self_ops > 1300);
+
+    let mut folded = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    assert!(folded.lines().any(|line| line.starts_with(
+        "module0.wasm!main;module0.wasm!count;module0.wasm!wasm-function[2];\
+             module0.wasm!count;module0.wasm!spin "
+    )));
+    let total: u64 = folded
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(total, ops);
+
+    let mut table = vec![];
+    profile.write_table(&mut table).unwrap();
+    let table = String::from_utf8(table).unwrap();
+    assert!(table
+        .lines()
+        .nth(1)
+        .unwrap()
+        .ends_with("  module0.wasm!spin"));
+}
+
+#[test]
+fn sampling_interval() {
+    let (mut store, instance) = setup();
+    store.start_profiling(1);
+    store.invoke(instance, "main", vec![]).unwrap();
+    let ops = store.profile().unwrap().samples();
+
+    store.start_profiling(7);
+    store.invoke(instance, "main", vec![]).unwrap();
+    let profile = store.profile().unwrap();
+    assert_eq!(profile.interval, 7);
+    assert_eq!(profile.samples(), ops / 7);
+
+    // samples carry on across pauses
+    store.start_profiling(7);
+    store.set_fuel(50);
+    let mut state = store.invoke(instance, "main", vec![]).unwrap();
+    while matches!(state, ExecutionState::FuelExhausted) {
+        store.set_fuel(50);
+        state = store.resume().unwrap();
+    }
+    state.into_completed().unwrap();
+    assert_eq!(store.profile().unwrap(), profile);
+
+    // profiles aren't part of snapshots
+    let restored = Store::from_snapshot(&store.snapshot());
+    assert!(restored.profile().is_none());
+}
+
+#[test]
+fn functions_are_told_apart_by_module() {
+    let bytes = wat::parse_str(WAT).unwrap();
+    let module = Module::new(&bytes).unwrap();
+    let mut store = Store::new();
+    let first = store.instantiate(&module, vec![]).unwrap();
+    let second = store.instantiate(&module, vec![]).unwrap();
+
+    store.start_profiling(1);
+    store.invoke(first, "main", vec![]).unwrap();
+    store.invoke(second, "main", vec![]).unwrap();
+    let functions = store.profile().unwrap().functions();
+
+    // same names, but each instance's functions are their own
+    let spins = functions
+        .iter()
+        .filter(|f| f.name.ends_with("!spin"))
+        .collect::<Vec<_>>();
+    assert_eq!(spins.len(), 2);
+    assert_ne!(spins[0].module_idx, spins[1].module_idx);
+    assert_eq!(spins[0].self_ops, spins[1].self_ops);
+}
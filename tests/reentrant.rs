@@ -0,0 +1,132 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{Error, ExecutionState, Instance, RawValue, Store, Trap, ValueType};
+
+use common::func_type;
+
+// `sum` asks the host to fill a buffer of `len` bytes, which the host
+// allocates by calling back into `malloc`
+const WAT: &str = r#"(module
+    (import "env" "fill" (func $fill (param i32) (result i32)))
+    (memory 1)
+    (global $heap (mut i32) (i32.const 16))
+    (func (export "malloc") (param $size i32) (result i32) (local $ptr i32)
+        (local.set $ptr (global.get $heap))
+        (global.set $heap (i32.add (global.get $heap) (local.get $size)))
+        (local.get $ptr))
+    (func (export "sum") (param $len i32) (result i32) (local $ptr i32) (local $acc i32)
+        (local.set $ptr (call $fill (local.get $len)))
+        (loop $l
+            (local.set $acc (i32.add (local.get $acc) (i32.load8_u (local.get $ptr))))
+            (local.set $ptr (i32.add (local.get $ptr) (i32.const 1)))
+            (local.set $len (i32.sub (local.get $len) (i32.const 1)))
+            (br_if $l (local.get $len)))
+        (i32.mul (local.get $acc) (i32.const 10)))
+    (func (export "boom") unreachable))"#;
+
+fn setup() -> (Store, Instance) {
+    common::setup(
+        WAT,
+        &[("fill", func_type(&[ValueType::I32], &[ValueType::I32]))],
+    )
+}
+
+fn fill_len(state: &ExecutionState) -> i32 {
+    match state {
+        ExecutionState::Suspended {
+            func_name, args, ..
+        } if func_name == "fill" => args[0].as_i32(),
+        state => panic!("expected a fill call, got {state:?}"),
+    }
+}
+
+/// Answers the pending `fill` with a buffer of `len` bytes `1..=len` from
+/// the guest's own allocator
+fn fill(store: &mut Store, instance: Instance, len: i32) -> ExecutionState {
+    let results = store
+        .invoke(instance, "malloc", vec![RawValue::from(len)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    let ptr = results[0].as_i32();
+    let memory = store.memory_mut(instance, 0).unwrap();
+    for i in 0..len {
+        memory[(ptr + i) as usize] = i as u8 + 1;
+    }
+    store.resume_with(&[RawValue::from(ptr)]).unwrap()
+}
+
+#[test]
+fn host_calls_back_into_guest() {
+    let (mut store, instance) = setup();
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(4i32)])
+        .unwrap();
+    assert_eq!(fill_len(&state), 4);
+
+    let state = fill(&mut store, instance, 4);
+    assert_eq!(state.into_completed().unwrap()[0].as_i32(), 100);
+
+    // nothing is left nested
+    let results = store
+        .invoke(instance, "malloc", vec![RawValue::from(0i32)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    assert_eq!(results[0].as_i32(), 20);
+}
+
+#[test]
+fn nested_invocations_suspend_and_snapshot() {
+    let (mut store, instance) = setup();
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(2i32)])
+        .unwrap();
+    assert_eq!(fill_len(&state), 2);
+
+    // the host consults the guest again, which itself needs the host
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(3i32)])
+        .unwrap();
+    assert_eq!(fill_len(&state), 3);
+    assert_eq!(store.frames().len(), 2);
+
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    for store in [&mut store, &mut restored] {
+        let inner = fill(store, instance, 3).into_completed().unwrap();
+        assert_eq!(inner[0].as_i32(), 60);
+        let outer = fill(store, instance, 2).into_completed().unwrap();
+        assert_eq!(outer[0].as_i32(), 30);
+    }
+}
+
+#[test]
+fn nested_trap_leaves_caller_waiting() {
+    let (mut store, instance) = setup();
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert_eq!(fill_len(&state), 1);
+
+    let err = store.invoke(instance, "boom", vec![]).unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::Unreachable, _)));
+
+    let state = fill(&mut store, instance, 1);
+    assert_eq!(state.into_completed().unwrap()[0].as_i32(), 10);
+}
+
+#[test]
+fn paused_execution_still_refuses_invoke() {
+    let (mut store, instance) = setup();
+    store.set_fuel(1);
+    let state = store
+        .invoke(instance, "malloc", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+    assert!(matches!(
+        store.invoke(instance, "malloc", vec![RawValue::from(1i32)]),
+        Err(Error::Instantiation(_))
+    ));
+}
@@ -1,7 +1,8 @@
+#![cfg(feature = "spec-tests")]
+
 use gabagool::{
-    parser::Parser, AddrType, CompositeType, ExportInstance, ExternalValue, FunctionInstance,
-    GlobalInstance, GlobalType, ImportDescription, Instance, Limit, MemoryInstance, MemoryType,
-    Module, RawValue, Ref, Store, ValueType,
+    parser::Parser, AddrType, FunctionType, GlobalType, Instance, Limit, Linker, MemoryType,
+    Module, Mutability, RawValue, Ref, RefType, ResultType, Store, TableType, ValueType,
 };
 
 #[derive(Debug)]
@@ -29,76 +30,90 @@ enum ExpectedValue {
     Ref(ExpectedRef),
 }
 
-/// Create a spectest-style memory: 1 page initial, 2 pages max
-/// (matching the standard WebAssembly spectest module)
-fn create_spectest_memory(store: &mut Store, mt: &MemoryType) -> ExternalValue {
-    let addr = store.memories.len();
-    // spectest module always provides memory with 1 page initial, 2 pages max
-    store.memories.push(MemoryInstance {
-        memory_type: MemoryType {
-            addr_type: mt.addr_type,
-            limit: Limit { min: 1, max: 2 },
-        },
-        data: vec![0u8; 65536],
-    });
-    ExternalValue::Memory { addr }
-}
+/// Builds a linker exposing the standard `spectest` module used by the spec
+/// test suite
+fn spectest_linker(store: &mut Store) -> Linker {
+    let mut linker = Linker::new();
 
-fn setup_spectest_imports(store: &mut Store, module: &Module) -> Vec<ExternalValue> {
-    module
-        .import_declarations()
-        .iter()
-        .map(|import| match &import.description {
-            ImportDescription::Global(gt) => {
-                let value = match gt.value_type {
-                    ValueType::I32 => RawValue::from(666i32),
-                    ValueType::I64 => RawValue::from(666i64),
-                    ValueType::F32 => RawValue::from(666.6f32),
-                    ValueType::F64 => RawValue::from(666.6f64),
-                    _ => RawValue::from(0i32),
-                };
-                let addr = store.globals.len();
-                store.globals.push(GlobalInstance {
-                    global_type: GlobalType {
-                        value_type: gt.value_type.clone(),
-                        mutability: gt.mutability.clone(),
-                    },
-                    value,
-                });
-                ExternalValue::Global { addr }
-            }
-            ImportDescription::Mem(mt) => create_spectest_memory(store, mt),
-            ImportDescription::Table(_) => {
-                let addr = store.tables.len();
-                store.tables.push(gabagool::TableInstance {
-                    table_type: gabagool::TableType {
-                        element_reference_type: gabagool::RefType::FuncRef,
-                        addr_type: AddrType::I32,
-                        limit: Limit { min: 10, max: 20 },
-                    },
-                    elem: vec![Ref::Null; 10],
-                });
-                ExternalValue::Table { addr }
-            }
-            ImportDescription::Func(type_idx) => {
-                let addr = store.functions.len();
-                let function_type = match &module.types()[*type_idx as usize].composite_type {
-                    CompositeType::Func(ft) => ft.clone(),
-                    _ => panic!("expected function type at index {}", type_idx),
-                };
-                store.functions.push(FunctionInstance::Host {
-                    function_type,
-                    module_name: import.module.clone(),
-                    function_name: import.name.clone(),
-                });
-                ExternalValue::Function { addr }
-            }
-            ImportDescription::Tag(_) => {
-                let addr = store.tags.len();
-                ExternalValue::Tag { addr }
-            }
-        })
-        .collect()
+    let func = |params: Vec<ValueType>| FunctionType(ResultType(params), ResultType(vec![]));
+    let global = |value_type: ValueType| GlobalType {
+        value_type,
+        mutability: Mutability::Const,
+    };
+    let table = |addr_type: AddrType| TableType {
+        element_reference_type: RefType::FuncRef,
+        addr_type,
+        limit: Limit { min: 10, max: 20 },
+    };
+
+    linker
+        .func(store, "spectest", "print", func(vec![]))
+        .func(store, "spectest", "print_i32", func(vec![ValueType::I32]))
+        .func(store, "spectest", "print_i64", func(vec![ValueType::I64]))
+        .func(store, "spectest", "print_f32", func(vec![ValueType::F32]))
+        .func(store, "spectest", "print_f64", func(vec![ValueType::F64]))
+        .func(
+            store,
+            "spectest",
+            "print_i32_f32",
+            func(vec![ValueType::I32, ValueType::F32]),
+        )
+        .func(
+            store,
+            "spectest",
+            "print_f64_f64",
+            func(vec![ValueType::F64, ValueType::F64]),
+        )
+        .global(
+            store,
+            "spectest",
+            "global_i32",
+            global(ValueType::I32),
+            RawValue::from(666i32),
+        )
+        .global(
+            store,
+            "spectest",
+            "global_i64",
+            global(ValueType::I64),
+            RawValue::from(666i64),
+        )
+        .global(
+            store,
+            "spectest",
+            "global_f32",
+            global(ValueType::F32),
+            RawValue::from(666.6f32),
+        )
+        .global(
+            store,
+            "spectest",
+            "global_f64",
+            global(ValueType::F64),
+            RawValue::from(666.6f64),
+        )
+        .table(store, "spectest", "table", table(AddrType::I32), Ref::Null)
+        .unwrap()
+        .table(
+            store,
+            "spectest",
+            "table64",
+            table(AddrType::I64),
+            Ref::Null,
+        )
+        .unwrap()
+        .memory(
+            store,
+            "spectest",
+            "memory",
+            MemoryType {
+                addr_type: AddrType::I32,
+                limit: Limit { min: 1, max: 2 },
+            },
+        )
+        .unwrap();
+
+    linker
 }
 
 fn invoke_and_resume(
@@ -117,6 +132,15 @@ fn invoke_and_resume(
             gabagool::ExecutionState::FuelExhausted => {
                 return Err(gabagool::Error::Instantiation("fuel exhausted".into()));
             }
+            gabagool::ExecutionState::Interrupted => {
+                return Err(gabagool::Error::Instantiation("interrupted".into()));
+            }
+            gabagool::ExecutionState::Breakpoint => {
+                return Err(gabagool::Error::Instantiation("breakpoint".into()));
+            }
+            gabagool::ExecutionState::Watchpoint(_) => {
+                return Err(gabagool::Error::Instantiation("watchpoint".into()));
+            }
         }
     }
 }
@@ -213,78 +237,4 @@ fn values_match(expected: &[ExpectedValue], actual: &[RawValue]) -> bool {
         })
 }
 
-fn resolve_imports_with_registered(
-    store: &mut Store,
-    module: &Module,
-    registered_exports: &[(&str, &[ExportInstance])],
-) -> Vec<ExternalValue> {
-    module
-        .import_declarations()
-        .iter()
-        .map(|import| {
-            // Try to find the import in registered modules
-            for &(reg_name, exports) in registered_exports {
-                if import.module == reg_name {
-                    for export in exports {
-                        if export.name == import.name {
-                            return export.value.clone();
-                        }
-                    }
-                }
-            }
-            // Fall back to spectest-style import
-            match &import.description {
-                ImportDescription::Global(gt) => {
-                    let value = match gt.value_type {
-                        ValueType::I32 => RawValue::from(666i32),
-                        ValueType::I64 => RawValue::from(666i64),
-                        ValueType::F32 => RawValue::from(666.6f32),
-                        ValueType::F64 => RawValue::from(666.6f64),
-                        _ => RawValue::from(0i32),
-                    };
-                    let addr = store.globals.len();
-                    store.globals.push(GlobalInstance {
-                        global_type: GlobalType {
-                            value_type: gt.value_type.clone(),
-                            mutability: gt.mutability.clone(),
-                        },
-                        value,
-                    });
-                    ExternalValue::Global { addr }
-                }
-                ImportDescription::Mem(mt) => create_spectest_memory(store, mt),
-                ImportDescription::Table(_) => {
-                    let addr = store.tables.len();
-                    store.tables.push(gabagool::TableInstance {
-                        table_type: gabagool::TableType {
-                            element_reference_type: gabagool::RefType::FuncRef,
-                            addr_type: AddrType::I32,
-                            limit: Limit { min: 10, max: 20 },
-                        },
-                        elem: vec![Ref::Null; 10],
-                    });
-                    ExternalValue::Table { addr }
-                }
-                ImportDescription::Func(type_idx) => {
-                    let addr = store.functions.len();
-                    let function_type = match &module.types()[*type_idx as usize].composite_type {
-                        CompositeType::Func(ft) => ft.clone(),
-                        _ => panic!("expected function type at index {}", type_idx),
-                    };
-                    store.functions.push(FunctionInstance::Host {
-                        function_type,
-                        module_name: import.module.clone(),
-                        function_name: import.name.clone(),
-                    });
-                    ExternalValue::Function { addr }
-                }
-                ImportDescription::Tag(_) => {
-                    let addr = store.tags.len();
-                    ExternalValue::Tag { addr }
-                }
-            }
-        })
-        .collect()
-}
-
 include!(concat!(env!("OUT_DIR"), "/spec_tests_generated.rs"));
@@ -0,0 +1,192 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{ExecutionState, Instance, Module, RawValue, StepKind, Store, ValueType};
+
+use common::func_type;
+
+const WAT: &str = r#"(module
+    (import "env" "next" (func $next (result i32)))
+    (memory 1)
+    (func (export "scribble") (param $n i32) (local $i i32)
+        (loop $l
+            (i32.store (i32.const 0) (local.get $i))
+            (if (i32.eq (local.get $i) (i32.const 30))
+                (then (i32.store (i32.const 8) (i32.const 0xbad))))
+            (local.set $i (i32.add (local.get $i) (i32.const 1)))
+            (br_if $l (i32.lt_u (local.get $i) (local.get $n)))))
+    (func (export "sum") (param $n i32) (result i32) (local $acc i32)
+        (loop $l
+            (local.set $acc (i32.add (local.get $acc) (call $next)))
+            (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+            (br_if $l (local.get $n)))
+        (local.get $acc)))"#;
+
+fn setup(interval: u64) -> (Vec<u8>, Module, Store, Instance) {
+    let bytes = wat::parse_str(WAT).unwrap();
+    let module = Module::new(&bytes).unwrap();
+    let host = [("next", func_type(&[], &[ValueType::I32]))];
+    let (mut store, instance) = common::instantiate(Store::new(), &module, &host);
+    store.start_recording(interval);
+    (bytes, module, store, instance)
+}
+
+fn word(store: &Store, instance: Instance, addr: usize) -> i32 {
+    let memory = store.memory(instance, 0).unwrap();
+    i32::from_le_bytes(memory[addr..addr + 4].try_into().unwrap())
+}
+
+#[test]
+fn seek_finds_first_write() {
+    let (bytes, _, mut store, instance) = setup(16);
+    store
+        .invoke(instance, "scribble", vec![RawValue::from(50i32)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+    let end = store.position().unwrap();
+    assert_eq!(word(&store, instance, 8), 0xbad);
+
+    // the first position where the corrupt value is visible. Fused ops cost
+    // more than one unit, so seeks land on the next op that starts
+    let (mut lo, mut hi) = (0, end);
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        assert!(matches!(
+            store.seek(mid).unwrap(),
+            ExecutionState::Breakpoint
+        ));
+        assert!(store.position().unwrap() >= mid);
+        match word(&store, instance, 8) {
+            0xbad => hi = mid,
+            _ => lo = mid + 1,
+        }
+    }
+
+    store.seek(lo).unwrap();
+    assert_eq!(word(&store, instance, 0), 30);
+
+    // the instruction that wrote it
+    store.reverse_step().unwrap();
+    assert_eq!(word(&store, instance, 8), 0);
+    let offset = store.frames()[0].wasm_offset.unwrap();
+    assert_eq!(bytes[offset as usize], 0x36);
+    assert_eq!(store.locals(0).unwrap()[1].as_i32(), 30);
+
+    // seeking past the end is refused, seeking to it finishes the run again
+    assert!(store.seek(end + 1).is_err());
+    assert!(matches!(
+        store.seek(end).unwrap(),
+        ExecutionState::Completed(_)
+    ));
+    assert_eq!(word(&store, instance, 0), 49);
+}
+
+#[test]
+fn reverse_step_retraces_steps() {
+    let (_, _, mut store, instance) = setup(4);
+    store.set_fuel(0);
+    let state = store
+        .invoke(instance, "scribble", vec![RawValue::from(3i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+    store.set_fuel(u64::MAX);
+
+    let mut trail = vec![];
+    for _ in 0..12 {
+        trail.push((store.position(), store.frames()[0].wasm_offset));
+        store.step(StepKind::Into).unwrap();
+    }
+
+    for expected in trail.into_iter().rev() {
+        store.reverse_step().unwrap();
+        assert_eq!((store.position(), store.frames()[0].wasm_offset), expected);
+    }
+
+    assert_eq!(store.position(), Some(0));
+    assert!(store.reverse_step().is_err());
+    assert_eq!(store.position(), Some(0));
+
+    // the rewound invocation still runs to completion
+    store.resume().unwrap().into_completed().unwrap();
+    assert_eq!(word(&store, instance, 0), 2);
+}
+
+#[test]
+fn reverse_continue_visits_breakpoint_hits() {
+    let (bytes, module, mut store, instance) = setup(8);
+    let (_, store_offset) = module
+        .function_offsets(1)
+        .unwrap()
+        .iter()
+        .find(|&(_, offset)| bytes[offset as usize] == 0x36)
+        .unwrap();
+    store.add_breakpoint(instance, 1, store_offset).unwrap();
+
+    let mut state = store
+        .invoke(instance, "scribble", vec![RawValue::from(4i32)])
+        .unwrap();
+    while matches!(state, ExecutionState::Breakpoint) {
+        state = store.resume().unwrap();
+    }
+    state.into_completed().unwrap();
+
+    for i in (0..4).rev() {
+        let state = store.reverse_continue().unwrap();
+        assert!(matches!(state, ExecutionState::Breakpoint));
+        assert_eq!(store.frames()[0].wasm_offset, Some(store_offset));
+        assert_eq!(store.locals(0).unwrap()[1].as_i32(), i);
+    }
+
+    // no earlier hits, so back to the start
+    store.reverse_continue().unwrap();
+    assert_eq!(store.position(), Some(0));
+
+    // and forward again to the first hit
+    assert!(matches!(
+        store.resume().unwrap(),
+        ExecutionState::Breakpoint
+    ));
+    assert_eq!(store.locals(0).unwrap()[1].as_i32(), 0);
+}
+
+#[test]
+fn host_results_are_replayed() {
+    let (_, _, mut store, instance) = setup(2);
+    store.set_fuel(1_000);
+
+    let mut state = store
+        .invoke(instance, "sum", vec![RawValue::from(3i32)])
+        .unwrap();
+    for value in [10i32, 20, 30] {
+        assert!(matches!(state, ExecutionState::Suspended { .. }));
+        state = store.resume_with(&[RawValue::from(value)]).unwrap();
+    }
+    assert_eq!(state.into_completed().unwrap()[0].as_i32(), 60);
+    let fuel = store.fuel();
+
+    // replaying from the start doesn't ask the embedder again
+    store.seek(0).unwrap();
+    assert_eq!(store.fuel(), fuel);
+    let results = store.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 60);
+
+    // a new invocation starts a new recording
+    let state = store
+        .invoke(instance, "sum", vec![RawValue::from(1i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    assert!(store.seek(100).is_err());
+}
+
+#[test]
+fn not_recording() {
+    let (_, _, mut store, _) = setup(1);
+    assert!(store.seek(0).is_err());
+
+    store.stop_recording();
+    assert_eq!(store.position(), None);
+    assert!(store.reverse_step().is_err());
+    assert!(store.reverse_continue().is_err());
+}
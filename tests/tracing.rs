@@ -0,0 +1,195 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use gabagool::{
+    ExecutionState, Instance, JsonTracer, RawValue, Store, TracedFunction, Tracer, ValueType,
+};
+
+use common::func_type;
+
+const WAT: &str = r#"(module
+    (import "env" "next" (func $next (result i32)))
+    (memory 1 2)
+    (func $double (param i32) (result i32)
+        (i32.mul (local.get 0) (i32.const 2)))
+    (func $tail (param i32) (result i32)
+        (return_call $double (local.get 0)))
+    (func (export "run") (param i32) (result i32)
+        (drop (memory.grow (i32.const 1)))
+        (drop (memory.grow (i32.const 1)))
+        (i32.add (call $tail (local.get 0)) (call $next)))
+    (func (export "crash")
+        (unreachable)))"#;
+
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn lines(&self) -> Vec<String> {
+        let bytes = self.0.borrow();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+/// The (function, pc) of every traced op
+type OpLog = Vec<(Option<u32>, usize)>;
+
+#[derive(Clone, Default)]
+struct Ops(Rc<RefCell<OpLog>>);
+
+impl Tracer for Ops {
+    fn traces_ops(&self) -> bool {
+        true
+    }
+
+    fn op(&mut self, function: TracedFunction<'_>, pc: usize, _op: &gabagool::ir::Op) {
+        self.0.borrow_mut().push((function.func_idx, pc));
+    }
+}
+
+fn setup() -> (Store, Instance) {
+    common::setup(WAT, &[("next", func_type(&[], &[ValueType::I32]))])
+}
+
+/// Runs `run(5)`, answering the host call with 1
+fn run(store: &mut Store, instance: Instance) -> ExecutionState {
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(5i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    store.resume_with(&[RawValue::from(1i32)]).unwrap()
+}
+
+#[test]
+fn json_lines() {
+    let (mut store, instance) = setup();
+    let out = Shared::default();
+    store.set_tracer(Box::new(JsonTracer::new(out.clone())));
+
+    let results = run(&mut store, instance).into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 11);
+
+    assert_eq!(
+        out.lines(),
+        [
+            r#"{"event":"enter","module":0,"func":3,"args":[5]}"#,
+            r#"{"event":"memory_grow","memory":0,"old_pages":1,"new_pages":2}"#,
+            r#"{"event":"memory_grow","memory":0,"old_pages":2,"new_pages":null}"#,
+            r#"{"event":"enter","module":0,"func":2,"name":"tail","args":[5]}"#,
+            r#"{"event":"exit","module":0,"func":2,"name":"tail","results":[]}"#,
+            r#"{"event":"enter","module":0,"func":1,"name":"double","args":[5]}"#,
+            r#"{"event":"exit","module":0,"func":1,"name":"double","results":[10]}"#,
+            r#"{"event":"host_call","module":"env","name":"next","args":[]}"#,
+            r#"{"event":"host_return","results":[1]}"#,
+            r#"{"event":"exit","module":0,"func":3,"results":[11]}"#,
+        ]
+    );
+
+    out.0.borrow_mut().clear();
+    store.set_fuel(0);
+    let state = store.invoke(instance, "crash", vec![]).unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+    store.set_fuel(10);
+    assert!(store.resume().is_err());
+    assert_eq!(
+        out.lines(),
+        [
+            r#"{"event":"enter","module":0,"func":4,"args":[]}"#,
+            r#"{"event":"suspended","reason":"fuel_exhausted"}"#,
+            r#"{"event":"trap","trap":"unreachable","frames":[{"module":0,"func":4,"offset":107}]}"#,
+        ]
+    );
+
+    // no events once the tracer is removed
+    assert!(store.take_tracer().is_some());
+    out.0.borrow_mut().clear();
+    store.set_fuel(u64::MAX);
+    run(&mut store, instance).into_completed().unwrap();
+    assert!(out.lines().is_empty());
+}
+
+#[test]
+fn ops_traced_once_across_pauses() {
+    let (mut store, instance) = setup();
+    let ops = Ops::default();
+    store.set_tracer(Box::new(ops.clone()));
+    run(&mut store, instance).into_completed().unwrap();
+    let expected = ops.0.take();
+    assert!(expected.len() > 10);
+
+    // one unit of fuel at a time, host calls needing two with the surcharge
+    store.set_fuel(0);
+    let mut state = store
+        .invoke(instance, "run", vec![RawValue::from(5i32)])
+        .unwrap();
+    let results = loop {
+        state = match state {
+            ExecutionState::FuelExhausted => {
+                store.set_fuel(store.fuel().unwrap() + 1);
+                store.resume().unwrap()
+            }
+            ExecutionState::Suspended { .. } => store.resume_with(&[RawValue::from(1i32)]).unwrap(),
+            state => break state.into_completed().unwrap(),
+        };
+    };
+    assert_eq!(results[0].as_i32(), 11);
+    assert_eq!(ops.0.take(), expected);
+}
+
+#[test]
+fn same_trace_after_restore() {
+    let (mut store, instance) = setup();
+    let out = Shared::default();
+    store.set_tracer(Box::new(JsonTracer::new(out.clone()).with_ops()));
+    run(&mut store, instance).into_completed().unwrap();
+    let expected = out.lines();
+    assert!(expected.iter().any(|line| line.contains(r#""event":"op""#)));
+
+    // the original traces up to a pause, a restored copy the rest
+    let before = Shared::default();
+    let (mut store, instance) = setup();
+    store.set_tracer(Box::new(JsonTracer::new(before.clone()).with_ops()));
+    store.set_fuel(8);
+    let state = store
+        .invoke(instance, "run", vec![RawValue::from(5i32)])
+        .unwrap();
+    assert!(matches!(state, ExecutionState::FuelExhausted));
+
+    let after = Shared::default();
+    let mut restored = Store::from_snapshot(&store.snapshot());
+    restored.set_tracer(Box::new(JsonTracer::new(after.clone()).with_ops()));
+    restored.set_fuel(u64::MAX);
+    let state = restored.resume().unwrap();
+    assert!(matches!(state, ExecutionState::Suspended { .. }));
+    restored
+        .resume_with(&[RawValue::from(1i32)])
+        .unwrap()
+        .into_completed()
+        .unwrap();
+
+    let mut lines = before.lines();
+    assert_eq!(
+        lines.pop().unwrap(),
+        r#"{"event":"suspended","reason":"fuel_exhausted"}"#
+    );
+    lines.extend(after.lines());
+    assert_eq!(lines, expected);
+}
@@ -0,0 +1,576 @@
+#![cfg(not(feature = "spec-tests"))]
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use gabagool::{Error, ExternalValue, Instance, Linker, Module, RawValue, Store, Trap, Vfs, Wasi};
+
+// thin wrappers so tests can drive each call, with fixed scratch addresses
+// for the results the calls write back
+const WAT: &str = r#"(module
+    (import "wasi_snapshot_preview1" "args_sizes_get" (func $args_sizes_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "args_get" (func $args_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "environ_sizes_get" (func $environ_sizes_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "environ_get" (func $environ_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "clock_time_get" (func $clock_time_get (param i32 i64 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_close" (func $fd_close (param i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_prestat_get" (func $fd_prestat_get (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_prestat_dir_name" (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_read" (func $fd_read (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_seek" (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_write" (func $fd_write (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "fd_renumber" (func $fd_renumber (param i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "path_open" (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "poll_oneoff" (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+    (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+    (import "wasi_snapshot_preview1" "random_get" (func $random_get (param i32 i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 256) "hello, world\n")
+    (data (i32.const 512) "out.txt")
+    (data (i32.const 528) "../escape")
+
+    ;; writes `len` bytes at `buf` to `fd`, with the count written at 16
+    (func $write (export "write") (param $fd i32) (param $buf i32) (param $len i32) (result i32)
+        (i32.store (i32.const 0) (local.get $buf))
+        (i32.store (i32.const 4) (local.get $len))
+        (call $fd_write (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 16)))
+    ;; reads up to `len` bytes from `fd` to `buf`, with the count read at 16
+    (func (export "read") (param $fd i32) (param $buf i32) (param $len i32) (result i32)
+        (i32.store (i32.const 0) (local.get $buf))
+        (i32.store (i32.const 4) (local.get $len))
+        (call $fd_read (local.get $fd) (i32.const 0) (i32.const 1) (i32.const 16)))
+    (func (export "_start")
+        (drop (call $write (i32.const 1) (i32.const 256) (i32.const 13))))
+
+    ;; copies the strings to 1024 and writes them to stdout
+    (func (export "args") (result i32)
+        (drop (call $args_sizes_get (i32.const 32) (i32.const 36)))
+        (drop (call $args_get (i32.const 64) (i32.const 1024)))
+        (call $write (i32.const 1) (i32.const 1024) (i32.load (i32.const 36))))
+    (func (export "environ") (result i32)
+        (drop (call $environ_sizes_get (i32.const 32) (i32.const 36)))
+        (drop (call $environ_get (i32.const 64) (i32.const 1024)))
+        (call $write (i32.const 1) (i32.const 1024) (i32.load (i32.const 36))))
+
+    ;; the fd opened lands at 16
+    (func (export "open") (param $dir i32) (param $path i32) (param $len i32) (param $oflags i32) (result i32)
+        (call $path_open (local.get $dir) (i32.const 0) (local.get $path) (local.get $len)
+            (local.get $oflags) (i64.const 66) (i64.const 66) (i32.const 0) (i32.const 16)))
+    ;; the new offset lands at 16
+    (func (export "seek") (param $fd i32) (param $offset i64) (param $whence i32) (result i32)
+        (call $fd_seek (local.get $fd) (local.get $offset) (local.get $whence) (i32.const 16)))
+    (func (export "open_read_only") (param $dir i32) (param $path i32) (param $len i32) (result i32)
+        (call $path_open (local.get $dir) (i32.const 0) (local.get $path) (local.get $len)
+            (i32.const 0) (i64.const 2) (i64.const 2) (i32.const 0) (i32.const 16)))
+    (func (export "close") (param i32) (result i32)
+        (call $fd_close (local.get 0)))
+    (func (export "prestat") (param $fd i32) (result i32)
+        (call $fd_prestat_get (local.get $fd) (i32.const 16)))
+    (func (export "prestat_dir_name") (param $fd i32) (param $len i32) (result i32)
+        (call $fd_prestat_dir_name (local.get $fd) (i32.const 1024) (local.get $len)))
+
+    ;; the time lands at 16
+    (func (export "clock") (param $id i32) (result i32)
+        (call $clock_time_get (local.get $id) (i64.const 1) (i32.const 16)))
+    ;; `n` subscriptions at 2048, events at 4096 and their count at 16
+    (func (export "poll") (param $n i32) (result i32)
+        (call $poll_oneoff (i32.const 2048) (i32.const 4096) (local.get $n) (i32.const 16)))
+    (func (export "random") (param $buf i32) (param $len i32) (result i32)
+        (call $random_get (local.get $buf) (local.get $len)))
+    (func (export "renumber") (result i32)
+        (call $fd_renumber (i32.const 1) (i32.const 2)))
+    (func (export "exit") (param i32)
+        (call $proc_exit (local.get 0))
+        (unreachable)))"#;
+
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn take(&self) -> String {
+        String::from_utf8(self.0.take()).unwrap()
+    }
+}
+
+struct Guest {
+    store: Store,
+    instance: Instance,
+    memory: usize,
+}
+
+impl Guest {
+    fn new(wasi: Wasi) -> Self {
+        Self::with_store(Store::new(), wasi)
+    }
+
+    fn with_store(mut store: Store, wasi: Wasi) -> Self {
+        let bytes = wat::parse_str(WAT).unwrap();
+        let module = Module::new(&bytes).unwrap();
+        let instance = Linker::new()
+            .wasi(&mut store, wasi)
+            .instantiate(&mut store, &module)
+            .unwrap();
+        let memory = match store.exports(instance)[0].value {
+            ExternalValue::Memory { addr } => addr,
+            _ => unreachable!(),
+        };
+
+        Self {
+            store,
+            instance,
+            memory,
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[RawValue]) -> Vec<RawValue> {
+        self.store
+            .invoke(self.instance, name, args.to_vec())
+            .unwrap()
+            .into_completed()
+            .unwrap()
+    }
+
+    /// Runs `name`, returning its errno
+    fn errno(&mut self, name: &str, args: &[i32]) -> i32 {
+        let args = args
+            .iter()
+            .map(|&arg| RawValue::from(arg))
+            .collect::<Vec<_>>();
+        self.call(name, &args)[0].as_i32()
+    }
+
+    fn restore(&self) -> Self {
+        Self {
+            store: Store::from_snapshot(&self.store.snapshot()),
+            instance: self.instance,
+            memory: self.memory,
+        }
+    }
+
+    fn vfs(&self) -> &Vfs {
+        self.store.wasi().unwrap().vfs().unwrap()
+    }
+
+    fn memory(&mut self) -> &mut [u8] {
+        &mut self.store.memories[self.memory].data
+    }
+
+    fn u32_at(&mut self, addr: usize) -> u32 {
+        u32::from_le_bytes(self.memory()[addr..addr + 4].try_into().unwrap())
+    }
+
+    fn u64_at(&mut self, addr: usize) -> u64 {
+        u64::from_le_bytes(self.memory()[addr..addr + 8].try_into().unwrap())
+    }
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gabagool-wasi-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn stdout_args_and_environ() {
+    let stdout = Shared::default();
+    let mut guest = Guest::new(
+        Wasi::new()
+            .args(["prog", "a b"])
+            .env("HOME", "/home/guest")
+            .env("EMPTY", "")
+            .stdout(stdout.clone()),
+    );
+
+    guest.call("_start", &[]);
+    assert_eq!(stdout.take(), "hello, world\n");
+    assert_eq!(guest.u32_at(16), 13);
+
+    assert_eq!(guest.errno("args", &[]), 0);
+    assert_eq!(guest.u32_at(32), 2);
+    assert_eq!(stdout.take(), "prog\0a b\0");
+    assert_eq!(guest.u32_at(64), 1024);
+    assert_eq!(guest.u32_at(68), 1029);
+
+    assert_eq!(guest.errno("environ", &[]), 0);
+    assert_eq!(guest.u32_at(32), 2);
+    assert_eq!(stdout.take(), "HOME=/home/guest\0EMPTY=\0");
+}
+
+#[test]
+fn errors() {
+    let mut guest = Guest::new(Wasi::new().stdout(Shared::default()));
+
+    // unimplemented functions, bad descriptors and out of bounds buffers fail
+    // with errnos
+    assert_eq!(guest.errno("renumber", &[]), 52);
+    assert_eq!(guest.errno("write", &[9, 256, 13]), 8);
+    assert_eq!(guest.errno("write", &[1, 65530, 13]), 21);
+    assert_eq!(guest.errno("seek", &[1, 0, 0]), 70);
+    assert_eq!(guest.errno("prestat", &[1]), 8);
+    assert_eq!(guest.errno("clock", &[7]), 28);
+
+    // proc_exit unwinds the guest with its code
+    let err = guest
+        .store
+        .invoke(guest.instance, "exit", vec![RawValue::from(3i32)])
+        .unwrap_err();
+    assert!(matches!(err, Error::Trap(Trap::Exit(3), _)), "{err}");
+    assert_eq!(
+        err.to_string().lines().next(),
+        Some("trap: exit with code 3")
+    );
+}
+
+#[test]
+fn preopened_files() {
+    let dir = temp_dir("files");
+    fs::write(dir.join("existing.txt"), "from the host").unwrap();
+    let stdout = Shared::default();
+    let mut guest = Guest::new(
+        Wasi::new()
+            .preopen_dir(&dir, "/sandbox")
+            .stdout(stdout.clone()),
+    );
+
+    // the preopen is the first descriptor after stdio
+    assert_eq!(guest.errno("prestat", &[3]), 0);
+    assert_eq!(guest.u32_at(16), 0);
+    assert_eq!(guest.u32_at(20), 8);
+    assert_eq!(guest.errno("prestat_dir_name", &[3, 4]), 37);
+    assert_eq!(guest.errno("prestat_dir_name", &[3, 8]), 0);
+    assert_eq!(&guest.memory()[1024..1032], b"/sandbox");
+    assert_eq!(guest.errno("prestat", &[4]), 8);
+
+    // create, write, seek back and read
+    const CREAT_TRUNC: i32 = 1 | 8;
+    assert_eq!(guest.errno("open", &[3, 512, 7, CREAT_TRUNC]), 0);
+    let fd = guest.u32_at(16) as i32;
+    assert_eq!(fd, 4);
+    assert_eq!(guest.errno("write", &[fd, 256, 13]), 0);
+    assert_eq!(
+        fs::read_to_string(dir.join("out.txt")).unwrap(),
+        "hello, world\n"
+    );
+
+    let seek = |guest: &mut Guest, offset: i64, whence: i32| {
+        let args = [fd.into(), RawValue::from(offset), whence.into()];
+        guest.call("seek", &args)[0].as_i32()
+    };
+    assert_eq!(seek(&mut guest, -6, 2), 0);
+    assert_eq!(guest.u64_at(16), 7);
+    assert_eq!(guest.errno("read", &[fd, 1024, 100]), 0);
+    assert_eq!(guest.u32_at(16), 6);
+    assert_eq!(&guest.memory()[1024..1030], b"world\n");
+    assert_eq!(seek(&mut guest, 0, 1), 0);
+    assert_eq!(guest.u64_at(16), 13);
+
+    assert_eq!(guest.errno("close", &[fd]), 0);
+    assert_eq!(guest.errno("close", &[fd]), 8);
+
+    // paths can't leave the preopen
+    assert_eq!(guest.errno("open", &[3, 528, 9, 0]), 76);
+    assert_eq!(guest.errno("open", &[3, 512, 7, 4 | 1]), 20);
+    guest.memory()[512..519].copy_from_slice(b"nope.tx");
+    assert_eq!(guest.errno("open", &[3, 512, 7, 0]), 44);
+
+    guest.memory()[512..524].copy_from_slice(b"existing.txt");
+    assert_eq!(guest.errno("open", &[3, 512, 12, 0]), 0);
+    let fd = guest.u32_at(16) as i32;
+    assert_eq!(fd, 4, "closed descriptors are reused");
+    assert_eq!(guest.errno("read", &[fd, 1024, 100]), 0);
+    assert_eq!(guest.u32_at(16), 13);
+    assert_eq!(&guest.memory()[1024..1037], b"from the host");
+    assert!(stdout.take().is_empty());
+
+    // restored guests find host files closed, with the descriptor kept from
+    // other files until they close it
+    let mut restored = guest.restore();
+    assert_eq!(restored.errno("read", &[fd, 1024, 100]), 8);
+    assert_eq!(restored.errno("open", &[3, 512, 12, 0]), 0);
+    assert_eq!(restored.u32_at(16), 5);
+    assert_eq!(restored.errno("close", &[fd]), 0);
+    assert_eq!(restored.errno("close", &[fd]), 8);
+    assert_eq!(restored.errno("open", &[3, 512, 12, 0]), 0);
+    assert_eq!(restored.u32_at(16), 4);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn clocks_and_polling() {
+    let mut guest = Guest::new(Wasi::new());
+
+    assert_eq!(guest.errno("clock", &[0]), 0);
+    assert!(guest.u64_at(16) > 1_600_000_000 * 1_000_000_000);
+    assert_eq!(guest.errno("clock", &[1]), 0);
+    let monotonic = guest.u64_at(16);
+    assert_eq!(guest.errno("clock", &[1]), 0);
+    assert!(guest.u64_at(16) >= monotonic);
+
+    // a relative 5ms timeout and a later one, only the first fires
+    let subscriptions = &mut guest.memory()[2048..2048 + 96];
+    subscriptions.fill(0);
+    for (i, (userdata, timeout)) in [(7u64, 5_000_000u64), (8, 60_000_000_000)]
+        .into_iter()
+        .enumerate()
+    {
+        let sub = &mut subscriptions[i * 48..];
+        sub[0..8].copy_from_slice(&userdata.to_le_bytes());
+        sub[16..20].copy_from_slice(&1u32.to_le_bytes());
+        sub[24..32].copy_from_slice(&timeout.to_le_bytes());
+    }
+
+    let start = Instant::now();
+    assert_eq!(guest.errno("poll", &[2]), 0);
+    assert!(start.elapsed() >= Duration::from_millis(5));
+    assert!(start.elapsed() < Duration::from_secs(30));
+    assert_eq!(guest.u32_at(16), 1);
+    assert_eq!(guest.u64_at(4096), 7);
+    assert_eq!(&guest.memory()[4104..4107], [0, 0, 0]);
+
+    assert_eq!(guest.errno("poll", &[0]), 28);
+}
+
+#[test]
+fn random() {
+    let mut guest = Guest::new(Wasi::new());
+    assert_eq!(guest.errno("random", &[1024, 64]), 0);
+    let first = guest.memory()[1024..1088].to_vec();
+    assert!(first.iter().any(|&b| b != 0));
+    assert_eq!(guest.errno("random", &[1024, 64]), 0);
+    assert_ne!(guest.memory()[1024..1088], first);
+    assert_eq!(guest.errno("random", &[65530, 64]), 21);
+}
+
+#[test]
+fn vfs_files() {
+    let mut vfs = Vfs::new();
+    vfs.write_file("data/in.txt", "0123456789").unwrap();
+    let mut guest = Guest::new(Wasi::new().preopen_vfs(vfs, "/"));
+
+    assert_eq!(guest.errno("prestat", &[3]), 0);
+    assert_eq!(guest.u32_at(20), 1);
+
+    guest.memory()[512..523].copy_from_slice(b"data/in.txt");
+    assert_eq!(guest.errno("open_read_only", &[3, 512, 11]), 0);
+    let fd = guest.u32_at(16) as i32;
+    assert_eq!(guest.errno("read", &[fd, 1024, 4]), 0);
+    assert_eq!(&guest.memory()[1024..1028], b"0123");
+    assert_eq!(
+        guest.errno("write", &[fd, 256, 13]),
+        76,
+        "not opened for writing"
+    );
+
+    // created files show up in the vfs, and `..` can't leave it
+    guest.memory()[512..519].copy_from_slice(b"out.txt");
+    assert_eq!(guest.errno("open", &[3, 512, 7, 1]), 0);
+    let out = guest.u32_at(16) as i32;
+    assert_eq!(guest.errno("write", &[out, 256, 13]), 0);
+    assert_eq!(
+        guest.vfs().read_file("out.txt"),
+        Some(&b"hello, world\n"[..])
+    );
+    assert_eq!(guest.vfs().read_dir(""), Some(vec!["data", "out.txt"]));
+    assert_eq!(guest.errno("open", &[3, 528, 9, 0]), 76);
+    guest.memory()[512..519].copy_from_slice(b"nope.tx");
+    assert_eq!(guest.errno("open", &[3, 512, 7, 0]), 44);
+}
+
+#[test]
+fn vfs_writes_past_its_cap() {
+    let mut guest = Guest::new(Wasi::new().preopen_vfs(Vfs::new().max_bytes(1024), "/"));
+    guest.memory()[512..519].copy_from_slice(b"out.txt");
+    assert_eq!(guest.errno("open", &[3, 512, 7, 1]), 0);
+    let fd = guest.u32_at(16) as i32;
+
+    // a write ending at the cap fits, past it there's no space, and past
+    // what any file can hold it's too large
+    for (offset, errno) in [(1011i64, 0), (1012, 51), (1 << 40, 51), (i64::MAX, 22)] {
+        let args = [fd.into(), offset.into(), 0i32.into()];
+        assert_eq!(guest.call("seek", &args)[0].as_i32(), 0);
+        assert_eq!(guest.errno("write", &[fd, 256, 13]), errno);
+    }
+    assert_eq!(
+        guest.vfs().read_file("out.txt").map(<[u8]>::len),
+        Some(1024)
+    );
+}
+
+#[test]
+fn vfs_survives_snapshots() {
+    let mut vfs = Vfs::new();
+    vfs.write_file("in.txt", "0123456789").unwrap();
+    let mut guest = Guest::new(Wasi::new().args(["prog"]).preopen_vfs(vfs, "."));
+
+    guest.memory()[512..518].copy_from_slice(b"in.txt");
+    assert_eq!(guest.errno("open", &[3, 512, 6, 0]), 0);
+    let fd = guest.u32_at(16) as i32;
+    assert_eq!(guest.errno("read", &[fd, 1024, 4]), 0);
+
+    // the restored guest carries on at the same offset, and its writes don't
+    // reach the original
+    let mut restored = guest.restore();
+    assert_eq!(restored.errno("read", &[fd, 1024, 4]), 0);
+    assert_eq!(&restored.memory()[1024..1028], b"4567");
+    assert_eq!(restored.errno("write", &[fd, 256, 5]), 0);
+    assert_eq!(
+        restored.vfs().read_file("in.txt"),
+        Some(&b"01234567hello"[..])
+    );
+    assert_eq!(guest.vfs().read_file("in.txt"), Some(&b"0123456789"[..]));
+
+    assert_eq!(guest.errno("read", &[fd, 1024, 4]), 0);
+    assert_eq!(&guest.memory()[1024..1028], b"4567");
+
+    // streams aren't part of snapshots but can be swapped back in
+    let stdout = Shared::default();
+    let wasi = restored.store.take_wasi().unwrap();
+    restored.store.set_wasi(wasi.stdout(stdout.clone()));
+    assert_eq!(restored.errno("args", &[]), 0);
+    assert_eq!(stdout.take(), "prog\0");
+}
+
+#[test]
+fn vfs_from_host_dir() {
+    let dir = temp_dir("seed");
+    fs::create_dir_all(dir.join("a/b")).unwrap();
+    fs::write(dir.join("a/b/c.txt"), "nested").unwrap();
+    fs::write(dir.join("top.txt"), "top").unwrap();
+    // linked files are copied, while a directory linking back up is skipped
+    // rather than followed forever
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.join("top.txt"), dir.join("a/link.txt")).unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("a/b/loop")).unwrap();
+    }
+
+    let vfs = Vfs::from_host_dir(&dir).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+
+    #[cfg(unix)]
+    {
+        assert_eq!(vfs.read_file("a/link.txt"), Some(&b"top"[..]));
+        assert_eq!(vfs.read_dir("a/b"), Some(vec!["c.txt"]));
+    }
+    assert_eq!(vfs.read_dir("."), Some(vec!["a", "top.txt"]));
+    assert_eq!(vfs.read_file("a/b/c.txt"), Some(&b"nested"[..]));
+    assert_eq!(vfs.read_file("a/./b/../b/c.txt"), Some(&b"nested"[..]));
+    assert_eq!(vfs.read_file("top.txt"), Some(&b"top"[..]));
+    assert_eq!(vfs.read_file("a"), None);
+    assert_eq!(vfs.read_file("../top.txt"), None);
+}
+
+#[test]
+fn deterministic_clocks_and_random() {
+    let mut guest = Guest::with_store(Store::deterministic(42), Wasi::new());
+
+    // virtual clocks tick on every read, from a fixed epoch
+    assert_eq!(guest.errno("clock", &[1]), 0);
+    assert_eq!(guest.u64_at(16), 0);
+    assert_eq!(guest.errno("clock", &[1]), 0);
+    assert_eq!(guest.u64_at(16), 1_000);
+    assert_eq!(guest.errno("clock", &[0]), 0);
+    assert_eq!(guest.u64_at(16), 1_577_836_800 * 1_000_000_000 + 2_000);
+
+    // waits skip ahead instead of sleeping
+    let sub = &mut guest.memory()[2048..2048 + 48];
+    sub.fill(0);
+    sub[0..8].copy_from_slice(&9u64.to_le_bytes());
+    sub[16..20].copy_from_slice(&1u32.to_le_bytes());
+    sub[24..32].copy_from_slice(&3_600_000_000_000u64.to_le_bytes());
+    let start = Instant::now();
+    assert_eq!(guest.errno("poll", &[1]), 0);
+    assert!(start.elapsed() < Duration::from_secs(60));
+    assert_eq!(guest.u32_at(16), 1);
+    assert_eq!(guest.errno("clock", &[1]), 0);
+    assert_eq!(guest.u64_at(16), 3_600_000_000_000 + 4_000);
+
+    // the same seed gives the same bytes, carried on by restored stores
+    assert_eq!(guest.errno("random", &[1024, 37]), 0);
+    let first = guest.memory()[1024..1061].to_vec();
+    let mut restored = guest.restore();
+    assert_eq!(guest.errno("random", &[1024, 37]), 0);
+    assert_eq!(restored.errno("random", &[1024, 37]), 0);
+    assert_eq!(restored.memory()[1024..1061], guest.memory()[1024..1061]);
+    assert_ne!(guest.memory()[1024..1061], first);
+    assert_eq!(restored.errno("clock", &[1]), 0);
+    assert_eq!(restored.u64_at(16), 3_600_000_000_000 + 5_000);
+
+    let mut same = Guest::with_store(Store::deterministic(42), Wasi::new());
+    assert_eq!(same.errno("random", &[1024, 37]), 0);
+    assert_eq!(same.memory()[1024..1061], first);
+
+    let mut other = Guest::with_store(Store::deterministic(43), Wasi::new());
+    assert_eq!(other.errno("random", &[1024, 37]), 0);
+    assert_ne!(other.memory()[1024..1061], first);
+}
+
+#[test]
+fn deterministic_waits_forever_and_snapshots() {
+    let mut guest = Guest::with_store(Store::deterministic(7), Wasi::new());
+    std::thread::sleep(Duration::from_millis(2));
+    let mut same = Guest::with_store(Store::deterministic(7), Wasi::new());
+    assert!(guest.store.snapshot() == same.store.snapshot());
+
+    // waiting forever pins the virtual clocks at their end
+    let sub = &mut guest.memory()[2048..2048 + 48];
+    sub.fill(0);
+    sub[16..20].copy_from_slice(&1u32.to_le_bytes());
+    sub[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(guest.errno("poll", &[1]), 0);
+    assert_eq!(guest.errno("clock", &[1]), 0);
+    assert_eq!(guest.u64_at(16), u64::MAX);
+    assert_eq!(guest.errno("clock", &[0]), 0);
+    assert_eq!(guest.u64_at(16), u64::MAX);
+
+    assert_eq!(same.errno("clock", &[1]), 0);
+    assert_eq!(same.u64_at(16), 0);
+}
+
+#[test]
+fn replays_answer_from_the_recording() {
+    let stdout = Shared::default();
+    let wasi = Wasi::new()
+        .stdin(io::Cursor::new(b"abcdefgh".to_vec()))
+        .stdout(stdout.clone());
+    let mut guest = Guest::new(wasi);
+    guest.store.start_recording(4);
+
+    // replays see the input and entropy the guest saw, without reading more
+    assert_eq!(guest.errno("read", &[0, 1024, 4]), 0);
+    guest.memory()[1024..1028].fill(0);
+    guest.store.seek(0).unwrap();
+    guest.store.resume().unwrap();
+    assert_eq!(&guest.memory()[1024..1028], b"abcd");
+
+    assert_eq!(guest.errno("random", &[1024, 16]), 0);
+    let random = guest.memory()[1024..1040].to_vec();
+    guest.store.seek(0).unwrap();
+    guest.store.resume().unwrap();
+    assert_eq!(guest.memory()[1024..1040], random);
+
+    assert_eq!(guest.errno("read", &[0, 1024, 4]), 0);
+    assert_eq!(&guest.memory()[1024..1028], b"efgh");
+
+    // nor repeat output
+    guest.call("_start", &[]);
+    guest.store.seek(0).unwrap();
+    guest.store.resume().unwrap();
+    assert_eq!(stdout.take(), "hello, world\n");
+}
@@ -0,0 +1,156 @@
+#![cfg(not(feature = "spec-tests"))]
+
+mod common;
+
+use gabagool::{ExecutionState, Instance, Module, RawValue, Store, WatchHit, WatchKind};
+
+const WAT: &str = r#"(module
+    (memory 1)
+    (data (i32.const 32) "\01\02\03\04")
+    (func (export "store") (param i32 i32)
+        (i32.store (local.get 0) (local.get 1)))
+    (func (export "load") (param i32) (result i32)
+        (i32.load (local.get 0)))
+    (func (export "fill") (param i32 i32 i32)
+        (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+    (func (export "copy") (param i32 i32 i32)
+        (memory.copy (local.get 0) (local.get 1) (local.get 2))))"#;
+
+fn setup() -> (Vec<u8>, Store, Instance) {
+    let bytes = wat::parse_str(WAT).unwrap();
+    let (store, instance) = common::instantiate(Store::new(), &Module::new(&bytes).unwrap(), &[]);
+    (bytes, store, instance)
+}
+
+fn call(store: &mut Store, instance: Instance, name: &str, args: &[i32]) -> ExecutionState {
+    let args = args.iter().map(|&arg| RawValue::from(arg)).collect();
+    store.invoke(instance, name, args).unwrap()
+}
+
+fn expect_hit(state: ExecutionState) -> Box<WatchHit> {
+    match state {
+        ExecutionState::Watchpoint(hit) => hit,
+        state => panic!("expected a watchpoint hit, got {state:?}"),
+    }
+}
+
+#[test]
+fn write_reports_old_and_new_bytes() {
+    let (bytes, mut store, instance) = setup();
+    store
+        .add_watchpoint(instance, 0, 8..12, WatchKind::Write)
+        .unwrap();
+
+    // outside the range
+    let state = call(&mut store, instance, "store", &[12, 7]);
+    assert!(matches!(state, ExecutionState::Completed(_)));
+
+    let hit = expect_hit(call(&mut store, instance, "store", &[6, 0x0403_0201]));
+    assert!(hit.write);
+    assert_eq!(hit.memory_idx, 0);
+    assert_eq!(hit.address, 8);
+    assert_eq!(hit.old, [0, 0]);
+    assert_eq!(hit.new, [3, 4]);
+    assert_eq!(hit.frame.func_idx, Some(0));
+    assert_eq!(bytes[hit.frame.wasm_offset.unwrap() as usize], 0x36);
+
+    // paused after the store
+    assert_eq!(&store.memory(instance, 0).unwrap()[6..10], [1, 2, 3, 4]);
+    assert!(store.resume().unwrap().into_completed().is_ok());
+
+    // reads don't trigger write watchpoints
+    let state = call(&mut store, instance, "load", &[8]);
+    assert!(matches!(state, ExecutionState::Completed(_)));
+}
+
+#[test]
+fn reads_and_bulk_ops() {
+    let (_, mut store, instance) = setup();
+    store
+        .add_watchpoint(instance, 0, 32..34, WatchKind::Read)
+        .unwrap();
+    store
+        .add_watchpoint(instance, 0, 100..200, WatchKind::ReadWrite)
+        .unwrap();
+
+    let hit = expect_hit(call(&mut store, instance, "load", &[30]));
+    assert!(!hit.write);
+    assert_eq!(
+        (hit.address, &hit.old[..], &hit.new[..]),
+        (32, &[1, 2][..], &[1, 2][..])
+    );
+    let results = store.resume().unwrap().into_completed().unwrap();
+    assert_eq!(results[0].as_i32(), 0x0201_0000);
+
+    // memory.copy reads the watched source and writes the watched target
+    let hit = expect_hit(call(&mut store, instance, "copy", &[198, 32, 4]));
+    assert!(hit.write);
+    assert_eq!(
+        (hit.address, &hit.old[..], &hit.new[..]),
+        (198, &[0, 0][..], &[1, 2][..])
+    );
+    store.resume().unwrap().into_completed().unwrap();
+
+    let hit = expect_hit(call(&mut store, instance, "fill", &[90, 0xff, 20]));
+    assert_eq!((hit.address, hit.new.len()), (100, 10));
+    assert!(hit.new.iter().all(|&b| b == 0xff));
+    store.resume().unwrap().into_completed().unwrap();
+
+    // empty bulk ops access nothing
+    let state = call(&mut store, instance, "fill", &[100, 0, 0]);
+    assert!(matches!(state, ExecutionState::Completed(_)));
+}
+
+#[test]
+fn watchpoints_survive_snapshot() {
+    let (_, mut store, instance) = setup();
+    store
+        .add_watchpoint(instance, 0, 0..4, WatchKind::Write)
+        .unwrap();
+
+    expect_hit(call(&mut store, instance, "store", &[0, 5]));
+    let mut restored = Store::from_snapshot(&store.snapshot());
+
+    for store in [&mut store, &mut restored] {
+        store.resume().unwrap().into_completed().unwrap();
+        expect_hit(call(store, instance, "store", &[2, 5]));
+        store.resume().unwrap().into_completed().unwrap();
+
+        assert!(store
+            .remove_watchpoint(instance, 0, 0..4, WatchKind::Write)
+            .unwrap());
+        let state = call(store, instance, "store", &[0, 5]);
+        assert!(matches!(state, ExecutionState::Completed(_)));
+    }
+}
+
+#[test]
+fn invalid_watchpoints() {
+    let (_, mut store, instance) = setup();
+    assert!(store
+        .add_watchpoint(instance, 1, 0..4, WatchKind::Write)
+        .is_err());
+    assert!(store
+        .add_watchpoint(instance, 0, 4..4, WatchKind::Write)
+        .is_err());
+    assert!(!store
+        .remove_watchpoint(instance, 0, 0..4, WatchKind::Read)
+        .unwrap());
+
+    // out of bounds accesses still trap
+    store
+        .add_watchpoint(instance, 0, 65530..65540, WatchKind::Write)
+        .unwrap();
+    let err = store
+        .invoke(
+            instance,
+            "store",
+            vec![RawValue::from(65534i32), RawValue::from(1i32)],
+        )
+        .unwrap_err();
+    assert!(matches!(err, gabagool::Error::Trap(..)));
+
+    store.clear_watchpoints();
+    let state = call(&mut store, instance, "store", &[65530, 1]);
+    assert!(matches!(state, ExecutionState::Completed(_)));
+}